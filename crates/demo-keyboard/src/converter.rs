@@ -0,0 +1,425 @@
+use anyhow::{Context, Result};
+use keyboard_formats::annotation::{Annotation, CandidateFlags};
+use keyboard_formats::candidate_filter::CandidateFilter;
+use mozc_dict_gen::{ConnectionMatrix, POS_COLUMNS};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Leading POS columns naming the category (名詞,固有名詞,地域); the ones
+/// after them are conjugations and forms.
+const POS_CATEGORY_COLUMNS: usize = 4;
+/// Longest reading looked up from one lattice position.
+const MAX_READING_CHARS: usize = 16;
+/// Cost of passing a kana through unconverted when no word covers it.
+const UNKNOWN_COST: i32 = 20000;
+
+#[derive(Clone)]
+struct Word {
+    surface: String,
+    left_id: u16,
+    right_id: u16,
+    cost: i16,
+    annotation: Annotation,
+}
+
+/// Reading and POS category of the cheapest lexicon entry of a surface.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WordInfo {
+    pub reading: String,
+    /// Leading POS columns without their `*` fields, e.g. 名詞,一般
+    pub pos: String,
+    cost: i16,
+}
+
+/// A segment of the best conversion.
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub reading: String,
+    pub surface: String,
+    pub annotation: Annotation,
+}
+
+/// Kana-to-kanji conversion over the Vibrato inputs produced by mozc-dict-gen:
+/// words from lex.csv indexed by reading, and connection costs from matrix.def.
+///
+/// A [`CandidateFilter`] hides or demotes words by their flags in
+/// conversions, and tells callers which predicted words to drop.
+pub struct Converter {
+    words: HashMap<String, Vec<Word>>,
+    /// Flags of flagged surfaces, for filtering words that come without an
+    /// entry (predictions)
+    flags: HashMap<String, CandidateFlags>,
+    /// Cheapest entry of each surface, for describing words that come
+    /// without a reading (predictions)
+    infos: HashMap<String, WordInfo>,
+    matrix: Vec<i16>,
+    size: usize,
+    filter: CandidateFilter,
+}
+
+impl Converter {
+    /// Loads lex.csv and matrix.def. Context IDs outside the matrix are an
+    /// error, as a lexicon and matrix of different builds would convert with
+    /// the wrong costs.
+    pub fn load(lex: &Path, matrix: &Path) -> Result<Self> {
+        let file = File::open(matrix).with_context(|| format!("Failed to open {:?}", matrix))?;
+        let matrix = ConnectionMatrix::read(BufReader::new(file)).with_context(|| format!("Bad matrix {:?}", matrix))?;
+        let size = matrix.size as usize;
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(lex)
+            .with_context(|| format!("Failed to open {:?} (run mozc-dict-gen with --keep-intermediate)", lex))?;
+
+        let mut words: HashMap<String, Vec<Word>> = HashMap::new();
+        let mut flags: HashMap<String, CandidateFlags> = HashMap::new();
+        let mut infos: HashMap<String, WordInfo> = HashMap::new();
+        for record in reader.records() {
+            let record = record?;
+            // surface, left_id, right_id, cost, 7 POS columns, reading, pronunciation,
+            // optionally annotation and flags
+            if record.len() < 4 + POS_COLUMNS + 1 {
+                continue;
+            }
+            let (Ok(left_id), Ok(right_id), Ok(cost)) = (record[1].parse::<u16>(), record[2].parse::<u16>(), record[3].parse())
+            else {
+                continue;
+            };
+            anyhow::ensure!(
+                (left_id as usize) < size && (right_id as usize) < size,
+                "{:?} line {}: context IDs {} {} are outside the {}x{} matrix",
+                lex,
+                record.position().map_or(0, |position| position.line()),
+                left_id,
+                right_id,
+                size,
+                size
+            );
+            let annotation = Annotation::from_columns(record.get(6 + POS_COLUMNS), record.get(7 + POS_COLUMNS));
+            if !annotation.flags.is_empty() {
+                flags.entry(record[0].to_string()).or_default().insert(annotation.flags);
+            }
+            if infos.get(&record[0]).is_none_or(|info| cost < info.cost) {
+                let pos: Vec<&str> = (4..4 + POS_CATEGORY_COLUMNS).map(|i| &record[i]).filter(|f| *f != "*").collect();
+                let info = WordInfo {
+                    reading: record[4 + POS_COLUMNS].to_string(),
+                    pos: pos.join(","),
+                    cost,
+                };
+                infos.insert(record[0].to_string(), info);
+            }
+            words.entry(record[4 + POS_COLUMNS].to_string()).or_default().push(Word {
+                surface: record[0].to_string(),
+                left_id,
+                right_id,
+                cost,
+                annotation,
+            });
+        }
+
+        Ok(Self {
+            words,
+            flags,
+            infos,
+            matrix: matrix.costs,
+            size,
+            filter: CandidateFilter::default(),
+        })
+    }
+
+    pub fn with_filter(self, filter: CandidateFilter) -> Self {
+        Self { filter, ..self }
+    }
+
+    /// Whether the filter lets `surface` be offered. Surfaces the lexicon
+    /// doesn't flag are always allowed.
+    pub fn allows(&self, surface: &str) -> bool {
+        self.flags.get(surface).is_none_or(|flags| self.filter.allows(*flags))
+    }
+
+    /// Reading and POS category of `surface`, if the lexicon has it.
+    pub fn info(&self, surface: &str) -> Option<&WordInfo> {
+        self.infos.get(surface)
+    }
+
+    /// Cost of a word with `left_id` following one with `right_id`; both are
+    /// checked against the matrix size on load.
+    fn connection(&self, right_id: u16, left_id: u16) -> i32 {
+        self.matrix[right_id as usize * self.size + left_id as usize] as i32
+    }
+
+    /// Cheapest word read as `reading` that the filter allows, if any.
+    pub fn best_surface(&self, reading: &str) -> Option<&str> {
+        let words = self.words.get(reading)?;
+        words
+            .iter()
+            .filter_map(|w| Some((w.cost as i32 + self.filter.penalty(w.annotation.flags)?, w)))
+            .min_by_key(|(cost, _)| *cost)
+            .map(|(_, w)| w.surface.as_str())
+    }
+
+    /// Appends `chars` to the input of `lattice`, computing the lowest-cost
+    /// paths (Viterbi search) ending at each new position. Earlier positions
+    /// are left untouched. `boost(reading, surface)` is subtracted from the
+    /// cost of each word, e.g. to prefer words the user committed recently.
+    pub fn extend(
+        &self,
+        lattice: &mut Lattice,
+        chars: impl IntoIterator<Item = char>,
+        boost: impl Fn(&str, &str) -> f64,
+    ) {
+        for c in chars {
+            lattice.chars.push(c);
+            let mut best: Option<Node> = None;
+            self.visit_nodes(lattice, lattice.chars.len(), &boost, |start, word, costs| {
+                if best.as_ref().is_none_or(|(c, _, _)| costs.path < *c) {
+                    best = Some((costs.path, start, word.clone()));
+                }
+            });
+            lattice.best.push(best);
+        }
+    }
+
+    /// Every node of `lattice` with its costs and whether the best path of
+    /// the whole input goes through it, `boost` being the one the lattice
+    /// was extended with.
+    pub fn dump(&self, lattice: &Lattice, boost: impl Fn(&str, &str) -> f64) -> LatticeDump {
+        let mut chosen = Vec::new();
+        let mut end = lattice.len();
+        while end > 0 {
+            let Some((_, start, _)) = &lattice.best[end] else { break };
+            chosen.push((*start, end));
+            end = *start;
+        }
+        let mut nodes = Vec::new();
+        for end in 1..=lattice.len() {
+            let best = lattice.best[end].as_ref();
+            self.visit_nodes(lattice, end, &boost, |start, word, costs| {
+                // The node the best path to `end` takes
+                let is_best = best.is_some_and(|(cost, best_start, best_word)| {
+                    *cost == costs.path && *best_start == start && best_word.surface == word.surface
+                });
+                nodes.push(LatticeNode {
+                    start,
+                    end,
+                    reading: lattice.chars[start..end].iter().collect(),
+                    surface: word.surface.clone(),
+                    left_id: word.left_id,
+                    right_id: word.right_id,
+                    word_cost: costs.word,
+                    connection_cost: costs.connection,
+                    path_cost: costs.path,
+                    best: is_best,
+                    chosen: is_best && chosen.contains(&(start, end)),
+                });
+            });
+        }
+        LatticeDump {
+            input: lattice.chars.iter().collect(),
+            nodes,
+        }
+    }
+
+    /// Calls `visit(start, word, costs)` for each word read as the input
+    /// before `end` that follows a best path to its start, and for the
+    /// unconverted kana that keeps a path alive through input the lexicon
+    /// does not cover.
+    fn visit_nodes(
+        &self,
+        lattice: &Lattice,
+        end: usize,
+        boost: &impl Fn(&str, &str) -> f64,
+        mut visit: impl FnMut(usize, &Word, NodeCosts),
+    ) {
+        for start in end.saturating_sub(MAX_READING_CHARS)..end {
+            let Some((prev_cost, _, prev)) = &lattice.best[start] else { continue };
+            let reading: String = lattice.chars[start..end].iter().collect();
+            for word in self.words.get(&reading).into_iter().flatten() {
+                let Some(penalty) = self.filter.penalty(word.annotation.flags) else { continue };
+                let word_cost = word.cost as i32 + penalty - boost(&reading, &word.surface).round() as i32;
+                let connection = self.connection(prev.right_id, word.left_id);
+                visit(start, word, NodeCosts::new(*prev_cost, connection, word_cost));
+            }
+        }
+        if let Some((prev_cost, _, _)) = &lattice.best[end - 1] {
+            let word = Word {
+                surface: lattice.chars[end - 1].to_string(),
+                left_id: 0,
+                right_id: 0,
+                cost: 0,
+                annotation: Annotation::default(),
+            };
+            visit(end - 1, &word, NodeCosts::new(*prev_cost, 0, UNKNOWN_COST));
+        }
+    }
+}
+
+/// Costs of a lattice node: its word (with the filter's penalty and the
+/// boost applied), the connection from the best path before it, and the
+/// path through it.
+struct NodeCosts {
+    word: i32,
+    connection: i32,
+    path: i32,
+}
+
+impl NodeCosts {
+    fn new(prev_path: i32, connection: i32, word: i32) -> Self {
+        Self {
+            word,
+            connection,
+            path: prev_path + connection + word,
+        }
+    }
+}
+
+/// A word the conversion of [`LatticeDump::input`] considered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatticeNode {
+    /// Kana span of the input, in characters
+    pub start: usize,
+    pub end: usize,
+    pub reading: String,
+    pub surface: String,
+    pub left_id: u16,
+    pub right_id: u16,
+    pub word_cost: i32,
+    /// From the best path ending at `start`
+    pub connection_cost: i32,
+    /// Of the best path ending with this node
+    pub path_cost: i32,
+    /// The best path to `end` ends with this node
+    pub best: bool,
+    /// The best path of the whole input goes through this node
+    pub chosen: bool,
+}
+
+/// The nodes of a conversion lattice, for seeing why a candidate won
+/// (demo-keyboard `--dump-lattice`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatticeDump {
+    pub input: String,
+    pub nodes: Vec<LatticeNode>,
+}
+
+impl LatticeDump {
+    pub fn to_json(&self) -> serde_json::Value {
+        let nodes: Vec<serde_json::Value> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                serde_json::json!({
+                    "start": node.start,
+                    "end": node.end,
+                    "reading": node.reading,
+                    "surface": node.surface,
+                    "left_id": node.left_id,
+                    "right_id": node.right_id,
+                    "word_cost": node.word_cost,
+                    "connection_cost": node.connection_cost,
+                    "path_cost": node.path_cost,
+                    "best": node.best,
+                    "chosen": node.chosen,
+                })
+            })
+            .collect();
+        serde_json::json!({ "input": self.input, "nodes": nodes })
+    }
+
+    /// Graphviz DOT with a column per input position: every node, an edge
+    /// from the best path before it labeled with the connection cost, and
+    /// the chosen path in bold.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph lattice {\n  rankdir=LR;\n  node [shape=box];\n  p0 [label=\"BOS\"];\n");
+        // The best node ending at each position, where later nodes attach
+        let mut best_at = vec![String::from("p0"); self.input.chars().count() + 1];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.best {
+                best_at[node.end] = format!("n{}", i);
+            }
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            let style = if node.chosen { ", style=bold" } else { "" };
+            let label = format!(
+                "{}\n{}\nL{} R{} word={}",
+                node.surface, node.reading, node.left_id, node.right_id, node.word_cost
+            );
+            dot.push_str(&format!("  n{} [label={:?}{}];\n", i, label, style));
+            dot.push_str(&format!(
+                "  {} -> n{} [label=\"conn={} path={}\"{}];\n",
+                best_at[node.start], i, node.connection_cost, node.path_cost, style
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// (cost, start, word) of the best path ending at a position.
+type Node = (i32, usize, Word);
+
+/// Best paths ending at every position of an input. Since a position only
+/// depends on the input before it, appending input keeps existing entries and
+/// a changed suffix only needs [`Lattice::truncate`] before extending.
+#[derive(Clone)]
+pub struct Lattice {
+    chars: Vec<char>,
+    best: Vec<Option<Node>>,
+}
+
+impl Default for Lattice {
+    fn default() -> Self {
+        let bos = Word {
+            surface: String::new(),
+            left_id: 0,
+            right_id: 0,
+            cost: 0,
+            annotation: Annotation::default(),
+        };
+        Self {
+            chars: Vec::new(),
+            best: vec![Some((0, 0, bos))],
+        }
+    }
+}
+
+impl Lattice {
+    /// Number of input characters.
+    pub fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    pub fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    /// Drops the input after the first `len` characters.
+    pub fn truncate(&mut self, len: usize) {
+        self.chars.truncate(len);
+        self.best.truncate(len + 1);
+    }
+
+    /// Best conversion of the first `end` characters.
+    pub fn best_path(&self, end: usize) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        let mut end = end.min(self.len());
+        while end > 0 {
+            let Some((_, start, word)) = &self.best[end] else { break };
+            segments.push(Segment {
+                reading: self.chars[*start..end].iter().collect(),
+                surface: word.surface.clone(),
+                annotation: word.annotation.clone(),
+            });
+            end = *start;
+        }
+        segments.reverse();
+        segments
+    }
+}
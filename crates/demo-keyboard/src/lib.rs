@@ -0,0 +1,380 @@
+//! Live conversion and prediction over the built artifacts, behind the
+//! demo-keyboard binary: type romaji and see what a keyboard using the
+//! dictionary, N-gram FST and reading trie would offer.
+
+use anyhow::Result;
+use clap::Parser;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, terminal};
+use keyboard_formats::candidate_filter::Modes;
+use keyboard_formats::completion::Completer;
+use keyboard_formats::learning::ShortTermMemory;
+use keyboard_formats::sequence_guard::SequenceGuard;
+use keyboard_formats::suppression::SuppressionList;
+use keyboard_formats::user_history::UserHistory;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub mod converter;
+pub mod predictor;
+pub mod romaji;
+pub mod session;
+
+use converter::Converter;
+use predictor::{Prediction, Predictor};
+use session::SessionContext;
+
+#[derive(Parser, Debug)]
+#[command(name = "demo-keyboard")]
+#[command(about = "Type romaji and see live conversion and next-word predictions from the built artifacts")]
+pub struct Args {
+    /// Lexicon CSV (mozc-dict-gen --keep-intermediate)
+    #[arg(long, default_value = "output/lex.csv")]
+    lex: PathBuf,
+
+    /// Connection cost matrix (mozc-dict-gen --keep-intermediate)
+    #[arg(long, default_value = "output/matrix.def")]
+    matrix: PathBuf,
+
+    /// N-gram FST for predictions (wiki-ngram)
+    #[arg(long, default_value = "output/wiki-ngrams.fst")]
+    ngrams: PathBuf,
+
+    /// Reading → frequency FST (input-data-gen swipe) for completing the word
+    /// being typed
+    #[arg(long)]
+    readings: Option<PathBuf>,
+
+    /// Words never to predict (mozc-dict-gen's suppression.fst, from Mozc's
+    /// suggestion filter and suppression word list)
+    #[arg(long)]
+    suppression: Option<PathBuf>,
+
+    /// Order of the N-gram FST (wiki-ngram --max-ngram)
+    #[arg(long, default_value = "3")]
+    max_ngram: usize,
+
+    /// Never predict a word completing a run of more than this many words
+    /// stored in the N-gram FST end to end, so chained predictions can't
+    /// reproduce corpus passages; 0 turns the guard off
+    #[arg(long, default_value_t = SequenceGuard::DEFAULT_MAX_WORDS)]
+    max_reproduced_words: usize,
+
+    /// Number of predictions shown
+    #[arg(long, default_value = "5")]
+    predictions: usize,
+
+    /// Convert each line of this file instead of running interactively
+    /// ("-" for stdin)
+    #[arg(long)]
+    script: Option<PathBuf>,
+
+    /// Print the conversion lattice of each script line (every node with
+    /// its costs, and the chosen path) as dot or json instead of the report
+    #[arg(long, value_enum, requires = "script")]
+    dump_lattice: Option<LatticeFormat>,
+
+    /// Learn committed conversions and prefer them in later conversions and
+    /// predictions. In script mode each line is committed after it is shown
+    #[arg(long)]
+    learn: bool,
+
+    /// User history file to start learning from, written back on exit
+    /// (implies --learn)
+    #[arg(long)]
+    history: Option<PathBuf>,
+
+    /// Candidate filter mode to enable, e.g. no-slang, no-emoji, formal,
+    /// kid-safe; repeatable
+    #[arg(long = "mode")]
+    modes: Vec<String>,
+
+    /// Candidate filter mode definitions replacing the built-in ones
+    #[arg(long)]
+    mode_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LatticeFormat {
+    /// Graphviz DOT
+    Dot,
+    /// One JSON object per line
+    Json,
+}
+
+struct Demo {
+    converter: Converter,
+    predictor: Predictor,
+    suppression: Option<SuppressionList<Vec<u8>>>,
+    completer: Option<Completer<Vec<u8>>>,
+    predictions: usize,
+}
+
+/// What the demo shows for the current input.
+struct View {
+    kana: String,
+    conversion: String,
+    segments: Vec<String>,
+    predictions: Vec<Prediction>,
+    /// Words the end of the input may be the start of
+    completions: Vec<String>,
+}
+
+impl Demo {
+    fn view(&self, session: &mut SessionContext) -> View {
+        let segments = session.segments();
+        let conversion: String = segments.iter().map(|s| s.surface.as_str()).collect();
+
+        // Predict from the last words of the conversion, or of committed text
+        let mut context = session.committed().to_vec();
+        context.extend(segments.iter().map(|s| s.surface.clone()));
+        let predictions = self.next_words(session, &context, self.predictions);
+        let completions = self.completions(session);
+
+        View {
+            kana: session.kana(),
+            conversion,
+            segments: segments
+                .into_iter()
+                .map(|s| {
+                    if s.annotation.is_empty() {
+                        format!("{}/{}", s.surface, s.reading)
+                    } else {
+                        format!("{}/{} {}", s.surface, s.reading, s.annotation)
+                    }
+                })
+                .collect(),
+            predictions,
+            completions,
+        }
+    }
+
+    /// Words following the last two words of `context`, or the last one if
+    /// the bigram context has never been seen. Words the candidate filter
+    /// hides or the suppression list holds are dropped; the rest get their
+    /// reading and POS from the lexicon.
+    fn next_words(&self, session: &mut SessionContext, context: &[String], limit: usize) -> Vec<Prediction> {
+        let len = context.len();
+        if len == 0 {
+            return Vec::new();
+        }
+        let span = tracing::info_span!("predict", context = %context.join(" "), limit, predictions = tracing::field::Empty);
+        let _predict = span.enter();
+        let fetch = limit.max(FILTERED_POOL);
+        let mut words = session.predict(&self.predictor, &context[len.saturating_sub(2)..], fetch);
+        if words.is_empty() {
+            words = session.predict(&self.predictor, &context[len - 1..], fetch);
+        }
+        words.retain(|word| {
+            self.converter.allows(&word.surface)
+                && self.suppression.as_ref().is_none_or(|list| list.allows_prediction(&word.surface))
+                && self.predictor.allows(context, &word.surface)
+        });
+        words.truncate(limit);
+        for word in &mut words {
+            if let Some(info) = self.converter.info(&word.surface) {
+                word.reading = Some(info.reading.clone());
+                word.pos = Some(info.pos.clone());
+            }
+        }
+        span.record("predictions", words.len());
+        words
+    }
+
+    /// Completions of the word being typed, including pending romaji. The
+    /// reading trie proposes words; those the N-gram model also expects after
+    /// the converted context rank higher.
+    fn completions(&self, session: &mut SessionContext) -> Vec<String> {
+        let Some(completer) = &self.completer else {
+            return Vec::new();
+        };
+        let (kana, pending) = session.composing();
+        let pending = pending.to_string();
+        let completions = completer.complete_in_context(
+            &kana,
+            &romaji::next_kana(&pending),
+            COMPLETION_CANDIDATES,
+            self.predictions,
+            |reading| self.converter.best_surface(reading).unwrap_or(reading).to_string(),
+            |start| {
+                // The kana before the word is a prefix of the composing input,
+                // so its conversion is already in the session's lattice
+                let mut context = session.committed().to_vec();
+                context.extend(session.segments_to(kana[..start].chars().count()).into_iter().map(|s| s.surface));
+                let expected = self.next_words(session, &context, COMPLETION_CANDIDATES);
+                move |reading: &str, surface: &str| {
+                    let context_score = expected
+                        .iter()
+                        .find(|word| word.surface == surface)
+                        .map_or(0.0, |word| word.score as f64 / 1000.0);
+                    context_score + session.boost(reading, surface) / 1000.0
+                }
+            },
+        );
+        completions.into_iter().map(|c| format!("{}/{}", c.surface, c.reading)).collect()
+    }
+}
+
+/// Candidates considered before ranking by context.
+const COMPLETION_CANDIDATES: usize = 50;
+
+/// Predictions fetched so enough remain after filtering.
+const FILTERED_POOL: usize = 20;
+
+/// Runs the demo as the binary does: interactively, or over the
+/// `--script` file with its report written to `out`.
+pub fn run(args: &Args, out: impl Write) -> Result<()> {
+    let modes = match &args.mode_file {
+        Some(path) => Modes::read(io::BufReader::new(fs::File::open(path)?))?,
+        None => Modes::builtin(),
+    };
+    let filter = modes.filter(args.modes.iter().map(String::as_str))?;
+    let demo = Demo {
+        converter: Converter::load(&args.lex, &args.matrix)?.with_filter(filter),
+        predictor: match args.max_reproduced_words {
+            0 => Predictor::load(&args.ngrams)?,
+            max_words => Predictor::load(&args.ngrams)?.with_guard(SequenceGuard::new(args.max_ngram, max_words)),
+        },
+        suppression: match &args.suppression {
+            Some(path) => Some(SuppressionList::new(fst::Map::new(fs::read(path)?)?)),
+            None => None,
+        },
+        completer: match &args.readings {
+            Some(path) => Some(Completer::new(fst::Map::new(fs::read(path)?)?)),
+            None => None,
+        },
+        predictions: args.predictions,
+    };
+
+    let memory = match &args.history {
+        Some(path) if path.exists() => {
+            let history = UserHistory::read(io::BufReader::new(fs::File::open(path)?))?;
+            Some(ShortTermMemory::from_history(history, ShortTermMemory::DEFAULT_WEIGHT))
+        }
+        _ if args.learn || args.history.is_some() => Some(ShortTermMemory::default()),
+        _ => None,
+    };
+
+    let memory = match &args.script {
+        Some(path) => run_script(&demo, path, args.dump_lattice, out, memory)?,
+        None => run_interactive(&demo, memory)?,
+    };
+
+    if let (Some(path), Some(memory)) = (&args.history, memory) {
+        memory.history().write(io::BufWriter::new(fs::File::create(path)?))?;
+    }
+    Ok(())
+}
+
+fn new_session(memory: Option<ShortTermMemory>) -> SessionContext {
+    match memory {
+        Some(memory) => SessionContext::with_memory(memory),
+        None => SessionContext::new(),
+    }
+}
+
+/// Types each input line key by key and prints the resulting conversion and
+/// predictions, or its lattice in `dump_lattice` format. Returns the memory
+/// learned from the lines, if learning.
+fn run_script(
+    demo: &Demo,
+    path: &PathBuf,
+    dump_lattice: Option<LatticeFormat>,
+    mut out: impl Write,
+    mut memory: Option<ShortTermMemory>,
+) -> Result<Option<ShortTermMemory>> {
+    let input = if path.as_os_str() == "-" {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(path)?
+    };
+    for line in input.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let mut session = new_session(memory.take());
+        for c in line.chars() {
+            session.push(&demo.converter, c);
+        }
+        match dump_lattice {
+            Some(LatticeFormat::Dot) => write!(out, "{}", session.lattice_dump(&demo.converter).to_dot())?,
+            Some(LatticeFormat::Json) => writeln!(out, "{}", session.lattice_dump(&demo.converter).to_json())?,
+            None => write_report(&demo.view(&mut session), line, &mut out)?,
+        }
+        session.commit(&[]);
+        memory = session.into_memory();
+    }
+    Ok(memory)
+}
+
+fn write_report(view: &View, line: &str, mut out: impl Write) -> Result<()> {
+    writeln!(out, "input\t{}", line)?;
+    writeln!(out, "kana\t{}", view.kana)?;
+    writeln!(out, "conversion\t{}", view.conversion)?;
+    writeln!(out, "segments\t{}", view.segments.join(" "))?;
+    let words: Vec<&str> = view.predictions.iter().map(|p| p.surface.as_str()).collect();
+    writeln!(out, "predictions\t{}", words.join(" "))?;
+    let details: Vec<String> = view.predictions.iter().map(Prediction::details).collect();
+    writeln!(out, "prediction_details\t{}", details.join(" "))?;
+    writeln!(out, "completions\t{}", view.completions.join(" "))?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn run_interactive(demo: &Demo, memory: Option<ShortTermMemory>) -> Result<Option<ShortTermMemory>> {
+    terminal::enable_raw_mode()?;
+    let mut session = new_session(memory);
+    let result = interactive_loop(demo, &mut session);
+    terminal::disable_raw_mode()?;
+    println!();
+    result.map(|()| session.into_memory())
+}
+
+fn interactive_loop(demo: &Demo, session: &mut SessionContext) -> Result<()> {
+    let mut stdout = io::stdout();
+
+    loop {
+        let view = demo.view(session);
+        execute!(stdout, cursor::MoveTo(0, 0), terminal::Clear(terminal::ClearType::All))?;
+        // Raw mode needs explicit carriage returns
+        write!(stdout, "Type romaji. Enter commits, Tab takes the first prediction, Esc quits.\r\n\r\n")?;
+        write!(stdout, "committed   {}\r\n", session.committed().concat())?;
+        write!(stdout, "input       {}\r\n", session.romaji())?;
+        write!(stdout, "kana        {}\r\n", view.kana)?;
+        write!(stdout, "conversion  {}\r\n", view.conversion)?;
+        write!(stdout, "segments    {}\r\n", view.segments.join(" "))?;
+        let predictions: Vec<String> = view
+            .predictions
+            .iter()
+            .map(|p| match &p.reading {
+                Some(reading) => format!("{}/{}", p.surface, reading),
+                None => p.surface.clone(),
+            })
+            .collect();
+        write!(stdout, "predictions {}\r\n", predictions.join(" "))?;
+        write!(stdout, "completions {}\r\n", view.completions.join(" "))?;
+        stdout.flush()?;
+
+        let Event::Key(KeyEvent {
+            code, modifiers, kind, ..
+        }) = event::read()?
+        else {
+            continue;
+        };
+        if kind != KeyEventKind::Press {
+            continue;
+        }
+        match code {
+            KeyCode::Esc => break,
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => break,
+            KeyCode::Char(c) => session.push(&demo.converter, c),
+            KeyCode::Backspace => session.pop(&demo.converter),
+            KeyCode::Enter => session.commit(&[]),
+            KeyCode::Tab => {
+                if let Some(word) = view.predictions.first() {
+                    session.commit(std::slice::from_ref(&word.surface));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,8 @@
+use anyhow::Result;
+use clap::Parser;
+use demo_keyboard::Args;
+
+fn main() -> Result<()> {
+    let _telemetry = telemetry::init("demo-keyboard")?;
+    demo_keyboard::run(&Args::parse(), std::io::stdout().lock())
+}
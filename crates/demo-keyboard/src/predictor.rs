@@ -0,0 +1,112 @@
+use anyhow::{Context, Result};
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, Streamer};
+use keyboard_formats::sequence_guard::SequenceGuard;
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+
+/// Where a prediction's score comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+    /// The N-gram FST alone
+    Ngrams,
+    /// The N-gram FST, boosted by words the user committed
+    Learned,
+}
+
+impl Layer {
+    pub fn name(self) -> &'static str {
+        match self {
+            Layer::Ngrams => "ngrams",
+            Layer::Learned => "learned",
+        }
+    }
+}
+
+/// A predicted next word and what is known about it, so a UI can show the
+/// reading as ruby and filter by POS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Prediction {
+    pub surface: String,
+    pub score: u64,
+    /// Reading of the surface's cheapest lexicon entry
+    pub reading: Option<String>,
+    /// POS category of that entry, e.g. 名詞,一般
+    pub pos: Option<String>,
+    pub layer: Layer,
+    /// Order of the N-gram the word completes: the context words plus one
+    pub order: usize,
+}
+
+impl Prediction {
+    /// `surface/reading/pos/layer/order`, `*` for what the lexicon lacks.
+    pub fn details(&self) -> String {
+        format!(
+            "{}/{}/{}/{}/{}",
+            self.surface,
+            self.reading.as_deref().unwrap_or("*"),
+            self.pos.as_deref().unwrap_or("*"),
+            self.layer.name(),
+            self.order
+        )
+    }
+}
+
+/// Next-word predictions from the Wikipedia N-gram FST.
+pub struct Predictor {
+    map: Map<Mmap>,
+    guard: Option<SequenceGuard>,
+}
+
+impl Predictor {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open {:?} (run wiki-ngram first)", path))?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(Self {
+            map: Map::new(mmap)?,
+            guard: None,
+        })
+    }
+
+    /// Withholds predictions that would complete a longer run of stored
+    /// words than the guard allows.
+    pub fn with_guard(mut self, guard: SequenceGuard) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Whether `word` may be predicted after the text `context`.
+    pub fn allows(&self, context: &[String], word: &str) -> bool {
+        let Some(guard) = &self.guard else { return true };
+        let context: Vec<&str> = context.iter().map(String::as_str).collect();
+        guard.allows(&self.map, &context, word)
+    }
+
+    /// Words most often following `context` (one or two words), highest
+    /// score first. Readings and POS are left for the caller to fill from
+    /// the lexicon.
+    pub fn predict(&self, context: &[&str], limit: usize) -> Vec<Prediction> {
+        let prefix = format!("{} ", context.join(" "));
+        let mut stream = self.map.search(Str::new(&prefix).starts_with()).into_stream();
+        let mut predictions = Vec::new();
+        while let Some((key, score)) = stream.next() {
+            let Ok(key) = std::str::from_utf8(key) else { continue };
+            let next = &key[prefix.len()..];
+            // Only N-grams exactly one word longer than the context
+            if !next.is_empty() && !next.contains(' ') {
+                predictions.push(Prediction {
+                    surface: next.to_string(),
+                    score,
+                    reading: None,
+                    pos: None,
+                    layer: Layer::Ngrams,
+                    order: context.len() + 1,
+                });
+            }
+        }
+        predictions.sort_by(|a, b| b.score.cmp(&a.score).then(a.surface.cmp(&b.surface)));
+        predictions.truncate(limit);
+        predictions
+    }
+}
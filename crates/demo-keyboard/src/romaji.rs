@@ -0,0 +1,116 @@
+/// Romaji spellings and their kana. Conversion tries the longest spelling first.
+#[rustfmt::skip]
+const TABLE: &[(&str, &str)] = &[
+    ("a", "あ"), ("i", "い"), ("u", "う"), ("e", "え"), ("o", "お"),
+    ("ka", "か"), ("ki", "き"), ("ku", "く"), ("ke", "け"), ("ko", "こ"),
+    ("ga", "が"), ("gi", "ぎ"), ("gu", "ぐ"), ("ge", "げ"), ("go", "ご"),
+    ("sa", "さ"), ("si", "し"), ("shi", "し"), ("su", "す"), ("se", "せ"), ("so", "そ"),
+    ("za", "ざ"), ("zi", "じ"), ("ji", "じ"), ("zu", "ず"), ("ze", "ぜ"), ("zo", "ぞ"),
+    ("ta", "た"), ("ti", "ち"), ("chi", "ち"), ("tu", "つ"), ("tsu", "つ"), ("te", "て"), ("to", "と"),
+    ("da", "だ"), ("di", "ぢ"), ("du", "づ"), ("de", "で"), ("do", "ど"),
+    ("na", "な"), ("ni", "に"), ("nu", "ぬ"), ("ne", "ね"), ("no", "の"),
+    ("ha", "は"), ("hi", "ひ"), ("hu", "ふ"), ("fu", "ふ"), ("he", "へ"), ("ho", "ほ"),
+    ("ba", "ば"), ("bi", "び"), ("bu", "ぶ"), ("be", "べ"), ("bo", "ぼ"),
+    ("pa", "ぱ"), ("pi", "ぴ"), ("pu", "ぷ"), ("pe", "ぺ"), ("po", "ぽ"),
+    ("ma", "ま"), ("mi", "み"), ("mu", "む"), ("me", "め"), ("mo", "も"),
+    ("ya", "や"), ("yu", "ゆ"), ("yo", "よ"),
+    ("ra", "ら"), ("ri", "り"), ("ru", "る"), ("re", "れ"), ("ro", "ろ"),
+    ("wa", "わ"), ("wo", "を"), ("nn", "ん"), ("n'", "ん"),
+    ("kya", "きゃ"), ("kyu", "きゅ"), ("kyo", "きょ"),
+    ("gya", "ぎゃ"), ("gyu", "ぎゅ"), ("gyo", "ぎょ"),
+    ("sya", "しゃ"), ("syu", "しゅ"), ("syo", "しょ"), ("sha", "しゃ"), ("shu", "しゅ"), ("sho", "しょ"), ("she", "しぇ"),
+    ("ja", "じゃ"), ("ju", "じゅ"), ("jo", "じょ"), ("je", "じぇ"), ("zya", "じゃ"), ("zyu", "じゅ"), ("zyo", "じょ"),
+    ("tya", "ちゃ"), ("tyu", "ちゅ"), ("tyo", "ちょ"), ("cha", "ちゃ"), ("chu", "ちゅ"), ("cho", "ちょ"), ("che", "ちぇ"),
+    ("nya", "にゃ"), ("nyu", "にゅ"), ("nyo", "にょ"),
+    ("hya", "ひゃ"), ("hyu", "ひゅ"), ("hyo", "ひょ"),
+    ("bya", "びゃ"), ("byu", "びゅ"), ("byo", "びょ"),
+    ("pya", "ぴゃ"), ("pyu", "ぴゅ"), ("pyo", "ぴょ"),
+    ("mya", "みゃ"), ("myu", "みゅ"), ("myo", "みょ"),
+    ("rya", "りゃ"), ("ryu", "りゅ"), ("ryo", "りょ"),
+    ("fa", "ふぁ"), ("fi", "ふぃ"), ("fe", "ふぇ"), ("fo", "ふぉ"),
+    ("thi", "てぃ"), ("dhi", "でぃ"), ("vu", "ゔ"),
+    ("xa", "ぁ"), ("xi", "ぃ"), ("xu", "ぅ"), ("xe", "ぇ"), ("xo", "ぉ"),
+    ("la", "ぁ"), ("li", "ぃ"), ("lu", "ぅ"), ("le", "ぇ"), ("lo", "ぉ"),
+    ("xtu", "っ"), ("ltu", "っ"), ("xtsu", "っ"), ("ltsu", "っ"),
+    ("xya", "ゃ"), ("xyu", "ゅ"), ("xyo", "ょ"), ("lya", "ゃ"), ("lyu", "ゅ"), ("lyo", "ょ"),
+    ("-", "ー"), (",", "、"), (".", "。"), ("?", "？"), ("!", "！"),
+];
+
+const MAX_SPELLING: usize = 4;
+
+/// Converts typed romaji to hiragana. Returns the kana and the trailing
+/// romaji that may still become a different kana with more input (`k`, `ky`,
+/// a lone `n`). Characters that are not romaji pass through unchanged.
+pub fn to_kana(input: &str) -> (String, String) {
+    let chars: Vec<char> = input.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let mut kana = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        // Doubled consonant: っ
+        if i + 1 < chars.len() && c == chars[i + 1] && c.is_ascii_alphabetic() && !"aiueon".contains(c) {
+            kana.push('っ');
+            i += 1;
+            continue;
+        }
+        // ん before a consonant other than y
+        if c == 'n' && i + 1 < chars.len() && !"aiueoyn'".contains(chars[i + 1]) {
+            kana.push('ん');
+            i += 1;
+            continue;
+        }
+
+        let longest = (1..=MAX_SPELLING.min(chars.len() - i)).rev().find_map(|len| {
+            let spelling: String = chars[i..i + len].iter().collect();
+            TABLE.iter().find(|(r, _)| *r == spelling).map(|(_, k)| (len, *k))
+        });
+        match longest {
+            Some((len, k)) => {
+                kana.push_str(k);
+                i += len;
+            }
+            None if is_prefix(&chars[i..]) => {
+                return (kana, chars[i..].iter().collect());
+            }
+            None => {
+                kana.push(chars[i]);
+                i += 1;
+            }
+        }
+    }
+    (kana, String::new())
+}
+
+/// Kana for `input`, treating any pending romaji as final (a lone `n` is ん).
+pub fn to_kana_final(input: &str) -> String {
+    let (mut kana, pending) = to_kana(input);
+    match pending.as_str() {
+        "n" => kana.push('ん'),
+        _ => kana.push_str(&pending),
+    }
+    kana
+}
+
+/// Kana that the pending romaji `pending` (see [`to_kana`]) may still become.
+pub fn next_kana(pending: &str) -> Vec<&'static str> {
+    let mut kana: Vec<&'static str> = Vec::new();
+    if pending.is_empty() {
+        return kana;
+    }
+    for (spelling, k) in TABLE {
+        if spelling.starts_with(pending) && !kana.contains(k) {
+            kana.push(k);
+        }
+    }
+    // A doubled consonant is still to come (t → っ in tte)
+    if pending.len() == 1 && !"aiueon".contains(pending) {
+        kana.push("っ");
+    }
+    kana
+}
+
+/// Whether `chars` could still grow into a spelling in the table.
+fn is_prefix(chars: &[char]) -> bool {
+    let s: String = chars.iter().collect();
+    s.len() < MAX_SPELLING && TABLE.iter().any(|(r, _)| r.starts_with(&s) && r.len() > s.len())
+}
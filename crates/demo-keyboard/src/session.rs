@@ -0,0 +1,207 @@
+use keyboard_formats::learning::ShortTermMemory;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::converter::{Converter, Lattice, LatticeDump, Segment};
+use crate::predictor::{Layer, Prediction, Predictor};
+use crate::romaji;
+
+/// Prediction results cached before the cache is cleared.
+const MAX_CACHED_PREDICTIONS: usize = 256;
+
+/// Predictions fetched for reranking when learning, so recently committed
+/// words just outside the shown results can move up.
+const LEARNING_POOL: usize = 50;
+
+/// Context words and result limit of a cached prediction.
+type PredictionKey = (Vec<String>, usize);
+
+/// State of one input session, updated per keystroke.
+///
+/// Committed text is kept as the words it was converted to, so it never has
+/// to be tokenized again. Only the pending romaji (a few keys at most) is
+/// romanized again per keystroke: kana no later key can change are kept,
+/// with how to undo each keystroke for Backspace. The conversion lattice of
+/// the composing input is extended as keys are typed, and only the part
+/// after the first changed kana is recomputed. Predictions are cached by
+/// context.
+///
+/// With a [`ShortTermMemory`], committed conversions are learned and boost
+/// the same words in the following conversions and predictions.
+#[derive(Default)]
+pub struct SessionContext {
+    romaji: String,
+    /// Kana of the romaji typed so far that further keys can't change
+    settled: Vec<char>,
+    /// Romaji after the settled kana that may still become other kana
+    pending: String,
+    /// Settled kana count and pending romaji before each keystroke
+    undo: Vec<(usize, String)>,
+    lattice: Lattice,
+    committed: Vec<String>,
+    predictions: HashMap<PredictionKey, Vec<Prediction>>,
+    memory: Option<ShortTermMemory>,
+}
+
+impl SessionContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A session that learns from committed conversions.
+    pub fn with_memory(memory: ShortTermMemory) -> Self {
+        Self {
+            memory: Some(memory),
+            ..Self::default()
+        }
+    }
+
+    pub fn into_memory(self) -> Option<ShortTermMemory> {
+        self.memory
+    }
+
+    /// Romaji typed since the last commit.
+    pub fn romaji(&self) -> &str {
+        &self.romaji
+    }
+
+    /// Words of the committed text.
+    pub fn committed(&self) -> &[String] {
+        &self.committed
+    }
+
+    /// Settled kana of the composing input and the romaji still pending
+    /// after it, as [`romaji::to_kana`] splits the input.
+    pub fn composing(&self) -> (String, &str) {
+        (self.settled.iter().collect(), &self.pending)
+    }
+
+    pub fn push(&mut self, converter: &Converter, c: char) {
+        self.romaji.push(c);
+        self.undo.push((self.settled.len(), self.pending.clone()));
+        // Romanization never looks back, so the pending keys and the new one
+        // convert as they would at the end of the whole input
+        self.pending.push(c);
+        let (kana, pending) = romaji::to_kana(&self.pending);
+        let stable = self.settled.len();
+        self.settled.extend(kana.chars());
+        self.pending = pending;
+        self.sync(converter, stable);
+    }
+
+    pub fn pop(&mut self, converter: &Converter) {
+        let Some((settled, pending)) = self.romaji.pop().and(self.undo.pop()) else {
+            return;
+        };
+        self.settled.truncate(settled);
+        self.pending = pending;
+        self.sync(converter, settled);
+    }
+
+    /// Kana of the composing input, with pending romaji taken as final.
+    pub fn kana(&self) -> String {
+        self.lattice.chars().iter().collect()
+    }
+
+    /// Best conversion of the whole composing input.
+    pub fn segments(&self) -> Vec<Segment> {
+        self.lattice.best_path(self.lattice.len())
+    }
+
+    /// Best conversion of the first `chars` kana of the composing input.
+    pub fn segments_to(&self, chars: usize) -> Vec<Segment> {
+        self.lattice.best_path(chars)
+    }
+
+    /// Every node of the composing input's conversion lattice.
+    pub fn lattice_dump(&self, converter: &Converter) -> LatticeDump {
+        converter.dump(&self.lattice, |reading, surface| self.boost(reading, surface))
+    }
+
+    /// Boost the short-term memory gives `surface` as a conversion of
+    /// `reading`, in FST score units.
+    pub fn boost(&self, reading: &str, surface: &str) -> f64 {
+        self.memory.as_ref().map_or(0.0, |m| m.boost(reading, surface, now()))
+    }
+
+    /// Commits the current conversion followed by `extra` words, and starts
+    /// a new composition. Only converted segments are learned: `extra`
+    /// words come without a reading, and kana left as typed teach nothing.
+    pub fn commit(&mut self, extra: &[String]) {
+        let segments = self.segments();
+        if let Some(memory) = &mut self.memory {
+            let now = now();
+            for segment in segments.iter().filter(|s| s.surface != s.reading) {
+                memory.commit(&segment.reading, &segment.surface, now);
+            }
+        }
+        self.committed.extend(segments.into_iter().map(|s| s.surface));
+        self.committed.extend(extra.iter().cloned());
+        self.romaji.clear();
+        self.settled.clear();
+        self.pending.clear();
+        self.undo.clear();
+        self.lattice = Lattice::default();
+    }
+
+    /// Next-word predictions after `context`, from the cache when possible.
+    /// Learned words are boosted after the cache, so it stays valid as the
+    /// memory changes, and move to [`Layer::Learned`].
+    pub fn predict(&mut self, predictor: &Predictor, context: &[String], limit: usize) -> Vec<Prediction> {
+        let fetch = if self.memory.is_some() { limit.max(LEARNING_POOL) } else { limit };
+        let key = (context.to_vec(), fetch);
+        let mut predictions = match self.predictions.get(&key) {
+            Some(cached) => cached.clone(),
+            None => {
+                if self.predictions.len() >= MAX_CACHED_PREDICTIONS {
+                    self.predictions.clear();
+                }
+                let context: Vec<&str> = context.iter().map(String::as_str).collect();
+                let predictions = predictor.predict(&context, fetch);
+                self.predictions.insert(key, predictions.clone());
+                predictions
+            }
+        };
+        if let Some(memory) = &self.memory {
+            let now = now();
+            for prediction in &mut predictions {
+                let boost = memory.surface_boost(&prediction.surface, now).round() as u64;
+                if boost > 0 {
+                    prediction.score += boost;
+                    prediction.layer = Layer::Learned;
+                }
+            }
+            predictions.sort_by(|a, b| b.score.cmp(&a.score).then(a.surface.cmp(&b.surface)));
+        }
+        predictions.truncate(limit);
+        predictions
+    }
+
+    /// Brings the lattice in line with the kana, keeping the entries of the
+    /// unchanged prefix. The first `stable` settled kana are known to be in
+    /// the lattice already.
+    fn sync(&mut self, converter: &Converter, stable: usize) {
+        let stable = stable.min(self.lattice.len());
+        let tail: Vec<char> = self.settled[stable..]
+            .iter()
+            .copied()
+            .chain(romaji::to_kana_final(&self.pending).chars())
+            .collect();
+        let unchanged = tail
+            .iter()
+            .zip(&self.lattice.chars()[stable..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        self.lattice.truncate(stable + unchanged);
+        let now = now();
+        let memory = self.memory.as_ref();
+        converter.extend(&mut self.lattice, tail[unchanged..].iter().copied(), |reading, surface| {
+            memory.map_or(0.0, |m| m.boost(reading, surface, now))
+        });
+    }
+}
+
+/// Current Unix time in seconds.
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,119 @@
+use anyhow::Result;
+use fst::{Map, Streamer};
+use keyboard_formats::mapping::{Mapping, MappingTable};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::write_artifact;
+
+/// Informal contractions as (full form, contracted form). Both are kana, so
+/// they apply to surfaces and readings alike.
+const CONTRACTION_RULES: &[(&str, &str)] = &[
+    ("ている", "てる"),
+    ("でいる", "でる"),
+    ("ていた", "てた"),
+    ("でいた", "でた"),
+    ("ていく", "てく"),
+    ("ておく", "とく"),
+    ("でおく", "どく"),
+    ("てしまう", "ちゃう"),
+    ("でしまう", "じゃう"),
+    ("てしまった", "ちゃった"),
+    ("でしまった", "じゃった"),
+    ("なければ", "なきゃ"),
+    ("なくては", "なくちゃ"),
+    ("ければ", "けりゃ"),
+    ("ては", "ちゃ"),
+    ("では", "じゃ"),
+    ("のだ", "んだ"),
+    ("のです", "んです"),
+    ("という", "っていう"),
+];
+
+const CONTRACTED: &str = "contracted";
+const EXPANDED: &str = "expanded";
+
+#[derive(clap::Args, Debug)]
+pub struct ContractionsArgs {
+    /// Surface/reading N-gram FST (wiki-ngram --outputs reading-ngrams)
+    #[arg(long, default_value = "output/wiki-ngrams-reading.fst")]
+    ngrams: PathBuf,
+
+    /// Output path of the mapping table
+    #[arg(long, default_value = "output/contractions.tsv")]
+    output: PathBuf,
+
+    /// Maximum number of corpus phrases kept, most frequent first
+    #[arg(long, default_value = "5000")]
+    max_phrases: usize,
+}
+
+pub fn run(args: &ContractionsArgs) -> Result<()> {
+    let mut table = MappingTable::new("contractions");
+    for (full, contracted) in CONTRACTION_RULES {
+        push_pair(&mut table, full, full, contracted, contracted);
+    }
+
+    let data = fs::read(&args.ngrams)
+        .map_err(|e| anyhow::anyhow!("Failed to read {:?} (build it with --outputs reading-ngrams): {}", args.ngrams, e))?;
+    let map = Map::new(data)?;
+
+    // (score, surface, reading) of corpus phrases ending in a full form
+    let mut phrases: Vec<(u64, String, String)> = Vec::new();
+    let mut stream = map.stream();
+    while let Some((key, score)) = stream.next() {
+        let Ok(key) = std::str::from_utf8(key) else { continue };
+        let Some((surface, reading, boundaries)) = join_tokens(key) else { continue };
+        let ends_at_rule = CONTRACTION_RULES.iter().any(|(full, _)| {
+            surface.len() > full.len()
+                && surface.ends_with(full)
+                && reading.ends_with(full)
+                && boundaries.contains(&(surface.len() - full.len()))
+        });
+        if ends_at_rule {
+            phrases.push((score, surface, reading));
+        }
+    }
+    phrases.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    phrases.dedup_by(|a, b| a.1 == b.1 && a.2 == b.2);
+    phrases.truncate(args.max_phrases);
+    tracing::info!("{} corpus phrases end in a contractible form", phrases.len());
+
+    for (_, surface, reading) in &phrases {
+        for (full, contracted) in CONTRACTION_RULES {
+            if let (Some(stem), Some(reading_stem)) = (surface.strip_suffix(full), reading.strip_suffix(full)) {
+                push_pair(
+                    &mut table,
+                    surface,
+                    reading,
+                    &format!("{}{}", stem, contracted),
+                    &format!("{}{}", reading_stem, contracted),
+                );
+            }
+        }
+    }
+
+    tracing::info!("{} contraction mappings", table.len());
+    write_artifact(&args.output, |writer| table.write(writer))
+}
+
+/// Adds the mapping in both directions.
+fn push_pair(table: &mut MappingTable, full: &str, full_reading: &str, contracted: &str, contracted_reading: &str) {
+    table.push(Mapping::new(full, full_reading, contracted, contracted_reading, CONTRACTED));
+    table.push(Mapping::new(contracted, contracted_reading, full, full_reading, EXPANDED));
+}
+
+/// Joins `surface/reading` tokens of an N-gram key into a surface and reading,
+/// returning the byte offsets in the surface where tokens start.
+fn join_tokens(key: &str) -> Option<(String, String, Vec<usize>)> {
+    let mut surface = String::new();
+    let mut reading = String::new();
+    let mut boundaries = Vec::new();
+    for token in key.split(' ') {
+        let (token_surface, token_reading) = token.rsplit_once('/')?;
+        boundaries.push(surface.len());
+        surface.push_str(token_surface);
+        reading.push_str(token_reading);
+    }
+    Some((surface, reading, boundaries))
+}
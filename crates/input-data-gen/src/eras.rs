@@ -0,0 +1,67 @@
+use anyhow::Result;
+use keyboard_formats::eras::{Date, Era, EraTable};
+use keyboard_formats::mapping::{Mapping, MappingTable};
+use std::path::PathBuf;
+
+use crate::write_artifact;
+
+/// Modern eras as (name, reading, first day, last day). Dates are Gregorian;
+/// 明治 starts on the day it was retroactively applied from.
+const ERAS: &[(&str, &str, Date, Option<Date>)] = &[
+    ("明治", "めいじ", Date::new(1868, 1, 25), Some(Date::new(1912, 7, 29))),
+    ("大正", "たいしょう", Date::new(1912, 7, 30), Some(Date::new(1926, 12, 24))),
+    ("昭和", "しょうわ", Date::new(1926, 12, 25), Some(Date::new(1989, 1, 7))),
+    ("平成", "へいせい", Date::new(1989, 1, 8), Some(Date::new(2019, 4, 30))),
+    ("令和", "れいわ", Date::new(2019, 5, 1), None),
+];
+
+#[derive(clap::Args, Debug)]
+pub struct ErasArgs {
+    /// Output path of the era boundary table
+    #[arg(long, default_value = "output/eras.tsv")]
+    output: PathBuf,
+
+    /// Output path of the era year ↔ Gregorian year mapping table
+    #[arg(long, default_value = "output/era-years.tsv")]
+    years_output: PathBuf,
+
+    /// Last Gregorian year listed for the current era
+    #[arg(long, default_value = "2050")]
+    until_year: i32,
+}
+
+pub fn run(args: &ErasArgs) -> Result<()> {
+    let table = EraTable::new(
+        ERAS.iter()
+            .map(|&(name, reading, start, end)| Era {
+                name: name.to_string(),
+                reading: reading.to_string(),
+                start,
+                end,
+            })
+            .collect(),
+    );
+
+    let mut years = MappingTable::new("era-years");
+    for era in table.iter() {
+        let last = era.end.map_or(args.until_year, |end| end.year);
+        for year in era.start.year..=last {
+            let era_year = year - era.start.year + 1;
+            let gregorian = (format!("{}年", year), format!("{}ねん", year));
+            let mut spellings = vec![(
+                format!("{}{}年", era.name, era_year),
+                format!("{}{}ねん", era.reading, era_year),
+            )];
+            if era_year == 1 {
+                spellings.push((format!("{}元年", era.name), format!("{}がんねん", era.reading)));
+            }
+            for (surface, reading) in spellings {
+                years.push(Mapping::new(&surface, &reading, &gregorian.0, &gregorian.1, "gregorian"));
+                years.push(Mapping::new(&gregorian.0, &gregorian.1, &surface, &reading, "era"));
+            }
+        }
+    }
+
+    write_artifact(&args.output, |writer| table.write(writer))?;
+    write_artifact(&args.years_output, |writer| years.write(writer))
+}
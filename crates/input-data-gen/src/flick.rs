@@ -0,0 +1,114 @@
+use anyhow::Result;
+use keyboard_formats::confusion::{ConfusionModel, DefaultCosts};
+use std::path::PathBuf;
+
+use crate::write_artifact;
+
+/// Kana on each key of the 12-key layout, in grid order, as the characters
+/// entered by tap, left, up, right and down flicks. Spaces mark unused
+/// directions; the modifier key (゛゜小) has no kana of its own and is `None`.
+const FLICK_GRID: &[&[Option<&str>]] = &[
+    &[Some("あいうえお"), Some("かきくけこ"), Some("さしすせそ")],
+    &[Some("たちつてと"), Some("なにぬねの"), Some("はひふへほ")],
+    &[Some("まみむめも"), Some("や ゆ よ"), Some("らりるれろ")],
+    &[None, Some("わをんー "), Some("、。？！ ")],
+];
+
+const TAP: usize = 0;
+
+/// Kana whose modifier (dakuten, handakuten or small form) is entered with an
+/// extra press of the modifier key, paired with the base kana.
+pub(crate) const MODIFIED_KANA: &[(&str, &str)] = &[
+    ("がぎぐげござじずぜぞだぢづでどばびぶべぼ", "かきくけこさしすせそたちつてとはひふへほ"),
+    ("ぱぴぷぺぽ", "はひふへほ"),
+    ("ぁぃぅぇぉっゃゅょゎ", "あいうえおつやゆよわ"),
+];
+
+#[derive(clap::Args, Debug)]
+pub struct FlickArgs {
+    /// Output path of the confusion model
+    #[arg(long, default_value = "output/flick-confusion.tsv")]
+    output: PathBuf,
+
+    /// Flick registered in a neighboring direction (e.g. left instead of up)
+    #[arg(long, default_value = "1000")]
+    adjacent_direction_cost: u32,
+
+    /// Short flick registered as a tap, or a tap as a flick
+    #[arg(long, default_value = "1200")]
+    tap_flick_cost: u32,
+
+    /// Flick registered in the opposite direction
+    #[arg(long, default_value = "2000")]
+    opposite_direction_cost: u32,
+
+    /// Same direction on a horizontally or vertically neighboring key (き → し)
+    #[arg(long, default_value = "1500")]
+    neighbor_key_cost: u32,
+
+    /// Modifier key press left out (が → か); adding a stray one costs twice this
+    #[arg(long, default_value = "800")]
+    missing_modifier_cost: u32,
+}
+
+pub fn run(args: &FlickArgs) -> Result<()> {
+    let model = build_model(args);
+    write_artifact(&args.output, |writer| model.write(writer))
+}
+
+fn build_model(args: &FlickArgs) -> ConfusionModel {
+    let mut model = ConfusionModel::new("kana", DefaultCosts::default());
+    let grid: Vec<Vec<Option<Vec<char>>>> = FLICK_GRID
+        .iter()
+        .map(|row| row.iter().map(|key| key.map(|kana| kana.chars().collect())).collect())
+        .collect();
+
+    for (row, keys) in grid.iter().enumerate() {
+        for (col, key) in keys.iter().enumerate() {
+            let Some(key) = key else { continue };
+
+            // Mistakes within one key
+            for (a, &intended) in key.iter().enumerate() {
+                for (b, &typed) in key.iter().enumerate() {
+                    if a == b {
+                        continue;
+                    }
+                    let cost = if a == TAP || b == TAP {
+                        args.tap_flick_cost
+                    } else if (a as i32 - b as i32).abs() == 2 {
+                        args.opposite_direction_cost
+                    } else {
+                        args.adjacent_direction_cost
+                    };
+                    set(&mut model, intended, typed, cost);
+                }
+            }
+
+            // Same direction on a neighboring key
+            let neighbors = [(row.wrapping_sub(1), col), (row + 1, col), (row, col.wrapping_sub(1)), (row, col + 1)];
+            for (r, c) in neighbors {
+                let Some(Some(other)) = grid.get(r).and_then(|keys| keys.get(c)) else {
+                    continue;
+                };
+                for (&intended, &typed) in key.iter().zip(other) {
+                    set(&mut model, intended, typed, args.neighbor_key_cost);
+                }
+            }
+        }
+    }
+
+    for (modified, base) in MODIFIED_KANA {
+        for (m, b) in modified.chars().zip(base.chars()) {
+            set(&mut model, m, b, args.missing_modifier_cost);
+            set(&mut model, b, m, args.missing_modifier_cost * 2);
+        }
+    }
+    model
+}
+
+/// Records a substitution unless either side is an unused direction.
+fn set(model: &mut ConfusionModel, intended: char, typed: char, cost: u32) {
+    if intended != ' ' && typed != ' ' {
+        model.set_substitution(&intended.to_string(), &typed.to_string(), cost);
+    }
+}
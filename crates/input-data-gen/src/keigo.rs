@@ -0,0 +1,148 @@
+use anyhow::Result;
+use keyboard_formats::mapping::{Mapping, MappingTable};
+use std::path::PathBuf;
+
+use crate::lexicon::POS_COLUMNS;
+use crate::write_artifact;
+
+const HONORIFIC: &str = "honorific";
+const HUMBLE: &str = "humble";
+
+/// (surface, reading) pairs.
+type Forms = &'static [(&'static str, &'static str)];
+
+/// Verbs with suppletive honorific/humble forms, as (plain, reading,
+/// honorific forms, humble forms).
+const IRREGULAR: &[(&str, &str, Forms, Forms)] = &[
+    ("行く", "いく", &[("いらっしゃる", "いらっしゃる")], &[("参る", "まいる"), ("伺う", "うかがう")]),
+    ("来る", "くる", &[("いらっしゃる", "いらっしゃる"), ("お見えになる", "おみえになる")], &[("参る", "まいる")]),
+    ("いる", "いる", &[("いらっしゃる", "いらっしゃる")], &[("おる", "おる")]),
+    ("言う", "いう", &[("おっしゃる", "おっしゃる")], &[("申す", "もうす"), ("申し上げる", "もうしあげる")]),
+    ("見る", "みる", &[("ご覧になる", "ごらんになる")], &[("拝見する", "はいけんする")]),
+    ("食べる", "たべる", &[("召し上がる", "めしあがる")], &[("いただく", "いただく")]),
+    ("飲む", "のむ", &[("召し上がる", "めしあがる")], &[("いただく", "いただく")]),
+    ("する", "する", &[("なさる", "なさる")], &[("いたす", "いたす")]),
+    ("知る", "しる", &[("ご存じだ", "ごぞんじだ")], &[("存じる", "ぞんじる")]),
+    ("思う", "おもう", &[], &[("存じる", "ぞんじる")]),
+    ("会う", "あう", &[("お会いになる", "おあいになる")], &[("お目にかかる", "おめにかかる")]),
+    ("聞く", "きく", &[("お聞きになる", "おききになる")], &[("伺う", "うかがう"), ("拝聴する", "はいちょうする")]),
+    ("訪ねる", "たずねる", &[("お訪ねになる", "おたずねになる")], &[("伺う", "うかがう")]),
+    ("くれる", "くれる", &[("くださる", "くださる")], &[]),
+    ("もらう", "もらう", &[], &[("いただく", "いただく")]),
+    ("あげる", "あげる", &[], &[("差し上げる", "さしあげる")]),
+    ("寝る", "ねる", &[("お休みになる", "おやすみになる")], &[]),
+    ("着る", "きる", &[("お召しになる", "おめしになる")], &[]),
+    ("見せる", "みせる", &[], &[("お目にかける", "おめにかける"), ("ご覧に入れる", "ごらんにいれる")]),
+    ("分かる", "わかる", &[], &[("承知する", "しょうちする"), ("かしこまる", "かしこまる")]),
+];
+
+/// Godan rows as (row in the conjugation type, 連用形 ending, 終止形 ending).
+const GODAN_ROWS: &[(&str, char, char)] = &[
+    ("カ", 'き', 'く'),
+    ("ガ", 'ぎ', 'ぐ'),
+    ("サ", 'し', 'す'),
+    ("タ", 'ち', 'つ'),
+    ("ナ", 'に', 'ぬ'),
+    ("バ", 'び', 'ぶ'),
+    ("マ", 'み', 'む'),
+    ("ラ", 'り', 'る'),
+    ("ワ", 'い', 'う'),
+];
+
+#[derive(clap::Args, Debug)]
+pub struct KeigoArgs {
+    /// Lexicon to derive regular forms from (mozc-dict-gen --keep-intermediate)
+    #[arg(long, default_value = "output/lex.csv")]
+    lex: PathBuf,
+
+    /// Output path of the mapping table
+    #[arg(long, default_value = "output/keigo.tsv")]
+    output: PathBuf,
+
+    /// Only derive regular forms for verbs with a word cost at most this
+    /// (lower is more common)
+    #[arg(long)]
+    max_cost: Option<i32>,
+}
+
+pub fn run(args: &KeigoArgs) -> Result<()> {
+    let mut table = MappingTable::new("keigo");
+    for (plain, reading, honorific, humble) in IRREGULAR {
+        for (forms, label) in [(honorific, HONORIFIC), (humble, HUMBLE)] {
+            for (surface, surface_reading) in *forms {
+                table.push(Mapping::new(plain, reading, surface, surface_reading, label));
+            }
+        }
+    }
+    let irregular = table.len();
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(&args.lex)
+        .map_err(|e| anyhow::anyhow!("Failed to open {:?} (run mozc-dict-gen with --keep-intermediate): {}", args.lex, e))?;
+
+    for record in reader.records() {
+        let record = record?;
+        // surface, left_id, right_id, cost, 7 POS columns, reading, pronunciation
+        if record.len() < 4 + POS_COLUMNS + 1 {
+            continue;
+        }
+        let (surface, reading) = (&record[0], &record[4 + POS_COLUMNS]);
+        let (pos, sub_pos, conj_type, conj_form) = (&record[4], &record[5], &record[8], &record[9]);
+        if pos != "動詞" || sub_pos != "自立" || conj_form != "連用形" {
+            continue;
+        }
+        if args.max_cost.is_some_and(|max| record[3].parse::<i32>().map_or(true, |cost| cost > max)) {
+            continue;
+        }
+        // Only words written with kanji; kana-only verbs are mostly already
+        // honorific (なさる) or grammatical (ある)
+        if !surface.chars().any(is_kanji) {
+            continue;
+        }
+        let Some((plain, plain_reading)) = dictionary_form(surface, reading, conj_type) else {
+            continue;
+        };
+        if table.contains(&plain) {
+            continue;
+        }
+
+        table.push(Mapping::new(
+            &plain,
+            &plain_reading,
+            &format!("お{}になる", surface),
+            &format!("お{}になる", reading),
+            HONORIFIC,
+        ));
+        table.push(Mapping::new(
+            &plain,
+            &plain_reading,
+            &format!("お{}する", surface),
+            &format!("お{}する", reading),
+            HUMBLE,
+        ));
+    }
+
+    tracing::info!("{} curated and {} derived keigo mappings", irregular, table.len() - irregular);
+    write_artifact(&args.output, |writer| table.write(writer))
+}
+
+/// Rebuilds the dictionary form of a verb from its 連用形 (書き → 書く).
+fn dictionary_form(surface: &str, reading: &str, conj_type: &str) -> Option<(String, String)> {
+    if conj_type == "一段" {
+        return Some((format!("{}る", surface), format!("{}る", reading)));
+    }
+    let row = conj_type.strip_prefix("五段・")?;
+    // ラ行特殊 verbs (いらっしゃる, なさる) are honorific already
+    if row.ends_with("特殊") {
+        return None;
+    }
+    let &(_, renyou, shuushi) = GODAN_ROWS.iter().find(|(name, _, _)| row.starts_with(name))?;
+    let replace = |s: &str| s.strip_suffix(renyou).map(|stem| format!("{}{}", stem, shuushi));
+    Some((replace(surface)?, replace(reading)?))
+}
+
+fn is_kanji(c: char) -> bool {
+    ('\u{4E00}'..='\u{9FFF}').contains(&c) || c == '々'
+}
@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Number of POS columns in lex.csv, following the cost column.
+pub const POS_COLUMNS: usize = 7;
+
+/// A dictionary entry in Mozc's format, for mozc-dict-gen --extra-dictionary.
+pub struct MozcEntry {
+    pub reading: String,
+    pub id: u16,
+    pub cost: i16,
+    pub surface: String,
+    /// Extra columns after the surface; ignored by mozc-dict-gen except for
+    /// `annotation=` and `flags=` (see keyboard_formats::annotation)
+    pub notes: Vec<String>,
+}
+
+pub fn write_mozc_dictionary<W: Write>(writer: &mut W, entries: &[MozcEntry]) -> Result<()> {
+    for e in entries {
+        write!(writer, "{}\t{}\t{}\t{}\t{}", e.reading, e.id, e.id, e.cost, e.surface)?;
+        for note in &e.notes {
+            write!(writer, "\t{}", note)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Context ID of the first POS in Mozc's id.def starting with `pos_prefix`.
+pub fn find_pos_id(id_def: &Path, pos_prefix: &str) -> Result<u16> {
+    let file = File::open(id_def).with_context(|| format!("Failed to open {:?}", id_def))?;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if let Some((id, pos)) = line.split_once(' ') {
+            if pos.starts_with(pos_prefix) {
+                return Ok(id.parse()?);
+            }
+        }
+    }
+    anyhow::bail!("No POS starting with {} in {:?}", pos_prefix, id_def)
+}
+
+/// Lowest word cost per reading among lex.csv entries whose POS starts with
+/// `pos_prefix`.
+pub fn min_costs_by_reading(lex: &Path, pos_prefix: &str) -> Result<HashMap<String, i16>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(lex)
+        .map_err(|e| anyhow::anyhow!("Failed to open {:?} (run mozc-dict-gen with --keep-intermediate): {}", lex, e))?;
+
+    let mut costs: HashMap<String, i16> = HashMap::new();
+    for record in reader.records() {
+        let record = record?;
+        // surface, left_id, right_id, cost, 7 POS columns, reading, pronunciation
+        if record.len() < 4 + POS_COLUMNS + 1 {
+            continue;
+        }
+        let pos: Vec<&str> = (4..4 + POS_COLUMNS).map(|i| &record[i]).collect();
+        if !pos.join(",").starts_with(pos_prefix) {
+            continue;
+        }
+        let Ok(cost) = record[3].parse::<i16>() else { continue };
+        let slot = costs.entry(record[4 + POS_COLUMNS].to_string()).or_insert(cost);
+        *slot = (*slot).min(cost);
+    }
+    Ok(costs)
+}
@@ -0,0 +1,31 @@
+//! Input-assistance data generators behind the input-data-gen binary: typo
+//! models, conversion tables and extra dictionaries, one module per
+//! subcommand.
+
+use anyhow::Result;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+pub mod contractions;
+pub mod eras;
+pub mod flick;
+pub mod keigo;
+pub mod lexicon;
+pub mod names;
+pub mod places;
+pub mod romaji;
+pub mod swipe;
+pub mod units;
+
+/// Creates `path` (and its parent directory) and hands a buffered writer to `write`.
+pub fn write_artifact(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    tracing::info!("Written {:?}", path);
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use input_data_gen::{contractions, eras, flick, keigo, names, places, romaji, swipe, units};
+
+#[derive(Parser, Debug)]
+#[command(name = "input-data-gen")]
+#[command(about = "Generate input-assistance data (typo models, conversion tables) for the keyboard")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Romaji typo model from QWERTY key adjacency and common romanization mistakes
+    RomajiConfusion(romaji::RomajiArgs),
+    /// Kana typo model for the 12-key flick layout
+    FlickConfusion(flick::FlickArgs),
+    /// Plain verb to honorific/humble form table for polite-mode suggestions
+    Keigo(keigo::KeigoArgs),
+    /// Bidirectional informal contraction table (している ↔ してる) mined from N-grams
+    Contractions(contractions::ContractionsArgs),
+    /// Prefecture → city → town place names with readings from Japan Post postal data
+    Places(places::PlacesArgs),
+    /// Family/given name dictionary from JMnedict, for mozc-dict-gen --extra-dictionary
+    Names(names::NamesArgs),
+    /// Era boundaries and era year ↔ Gregorian year tables (令和6年 ↔ 2024年)
+    Eras(eras::ErasArgs),
+    /// Reading → unit and currency symbol entries (ぱーせんと → %), for mozc-dict-gen --extra-dictionary
+    Units(units::UnitsArgs),
+    /// Kana-sequence word FST with frequencies and key geometry for swipe decoding
+    Swipe(swipe::SwipeArgs),
+}
+
+fn main() -> Result<()> {
+    let _telemetry = telemetry::init("input-data-gen")?;
+    let cli = Cli::parse();
+    match cli.command {
+        Command::RomajiConfusion(args) => romaji::run(&args),
+        Command::FlickConfusion(args) => flick::run(&args),
+        Command::Keigo(args) => keigo::run(&args),
+        Command::Contractions(args) => contractions::run(&args),
+        Command::Places(args) => places::run(&args),
+        Command::Names(args) => names::run(&args),
+        Command::Eras(args) => eras::run(&args),
+        Command::Units(args) => units::run(&args),
+        Command::Swipe(args) => swipe::run(&args),
+    }
+}
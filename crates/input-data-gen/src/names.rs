@@ -0,0 +1,233 @@
+use anyhow::Result;
+use keyboard_formats::kana::to_hiragana;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::lexicon::{self, MozcEntry};
+use crate::write_artifact;
+
+const FAMILY_POS: &str = "名詞,固有名詞,人名,姓";
+const GIVEN_POS: &str = "名詞,固有名詞,人名,名";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum NameKind {
+    Family,
+    Given,
+}
+
+impl NameKind {
+    fn label(self) -> &'static str {
+        match self {
+            NameKind::Family => "family",
+            NameKind::Given => "given",
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct NamesArgs {
+    /// JMnedict XML (JMnedict.xml, uncompressed)
+    #[arg(long)]
+    jmnedict: PathBuf,
+
+    /// Wikipedia title list (e.g. jawiki-latest-all-titles-in-ns0) used as
+    /// evidence of how common a name is
+    #[arg(long)]
+    wiki_titles: Option<PathBuf>,
+
+    /// Lexicon whose common nouns the name costs are tuned against
+    #[arg(long, default_value = "output/lex.csv")]
+    lex: PathBuf,
+
+    /// Mozc id.def for the name POS IDs
+    #[arg(long, default_value = "mozc_src/id.def")]
+    id_def: PathBuf,
+
+    /// Output dictionary in Mozc's format, for mozc-dict-gen --extra-dictionary
+    #[arg(long, default_value = "output/names.txt")]
+    output: PathBuf,
+
+    /// Cost of a name without Wikipedia evidence
+    #[arg(long, default_value = "7000")]
+    base_cost: i16,
+
+    /// Cost reduction per natural-log unit of Wikipedia person-title count
+    #[arg(long, default_value = "400")]
+    evidence_weight: f64,
+
+    /// Lowest cost a name can reach through evidence
+    #[arg(long, default_value = "4000")]
+    min_cost: i16,
+
+    /// Extra cost of readings other than a name's first listed one
+    #[arg(long, default_value = "500")]
+    alternative_reading_penalty: i16,
+
+    /// A name may cost at most this much more than a common noun with the same
+    /// reading, so it stays among the top candidates
+    #[arg(long, default_value = "300")]
+    homophone_margin: i16,
+}
+
+/// A JMnedict name: surface, readings in listed order, kinds.
+struct JmnEntry {
+    surface: String,
+    readings: Vec<String>,
+    kinds: Vec<NameKind>,
+}
+
+pub fn run(args: &NamesArgs) -> Result<()> {
+    let entries = read_jmnedict(&args.jmnedict)?;
+    tracing::info!("{} family/given names in JMnedict", entries.len());
+
+    let evidence = match &args.wiki_titles {
+        Some(path) => count_title_evidence(path, &entries)?,
+        None => HashMap::new(),
+    };
+    tracing::info!("{} names seen in Wikipedia person titles", evidence.len());
+
+    let noun_costs = lexicon::min_costs_by_reading(&args.lex, "名詞,一般")?;
+    let family_id = lexicon::find_pos_id(&args.id_def, FAMILY_POS)?;
+    let given_id = lexicon::find_pos_id(&args.id_def, GIVEN_POS)?;
+
+    let mut output = Vec::new();
+    for entry in &entries {
+        for &kind in &entry.kinds {
+            let count = evidence.get(&(entry.surface.clone(), kind)).copied().unwrap_or(0);
+            let cost = (args.base_cost as f64 - args.evidence_weight * (count as f64 + 1.0).ln())
+                .max(args.min_cost as f64) as i16;
+
+            for (i, reading) in entry.readings.iter().enumerate() {
+                let mut cost = if i == 0 { cost } else { cost.saturating_add(args.alternative_reading_penalty) };
+                if let Some(&noun_cost) = noun_costs.get(reading) {
+                    cost = cost.min(noun_cost.saturating_add(args.homophone_margin));
+                }
+                output.push(MozcEntry {
+                    reading: reading.clone(),
+                    id: if kind == NameKind::Family { family_id } else { given_id },
+                    cost,
+                    surface: entry.surface.clone(),
+                    notes: vec![
+                        kind.label().to_string(),
+                        if i == 0 { "primary" } else { "alternative" }.to_string(),
+                    ],
+                });
+            }
+        }
+    }
+
+    tracing::info!("{} name entries", output.len());
+    write_artifact(&args.output, |writer| lexicon::write_mozc_dictionary(writer, &output))
+}
+
+/// Reads kanji family and given names from JMnedict, one entry per surface.
+/// Readings restricted to other spellings (`re_restr`) only go to those.
+fn read_jmnedict(path: &Path) -> Result<Vec<JmnEntry>> {
+    let mut reader = Reader::from_reader(BufReader::new(File::open(path)?));
+    reader.config_mut().trim_text(true);
+
+    // Grouped by surface so names listed in several entries are merged
+    let mut names: BTreeMap<String, (Vec<String>, Vec<NameKind>)> = BTreeMap::new();
+
+    let mut buf = Vec::new();
+    let mut element = Vec::new();
+    let mut kebs: Vec<String> = Vec::new();
+    // (reading, restricted to these kebs)
+    let mut rebs: Vec<(String, Vec<String>)> = Vec::new();
+    let mut kinds: Vec<NameKind> = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => element = e.name().as_ref().to_vec(),
+            Event::Text(e) => {
+                // Name types are entity references (&surname;) that are never expanded
+                let text = String::from_utf8_lossy(&e).into_owned();
+                match element.as_slice() {
+                    b"keb" => kebs.push(text),
+                    b"reb" => rebs.push((to_hiragana(&text), Vec::new())),
+                    b"re_restr" => {
+                        if let Some((_, restr)) = rebs.last_mut() {
+                            restr.push(text);
+                        }
+                    }
+                    b"name_type" => match text.as_str() {
+                        "&surname;" => kinds.push(NameKind::Family),
+                        "&given;" | "&fem;" | "&masc;" => kinds.push(NameKind::Given),
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+            Event::End(e) if e.name().as_ref() == b"entry" => {
+                for keb in &kebs {
+                    if kinds.is_empty() {
+                        break;
+                    }
+                    let (readings, entry_kinds) = names.entry(keb.clone()).or_default();
+                    for (reb, restr) in &rebs {
+                        if (restr.is_empty() || restr.contains(keb)) && !readings.contains(reb) {
+                            readings.push(reb.clone());
+                        }
+                    }
+                    for kind in &kinds {
+                        if !entry_kinds.contains(kind) {
+                            entry_kinds.push(*kind);
+                        }
+                    }
+                }
+                kebs.clear();
+                rebs.clear();
+                kinds.clear();
+            }
+            Event::End(_) => element.clear(),
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(names
+        .into_iter()
+        .filter(|(_, (readings, _))| !readings.is_empty())
+        .map(|(surface, (readings, kinds))| JmnEntry {
+            surface,
+            readings,
+            kinds,
+        })
+        .collect())
+}
+
+/// Counts how often each name appears in titles that split into a known
+/// family name followed by a known given name (山田太郎, 山田太郎_(政治家)).
+fn count_title_evidence(path: &Path, entries: &[JmnEntry]) -> Result<HashMap<(String, NameKind), u64>> {
+    let has = |surface: &str, kind: NameKind| {
+        entries
+            .binary_search_by(|e| e.surface.as_str().cmp(surface))
+            .is_ok_and(|i| entries[i].kinds.contains(&kind))
+    };
+
+    let mut counts = HashMap::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        // Titles use underscores for spaces; drop disambiguation suffixes
+        let title = line.split("_(").next().unwrap_or(&line);
+        let chars: Vec<char> = title.chars().filter(|&c| c != '_').collect();
+        if !(2..=6).contains(&chars.len()) {
+            continue;
+        }
+        for split in 1..chars.len() {
+            let family: String = chars[..split].iter().collect();
+            let given: String = chars[split..].iter().collect();
+            if has(&family, NameKind::Family) && has(&given, NameKind::Given) {
+                *counts.entry((family, NameKind::Family)).or_insert(0) += 1;
+                *counts.entry((given, NameKind::Given)).or_insert(0) += 1;
+                break;
+            }
+        }
+    }
+    Ok(counts)
+}
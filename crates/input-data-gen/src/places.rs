@@ -0,0 +1,29 @@
+use anyhow::Result;
+use keyboard_formats::places;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::write_artifact;
+
+#[derive(clap::Args, Debug)]
+pub struct PlacesArgs {
+    /// Japan Post KEN_ALL.CSV (Shift_JIS) or utf_ken_all.csv (UTF-8), unzipped
+    #[arg(long)]
+    ken_all: PathBuf,
+
+    /// Output path of the place table
+    #[arg(long, default_value = "output/places.tsv")]
+    output: PathBuf,
+}
+
+pub fn run(args: &PlacesArgs) -> Result<()> {
+    let bytes = fs::read(&args.ken_all)?;
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => encoding_rs::SHIFT_JIS.decode(e.as_bytes()).0.into_owned(),
+    };
+    let table = places::read_ken_all(&text)?;
+
+    tracing::info!("{} places", table.len());
+    write_artifact(&args.output, |writer| table.write(writer))
+}
@@ -0,0 +1,108 @@
+use anyhow::Result;
+use keyboard_formats::confusion::{ConfusionModel, DefaultCosts};
+use std::path::PathBuf;
+
+use crate::write_artifact;
+
+/// Letter rows of a QWERTY keyboard with their horizontal offset in key widths.
+const QWERTY_ROWS: &[(&str, f64)] = &[("qwertyuiop", 0.0), ("asdfghjkl", 0.25), ("zxcvbnm", 0.75)];
+
+/// Keys whose centers are at most this many key widths apart count as neighbors.
+const ADJACENCY_RADIUS: f64 = 1.3;
+
+/// Spellings IMEs accept interchangeably. Typing one for the other is barely a
+/// mistake, so they are nearly free.
+const ROMANIZATION_VARIANTS: &[(&str, &str)] = &[
+    ("shi", "si"),
+    ("chi", "ti"),
+    ("tsu", "tu"),
+    ("fu", "hu"),
+    ("ji", "zi"),
+    ("sha", "sya"),
+    ("shu", "syu"),
+    ("sho", "syo"),
+    ("cha", "tya"),
+    ("chu", "tyu"),
+    ("cho", "tyo"),
+    ("ja", "zya"),
+    ("ju", "zyu"),
+    ("jo", "zyo"),
+    ("ja", "jya"),
+    ("ju", "jyu"),
+    ("jo", "jyo"),
+];
+const VARIANT_COST: u32 = 200;
+
+/// Common romanization mistakes as (intended, typed, cost).
+const ROMANIZATION_MISTAKES: &[(&str, &str, u32)] = &[
+    // ん before a consonant typed with a single n (こんにちは → konichiha)
+    ("nn", "n", 500),
+    ("n", "nn", 800),
+    // Long vowels written as they sound (とうきょう → tokyo)
+    ("ou", "o", 800),
+    ("uu", "u", 800),
+    ("ei", "e", 1000),
+    // Particles typed as pronounced
+    ("wo", "o", 500),
+    ("ha", "wa", 600),
+    ("he", "e", 800),
+    // Sounds English speakers spell differently
+    ("r", "l", 1000),
+    ("k", "c", 1000),
+    ("b", "v", 1200),
+];
+
+#[derive(clap::Args, Debug)]
+pub struct RomajiArgs {
+    /// Output path of the confusion model
+    #[arg(long, default_value = "output/romaji-confusion.tsv")]
+    output: PathBuf,
+
+    /// Substitution cost for two horizontally adjacent keys; scaled by distance
+    /// for diagonal neighbors
+    #[arg(long, default_value = "1000")]
+    adjacent_cost: u32,
+}
+
+pub fn run(args: &RomajiArgs) -> Result<()> {
+    let model = build_model(args.adjacent_cost);
+    write_artifact(&args.output, |writer| model.write(writer))
+}
+
+fn build_model(adjacent_cost: u32) -> ConfusionModel {
+    let mut model = ConfusionModel::new("romaji", DefaultCosts::default());
+
+    let keys = key_centers();
+    for &(a, ax, ay) in &keys {
+        for &(b, bx, by) in &keys {
+            let distance = ((ax - bx).powi(2) + (ay - by).powi(2)).sqrt();
+            if a != b && distance <= ADJACENCY_RADIUS {
+                let cost = (adjacent_cost as f64 * distance).round() as u32;
+                model.set_substitution(&a.to_string(), &b.to_string(), cost);
+            }
+        }
+    }
+
+    for (a, b) in ROMANIZATION_VARIANTS {
+        model.set_substitution(a, b, VARIANT_COST);
+        model.set_substitution(b, a, VARIANT_COST);
+    }
+    for (intended, typed, cost) in ROMANIZATION_MISTAKES {
+        model.set_substitution(intended, typed, *cost);
+    }
+    model
+}
+
+/// Letters with their key center in key widths (x) and rows (y).
+pub(crate) fn key_centers() -> Vec<(char, f64, f64)> {
+    QWERTY_ROWS
+        .iter()
+        .enumerate()
+        .flat_map(|(row, (letters, offset))| {
+            letters
+                .chars()
+                .enumerate()
+                .map(move |(col, c)| (c, offset + col as f64, row as f64))
+        })
+        .collect()
+}
@@ -0,0 +1,139 @@
+use anyhow::Result;
+use fst::{Map, MapBuilder};
+use keyboard_formats::layout::{Key, Layout};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::lexicon::POS_COLUMNS;
+use crate::{flick, romaji, write_artifact};
+
+/// Columns of the 50-on kana layout, left to right, top to bottom. Spaces are
+/// empty cells.
+const KANA50_COLUMNS: &[&str] = &[
+    "あいうえお",
+    "かきくけこ",
+    "さしすせそ",
+    "たちつてと",
+    "なにぬねの",
+    "はひふへほ",
+    "まみむめも",
+    "や ゆ よ",
+    "らりるれろ",
+    "わ を ん",
+    "ー    ",
+];
+
+#[derive(clap::Args, Debug)]
+pub struct SwipeArgs {
+    /// Lexicon providing the readings of words
+    #[arg(long, default_value = "output/lex.csv")]
+    lex: PathBuf,
+
+    /// Vocabulary FST with word frequencies (wiki-ngram --outputs vocab)
+    #[arg(long, default_value = "output/wiki-ngrams-vocab.fst")]
+    vocab: PathBuf,
+
+    /// Output FST mapping kana sequences to frequency scores
+    #[arg(long, default_value = "output/swipe-words.fst")]
+    output: PathBuf,
+
+    /// Directory the layout geometry files (swipe-layout-<name>.tsv) are written to
+    #[arg(long, default_value = "output")]
+    layout_dir: PathBuf,
+
+    /// Shortest reading kept, in kana; single kana are tapped rather than swiped
+    #[arg(long, default_value = "2")]
+    min_kana: usize,
+}
+
+pub fn run(args: &SwipeArgs) -> Result<()> {
+    let vocab = Map::new(
+        fs::read(&args.vocab)
+            .map_err(|e| anyhow::anyhow!("Failed to read {:?} (build it with --outputs vocab): {}", args.vocab, e))?,
+    )?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(&args.lex)
+        .map_err(|e| anyhow::anyhow!("Failed to open {:?} (run mozc-dict-gen with --keep-intermediate): {}", args.lex, e))?;
+
+    // Score of a reading: that of its most frequent surface
+    let mut scores: BTreeMap<String, u64> = BTreeMap::new();
+    for record in reader.records() {
+        let record = record?;
+        // surface, left_id, right_id, cost, 7 POS columns, reading, pronunciation
+        if record.len() < 4 + POS_COLUMNS + 1 {
+            continue;
+        }
+        let reading = &record[4 + POS_COLUMNS];
+        if reading.chars().count() < args.min_kana || !reading.chars().all(is_swipeable) {
+            continue;
+        }
+        let Some(score) = vocab.get(&record[0]) else { continue };
+        let slot = scores.entry(reading.to_string()).or_insert(score);
+        *slot = (*slot).max(score);
+    }
+    tracing::info!("{} readings with corpus frequencies", scores.len());
+
+    write_artifact(&args.output, |writer| {
+        let mut builder = MapBuilder::new(writer)?;
+        for (reading, score) in &scores {
+            builder.insert(reading, *score)?;
+        }
+        builder.finish()?;
+        Ok(())
+    })?;
+
+    for layout in [qwerty_layout(), kana50_layout()] {
+        let path = args.layout_dir.join(format!("swipe-layout-{}.tsv", layout.name));
+        write_artifact(&path, |writer| layout.write(writer))?;
+    }
+    Ok(())
+}
+
+/// Hiragana (including small and voiced kana) and the long vowel mark.
+fn is_swipeable(c: char) -> bool {
+    ('ぁ'..='ゖ').contains(&c) || c == 'ー'
+}
+
+fn key(output: &str, x: f64, y: f64) -> Key {
+    Key {
+        output: output.to_string(),
+        x,
+        y,
+        width: 1.0,
+        height: 1.0,
+    }
+}
+
+/// Romaji QWERTY; decoders spell readings in romaji to follow the path.
+fn qwerty_layout() -> Layout {
+    let mut layout = Layout::new("qwerty", "romaji");
+    for (c, x, y) in romaji::key_centers() {
+        layout.push(key(&c.to_string(), x + 0.5, y + 0.5));
+    }
+    layout
+}
+
+/// 50-on kana grid. Voiced and small kana share their base kana's key.
+fn kana50_layout() -> Layout {
+    let mut layout = Layout::new("kana50", "kana");
+    for (col, kana) in KANA50_COLUMNS.iter().enumerate() {
+        for (row, c) in kana.chars().enumerate().filter(|(_, c)| *c != ' ') {
+            layout.push(key(&c.to_string(), col as f64 + 0.5, row as f64 + 0.5));
+        }
+    }
+    for (modified, base) in flick::MODIFIED_KANA {
+        for (m, b) in modified.chars().zip(base.chars()) {
+            if let Some(base_key) = layout.key(&b.to_string()).cloned() {
+                layout.push(Key {
+                    output: m.to_string(),
+                    ..base_key
+                });
+            }
+        }
+    }
+    layout
+}
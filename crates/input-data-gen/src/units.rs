@@ -0,0 +1,101 @@
+use anyhow::Result;
+use std::path::PathBuf;
+
+use crate::lexicon::{self, MozcEntry};
+use crate::write_artifact;
+
+/// POS of symbols written after a number; connects like counters (5%, 3kg).
+const AFTER_NUMBER_POS: &str = "名詞,接尾,助数詞";
+/// POS of symbols written before a number ($5) or on their own.
+const SYMBOL_POS: &str = "記号,一般";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Hint {
+    AfterNumber,
+    BeforeNumber,
+}
+
+impl Hint {
+    fn label(self) -> &'static str {
+        match self {
+            Hint::AfterNumber => "after-number",
+            Hint::BeforeNumber => "before-number",
+        }
+    }
+}
+
+/// Unit and currency symbols as (reading, symbols, where they go relative to a number).
+const UNITS: &[(&str, &[&str], Hint)] = &[
+    ("ぱーせんと", &["%", "％"], Hint::AfterNumber),
+    ("ぱーみる", &["‰"], Hint::AfterNumber),
+    ("えん", &["円"], Hint::AfterNumber),
+    ("えん", &["¥", "￥"], Hint::BeforeNumber),
+    ("どる", &["$", "＄"], Hint::BeforeNumber),
+    ("ゆーろ", &["€"], Hint::BeforeNumber),
+    ("ぽんど", &["£"], Hint::BeforeNumber),
+    ("うぉん", &["₩"], Hint::BeforeNumber),
+    ("ど", &["°"], Hint::AfterNumber),
+    ("せっし", &["℃"], Hint::AfterNumber),
+    ("かし", &["℉"], Hint::AfterNumber),
+    ("みりめーとる", &["mm", "㎜"], Hint::AfterNumber),
+    ("せんちめーとる", &["cm", "㎝"], Hint::AfterNumber),
+    ("せんち", &["cm"], Hint::AfterNumber),
+    ("めーとる", &["m"], Hint::AfterNumber),
+    ("きろめーとる", &["km", "㎞"], Hint::AfterNumber),
+    ("へいほうめーとる", &["m²", "㎡"], Hint::AfterNumber),
+    ("りっぽうめーとる", &["m³", "㎥"], Hint::AfterNumber),
+    ("みりぐらむ", &["mg", "㎎"], Hint::AfterNumber),
+    ("ぐらむ", &["g"], Hint::AfterNumber),
+    ("きろぐらむ", &["kg", "㎏"], Hint::AfterNumber),
+    ("きろ", &["kg", "km"], Hint::AfterNumber),
+    ("とん", &["t"], Hint::AfterNumber),
+    ("みりりっとる", &["mL", "㎖"], Hint::AfterNumber),
+    ("りっとる", &["L", "ℓ"], Hint::AfterNumber),
+    ("へるつ", &["Hz"], Hint::AfterNumber),
+    ("わっと", &["W"], Hint::AfterNumber),
+    ("きろわっと", &["kW"], Hint::AfterNumber),
+    ("ぼると", &["V"], Hint::AfterNumber),
+    ("あんぺあ", &["A"], Hint::AfterNumber),
+    ("かろりー", &["cal"], Hint::AfterNumber),
+    ("きろかろりー", &["kcal"], Hint::AfterNumber),
+    ("きろばいと", &["KB"], Hint::AfterNumber),
+    ("めがばいと", &["MB"], Hint::AfterNumber),
+    ("ぎがばいと", &["GB"], Hint::AfterNumber),
+    ("てらばいと", &["TB"], Hint::AfterNumber),
+];
+
+#[derive(clap::Args, Debug)]
+pub struct UnitsArgs {
+    /// Mozc id.def for the symbol and counter POS IDs
+    #[arg(long, default_value = "mozc_src/id.def")]
+    id_def: PathBuf,
+
+    /// Output dictionary in Mozc's format, for mozc-dict-gen --extra-dictionary
+    #[arg(long, default_value = "output/units.txt")]
+    output: PathBuf,
+
+    /// Cost of the first symbol for a reading; later ones cost a little more
+    #[arg(long, default_value = "6000")]
+    cost: i16,
+}
+
+pub fn run(args: &UnitsArgs) -> Result<()> {
+    let after_number_id = lexicon::find_pos_id(&args.id_def, AFTER_NUMBER_POS)?;
+    let symbol_id = lexicon::find_pos_id(&args.id_def, SYMBOL_POS)?;
+
+    let mut entries = Vec::new();
+    for &(reading, symbols, hint) in UNITS {
+        for (i, symbol) in symbols.iter().enumerate() {
+            entries.push(MozcEntry {
+                reading: reading.to_string(),
+                id: if hint == Hint::AfterNumber { after_number_id } else { symbol_id },
+                cost: args.cost.saturating_add(i as i16 * 100),
+                surface: symbol.to_string(),
+                notes: vec![hint.label().to_string()],
+            });
+        }
+    }
+
+    tracing::info!("{} unit and currency entries", entries.len());
+    write_artifact(&args.output, |writer| lexicon::write_mozc_dictionary(writer, &entries))
+}
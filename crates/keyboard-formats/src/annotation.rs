@@ -0,0 +1,172 @@
+//! Annotations shown next to conversion candidates, as Mozc does (【顔文字】,
+//! usage notes), and flags telling the UI what kind of candidate a word is.
+//!
+//! In lex.csv they are two optional feature columns after the pronunciation:
+//!
+//! ```text
+//! <surface>,<left>,<right>,<cost>,<7 POS columns>,<reading>,<pronunciation>[,<annotation>,<flags>]
+//! ```
+//!
+//! `annotation` is `*` when there is none and `flags` is `*` or flag names
+//! joined by `|` (`emoji|slang`); see [`CandidateFlags`] for the names. Entries without either end after the
+//! pronunciation. Mozc-format source dictionaries carry them as extra
+//! columns after the surface, `annotation=<text>` and `flags=<names>`.
+
+use std::fmt;
+
+/// Placeholder for an empty column, as in MeCab features.
+const NONE: &str = "*";
+const FLAG_SEPARATOR: char = '|';
+
+/// Kinds of candidate the UI may show or rank differently.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CandidateFlags(u8);
+
+impl CandidateFlags {
+    pub const EMOJI: Self = Self(1);
+    pub const SYMBOL: Self = Self(1 << 1);
+    pub const SLANG: Self = Self(1 << 2);
+    /// Crude or adult words, kept out of kid-safe modes
+    pub const VULGAR: Self = Self(1 << 3);
+
+    const NAMES: [(Self, &'static str); 4] = [
+        (Self::EMOJI, "emoji"),
+        (Self::SYMBOL, "symbol"),
+        (Self::SLANG, "slang"),
+        (Self::VULGAR, "vulgar"),
+    ];
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether any flag of `other` is set.
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Flags named in `names` (`emoji|slang`). Unknown names are ignored so
+    /// older clients can read dictionaries with newer flags.
+    pub fn parse(names: &str) -> Self {
+        let mut flags = Self::default();
+        for name in names.split(FLAG_SEPARATOR) {
+            if let Some((flag, _)) = Self::NAMES.iter().find(|(_, n)| *n == name.trim()) {
+                flags.insert(*flag);
+            }
+        }
+        flags
+    }
+
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMES.iter().filter(|(flag, _)| self.contains(*flag)).map(|(_, name)| *name).collect()
+    }
+}
+
+/// Serialized as the names of the flags set (`["emoji", "slang"]`), so
+/// the bit layout stays private.
+#[cfg(feature = "serde")]
+impl serde::Serialize for CandidateFlags {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(&self.names(), serializer)
+    }
+}
+
+/// Unknown names are ignored, as in [`CandidateFlags::parse`].
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CandidateFlags {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let names: Vec<String> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(Self::parse(&names.join(&FLAG_SEPARATOR.to_string())))
+    }
+}
+
+/// Annotation and flags of a lexicon entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Annotation {
+    pub text: Option<String>,
+    pub flags: CandidateFlags,
+}
+
+impl Annotation {
+    pub fn is_empty(&self) -> bool {
+        self.text.is_none() && self.flags.is_empty()
+    }
+
+    /// Reads the lex.csv columns after the pronunciation.
+    pub fn from_columns(text: Option<&str>, flags: Option<&str>) -> Self {
+        Self {
+            text: text.filter(|t| !t.is_empty() && *t != NONE).map(str::to_string),
+            flags: flags.map(CandidateFlags::parse).unwrap_or_default(),
+        }
+    }
+
+    /// Reads the `annotation=` and `flags=` columns of a Mozc-format
+    /// dictionary line; other columns are notes and are skipped.
+    pub fn from_mozc_columns<'a>(columns: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut annotation = Self::default();
+        for column in columns {
+            match column.split_once('=') {
+                Some(("annotation", text)) if !text.is_empty() => annotation.text = Some(text.to_string()),
+                Some(("flags", names)) => annotation.flags.insert(CandidateFlags::parse(names)),
+                _ => {}
+            }
+        }
+        annotation
+    }
+
+    /// The lex.csv columns after the pronunciation, or None if there is
+    /// nothing to write. Commas and quotes in the text become their
+    /// fullwidth forms, since Vibrato splits features on commas.
+    pub fn columns(&self) -> Option<[String; 2]> {
+        if self.is_empty() {
+            return None;
+        }
+        let text = match &self.text {
+            Some(text) => text.replace(',', "，").replace('"', "＂"),
+            None => NONE.to_string(),
+        };
+        let names = self.flags.names();
+        let flags = if names.is_empty() { NONE.to_string() } else { names.join(&FLAG_SEPARATOR.to_string()) };
+        Some([text, flags])
+    }
+}
+
+impl fmt::Display for Annotation {
+    /// `【顔文字】 (emoji)`, for showing next to a candidate.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = self.flags.names();
+        match (&self.text, names.is_empty()) {
+            (Some(text), true) => write!(f, "{}", text),
+            (Some(text), false) => write!(f, "{} ({})", text, names.join(", ")),
+            (None, false) => write!(f, "({})", names.join(", ")),
+            (None, true) => Ok(()),
+        }
+    }
+}
+
+/// Whether `surface` is made of emoji (pictographs, with their variation
+/// selectors, joiners and tags). Symbols such as ☆ and ⌘ only count with
+/// the emoji variation selector, as they are ordinary text otherwise.
+pub fn is_emoji(surface: &str) -> bool {
+    let (mut pictographs, mut symbols, mut emoji_style) = (0, 0, false);
+    for c in surface.chars() {
+        match c as u32 {
+            0x1F000..=0x1FAFF => pictographs += 1,
+            0x2300..=0x23FF | 0x2600..=0x27BF | 0x2B00..=0x2BFF => symbols += 1,
+            0xFE0F => emoji_style = true,
+            // Zero width joiner, keycap, tags
+            0x200D | 0x20E3 | 0xE0020..=0xE007F => {}
+            _ => return false,
+        }
+    }
+    pictographs > 0 || (symbols > 0 && emoji_style)
+}
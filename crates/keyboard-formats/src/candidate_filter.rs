@@ -0,0 +1,176 @@
+//! Modes that hide or demote candidates by their [`CandidateFlags`]
+//! ("no slang", "kid-safe"), so one set of artifacts serves every audience.
+//!
+//! Modes are data: [`BUILTIN_MODES`] ships with the crate and keyboards may
+//! load their own. Text format (UTF-8, tab separated, `#` lines after the
+//! header are comments):
+//!
+//! ```text
+//! #candidate-filters<TAB>v1
+//! <mode><TAB><flags><TAB>hide|demote
+//! ```
+//!
+//! `flags` uses the lex.csv flag syntax (`slang|emoji`). Hidden candidates
+//! are never offered; demoted ones cost [`DEMOTE_COST`] more, so they only
+//! win when nothing else fits.
+
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, Write};
+
+use crate::annotation::CandidateFlags;
+
+const HEADER_MAGIC: &str = "#candidate-filters";
+const FORMAT_VERSION: &str = "v1";
+
+/// Modes every keyboard knows: no-slang, no-emoji, formal and kid-safe.
+pub const BUILTIN_MODES: &str = include_str!("candidate_filters.tsv");
+
+/// Cost added to demoted candidates, about the cost spread of Mozc's common
+/// nouns.
+pub const DEMOTE_COST: i32 = 5000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Suppression {
+    Hide,
+    Demote,
+}
+
+impl Suppression {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "hide" => Some(Suppression::Hide),
+            "demote" => Some(Suppression::Demote),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Suppression::Hide => "hide",
+            Suppression::Demote => "demote",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mode {
+    pub name: String,
+    pub flags: CandidateFlags,
+    pub suppression: Suppression,
+}
+
+/// The combined effect of the enabled modes. Hiding wins over demoting when
+/// modes disagree about a flag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CandidateFilter {
+    hidden: CandidateFlags,
+    demoted: CandidateFlags,
+}
+
+impl CandidateFilter {
+    pub fn is_empty(&self) -> bool {
+        self.hidden.is_empty() && self.demoted.is_empty()
+    }
+
+    pub fn enable(&mut self, mode: &Mode) {
+        match mode.suppression {
+            Suppression::Hide => self.hidden.insert(mode.flags),
+            Suppression::Demote => self.demoted.insert(mode.flags),
+        }
+    }
+
+    /// Cost to add to a candidate with `flags`, or None if it is hidden.
+    pub fn penalty(&self, flags: CandidateFlags) -> Option<i32> {
+        if flags.intersects(self.hidden) {
+            None
+        } else if flags.intersects(self.demoted) {
+            Some(DEMOTE_COST)
+        } else {
+            Some(0)
+        }
+    }
+
+    pub fn allows(&self, flags: CandidateFlags) -> bool {
+        self.penalty(flags).is_some()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Modes {
+    modes: Vec<Mode>,
+}
+
+impl Modes {
+    pub fn builtin() -> Self {
+        Self::read(BUILTIN_MODES.as_bytes()).expect("Built-in candidate filter modes are valid")
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Mode> {
+        self.modes.iter().find(|m| m.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Mode> {
+        self.modes.iter()
+    }
+
+    /// Filter enabling the modes named in `names`.
+    pub fn filter<'a>(&self, names: impl IntoIterator<Item = &'a str>) -> Result<CandidateFilter> {
+        let mut filter = CandidateFilter::default();
+        for name in names {
+            let Some(mode) = self.get(name) else {
+                let known: Vec<&str> = self.modes.iter().map(|m| m.name.as_str()).collect();
+                bail!("Unknown candidate filter mode {:?} (known: {})", name, known.join(", "));
+            };
+            filter.enable(mode);
+        }
+        Ok(filter)
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().context("Empty candidate filter modes")??;
+        let parts: Vec<&str> = header.split('\t').collect();
+        anyhow::ensure!(
+            parts.first() == Some(&HEADER_MAGIC),
+            "Not a candidate filter mode list (missing {} header)",
+            HEADER_MAGIC
+        );
+        anyhow::ensure!(
+            parts.get(1) == Some(&FORMAT_VERSION),
+            "Unsupported candidate filter mode version {:?}",
+            parts.get(1)
+        );
+
+        let mut modes = Self::default();
+        for (line_no, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            anyhow::ensure!(parts.len() == 3, "Line {}: expected 3 columns", line_no + 2);
+            let flags = CandidateFlags::parse(parts[1]);
+            anyhow::ensure!(!flags.is_empty(), "Line {}: no known flag in {:?}", line_no + 2, parts[1]);
+            let suppression = Suppression::parse(parts[2])
+                .with_context(|| format!("Line {}: expected hide or demote, found {:?}", line_no + 2, parts[2]))?;
+            anyhow::ensure!(modes.get(parts[0]).is_none(), "Line {}: mode {} defined twice", line_no + 2, parts[0]);
+            modes.modes.push(Mode {
+                name: parts[0].to_string(),
+                flags,
+                suppression,
+            });
+        }
+        Ok(modes)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{}\t{}", HEADER_MAGIC, FORMAT_VERSION)?;
+        for mode in &self.modes {
+            writeln!(writer, "{}\t{}\t{}", mode.name, mode.flags.names().join("|"), mode.suppression.name())?;
+        }
+        Ok(())
+    }
+}
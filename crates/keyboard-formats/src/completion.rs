@@ -0,0 +1,127 @@
+//! Completions of a partially typed reading, so candidates keep coming
+//! mid-keystroke (きょうのて → てんき, てがみ) rather than only once a word
+//! is complete.
+//!
+//! Readings come from a reading → score FST such as swipe-words.fst (scores
+//! are `ln(count) * 1000`). [`Completer::complete_in_context`] combines them
+//! with context: each reading's frequency plus a language-model score of its
+//! converted word after the text before it.
+
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, Streamer};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Completion {
+    pub reading: String,
+    /// Byte offset in the input where the completed word starts; the input
+    /// before it is context
+    pub start: usize,
+    /// Natural-log frequency of the reading
+    pub score: f64,
+}
+
+/// A completion with the word it is shown as, ranked with the context.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RankedCompletion {
+    pub reading: String,
+    pub surface: String,
+    /// Byte offset in the input where the completed word starts
+    pub start: usize,
+    /// Reading score plus context score, in natural-log units
+    pub score: f64,
+}
+
+pub struct Completer<D: AsRef<[u8]>> {
+    readings: Map<D>,
+}
+
+impl<D: AsRef<[u8]>> Completer<D> {
+    pub fn new(readings: Map<D>) -> Self {
+        Self { readings }
+    }
+
+    /// Most frequent words that the end of `kana` is the beginning of. The
+    /// longest trailing part that starts some word is taken as the word being
+    /// typed. `next_kana` lists the kana pending input may still become (`t`
+    /// → た, ち, つ, て, と, っ); when non-empty, the word must continue with
+    /// one of them.
+    pub fn complete(&self, kana: &str, next_kana: &[&str], limit: usize) -> Vec<Completion> {
+        let prefixes: Vec<String> = if next_kana.is_empty() {
+            vec![String::new()]
+        } else {
+            next_kana.iter().map(|k| k.to_string()).collect()
+        };
+
+        let starts = kana.char_indices().map(|(i, _)| i).chain([kana.len()]);
+        for start in starts {
+            let mut completions = Vec::new();
+            for next in &prefixes {
+                let prefix = format!("{}{}", &kana[start..], next);
+                if prefix.is_empty() {
+                    continue;
+                }
+                let mut stream = self.readings.search(Str::new(&prefix).starts_with()).into_stream();
+                while let Some((reading, score)) = stream.next() {
+                    completions.push(Completion {
+                        reading: String::from_utf8_lossy(reading).into_owned(),
+                        start,
+                        score: score as f64 / 1000.0,
+                    });
+                }
+            }
+            if !completions.is_empty() {
+                completions.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.reading.cmp(&b.reading)));
+                completions.truncate(limit);
+                return completions;
+            }
+        }
+        Vec::new()
+    }
+
+    /// The `candidates` most frequent completions of [`Completer::complete`],
+    /// re-ranked by context and cut to `limit`. `surface` gives the word a
+    /// reading is shown as (e.g. its best conversion). `context` is called
+    /// once, with the byte offset where the completed word starts, and
+    /// returns the score (`ln` of a count, like reading scores, 0 for none)
+    /// of a reading and word after the input before that offset, e.g. the
+    /// N-gram score of the word after the converted context.
+    pub fn complete_in_context<S: Fn(&str, &str) -> f64>(
+        &self,
+        kana: &str,
+        next_kana: &[&str],
+        candidates: usize,
+        limit: usize,
+        surface: impl Fn(&str) -> String,
+        context: impl FnOnce(usize) -> S,
+    ) -> Vec<RankedCompletion> {
+        let completions = self.complete(kana, next_kana, candidates);
+        let Some(start) = completions.first().map(|completion| completion.start) else {
+            return Vec::new();
+        };
+        let context_score = context(start);
+        let mut ranked: Vec<RankedCompletion> = completions
+            .into_iter()
+            .map(|completion| {
+                let surface = surface(&completion.reading);
+                let score = completion.score + context_score(&completion.reading, &surface);
+                RankedCompletion {
+                    reading: completion.reading,
+                    surface,
+                    start,
+                    score,
+                }
+            })
+            .collect();
+        ranked.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.surface.cmp(&b.surface))
+                .then_with(|| a.reading.cmp(&b.reading))
+        });
+        ranked.dedup_by(|a, b| a.surface == b.surface && a.reading == b.reading);
+        ranked.truncate(limit);
+        ranked
+    }
+}
@@ -0,0 +1,278 @@
+//! Typo models: costs of typing one thing when another was meant.
+//!
+//! A model is a weighted edit distance over characters. Substitutions may
+//! span several characters on either side (e.g. `si` typed for `shi`), which
+//! covers romanization variants as well as single-key slips. Lower costs mean
+//! likelier mistakes; pairs without an explicit entry use the header defaults.
+//!
+//! Text format (UTF-8, tab separated):
+//!
+//! ```text
+//! #confusion<TAB>v1<TAB>unit=romaji<TAB>sub=3000<TAB>ins=3000<TAB>del=3000<TAB>swap=2000
+//! sub<TAB><intended><TAB><typed><TAB><cost>
+//! ins<TAB><typed><TAB><cost>
+//! del<TAB><intended><TAB><cost>
+//! ```
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Write};
+
+use crate::decoder::InputModel;
+
+const HEADER_MAGIC: &str = "#confusion";
+const FORMAT_VERSION: &str = "v1";
+
+/// Costs used for edits without an explicit entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DefaultCosts {
+    pub substitution: u32,
+    pub insertion: u32,
+    pub deletion: u32,
+    /// Two adjacent characters typed in the wrong order
+    pub transposition: u32,
+}
+
+impl Default for DefaultCosts {
+    fn default() -> Self {
+        Self {
+            substitution: 3000,
+            insertion: 3000,
+            deletion: 3000,
+            transposition: 2000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfusionModel {
+    /// What the model's characters are, e.g. `romaji` or `kana`
+    pub unit: String,
+    pub defaults: DefaultCosts,
+    substitutions: HashMap<(String, String), u32>,
+    insertions: HashMap<char, u32>,
+    deletions: HashMap<char, u32>,
+    /// Substitutions with more than one character on either side, checked at
+    /// every position during [`ConfusionModel::distance`]
+    multi: Vec<(Vec<char>, Vec<char>, u32)>,
+}
+
+impl ConfusionModel {
+    pub fn new(unit: &str, defaults: DefaultCosts) -> Self {
+        Self {
+            unit: unit.to_string(),
+            defaults,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the cost of typing `typed` when `intended` was meant. Keeps the
+    /// cheaper cost if the pair is already present.
+    pub fn set_substitution(&mut self, intended: &str, typed: &str, cost: u32) {
+        let slot = self
+            .substitutions
+            .entry((intended.to_string(), typed.to_string()))
+            .or_insert(cost);
+        *slot = (*slot).min(cost);
+        if intended.chars().count() > 1 || typed.chars().count() > 1 {
+            let (from, to): (Vec<char>, Vec<char>) = (intended.chars().collect(), typed.chars().collect());
+            match self.multi.iter_mut().find(|(f, t, _)| *f == from && *t == to) {
+                Some(rule) => rule.2 = *slot,
+                None => self.multi.push((from, to, *slot)),
+            }
+        }
+    }
+
+    /// Sets the cost of an extra `typed` character.
+    pub fn set_insertion(&mut self, typed: char, cost: u32) {
+        let slot = self.insertions.entry(typed).or_insert(cost);
+        *slot = (*slot).min(cost);
+    }
+
+    /// Sets the cost of leaving out the `intended` character.
+    pub fn set_deletion(&mut self, intended: char, cost: u32) {
+        let slot = self.deletions.entry(intended).or_insert(cost);
+        *slot = (*slot).min(cost);
+    }
+
+    pub fn substitution_cost(&self, intended: &str, typed: &str) -> u32 {
+        if intended == typed {
+            return 0;
+        }
+        self.substitutions
+            .get(&(intended.to_string(), typed.to_string()))
+            .copied()
+            .unwrap_or(self.defaults.substitution)
+    }
+
+    pub fn insertion_cost(&self, typed: char) -> u32 {
+        self.insertions.get(&typed).copied().unwrap_or(self.defaults.insertion)
+    }
+
+    pub fn deletion_cost(&self, intended: char) -> u32 {
+        self.deletions.get(&intended).copied().unwrap_or(self.defaults.deletion)
+    }
+
+    /// Cheapest cost of typing `typed` when `intended` was meant.
+    pub fn distance(&self, intended: &str, typed: &str) -> u32 {
+        let a: Vec<char> = intended.chars().collect();
+        let b: Vec<char> = typed.chars().collect();
+        let mut buf = [0u8; 4];
+        let mut buf2 = [0u8; 4];
+
+        // dp[j][i]: cost of turning intended[..j] into typed[..i]
+        let mut dp = vec![vec![u32::MAX; b.len() + 1]; a.len() + 1];
+        dp[0][0] = 0;
+        for j in 0..=a.len() {
+            for i in 0..=b.len() {
+                let here = dp[j][i];
+                if here == u32::MAX {
+                    continue;
+                }
+                let mut relax = |j2: usize, i2: usize, cost: u32| {
+                    let slot = &mut dp[j2][i2];
+                    *slot = (*slot).min(here.saturating_add(cost));
+                };
+                if j < a.len() {
+                    relax(j + 1, i, self.deletion_cost(a[j]));
+                }
+                if i < b.len() {
+                    relax(j, i + 1, self.insertion_cost(b[i]));
+                }
+                if j < a.len() && i < b.len() {
+                    let cost = self.substitution_cost(a[j].encode_utf8(&mut buf), b[i].encode_utf8(&mut buf2));
+                    relax(j + 1, i + 1, cost);
+                }
+                if j + 1 < a.len() && i + 1 < b.len() && a[j] == b[i + 1] && a[j + 1] == b[i] && a[j] != a[j + 1] {
+                    relax(j + 2, i + 2, self.defaults.transposition);
+                }
+                for (from, to, cost) in &self.multi {
+                    if a[j..].starts_with(from) && b[i..].starts_with(to) {
+                        relax(j + from.len(), i + to.len(), *cost);
+                    }
+                }
+            }
+        }
+        dp[a.len()][b.len()]
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().context("Empty confusion model")??;
+        let mut model = parse_header(&header)?;
+
+        for (line_no, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            let cost = |index: usize| -> Result<u32> {
+                parts
+                    .get(index)
+                    .context("missing cost")?
+                    .parse()
+                    .with_context(|| format!("Line {}: bad cost", line_no + 2))
+            };
+            let single = |index: usize| -> Result<char> {
+                let mut chars = parts.get(index).context("missing character")?.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(c),
+                    _ => anyhow::bail!("Line {}: expected a single character", line_no + 2),
+                }
+            };
+            match parts[0] {
+                "sub" if parts.len() == 4 => model.set_substitution(parts[1], parts[2], cost(3)?),
+                "ins" if parts.len() == 3 => model.set_insertion(single(1)?, cost(2)?),
+                "del" if parts.len() == 3 => model.set_deletion(single(1)?, cost(2)?),
+                other => anyhow::bail!("Line {}: unexpected entry '{}'", line_no + 2, other),
+            }
+        }
+        Ok(model)
+    }
+
+    /// Writes the model with entries sorted so output is stable.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(
+            writer,
+            "{}\t{}\tunit={}\tsub={}\tins={}\tdel={}\tswap={}",
+            HEADER_MAGIC,
+            FORMAT_VERSION,
+            self.unit,
+            self.defaults.substitution,
+            self.defaults.insertion,
+            self.defaults.deletion,
+            self.defaults.transposition
+        )?;
+        let substitutions: BTreeMap<_, _> = self.substitutions.iter().collect();
+        for ((intended, typed), cost) in substitutions {
+            writeln!(writer, "sub\t{}\t{}\t{}", intended, typed, cost)?;
+        }
+        for (typed, cost) in self.insertions.iter().collect::<BTreeMap<_, _>>() {
+            writeln!(writer, "ins\t{}\t{}", typed, cost)?;
+        }
+        for (intended, cost) in self.deletions.iter().collect::<BTreeMap<_, _>>() {
+            writeln!(writer, "del\t{}\t{}", intended, cost)?;
+        }
+        Ok(())
+    }
+}
+
+/// Proposes the typed characters themselves, explicit substitutions (including
+/// multi-character ones), insertions, explicit deletions and transpositions.
+/// Default-cost substitutions are not proposed since the model has no alphabet.
+impl InputModel for ConfusionModel {
+    type Observation = char;
+
+    fn expand(&self, typed: &[char], position: usize) -> Vec<(usize, String, f64)> {
+        let likelihood = |cost: u32| -(cost as f64) / 1000.0;
+        let rest = &typed[position..];
+        let c = rest[0];
+
+        let mut expansions = vec![(1, c.to_string(), 0.0)];
+        for ((intended, typed), cost) in &self.substitutions {
+            let typed: Vec<char> = typed.chars().collect();
+            if rest.starts_with(&typed) {
+                expansions.push((typed.len(), intended.clone(), likelihood(*cost)));
+            }
+        }
+        expansions.push((1, String::new(), likelihood(self.insertion_cost(c))));
+        for (intended, cost) in &self.deletions {
+            expansions.push((1, format!("{}{}", intended, c), likelihood(*cost)));
+        }
+        if let [a, b, ..] = rest {
+            if a != b {
+                expansions.push((2, format!("{}{}", b, a), likelihood(self.defaults.transposition)));
+            }
+        }
+        expansions
+    }
+}
+
+fn parse_header(header: &str) -> Result<ConfusionModel> {
+    let parts: Vec<&str> = header.split('\t').collect();
+    anyhow::ensure!(
+        parts.first() == Some(&HEADER_MAGIC),
+        "Not a confusion model (missing {} header)",
+        HEADER_MAGIC
+    );
+    anyhow::ensure!(
+        parts.get(1) == Some(&FORMAT_VERSION),
+        "Unsupported confusion model version {:?}",
+        parts.get(1)
+    );
+    let mut model = ConfusionModel::default();
+    for field in &parts[2..] {
+        let (key, value) = field.split_once('=').context("Bad header field")?;
+        match key {
+            "unit" => model.unit = value.to_string(),
+            "sub" => model.defaults.substitution = value.parse().context("Bad sub")?,
+            "ins" => model.defaults.insertion = value.parse().context("Bad ins")?,
+            "del" => model.defaults.deletion = value.parse().context("Bad del")?,
+            "swap" => model.defaults.transposition = value.parse().context("Bad swap")?,
+            _ => {}
+        }
+    }
+    Ok(model)
+}
@@ -0,0 +1,182 @@
+//! Beam search over an input model (what the user meant, given what the
+//! keyboard observed) and a language model (what the user is likely to
+//! write), shared by fuzzy typing and swipe decoding.
+//!
+//! Scores are natural-log likelihoods; edit costs and FST scores, which are
+//! stored as `-1000 * ln(p)` and `1000 * ln(count)`, are divided by 1000.
+
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, Streamer};
+
+use crate::layout::Layout;
+
+/// Explains observations (typed characters, touch points) as intended output.
+pub trait InputModel {
+    type Observation;
+
+    /// Ways to explain `observations` starting at `position`, as (observations
+    /// consumed, output, log likelihood). Every expansion consumes at least
+    /// one observation; the output may be empty (a stray keypress).
+    fn expand(&self, observations: &[Self::Observation], position: usize) -> Vec<(usize, String, f64)>;
+}
+
+/// Scores and constrains the decoded text.
+pub trait LanguageModel {
+    /// Log score of appending `extension` to `prefix`, or `None` if nothing
+    /// the model knows continues that way, which drops the hypothesis.
+    fn extend(&self, prefix: &str, extension: &str) -> Option<f64>;
+
+    /// Log score of `text` as a complete result, or `None` if it is not one.
+    fn finish(&self, text: &str) -> Option<f64>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hypothesis {
+    pub text: String,
+    pub input_score: f64,
+    pub language_score: f64,
+}
+
+impl Hypothesis {
+    pub fn score(&self) -> f64 {
+        self.input_score + self.language_score
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BeamDecoder {
+    /// Hypotheses kept per observation position
+    pub beam_width: usize,
+    /// Results returned
+    pub max_results: usize,
+}
+
+impl Default for BeamDecoder {
+    fn default() -> Self {
+        Self {
+            beam_width: 64,
+            max_results: 10,
+        }
+    }
+}
+
+impl BeamDecoder {
+    /// Best complete explanations of `observations`, highest score first,
+    /// one per distinct text.
+    pub fn decode<I: InputModel, L: LanguageModel>(
+        &self,
+        input: &I,
+        language: &L,
+        observations: &[I::Observation],
+    ) -> Vec<Hypothesis> {
+        // beams[p]: hypotheses that have consumed the first p observations
+        let mut beams: Vec<Vec<Hypothesis>> = vec![Vec::new(); observations.len() + 1];
+        beams[0].push(Hypothesis {
+            text: String::new(),
+            input_score: 0.0,
+            language_score: 0.0,
+        });
+
+        for position in 0..observations.len() {
+            let beam = prune(std::mem::take(&mut beams[position]), self.beam_width);
+            if beam.is_empty() {
+                continue;
+            }
+            let expansions = input.expand(observations, position);
+            for hypothesis in &beam {
+                for (consumed, output, likelihood) in &expansions {
+                    let end = position + consumed;
+                    if *consumed == 0 || end > observations.len() {
+                        continue;
+                    }
+                    let Some(language_score) = language.extend(&hypothesis.text, output) else {
+                        continue;
+                    };
+                    beams[end].push(Hypothesis {
+                        text: format!("{}{}", hypothesis.text, output),
+                        input_score: hypothesis.input_score + likelihood,
+                        language_score: hypothesis.language_score + language_score,
+                    });
+                }
+            }
+        }
+
+        let finished = std::mem::take(&mut beams[observations.len()])
+            .into_iter()
+            .filter_map(|mut hypothesis| {
+                hypothesis.language_score += language.finish(&hypothesis.text)?;
+                Some(hypothesis)
+            })
+            .collect();
+        prune(finished, self.max_results)
+    }
+}
+
+/// Keeps the best hypothesis per text, then the `width` best overall.
+fn prune(mut hypotheses: Vec<Hypothesis>, width: usize) -> Vec<Hypothesis> {
+    hypotheses.sort_by(|a, b| a.text.cmp(&b.text).then(b.score().total_cmp(&a.score())));
+    hypotheses.dedup_by(|a, b| a.text == b.text);
+    hypotheses.sort_by(|a, b| b.score().total_cmp(&a.score()));
+    hypotheses.truncate(width);
+    hypotheses
+}
+
+/// Word list FST (e.g. swipe-words.fst, reading → score) as a language model:
+/// text must stay a prefix of some word and finish as a whole word, scored by
+/// its frequency.
+pub struct WordTrie<D: AsRef<[u8]>> {
+    words: Map<D>,
+}
+
+impl<D: AsRef<[u8]>> WordTrie<D> {
+    pub fn new(words: Map<D>) -> Self {
+        Self { words }
+    }
+}
+
+impl<D: AsRef<[u8]>> LanguageModel for WordTrie<D> {
+    fn extend(&self, prefix: &str, extension: &str) -> Option<f64> {
+        let text = format!("{}{}", prefix, extension);
+        let mut stream = self.words.search(Str::new(&text).starts_with()).into_stream();
+        stream.next().map(|_| 0.0)
+    }
+
+    fn finish(&self, text: &str) -> Option<f64> {
+        self.words.get(text).map(|score| score as f64 / 1000.0)
+    }
+}
+
+/// Touch points explained by the keys around them, with a Gaussian falloff.
+pub struct TouchModel<'a> {
+    pub layout: &'a Layout,
+    /// Standard deviation of touches around a key center, in key widths
+    pub sigma: f64,
+    /// Keys further than this from a touch are not considered
+    pub radius: f64,
+    /// Log likelihood of a point that belongs to no key, such as a sample of a
+    /// swipe in transit between keys; `None` requires every point to hit a key
+    pub skip: Option<f64>,
+}
+
+impl InputModel for TouchModel<'_> {
+    type Observation = (f64, f64);
+
+    fn expand(&self, observations: &[(f64, f64)], position: usize) -> Vec<(usize, String, f64)> {
+        let (x, y) = observations[position];
+        let mut expansions: Vec<(usize, String, f64)> = self
+            .layout
+            .keys()
+            .iter()
+            .filter(|key| key.distance(x, y) <= self.radius)
+            .map(|key| {
+                let distance = key.distance(x, y);
+                (1, key.output.clone(), -distance * distance / (2.0 * self.sigma * self.sigma))
+            })
+            .collect();
+        if let Some(skip) = self.skip {
+            expansions.push((1, String::new(), skip));
+        }
+        expansions
+    }
+}
@@ -0,0 +1,163 @@
+//! Japanese era names (元号) with their boundaries, for converting between era
+//! and Gregorian years while typing dates.
+//!
+//! Text format (UTF-8, tab separated, dates as YYYY-MM-DD in the Gregorian
+//! calendar; the end date is empty for the current era):
+//!
+//! ```text
+//! #eras<TAB>v1
+//! <name><TAB><reading><TAB><first day><TAB><last day>
+//! ```
+
+use anyhow::{Context, Result};
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+const HEADER_MAGIC: &str = "#eras";
+const FORMAT_VERSION: &str = "v1";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Date {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    pub const fn new(year: i32, month: u8, day: u8) -> Self {
+        Self { year, month, day }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(3, '-');
+        let mut next = || parts.next().with_context(|| format!("Bad date '{}'", s));
+        let year = next()?.parse()?;
+        let month = next()?.parse()?;
+        let day = next()?.parse()?;
+        Ok(Self { year, month, day })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Era {
+    pub name: String,
+    pub reading: String,
+    pub start: Date,
+    /// Last day of the era; `None` for the current one
+    pub end: Option<Date>,
+}
+
+impl Era {
+    /// Gregorian year of this era's `era_year` (1 = 元年), if the era lasted that long.
+    pub fn to_gregorian(&self, era_year: i32) -> Option<i32> {
+        let year = self.start.year + era_year - 1;
+        let within = era_year >= 1 && self.end.is_none_or(|end| year <= end.year);
+        within.then_some(year)
+    }
+
+    /// Era year of the Gregorian `year`, if any part of it fell within the era.
+    pub fn from_gregorian(&self, year: i32) -> Option<i32> {
+        let within = year >= self.start.year && self.end.is_none_or(|end| year <= end.year);
+        within.then(|| year - self.start.year + 1)
+    }
+}
+
+/// Eras ordered from oldest to newest.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EraTable {
+    eras: Vec<Era>,
+}
+
+impl EraTable {
+    pub fn new(mut eras: Vec<Era>) -> Self {
+        eras.sort_by_key(|era| era.start);
+        Self { eras }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Era> {
+        self.eras.iter()
+    }
+
+    /// Looks an era up by name (令和) or reading (れいわ).
+    pub fn find(&self, name_or_reading: &str) -> Option<&Era> {
+        self.eras
+            .iter()
+            .find(|era| era.name == name_or_reading || era.reading == name_or_reading)
+    }
+
+    /// Era and era year in effect on `date`.
+    pub fn era_at(&self, date: Date) -> Option<(&Era, i32)> {
+        self.eras
+            .iter()
+            .rev()
+            .find(|era| era.start <= date && era.end.is_none_or(|end| date <= end))
+            .map(|era| (era, date.year - era.start.year + 1))
+    }
+
+    /// Every era covering part of the Gregorian `year`, with its era year.
+    /// A year in which the era changed yields both (1989 → 昭和64, 平成元).
+    pub fn era_years(&self, year: i32) -> Vec<(&Era, i32)> {
+        self.eras
+            .iter()
+            .filter_map(|era| era.from_gregorian(year).map(|era_year| (era, era_year)))
+            .collect()
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().context("Empty era table")??;
+        let parts: Vec<&str> = header.split('\t').collect();
+        anyhow::ensure!(
+            parts.first() == Some(&HEADER_MAGIC),
+            "Not an era table (missing {} header)",
+            HEADER_MAGIC
+        );
+        anyhow::ensure!(
+            parts.get(1) == Some(&FORMAT_VERSION),
+            "Unsupported era table version {:?}",
+            parts.get(1)
+        );
+
+        let mut eras = Vec::new();
+        for (line_no, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            anyhow::ensure!(parts.len() == 4, "Line {}: expected 4 columns", line_no + 2);
+            eras.push(Era {
+                name: parts[0].to_string(),
+                reading: parts[1].to_string(),
+                start: parts[2].parse().with_context(|| format!("Line {}", line_no + 2))?,
+                end: match parts[3] {
+                    "" => None,
+                    end => Some(end.parse().with_context(|| format!("Line {}", line_no + 2))?),
+                },
+            });
+        }
+        Ok(Self::new(eras))
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{}\t{}", HEADER_MAGIC, FORMAT_VERSION)?;
+        for era in &self.eras {
+            let end = era.end.map(|end| end.to_string()).unwrap_or_default();
+            writeln!(writer, "{}\t{}\t{}\t{}", era.name, era.reading, era.start, end)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! Conversion between hiragana and katakana, for readings kept in either
+//! script (mozc-dict-gen `--reading-script`) and readings given in
+//! katakana (KEN_ALL, JMnedict, user dictionaries).
+//!
+//! Most kana are 0x60 code points apart in the two blocks. The iteration
+//! marks (ゝゞ, ヽヾ) are too, and the katakana ヷヸヹヺ, which have no
+//! hiragana letters, become わゐゑを followed by a combining voiced mark
+//! (U+3099) and back, so a round trip gives the text it started from.
+//! Half-width katakana are folded to full width first, with a half-width
+//! (semi-)voiced mark joined to the kana before it (ｶﾞ → ガ, ﾊﾟ → パ); a mark
+//! that kana can't take (ｯﾞ, ｶﾞﾞ) becomes a full-width ゛ or ゜. The
+//! prolonged sound mark, the combining marks and anything else are kept as
+//! they are.
+
+/// Combining voiced sound mark (dakuten), shared by both scripts.
+const COMBINING_VOICED_MARK: char = '\u{3099}';
+
+/// Code points from ワヰヱヲ to their voiced forms ヷヸヹヺ.
+const VOICED_W_OFFSET: u32 = 8;
+
+/// Code points from a hiragana to its katakana.
+const KATAKANA_OFFSET: u32 = 0x60;
+
+/// Half-width katakana, in code point order from U+FF66 (ｦ) to U+FF9D (ﾝ).
+const HALF_WIDTH_KATAKANA: &str = "ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン";
+
+const HALF_WIDTH_VOICED_MARK: char = '\u{FF9E}';
+const HALF_WIDTH_SEMI_VOICED_MARK: char = '\u{FF9F}';
+
+/// `text` with half-width katakana as full-width katakana.
+pub fn fold_half_width(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{FF66}'..='\u{FF9D}' => out.extend(HALF_WIDTH_KATAKANA.chars().nth(c as usize - 0xFF66)),
+            HALF_WIDTH_VOICED_MARK | HALF_WIDTH_SEMI_VOICED_MARK => {
+                let semi_voiced = c == HALF_WIDTH_SEMI_VOICED_MARK;
+                match out.pop().map(|last| (last, with_mark(last, semi_voiced))) {
+                    Some((_, Some(marked))) => out.push(marked),
+                    last => {
+                        out.extend(last.map(|(last, _)| last));
+                        out.push(if semi_voiced { '゜' } else { '゛' });
+                    }
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Katakana `c` with a voiced mark, or a semi-voiced one if `semi_voiced`;
+/// `None` if it takes no such mark.
+fn with_mark(c: char, semi_voiced: bool) -> Option<char> {
+    let from = |first: char| c as u32 - first as u32;
+    let marked = match (c, semi_voiced) {
+        // カ..チ and ツ..ト alternate plain and voiced kana; ッ sits between
+        ('カ'..='チ', false) if from('カ').is_multiple_of(2) => c as u32 + 1,
+        ('ツ'..='ト', false) if from('ツ').is_multiple_of(2) => c as u32 + 1,
+        // ハ, バ, パ
+        ('ハ'..='ホ', _) if from('ハ').is_multiple_of(3) => c as u32 + 1 + semi_voiced as u32,
+        ('ウ', false) => 'ヴ' as u32,
+        ('ワ' | 'ヲ', false) => c as u32 + VOICED_W_OFFSET,
+        _ => return None,
+    };
+    char::from_u32(marked)
+}
+
+/// `text` with its hiragana and half-width katakana as katakana.
+pub fn to_katakana(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in fold_half_width(text).chars() {
+        match c {
+            'ぁ'..='ゖ' | 'ゝ' | 'ゞ' => out.push(char::from_u32(c as u32 + KATAKANA_OFFSET).unwrap_or(c)),
+            COMBINING_VOICED_MARK => match out.pop() {
+                Some(last @ 'ワ'..='ヲ') => out.push(char::from_u32(last as u32 + VOICED_W_OFFSET).unwrap_or(last)),
+                last => {
+                    out.extend(last);
+                    out.push(c);
+                }
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// `text` with its katakana, full- or half-width, as hiragana.
+pub fn to_hiragana(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in fold_half_width(text).chars() {
+        match c {
+            'ァ'..='ヶ' | 'ヽ' | 'ヾ' => out.push(char::from_u32(c as u32 - KATAKANA_OFFSET).unwrap_or(c)),
+            'ヷ'..='ヺ' => {
+                out.extend(char::from_u32(c as u32 - VOICED_W_OFFSET - KATAKANA_OFFSET));
+                out.push(COMBINING_VOICED_MARK);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,168 @@
+//! Scores token sequences with the shipped N-gram and vocabulary FSTs, so
+//! tools outside the keyboard (autocorrect reranking, gesture decoding) rank
+//! hypotheses with the same language model.
+//!
+//! FST values are `ln(count) * 1000` of the (possibly smoothed) counts, see
+//! wiki-ngram. Scores use stupid backoff (Brants et al. 2007): the
+//! relative frequency of the longest known history, multiplied by
+//! [`BACKOFF_FACTOR`] for each order dropped. The result is a score rather than
+//! a normalized probability, which is what ranking needs.
+//!
+//! Perplexities and interpolation weights need probabilities that sum to
+//! one, which [`Model::token_probability`] gives by interpolated absolute
+//! discounting instead.
+
+use anyhow::Result;
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, Streamer};
+use std::path::Path;
+
+use crate::decoder::LanguageModel;
+use crate::ngram_key::SEPARATOR;
+
+pub const BACKOFF_FACTOR: f64 = 0.4;
+
+/// Taken off every N-gram count by [`Model::token_probability`]; counts
+/// decode to at least 1, so none goes below zero.
+pub const ABSOLUTE_DISCOUNT: f64 = 0.75;
+
+pub struct Model<D: AsRef<[u8]>> {
+    ngrams: Map<D>,
+    vocab: Map<D>,
+    max_order: usize,
+    /// Total unigram count, the denominator of unigram probabilities
+    total: f64,
+}
+
+impl Model<Vec<u8>> {
+    /// Loads `wiki-ngrams.fst` and `wiki-ngrams-vocab.fst` built with `--max-ngram max_order`.
+    pub fn open(ngrams: &Path, vocab: &Path, max_order: usize) -> Result<Self> {
+        Ok(Self::new(
+            Map::new(std::fs::read(ngrams)?)?,
+            Map::new(std::fs::read(vocab)?)?,
+            max_order,
+        ))
+    }
+}
+
+impl<D: AsRef<[u8]>> Model<D> {
+    pub fn new(ngrams: Map<D>, vocab: Map<D>, max_order: usize) -> Self {
+        let mut total = 0.0;
+        let mut stream = vocab.stream();
+        while let Some((_, score)) = stream.next() {
+            total += decode_count(score);
+        }
+        Self {
+            ngrams,
+            vocab,
+            max_order,
+            total,
+        }
+    }
+
+    /// Log score of `tokens` as one sentence, the sum of each token's log
+    /// score given up to `max_order - 1` preceding tokens.
+    pub fn score(&self, tokens: &[&str]) -> f64 {
+        (0..tokens.len())
+            .map(|i| {
+                let start = (i + 1).saturating_sub(self.max_order);
+                self.token_score(&tokens[start..i], tokens[i])
+            })
+            .sum()
+    }
+
+    /// Log stupid-backoff score of `word` after `history`, backing off to
+    /// shorter histories. Not a probability; see [`Model::token_probability`].
+    pub fn token_score(&self, history: &[&str], word: &str) -> f64 {
+        let mut penalty = 0.0;
+        for start in 0..history.len() {
+            let context = &history[start..];
+            if let (Some(joint), Some(context_count)) = (self.count(context, Some(word)), self.count(context, None)) {
+                return penalty + (joint / context_count).min(1.0).ln();
+            }
+            penalty += BACKOFF_FACTOR.ln();
+        }
+        // Unknown words get the probability of a word seen once
+        let count = self.count(&[], Some(word)).unwrap_or(1.0);
+        penalty + (count / (self.total + self.vocab.len() as f64)).ln()
+    }
+
+    /// Probability of `word` after `history` by interpolated absolute
+    /// discounting (Ney et al. 1994). Each count seen after a history loses
+    /// [`ABSOLUTE_DISCOUNT`] and the mass freed goes to the estimate for the
+    /// history a token shorter, down to add-one smoothed unigrams with one
+    /// more slot shared by unknown words. Over the vocabulary and that slot
+    /// the probabilities sum to one.
+    pub fn token_probability(&self, history: &[&str], word: &str) -> f64 {
+        let count = self.count(&[], Some(word)).unwrap_or(0.0);
+        let mut probability = (count + 1.0) / (self.total + self.vocab.len() as f64 + 1.0);
+        for start in (0..history.len()).rev() {
+            let context = &history[start..];
+            let (distinct, total) = self.followers(context);
+            if total > 0.0 {
+                let joint = self.count(context, Some(word)).unwrap_or(0.0);
+                let discounted = (joint - ABSOLUTE_DISCOUNT).max(0.0);
+                probability = (discounted + ABSOLUTE_DISCOUNT * distinct as f64 * probability) / total;
+            }
+        }
+        probability
+    }
+
+    /// How many distinct words follow `context` in the N-gram FST, and the
+    /// sum of their counts.
+    fn followers(&self, context: &[&str]) -> (usize, f64) {
+        let prefix = format!("{}{}", context.join(&SEPARATOR.to_string()), SEPARATOR);
+        let mut stream = self.ngrams.search(Str::new(&prefix).starts_with()).into_stream();
+        let (mut distinct, mut total) = (0, 0.0);
+        while let Some((key, score)) = stream.next() {
+            // Longer N-grams sharing the prefix
+            if !String::from_utf8_lossy(&key[prefix.len()..]).contains(SEPARATOR) {
+                distinct += 1;
+                total += decode_count(score);
+            }
+        }
+        (distinct, total)
+    }
+
+    /// Count of `context` followed by `word`, from the vocabulary for single
+    /// words and the N-gram FST otherwise.
+    fn count(&self, context: &[&str], word: Option<&str>) -> Option<f64> {
+        let mut key = context.join(&SEPARATOR.to_string());
+        if let Some(word) = word {
+            if !key.is_empty() {
+                key.push(SEPARATOR);
+            }
+            key.push_str(word);
+        }
+        let order = context.len() + word.is_some() as usize;
+        let score = match order {
+            0 => return None,
+            1 => self.vocab.get(&key)?,
+            _ => self.ngrams.get(&key)?,
+        };
+        Some(decode_count(score))
+    }
+}
+
+/// Inverse of wiki-ngram's score encoding; counts of one or less encode as 0.
+pub fn decode_count(score: u64) -> f64 {
+    (score as f64 / 1000.0).exp()
+}
+
+/// Text is tokens joined by [`SEPARATOR`]; each extension must be one token
+/// (a leading separator is ignored).
+impl<D: AsRef<[u8]>> LanguageModel for Model<D> {
+    fn extend(&self, prefix: &str, extension: &str) -> Option<f64> {
+        let token = extension.trim_start_matches(SEPARATOR);
+        if token.is_empty() {
+            return Some(0.0);
+        }
+        let history: Vec<&str> = prefix.split(SEPARATOR).filter(|token| !token.is_empty()).collect();
+        let start = (history.len() + 1).saturating_sub(self.max_order);
+        Some(self.token_score(&history[start..], token))
+    }
+
+    fn finish(&self, _text: &str) -> Option<f64> {
+        Some(0.0)
+    }
+}
@@ -0,0 +1,146 @@
+//! Keyboard layout geometry for decoders that work from touch positions,
+//! such as swipe typing: where each key sits and what it enters.
+//!
+//! Coordinates are key centers in key widths (x) and row heights (y) from the
+//! top-left corner. A key that enters several outputs (か also entering が via
+//! the modifier) is listed once per output with the same geometry.
+//!
+//! Text format (UTF-8, tab separated; `input` says what the outputs are, e.g.
+//! `romaji` letters to be converted to kana, or `kana`):
+//!
+//! ```text
+//! #layout<TAB>v1<TAB>name=qwerty<TAB>input=romaji
+//! <output><TAB><center x><TAB><center y><TAB><width><TAB><height>
+//! ```
+
+use anyhow::{Context, Result};
+use std::io::{BufRead, Write};
+
+const HEADER_MAGIC: &str = "#layout";
+const FORMAT_VERSION: &str = "v1";
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Key {
+    pub output: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Key {
+    /// Distance from the key center to (`x`, `y`).
+    pub fn distance(&self, x: f64, y: f64) -> f64 {
+        ((self.x - x).powi(2) + (self.y - y).powi(2)).sqrt()
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        (self.x - x).abs() <= self.width / 2.0 && (self.y - y).abs() <= self.height / 2.0
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Layout {
+    pub name: String,
+    /// What key outputs are, e.g. `romaji` or `kana`
+    pub input: String,
+    keys: Vec<Key>,
+}
+
+impl Layout {
+    pub fn new(name: &str, input: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            input: input.to_string(),
+            keys: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: Key) {
+        self.keys.push(key);
+    }
+
+    pub fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+
+    /// Key entering `output`.
+    pub fn key(&self, output: &str) -> Option<&Key> {
+        self.keys.iter().find(|key| key.output == output)
+    }
+
+    /// Key centers a swipe entering `outputs` in order passes through, or
+    /// `None` if an output has no key on this layout.
+    pub fn path<'a>(&self, outputs: impl IntoIterator<Item = &'a str>) -> Option<Vec<(f64, f64)>> {
+        outputs
+            .into_iter()
+            .map(|output| self.key(output).map(|key| (key.x, key.y)))
+            .collect()
+    }
+
+    /// Keys ordered by distance from (`x`, `y`), nearest first.
+    pub fn nearest(&self, x: f64, y: f64) -> Vec<&Key> {
+        let mut keys: Vec<&Key> = self.keys.iter().collect();
+        keys.sort_by(|a, b| a.distance(x, y).total_cmp(&b.distance(x, y)));
+        keys
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().context("Empty layout")??;
+        let parts: Vec<&str> = header.split('\t').collect();
+        anyhow::ensure!(
+            parts.first() == Some(&HEADER_MAGIC),
+            "Not a layout (missing {} header)",
+            HEADER_MAGIC
+        );
+        anyhow::ensure!(
+            parts.get(1) == Some(&FORMAT_VERSION),
+            "Unsupported layout version {:?}",
+            parts.get(1)
+        );
+        let field = |name: &str| {
+            parts[2..]
+                .iter()
+                .find_map(|field| field.strip_prefix(name)?.strip_prefix('='))
+                .unwrap_or_default()
+        };
+
+        let mut layout = Self::new(field("name"), field("input"));
+        for (line_no, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            anyhow::ensure!(parts.len() == 5, "Line {}: expected 5 columns", line_no + 2);
+            let number = |i: usize| {
+                parts[i]
+                    .parse::<f64>()
+                    .with_context(|| format!("Line {}: bad number '{}'", line_no + 2, parts[i]))
+            };
+            layout.push(Key {
+                output: parts[0].to_string(),
+                x: number(1)?,
+                y: number(2)?,
+                width: number(3)?,
+                height: number(4)?,
+            });
+        }
+        Ok(layout)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(
+            writer,
+            "{}\t{}\tname={}\tinput={}",
+            HEADER_MAGIC, FORMAT_VERSION, self.name, self.input
+        )?;
+        for key in &self.keys {
+            writeln!(writer, "{}\t{}\t{}\t{}\t{}", key.output, key.x, key.y, key.width, key.height)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+//! Short-term memory of committed text. Clients that opt in record each
+//! conversion the user commits, and the same words are boosted in later
+//! conversions and predictions right away, without waiting for a rebuilt
+//! dictionary.
+//!
+//! Memories are kept as a [`UserHistory`] with a short half-life, so the
+//! session can be saved in the user-history format and merged into the
+//! long-term history.
+
+use crate::user_history::{Decay, UserHistory};
+
+pub struct ShortTermMemory {
+    history: UserHistory,
+    /// Boost of a word committed once just now, in FST score units
+    /// (`ln(count) * 1000`)
+    weight: f64,
+}
+
+impl ShortTermMemory {
+    /// Half an hour: long enough to span one conversation or document.
+    pub const DEFAULT_DECAY: Decay = Decay {
+        half_life_secs: 30 * 60,
+    };
+    /// A word committed once counts as if it were about 20x more frequent (e^3).
+    pub const DEFAULT_WEIGHT: f64 = 3000.0;
+
+    pub fn new(decay: Decay, weight: f64) -> Self {
+        Self::from_history(UserHistory::new(decay), weight)
+    }
+
+    /// Continues from a saved history, e.g. the previous session's.
+    pub fn from_history(history: UserHistory, weight: f64) -> Self {
+        Self { history, weight }
+    }
+
+    pub fn history(&self) -> &UserHistory {
+        &self.history
+    }
+
+    pub fn into_history(self) -> UserHistory {
+        self.history
+    }
+
+    /// Records that the user committed `surface` as the conversion of
+    /// `reading` at time `now`.
+    pub fn commit(&mut self, reading: &str, surface: &str, now: u64) {
+        self.history.record(reading, surface, now);
+    }
+
+    /// Score to add to `surface` as a conversion of `reading`. Grows with the
+    /// log of the decayed usage so repeated commits don't swamp the model.
+    pub fn boost(&self, reading: &str, surface: &str, now: u64) -> f64 {
+        self.history
+            .score(reading, surface, now)
+            .map_or(0.0, |usage| self.weight * usage.ln_1p() / 2f64.ln())
+    }
+
+    /// Score to add to `surface` regardless of reading, for predictions that
+    /// are looked up by surface only.
+    pub fn surface_boost(&self, surface: &str, now: u64) -> f64 {
+        self.history
+            .iter()
+            .filter(|(_, s, _)| *s == surface)
+            .map(|(reading, s, _)| self.boost(reading, s, now))
+            .fold(0.0, f64::max)
+    }
+
+    /// Adds the surface boost to each prediction score and re-sorts them,
+    /// highest score first.
+    pub fn rerank(&self, predictions: &mut [(String, u64)], now: u64) {
+        for (word, score) in predictions.iter_mut() {
+            *score += self.surface_boost(word, now).round() as u64;
+        }
+        predictions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    }
+}
+
+impl Default for ShortTermMemory {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_DECAY, Self::DEFAULT_WEIGHT)
+    }
+}
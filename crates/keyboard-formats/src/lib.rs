@@ -0,0 +1,32 @@
+//! Data formats shared between the forge and keyboard clients.
+//!
+//! With the `serde` feature, the runtime types servers, evaluation harnesses
+//! and logs pass around (completions, decoder hypotheses, symbols, candidate
+//! annotations, places, eras, mappings, layouts, user dictionary and history
+//! entries, and scoring settings such as [`user_history::Decay`] and
+//! [`confusion::DefaultCosts`]) implement `Serialize` and `Deserialize`.
+//! Field names are their Rust names and enum variants are lowercase; both are
+//! a public contract, so renaming one is a breaking change.
+
+pub mod annotation;
+pub mod candidate_filter;
+pub mod completion;
+pub mod confusion;
+pub mod decoder;
+pub mod eras;
+pub mod kana;
+pub mod language_model;
+pub mod layout;
+pub mod learning;
+pub mod mapping;
+pub mod ngram_key;
+pub mod places;
+pub mod reverse_lookup;
+pub mod sequence_guard;
+pub mod single_kanji;
+pub mod suppression;
+pub mod symbols;
+pub mod text_length;
+pub mod user_dictionary;
+pub mod user_history;
+pub mod user_ngrams;
@@ -0,0 +1,129 @@
+//! Word-to-word mapping tables (honorific forms, contractions, ...), used to
+//! offer alternatives for a word the user has already typed or converted.
+//!
+//! Text format (UTF-8, tab separated):
+//!
+//! ```text
+//! #mapping<TAB>v1<TAB>kind=keigo
+//! <from><TAB><from reading><TAB><to><TAB><to reading><TAB><label>
+//! ```
+
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+const HEADER_MAGIC: &str = "#mapping";
+const FORMAT_VERSION: &str = "v1";
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mapping {
+    pub from: String,
+    pub from_reading: String,
+    pub to: String,
+    pub to_reading: String,
+    /// What kind of alternative `to` is, e.g. `honorific` or `humble`
+    pub label: String,
+}
+
+impl Mapping {
+    pub fn new(from: &str, from_reading: &str, to: &str, to_reading: &str, label: &str) -> Self {
+        Self {
+            from: from.to_string(),
+            from_reading: from_reading.to_string(),
+            to: to.to_string(),
+            to_reading: to_reading.to_string(),
+            label: label.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MappingTable {
+    /// What the table maps, e.g. `keigo`
+    pub kind: String,
+    /// Kept sorted so lookups by source word are range scans
+    entries: BTreeSet<Mapping>,
+}
+
+impl MappingTable {
+    pub fn new(kind: &str) -> Self {
+        Self {
+            kind: kind.to_string(),
+            entries: BTreeSet::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds a mapping unless an identical one is already present.
+    pub fn push(&mut self, mapping: Mapping) {
+        self.entries.insert(mapping);
+    }
+
+    /// Whether any mapping starts from `from`.
+    pub fn contains(&self, from: &str) -> bool {
+        self.lookup(from).next().is_some()
+    }
+
+    /// Mappings starting from `from`, sorted.
+    pub fn lookup<'a>(&'a self, from: &'a str) -> impl Iterator<Item = &'a Mapping> + 'a {
+        let start = Mapping::new(from, "", "", "", "");
+        self.entries.range(start..).take_while(move |m| m.from == from)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Mapping> {
+        self.entries.iter()
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().context("Empty mapping table")??;
+        let parts: Vec<&str> = header.split('\t').collect();
+        anyhow::ensure!(
+            parts.first() == Some(&HEADER_MAGIC),
+            "Not a mapping table (missing {} header)",
+            HEADER_MAGIC
+        );
+        anyhow::ensure!(
+            parts.get(1) == Some(&FORMAT_VERSION),
+            "Unsupported mapping table version {:?}",
+            parts.get(1)
+        );
+        let kind = parts[2..]
+            .iter()
+            .find_map(|field| field.strip_prefix("kind="))
+            .unwrap_or_default();
+
+        let mut table = Self::new(kind);
+        for (line_no, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            anyhow::ensure!(parts.len() == 5, "Line {}: expected 5 columns", line_no + 2);
+            table.push(Mapping::new(parts[0], parts[1], parts[2], parts[3], parts[4]));
+        }
+        Ok(table)
+    }
+
+    /// Writes the table sorted by source word so output is stable.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{}\t{}\tkind={}", HEADER_MAGIC, FORMAT_VERSION, self.kind)?;
+        for m in &self.entries {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                m.from, m.from_reading, m.to, m.to_reading, m.label
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+//! Schema of the keys stored in the N-gram FSTs.
+//!
+//! A key of order n is n non-empty tokens joined by single spaces, so it holds
+//! exactly n-1 separators. Tokens never contain the separator or control
+//! characters (tabs and newlines would also break the TSV formats that carry
+//! n-grams). Clients split keys on the separator and rely on this.
+
+use anyhow::{bail, Context, Result};
+
+pub const SEPARATOR: char = ' ';
+
+/// Whether `token` can appear in a key.
+pub fn is_valid_token(token: &str) -> bool {
+    !token.is_empty() && !token.chars().any(|c| c == SEPARATOR || c.is_control())
+}
+
+/// Orders a set of keys may have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeySchema {
+    pub min_order: usize,
+    pub max_order: usize,
+}
+
+impl KeySchema {
+    pub const fn new(min_order: usize, max_order: usize) -> Self {
+        Self { min_order, max_order }
+    }
+
+    /// Checks `key` against the schema and returns its order.
+    pub fn validate(&self, key: &[u8]) -> Result<usize> {
+        let key = std::str::from_utf8(key).with_context(|| format!("Key {:?} is not valid UTF-8", key))?;
+        let mut order = 0;
+        for token in key.split(SEPARATOR) {
+            if !is_valid_token(token) {
+                bail!("Key {:?} has an empty or invalid token {:?}", key, token);
+            }
+            order += 1;
+        }
+        if !(self.min_order..=self.max_order).contains(&order) {
+            bail!(
+                "Key {:?} has order {}, expected {}..={}",
+                key,
+                order,
+                self.min_order,
+                self.max_order
+            );
+        }
+        Ok(order)
+    }
+}
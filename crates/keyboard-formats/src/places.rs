@@ -0,0 +1,270 @@
+//! Place names for address entry, organised as prefecture → city → town.
+//!
+//! Text format (UTF-8, tab separated, readings in hiragana; the town columns
+//! are empty for entries that cover a whole city):
+//!
+//! ```text
+//! #places<TAB>v1
+//! <postal code><TAB><prefecture><TAB><reading><TAB><city><TAB><reading><TAB><town><TAB><reading>
+//! ```
+//!
+//! [`read_ken_all`] builds the table from Japan Post's KEN_ALL.CSV, which
+//! both input-data-gen `places` and mozc-dict-gen `--with-zipcode` read.
+
+use anyhow::{Context, Result};
+use std::io::{BufRead, Write};
+
+use crate::kana;
+
+const HEADER_MAGIC: &str = "#places";
+const FORMAT_VERSION: &str = "v1";
+
+/// A name with its reading.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Name {
+    pub surface: String,
+    pub reading: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Place {
+    /// Seven-digit postal code without a hyphen
+    pub postal_code: String,
+    pub prefecture: Name,
+    pub city: Name,
+    /// Empty when the entry covers the whole city
+    pub town: Name,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlaceTable {
+    places: Vec<Place>,
+}
+
+impl PlaceTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.places.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.places.is_empty()
+    }
+
+    pub fn push(&mut self, place: Place) {
+        self.places.push(place);
+    }
+
+    /// Sorts by postal code and drops exact duplicates.
+    pub fn normalize(&mut self) {
+        self.places.sort();
+        self.places.dedup();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Place> {
+        self.places.iter()
+    }
+
+    /// Places sharing a postal code (one code can cover several towns).
+    pub fn by_postal_code<'a>(&'a self, postal_code: &'a str) -> impl Iterator<Item = &'a Place> + 'a {
+        self.places.iter().filter(move |p| p.postal_code == postal_code)
+    }
+
+    /// Distinct prefectures in table order.
+    pub fn prefectures(&self) -> Vec<&Name> {
+        distinct(self.places.iter().map(|p| &p.prefecture))
+    }
+
+    /// Distinct cities of `prefecture` in table order.
+    pub fn cities(&self, prefecture: &str) -> Vec<&Name> {
+        distinct(
+            self.places
+                .iter()
+                .filter(|p| p.prefecture.surface == prefecture)
+                .map(|p| &p.city),
+        )
+    }
+
+    /// Distinct towns of `city` in `prefecture`, in table order.
+    pub fn towns(&self, prefecture: &str, city: &str) -> Vec<&Name> {
+        distinct(
+            self.places
+                .iter()
+                .filter(|p| p.prefecture.surface == prefecture && p.city.surface == city && !p.town.surface.is_empty())
+                .map(|p| &p.town),
+        )
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().context("Empty place table")??;
+        let parts: Vec<&str> = header.split('\t').collect();
+        anyhow::ensure!(
+            parts.first() == Some(&HEADER_MAGIC),
+            "Not a place table (missing {} header)",
+            HEADER_MAGIC
+        );
+        anyhow::ensure!(
+            parts.get(1) == Some(&FORMAT_VERSION),
+            "Unsupported place table version {:?}",
+            parts.get(1)
+        );
+
+        let mut table = Self::new();
+        for (line_no, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            anyhow::ensure!(parts.len() == 7, "Line {}: expected 7 columns", line_no + 2);
+            let name = |i: usize| Name {
+                surface: parts[i].to_string(),
+                reading: parts[i + 1].to_string(),
+            };
+            table.push(Place {
+                postal_code: parts[0].to_string(),
+                prefecture: name(1),
+                city: name(3),
+                town: name(5),
+            });
+        }
+        Ok(table)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{}\t{}", HEADER_MAGIC, FORMAT_VERSION)?;
+        for p in &self.places {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                p.postal_code,
+                p.prefecture.surface,
+                p.prefecture.reading,
+                p.city.surface,
+                p.city.reading,
+                p.town.surface,
+                p.town.reading
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Town column of KEN_ALL rows that stand for the whole city.
+const WHOLE_CITY_TOWNS: &[&str] = &["以下に掲載がない場合"];
+/// Suffixes of KEN_ALL towns that describe how addresses are numbered
+/// rather than naming a town (e.g. 大通西（１～１９丁目） is kept, but
+/// 〇〇の次に番地がくる場合 is not).
+const WHOLE_CITY_SUFFIXES: &[&str] = &["の次に番地がくる場合", "一円"];
+
+/// KEN_ALL.CSV columns.
+const CODE_COLUMN: usize = 2;
+const KANA_COLUMNS: [usize; 3] = [3, 4, 5];
+const NAME_COLUMNS: [usize; 3] = [6, 7, 8];
+
+/// Reads Japan Post's KEN_ALL.CSV, already decoded from Shift_JIS, into a
+/// normalized table.
+///
+/// Rows are cleaned as Mozc does: a town too long for one row continues on
+/// the next rows of the same code until its parenthesis closes and is
+/// joined, notes in parentheses are dropped, and placeholders for "the whole
+/// city" (以下に掲載がない場合, ...一円, ...の次に番地がくる場合) get an empty
+/// town. Readings, half-width katakana in KEN_ALL, are given in hiragana.
+pub fn read_ken_all(text: &str) -> Result<PlaceTable> {
+    let mut rows = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+
+    let mut table = PlaceTable::new();
+    let mut pending: Option<KenAllRow> = None;
+    for (row_no, record) in rows.records().enumerate() {
+        let record = record.with_context(|| format!("KEN_ALL row {}: unreadable", row_no + 1))?;
+        anyhow::ensure!(
+            record.len() > NAME_COLUMNS[2],
+            "KEN_ALL row {}: expected at least {} columns",
+            row_no + 1,
+            NAME_COLUMNS[2] + 1
+        );
+        let field = |column: usize| record.get(column).unwrap_or_default().trim().to_string();
+        let code = field(CODE_COLUMN);
+        if let Some(row) = &mut pending {
+            if row.code == code {
+                row.names[2].push_str(&field(NAME_COLUMNS[2]));
+                row.kana[2].push_str(&field(KANA_COLUMNS[2]));
+                if !row.is_continued() {
+                    table.push(pending.take().unwrap().place());
+                }
+                continue;
+            }
+            table.push(pending.take().unwrap().place());
+        }
+        let row = KenAllRow {
+            code,
+            names: NAME_COLUMNS.map(field),
+            kana: KANA_COLUMNS.map(field),
+        };
+        if row.is_continued() {
+            pending = Some(row);
+        } else {
+            table.push(row.place());
+        }
+    }
+    if let Some(row) = pending {
+        table.push(row.place());
+    }
+    table.normalize();
+    Ok(table)
+}
+
+/// A KEN_ALL row: prefecture, city and town names and their readings.
+struct KenAllRow {
+    code: String,
+    names: [String; 3],
+    kana: [String; 3],
+}
+
+impl KenAllRow {
+    /// Whether the town's note goes on in the next row.
+    fn is_continued(&self) -> bool {
+        self.names[2].contains('（') && !self.names[2].contains('）')
+    }
+
+    fn place(self) -> Place {
+        let [prefecture, city, town] = self.names;
+        let [prefecture_kana, city_kana, town_kana] = self.kana;
+        let whole_city = WHOLE_CITY_TOWNS.contains(&town.as_str())
+            || WHOLE_CITY_SUFFIXES.iter().any(|suffix| town.ends_with(suffix) && town != *suffix);
+        let name = |surface: &str, reading: &str| Name {
+            surface: surface.to_string(),
+            reading: kana::to_hiragana(reading),
+        };
+        let without_note = |text: &str, open: char| text.split(open).next().unwrap_or_default().to_string();
+        Place {
+            postal_code: self.code,
+            prefecture: name(&prefecture, &prefecture_kana),
+            city: name(&city, &city_kana),
+            town: if whole_city {
+                Name::default()
+            } else {
+                name(&without_note(&town, '（'), &without_note(&town_kana, '('))
+            },
+        }
+    }
+}
+
+fn distinct<'a>(names: impl Iterator<Item = &'a Name>) -> Vec<&'a Name> {
+    let mut seen = Vec::new();
+    for name in names {
+        if !seen.contains(&name) {
+            seen.push(name);
+        }
+    }
+    seen
+}
@@ -0,0 +1,81 @@
+//! Readings by surface (mozc-dict-gen `--reverse-lookup` writes them to
+//! `reverse.fst`), for reconversion (再変換) of text already committed:
+//! the keyboard looks the selected words up, offers their readings and
+//! converts those again.
+//!
+//! FST keys are `<surface> <reading>`, valued by the lowest lexicon cost of
+//! the pair, so the likeliest reading sorts first. FST values are unsigned
+//! and common words have negative costs, so costs are stored offset by
+//! `i64::MIN`, which keeps their order. Surfaces may contain
+//! spaces and readings never do, so the reading is everything after the
+//! last separator.
+
+use anyhow::Result;
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::ngram_key::SEPARATOR;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Reading {
+    pub reading: String,
+    /// Lexicon cost; the lower, the likelier
+    pub cost: i64,
+}
+
+pub struct ReverseLookup<D: AsRef<[u8]>> {
+    readings: Map<D>,
+}
+
+impl<D: AsRef<[u8]>> ReverseLookup<D> {
+    pub fn new(readings: Map<D>) -> Self {
+        Self { readings }
+    }
+
+    /// Readings of `surface`, cheapest first.
+    pub fn readings(&self, surface: &str) -> Vec<Reading> {
+        let prefix = format!("{}{}", surface, SEPARATOR);
+        let mut stream = self.readings.search(Str::new(&prefix).starts_with()).into_stream();
+        let mut readings = Vec::new();
+        while let Some((key, cost)) = stream.next() {
+            let reading = String::from_utf8_lossy(&key[prefix.len()..]);
+            // A longer surface that starts with this one and a space
+            if !reading.contains(SEPARATOR) {
+                readings.push(Reading {
+                    reading: reading.into_owned(),
+                    cost: decode_cost(cost),
+                });
+            }
+        }
+        readings.sort_by(|a, b| a.cost.cmp(&b.cost).then_with(|| a.reading.cmp(&b.reading)));
+        readings
+    }
+}
+
+/// Writes (surface, reading, cost) entries as a reverse-lookup FST. A pair
+/// given more than once keeps its lowest cost. Returns the number of keys.
+pub fn write_fst<'a>(entries: impl IntoIterator<Item = (&'a str, &'a str, i64)>, writer: impl Write) -> Result<usize> {
+    let mut costs: BTreeMap<String, i64> = BTreeMap::new();
+    for (surface, reading, cost) in entries {
+        let key = format!("{}{}{}", surface, SEPARATOR, reading);
+        costs.entry(key).and_modify(|lowest| *lowest = (*lowest).min(cost)).or_insert(cost);
+    }
+    let mut builder = MapBuilder::new(writer)?;
+    for (key, cost) in &costs {
+        builder.insert(key, encode_cost(*cost))?;
+    }
+    builder.finish()?;
+    Ok(costs.len())
+}
+
+/// `cost - i64::MIN`: every cost as an FST value, in the same order.
+fn encode_cost(cost: i64) -> u64 {
+    cost.wrapping_sub(i64::MIN) as u64
+}
+
+fn decode_cost(value: u64) -> i64 {
+    (value as i64).wrapping_add(i64::MIN)
+}
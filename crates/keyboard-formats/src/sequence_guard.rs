@@ -0,0 +1,54 @@
+//! Keeps next-word prediction from reproducing long stretches of the corpus
+//! the N-gram FST was built from.
+//!
+//! Each prediction only extends the text by one n-gram, but accepting
+//! predictions one after another can chain overlapping n-grams back into a
+//! long verbatim passage. The guard measures how many trailing words of the
+//! text plus a candidate are covered by stored n-grams end to end, every
+//! window of the model's order being a key of the FST, and rejects the
+//! candidate when that run would exceed a limit.
+
+use fst::Map;
+
+use crate::ngram_key::SEPARATOR;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SequenceGuard {
+    /// Highest order of the FST keys (wiki-ngram's `--max-ngram`)
+    pub max_order: usize,
+    /// Longest run of stored words a prediction may complete
+    pub max_words: usize,
+}
+
+impl SequenceGuard {
+    pub const DEFAULT_MAX_WORDS: usize = 8;
+
+    pub const fn new(max_order: usize, max_words: usize) -> Self {
+        Self { max_order, max_words }
+    }
+
+    /// Whether predicting `word` after `context` keeps the stored run within
+    /// the limit.
+    pub fn allows<D: AsRef<[u8]>>(&self, ngrams: &Map<D>, context: &[&str], word: &str) -> bool {
+        // Only the last max_words + 1 words can take the run over the limit
+        let start = context.len().saturating_sub(self.max_words);
+        let mut words = context[start..].to_vec();
+        words.push(word);
+        self.reproduced_run(ngrams, &words) <= self.max_words
+    }
+
+    /// Length of the longest suffix of `words` whose n-gram windows are all
+    /// stored in `ngrams`; 0 if not even the last two words are.
+    pub fn reproduced_run<D: AsRef<[u8]>>(&self, ngrams: &Map<D>, words: &[&str]) -> usize {
+        let separator = SEPARATOR.to_string();
+        let mut run = 0;
+        for start in (0..words.len().saturating_sub(1)).rev() {
+            let end = (start + self.max_order.max(2)).min(words.len());
+            if !ngrams.contains_key(words[start..end].join(&separator)) {
+                break;
+            }
+            run = words.len() - start;
+        }
+        run
+    }
+}
@@ -0,0 +1,59 @@
+//! Single-kanji candidates by reading (mozc-dict-gen `--single-kanji`), for
+//! offering one kanji at a time when conversion finds no word.
+//!
+//! FST keys are `<reading> <kanji>`, valued by the kanji's rank among the
+//! reading's candidates (0 first), in the order of Mozc's single_kanji.tsv.
+
+use anyhow::Result;
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::io::Write;
+
+use crate::ngram_key::SEPARATOR;
+
+pub struct SingleKanji<D: AsRef<[u8]>> {
+    kanji: Map<D>,
+}
+
+impl<D: AsRef<[u8]>> SingleKanji<D> {
+    pub fn new(kanji: Map<D>) -> Self {
+        Self { kanji }
+    }
+
+    /// Kanji read as `reading`, best first.
+    pub fn candidates(&self, reading: &str) -> Vec<String> {
+        let prefix = format!("{}{}", reading, SEPARATOR);
+        let mut stream = self.kanji.search(Str::new(&prefix).starts_with()).into_stream();
+        let mut candidates = Vec::new();
+        while let Some((key, rank)) = stream.next() {
+            candidates.push((rank, String::from_utf8_lossy(&key[prefix.len()..]).into_owned()));
+        }
+        candidates.sort();
+        candidates.into_iter().map(|(_, kanji)| kanji).collect()
+    }
+}
+
+/// Writes (reading, kanji in rank order) pairs as a single-kanji FST; a
+/// reading given more than once continues its ranking. Returns the number
+/// of keys.
+pub fn write_fst<'a>(readings: impl IntoIterator<Item = (&'a str, &'a str)>, writer: impl Write) -> Result<usize> {
+    let mut ranks: BTreeMap<String, u64> = BTreeMap::new();
+    let mut next_rank: BTreeMap<&str, u64> = BTreeMap::new();
+    for (reading, kanji) in readings {
+        for c in kanji.chars() {
+            let rank = next_rank.entry(reading).or_insert(0);
+            let key = format!("{}{}{}", reading, SEPARATOR, c);
+            if let Entry::Vacant(entry) = ranks.entry(key) {
+                entry.insert(*rank);
+                *rank += 1;
+            }
+        }
+    }
+    let mut builder = MapBuilder::new(writer)?;
+    for (key, rank) in &ranks {
+        builder.insert(key, *rank)?;
+    }
+    builder.finish()?;
+    Ok(ranks.len())
+}
@@ -0,0 +1,61 @@
+//! Words keyboards must not offer, from Mozc's suggestion_filter.txt and
+//! suppression_word.txt (mozc-dict-gen writes them to `suppression.fst`).
+//!
+//! The FST maps a surface, lowercased as Mozc matches the suggestion filter,
+//! to flags saying how far it is kept out:
+//!
+//! ```text
+//! SUGGESTION_FILTERED  never predicted, still converted to when typed out
+//! SUPPRESSED           never offered at all
+//! ```
+
+use anyhow::Result;
+use fst::{Map, MapBuilder};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Listed in suggestion_filter.txt: not offered as a prediction.
+pub const SUGGESTION_FILTERED: u64 = 1;
+
+/// Listed in suppression_word.txt: not offered as any candidate.
+pub const SUPPRESSED: u64 = 2;
+
+pub struct SuppressionList<D: AsRef<[u8]>> {
+    words: Map<D>,
+}
+
+impl<D: AsRef<[u8]>> SuppressionList<D> {
+    pub fn new(words: Map<D>) -> Self {
+        Self { words }
+    }
+
+    /// Flags of `surface`; 0 if it isn't listed.
+    pub fn flags(&self, surface: &str) -> u64 {
+        self.words.get(surface.to_lowercase()).unwrap_or(0)
+    }
+
+    /// Whether `surface` may be offered as a prediction.
+    pub fn allows_prediction(&self, surface: &str) -> bool {
+        self.flags(surface) == 0
+    }
+
+    /// Whether `surface` may be offered as a conversion candidate.
+    pub fn allows_candidate(&self, surface: &str) -> bool {
+        self.flags(surface) & SUPPRESSED == 0
+    }
+}
+
+/// Writes (surface, flag) pairs as a suppression FST, merging the flags of
+/// surfaces listed more than once; returns the number of surfaces.
+pub fn write_fst(words: impl IntoIterator<Item = (String, u64)>, writer: impl Write) -> Result<usize> {
+    let mut merged = BTreeMap::new();
+    for (surface, flags) in words {
+        *merged.entry(surface.to_lowercase()).or_insert(0) |= flags;
+    }
+    let mut builder = MapBuilder::new(writer)?;
+    for (surface, flags) in &merged {
+        builder.insert(surface, *flags)?;
+    }
+    builder.finish()?;
+    Ok(merged.len())
+}
@@ -0,0 +1,81 @@
+//! Symbol and kaomoji candidates by reading, from Mozc's symbol.tsv and
+//! emoticon.tsv (mozc-dict-gen writes them to `symbols.fst`), for candidate
+//! lists such as やじるし → →←↑↓ or かお → (^_^).
+//!
+//! FST keys are `<reading> <text>`; the text may itself contain spaces, as
+//! kaomoji often do, so it is everything after the first separator. Values
+//! are the candidate's rank among the reading's candidates (0 first, in
+//! source order) shifted left by one, with the low bit set for kaomoji.
+
+use anyhow::Result;
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::io::Write;
+
+use crate::ngram_key::SEPARATOR;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum SymbolKind {
+    /// From symbol.tsv: arrows, brackets, units, Greek letters...
+    Symbol,
+    /// From emoticon.tsv
+    Kaomoji,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Symbol {
+    pub text: String,
+    pub kind: SymbolKind,
+}
+
+pub struct Symbols<D: AsRef<[u8]>> {
+    symbols: Map<D>,
+}
+
+impl<D: AsRef<[u8]>> Symbols<D> {
+    pub fn new(symbols: Map<D>) -> Self {
+        Self { symbols }
+    }
+
+    /// Symbols read as `reading`, best first.
+    pub fn candidates(&self, reading: &str) -> Vec<Symbol> {
+        let prefix = format!("{}{}", reading, SEPARATOR);
+        let mut stream = self.symbols.search(Str::new(&prefix).starts_with()).into_stream();
+        let mut candidates = Vec::new();
+        while let Some((key, value)) = stream.next() {
+            let kind = if value & 1 == 1 { SymbolKind::Kaomoji } else { SymbolKind::Symbol };
+            let text = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+            candidates.push((value >> 1, Symbol { text, kind }));
+        }
+        candidates.sort_by_key(|(rank, _)| *rank);
+        candidates.into_iter().map(|(_, symbol)| symbol).collect()
+    }
+}
+
+/// Writes (reading, text, kind) entries, in rank order per reading, as a
+/// symbol FST. A text listed twice under a reading keeps its first rank.
+/// Returns the number of keys.
+pub fn write_fst<'a>(
+    entries: impl IntoIterator<Item = (&'a str, &'a str, SymbolKind)>,
+    writer: impl Write,
+) -> Result<usize> {
+    let mut values: BTreeMap<String, u64> = BTreeMap::new();
+    let mut next_rank: BTreeMap<&str, u64> = BTreeMap::new();
+    for (reading, text, kind) in entries {
+        let rank = next_rank.entry(reading).or_insert(0);
+        if let Entry::Vacant(entry) = values.entry(format!("{}{}{}", reading, SEPARATOR, text)) {
+            entry.insert(*rank << 1 | u64::from(kind == SymbolKind::Kaomoji));
+            *rank += 1;
+        }
+    }
+    let mut builder = MapBuilder::new(writer)?;
+    for (key, value) in &values {
+        builder.insert(key, *value)?;
+    }
+    builder.finish()?;
+    Ok(values.len())
+}
@@ -0,0 +1,106 @@
+//! Units text lengths are measured in, so length limits mean the same for
+//! Japanese as for ASCII: `東京` is 6 bytes but 2 characters, and が written
+//! as か and a combining voiced mark (U+304B U+3099) is 2 characters but one
+//! cluster, the letter a user sees.
+//!
+//! Clusters follow extended grapheme clusters for what Japanese text meets
+//! (combining and half-width voiced marks, variation selectors such as the
+//! ideographic ones of 葛 U+E0100, emoji modifiers and ZWJ sequences, flag
+//! pairs, CRLF) without the full Unicode segmentation tables.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum LengthUnit {
+    /// UTF-8 bytes
+    Bytes,
+    /// Unicode scalar values
+    #[default]
+    Chars,
+    /// Grapheme clusters, approximated as above
+    Clusters,
+}
+
+impl LengthUnit {
+    pub fn length(self, text: &str) -> usize {
+        match self {
+            LengthUnit::Bytes => text.len(),
+            LengthUnit::Chars => text.chars().count(),
+            LengthUnit::Clusters => clusters(text),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LengthUnit::Bytes => "bytes",
+            LengthUnit::Chars => "chars",
+            LengthUnit::Clusters => "clusters",
+        }
+    }
+}
+
+impl FromStr for LengthUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "bytes" => Ok(LengthUnit::Bytes),
+            "chars" => Ok(LengthUnit::Chars),
+            "clusters" => Ok(LengthUnit::Clusters),
+            _ => Err(format!("expected bytes, chars or clusters, got '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for LengthUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+fn clusters(text: &str) -> usize {
+    let mut count = 0;
+    let mut previous: Option<char> = None;
+    // Regional indicators seen in a row; every second one ends a flag
+    let mut indicators = 0;
+    for c in text.chars() {
+        let joins = match previous {
+            None => false,
+            Some('\u{200d}') => true,
+            Some('\r') => c == '\n',
+            Some(_) => extends(c) || (is_regional_indicator(c) && indicators % 2 == 1),
+        };
+        indicators = if is_regional_indicator(c) { indicators + 1 } else { 0 };
+        if !joins {
+            count += 1;
+        }
+        previous = Some(c);
+    }
+    count
+}
+
+/// Characters that attach to the one before them.
+fn extends(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036f}'
+            | '\u{1ab0}'..='\u{1aff}'
+            | '\u{1dc0}'..='\u{1dff}'
+            | '\u{200c}'..='\u{200d}'
+            | '\u{20d0}'..='\u{20ff}'
+            | '\u{3099}'..='\u{309a}'
+            | '\u{fe00}'..='\u{fe0f}'
+            | '\u{fe20}'..='\u{fe2f}'
+            | '\u{ff9e}'..='\u{ff9f}'
+            | '\u{1f3fb}'..='\u{1f3ff}'
+            | '\u{e0020}'..='\u{e007f}'
+            | '\u{e0100}'..='\u{e01ef}'
+    )
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1f1e6}'..='\u{1f1ff}').contains(&c)
+}
@@ -0,0 +1,231 @@
+//! User dictionary words, synced across a user's devices.
+//!
+//! Every entry is a last-writer-wins register keyed by (reading, surface).
+//! Deletions are kept as tombstones so they propagate instead of being
+//! resurrected by a device that still has the word. Merging is commutative,
+//! associative and idempotent, so devices can exchange whole files in any
+//! order and converge: entries with the same stamp are told apart by their
+//! contents. A local edit is stamped after the entry it replaces, even
+//! within the same second, so it is never lost to an earlier one.
+//!
+//! Text format (UTF-8, tab separated):
+//!
+//! ```text
+//! #user-dictionary<TAB>v1
+//! <reading><TAB><surface><TAB><pos><TAB><updated_at unix secs><TAB><device><TAB><0|1 deleted>
+//! ```
+
+use anyhow::{Context, Result};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+const HEADER_MAGIC: &str = "#user-dictionary";
+const FORMAT_VERSION: &str = "v1";
+
+/// Version stamp of an entry. Later timestamps win; the device ID breaks ties
+/// so that every replica picks the same winner.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stamp {
+    pub updated_at: u64,
+    pub device: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DictionaryEntry {
+    pub pos: String,
+    pub stamp: Stamp,
+    pub deleted: bool,
+}
+
+/// Words the user registered, keyed by (reading, surface).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserDictionary {
+    entries: HashMap<(String, String), DictionaryEntry>,
+}
+
+impl UserDictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of live (non-deleted) words.
+    pub fn len(&self) -> usize {
+        self.words().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds or updates a word on `device` at time `now`.
+    pub fn upsert(&mut self, reading: &str, surface: &str, pos: &str, device: &str, now: u64) {
+        let stamp = self.next_stamp(reading, surface, device, now);
+        self.apply(
+            reading,
+            surface,
+            DictionaryEntry {
+                pos: pos.to_string(),
+                stamp,
+                deleted: false,
+            },
+        );
+    }
+
+    /// Deletes a word on `device` at time `now`, leaving a tombstone.
+    pub fn remove(&mut self, reading: &str, surface: &str, device: &str, now: u64) {
+        let pos = self
+            .entries
+            .get(&(reading.to_string(), surface.to_string()))
+            .map(|entry| entry.pos.clone())
+            .unwrap_or_default();
+        let stamp = self.next_stamp(reading, surface, device, now);
+        self.apply(
+            reading,
+            surface,
+            DictionaryEntry {
+                pos,
+                stamp,
+                deleted: true,
+            },
+        );
+    }
+
+    /// Stamp of a local edit at `now`: `now`, or a second after the entry it
+    /// replaces if that one is as recent, so the edit always supersedes it.
+    fn next_stamp(&self, reading: &str, surface: &str, device: &str, now: u64) -> Stamp {
+        let updated_at = match self.entries.get(&(reading.to_string(), surface.to_string())) {
+            Some(current) => now.max(current.stamp.updated_at + 1),
+            None => now,
+        };
+        Stamp {
+            updated_at,
+            device: device.to_string(),
+        }
+    }
+
+    /// Keeps whichever of the current and incoming entry has the later stamp.
+    /// An exact stamp tie goes to the greater `(deleted, pos)`, so a deletion
+    /// wins and every replica keeps the same entry whatever the merge order.
+    fn apply(&mut self, reading: &str, surface: &str, incoming: DictionaryEntry) {
+        match self.entries.get_mut(&(reading.to_string(), surface.to_string())) {
+            Some(current) => {
+                let newer = match incoming.stamp.cmp(&current.stamp) {
+                    Ordering::Greater => true,
+                    Ordering::Equal => (incoming.deleted, &incoming.pos) > (current.deleted, &current.pos),
+                    Ordering::Less => false,
+                };
+                if newer {
+                    *current = incoming;
+                }
+            }
+            None => {
+                self.entries.insert((reading.to_string(), surface.to_string()), incoming);
+            }
+        }
+    }
+
+    /// Live entry for a word, if it exists and is not deleted.
+    pub fn get(&self, reading: &str, surface: &str) -> Option<&DictionaryEntry> {
+        self.entries
+            .get(&(reading.to_string(), surface.to_string()))
+            .filter(|entry| !entry.deleted)
+    }
+
+    /// Folds another replica into this one.
+    pub fn merge(&mut self, other: &UserDictionary) {
+        for ((reading, surface), entry) in &other.entries {
+            self.apply(reading, surface, entry.clone());
+        }
+    }
+
+    /// Drops tombstones last written before `before`. Only safe once every
+    /// device has synced past that time. Returns the number removed.
+    pub fn purge_tombstones(&mut self, before: u64) -> usize {
+        let count = self.entries.len();
+        self.entries
+            .retain(|_, entry| !entry.deleted || entry.stamp.updated_at >= before);
+        count - self.entries.len()
+    }
+
+    /// Live words as (reading, surface, entry).
+    pub fn words(&self) -> impl Iterator<Item = (&str, &str, &DictionaryEntry)> {
+        self.iter().filter(|(_, _, entry)| !entry.deleted)
+    }
+
+    /// All entries including tombstones.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, &DictionaryEntry)> {
+        self.entries
+            .iter()
+            .map(|((reading, surface), entry)| (reading.as_str(), surface.as_str(), entry))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().context("Empty user dictionary")??;
+        let parts: Vec<&str> = header.split('\t').collect();
+        anyhow::ensure!(
+            parts.first() == Some(&HEADER_MAGIC),
+            "Not a user dictionary file (missing {} header)",
+            HEADER_MAGIC
+        );
+        anyhow::ensure!(
+            parts.get(1) == Some(&FORMAT_VERSION),
+            "Unsupported user dictionary version {:?}",
+            parts.get(1)
+        );
+
+        let mut dictionary = Self::new();
+        for (line_no, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            anyhow::ensure!(parts.len() == 6, "Line {}: expected 6 columns", line_no + 2);
+            let updated_at: u64 = parts[3]
+                .parse()
+                .with_context(|| format!("Line {}: bad timestamp", line_no + 2))?;
+            let deleted = match parts[5] {
+                "0" => false,
+                "1" => true,
+                other => anyhow::bail!("Line {}: bad deleted flag '{}'", line_no + 2, other),
+            };
+            dictionary.apply(
+                parts[0],
+                parts[1],
+                DictionaryEntry {
+                    pos: parts[2].to_string(),
+                    stamp: Stamp {
+                        updated_at,
+                        device: parts[4].to_string(),
+                    },
+                    deleted,
+                },
+            );
+        }
+        Ok(dictionary)
+    }
+
+    /// Writes all entries, tombstones included, sorted by reading and surface.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{}\t{}", HEADER_MAGIC, FORMAT_VERSION)?;
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        for (reading, surface, entry) in entries {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                reading,
+                surface,
+                entry.pos,
+                entry.stamp.updated_at,
+                entry.stamp.device,
+                if entry.deleted { 1 } else { 0 }
+            )?;
+        }
+        Ok(())
+    }
+}
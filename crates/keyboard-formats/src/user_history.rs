@@ -0,0 +1,201 @@
+//! User personalization history: which candidates the user picked for a
+//! reading, with scores that decay over time so stale words lose rank.
+//!
+//! Text format (UTF-8, tab separated):
+//!
+//! ```text
+//! #user-history<TAB>v1<TAB>half_life_secs=604800
+//! <reading><TAB><surface><TAB><score><TAB><last_used unix secs>
+//! ```
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+const HEADER_MAGIC: &str = "#user-history";
+const FORMAT_VERSION: &str = "v1";
+
+/// Exponential time decay of usage scores.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Decay {
+    /// Time after which a score has dropped to half its value
+    pub half_life_secs: u64,
+}
+
+impl Decay {
+    /// One week, a common default for keyboard personalization.
+    pub const DEFAULT: Decay = Decay {
+        half_life_secs: 7 * 24 * 60 * 60,
+    };
+
+    /// Decays `score` last updated at `since` to its value at `now`.
+    pub fn apply(&self, score: f64, since: u64, now: u64) -> f64 {
+        if self.half_life_secs == 0 || now <= since {
+            return score;
+        }
+        let elapsed = (now - since) as f64;
+        score * 0.5f64.powf(elapsed / self.half_life_secs as f64)
+    }
+}
+
+impl Default for Decay {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HistoryEntry {
+    /// Score as of `last_used`; decayed lazily when read
+    pub score: f64,
+    pub last_used: u64,
+}
+
+/// Candidates the user committed, keyed by (reading, surface).
+#[derive(Clone, Debug, Default)]
+pub struct UserHistory {
+    decay: Decay,
+    entries: HashMap<(String, String), HistoryEntry>,
+}
+
+impl UserHistory {
+    pub fn new(decay: Decay) -> Self {
+        Self {
+            decay,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn decay(&self) -> Decay {
+        self.decay
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Records one use of `surface` for `reading` at time `now`.
+    pub fn record(&mut self, reading: &str, surface: &str, now: u64) {
+        self.add(reading, surface, 1.0, now);
+    }
+
+    fn add(&mut self, reading: &str, surface: &str, score: f64, at: u64) {
+        let decay = self.decay;
+        let entry = self
+            .entries
+            .entry((reading.to_string(), surface.to_string()))
+            .or_insert(HistoryEntry { score: 0.0, last_used: at });
+        // Bring both scores to the later timestamp before adding them up
+        let latest = entry.last_used.max(at);
+        entry.score = decay.apply(entry.score, entry.last_used, latest) + decay.apply(score, at, latest);
+        entry.last_used = latest;
+    }
+
+    /// Decayed score of an entry at time `now`.
+    pub fn score(&self, reading: &str, surface: &str, now: u64) -> Option<f64> {
+        self.entries
+            .get(&(reading.to_string(), surface.to_string()))
+            .map(|entry| self.decay.apply(entry.score, entry.last_used, now))
+    }
+
+    /// Surfaces recorded for `reading`, highest decayed score first.
+    pub fn ranked(&self, reading: &str, now: u64) -> Vec<(&str, f64)> {
+        let mut ranked: Vec<(&str, f64)> = self
+            .entries
+            .iter()
+            .filter(|((r, _), _)| r == reading)
+            .map(|((_, surface), entry)| {
+                (surface.as_str(), self.decay.apply(entry.score, entry.last_used, now))
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        ranked
+    }
+
+    /// Folds `other` into this history. Scores for the same entry are decayed
+    /// to the later of the two timestamps and summed.
+    pub fn merge(&mut self, other: &UserHistory) {
+        for ((reading, surface), entry) in &other.entries {
+            // Re-express the other side's score under this history's decay
+            self.add(reading, surface, entry.score, entry.last_used);
+        }
+    }
+
+    /// Drops entries whose decayed score at `now` fell below `min_score`.
+    /// Returns the number of entries removed.
+    pub fn prune(&mut self, now: u64, min_score: f64) -> usize {
+        let decay = self.decay;
+        let before = self.entries.len();
+        self.entries
+            .retain(|_, entry| decay.apply(entry.score, entry.last_used, now) >= min_score);
+        before - self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, &HistoryEntry)> {
+        self.entries
+            .iter()
+            .map(|((reading, surface), entry)| (reading.as_str(), surface.as_str(), entry))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().context("Empty user history")??;
+        let decay = parse_header(&header)?;
+
+        let mut history = Self::new(decay);
+        for (line_no, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            anyhow::ensure!(parts.len() == 4, "Line {}: expected 4 columns", line_no + 2);
+            let score: f64 = parts[2].parse().with_context(|| format!("Line {}: bad score", line_no + 2))?;
+            let last_used: u64 = parts[3].parse().with_context(|| format!("Line {}: bad timestamp", line_no + 2))?;
+            history.add(parts[0], parts[1], score, last_used);
+        }
+        Ok(history)
+    }
+
+    /// Writes the history sorted by reading and surface so output is stable.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(
+            writer,
+            "{}\t{}\thalf_life_secs={}",
+            HEADER_MAGIC, FORMAT_VERSION, self.decay.half_life_secs
+        )?;
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        for (reading, surface, entry) in entries {
+            writeln!(writer, "{}\t{}\t{}\t{}", reading, surface, entry.score, entry.last_used)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_header(header: &str) -> Result<Decay> {
+    let parts: Vec<&str> = header.split('\t').collect();
+    anyhow::ensure!(
+        parts.first() == Some(&HEADER_MAGIC),
+        "Not a user history file (missing {} header)",
+        HEADER_MAGIC
+    );
+    anyhow::ensure!(
+        parts.get(1) == Some(&FORMAT_VERSION),
+        "Unsupported user history version {:?}",
+        parts.get(1)
+    );
+    let mut decay = Decay::default();
+    for field in &parts[2..] {
+        if let Some(value) = field.strip_prefix("half_life_secs=") {
+            decay.half_life_secs = value.parse().context("Bad half_life_secs")?;
+        }
+    }
+    Ok(decay)
+}
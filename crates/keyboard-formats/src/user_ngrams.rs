@@ -0,0 +1,32 @@
+//! Per-user n-gram counts uploaded by keyboard clients for aggregation.
+//!
+//! One file per user and no user identifiers inside. Each line is
+//! `<space-joined n-gram><TAB><count>`; lines starting with `#` are ignored.
+
+use anyhow::{Context, Result};
+use std::io::{BufRead, Write};
+
+pub fn read_user_ngram_counts<R: BufRead>(reader: R) -> Result<Vec<(String, u32)>> {
+    let mut counts = Vec::new();
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (ngram, count) = line
+            .rsplit_once('\t')
+            .with_context(|| format!("Line {}: expected <ngram>\\t<count>", line_no + 1))?;
+        let count: u32 = count
+            .parse()
+            .with_context(|| format!("Line {}: bad count '{}'", line_no + 1, count))?;
+        counts.push((ngram.to_string(), count));
+    }
+    Ok(counts)
+}
+
+pub fn write_user_ngram_counts<W: Write>(mut writer: W, counts: &[(String, u32)]) -> Result<()> {
+    for (ngram, count) in counts {
+        writeln!(writer, "{}\t{}", ngram, count)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,30 @@
+//! Records the Vibrato the dictionaries are compiled with as
+//! `VIBRATO_VERSION`, for the `tools` of manifest.json: whether an app can
+//! load system.dic depends on it. It comes from the workspace's Cargo.lock,
+//! with the commit of a git checkout, and is `unknown` without one.
+
+use std::path::Path;
+
+fn main() {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let lock = Path::new(&manifest_dir).join("../../Cargo.lock");
+    println!("cargo:rerun-if-changed={}", lock.display());
+    let version = std::fs::read_to_string(&lock).ok().and_then(|lock| vibrato_version(&lock));
+    println!("cargo:rustc-env=VIBRATO_VERSION={}", version.as_deref().unwrap_or("unknown"));
+}
+
+/// The version of the vibrato package in `lock`, and its commit if it comes
+/// from git (`0.5.2 (git 1a2b3c4d5e6f)`).
+fn vibrato_version(lock: &str) -> Option<String> {
+    let package = lock
+        .split("[[package]]")
+        .find(|package| package.lines().any(|line| line.trim() == "name = \"vibrato\""))?;
+    let field = |name: &str| {
+        package.lines().find_map(|line| line.trim().strip_prefix(name)?.strip_prefix(" = \"")?.strip_suffix('"'))
+    };
+    let version = field("version")?;
+    match field("source").and_then(|source| source.strip_prefix("git+")?.rsplit_once('#')) {
+        Some((_, commit)) => Some(format!("{} (git {})", version, &commit[..commit.len().min(12)])),
+        None => Some(version.to_string()),
+    }
+}
@@ -0,0 +1,1117 @@
+//! Whole dictionary builds, as `mozc-dict-gen` runs them without a
+//! subcommand: fetch the sources, convert, compile, write the manifests and
+//! clean up. Other crates and build scripts use [`Builder`] instead of
+//! running the binary:
+//!
+//! ```no_run
+//! use mozc_dict_gen::{Builder, Source};
+//!
+//! let output = Builder::new(Source::Mozc).source_dir("../mozc").output_dir("dict").build()?;
+//! println!("Dictionary at {:?}", output.dictionary);
+//! # anyhow::Ok(())
+//! ```
+//!
+//! Nothing is printed: progress and warnings are `tracing` events, so a
+//! build script stays quiet unless it installs a subscriber. Each stage runs
+//! in a tracing span (`convert`, `prune`, `compile`, `smoke_test`,
+//! `manifests`, `stats`, `cleanup`) inside a `build` span, for timing long
+//! builds.
+
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::bundle::{self, Artifact, BundleManifest};
+use crate::conversion;
+use crate::download;
+use crate::export;
+use crate::extra_lexicon::PosMap;
+use crate::cost_rules::CostRules;
+use crate::features::{FeatureConversion, FeatureLayout, ReadingScript};
+use crate::lindera;
+use crate::mecab;
+use crate::pos_filter::PosFilter;
+use crate::prune::{self, EntryBudget};
+use crate::reverse;
+use crate::single_kanji;
+use crate::smoke;
+use crate::sqlite;
+use crate::stats::DictStats;
+use crate::sudachi::SudachiSources;
+use crate::suppression;
+use crate::symbols;
+use crate::zipcode;
+use crate::unidic::UniDicSources;
+use crate::{Compression, ConnectionMatrix, DictPipeline, DictSource, Input, MozcSources, Pronunciation, Report};
+use crate::VibratoSources;
+use crate::{CONNECTION_FILES, INTERMEDIATE_FILES};
+
+const MOZC_REPO_URL: &str = "https://github.com/google/mozc/archive/refs/heads/master.tar.gz";
+
+/// Archive of a tag or commit; GitHub resolves either from the ref name.
+const MOZC_ARCHIVE_URL: &str = "https://github.com/google/mozc/archive";
+
+/// Records what a dictionary was built from, next to system.dic.zst.
+pub const MANIFEST_FILE: &str = "mozc-dict-manifest.tsv";
+const MANIFEST_MAGIC: &str = "#mozc-dict-manifest";
+
+/// Where kept sources read from the Mozc archive are written.
+const SOURCES_DIR: &str = "mozc_src";
+
+/// UniDic for contemporary written Japanese, with the CSV sources.
+const UNIDIC_URL: &str = "https://clrd.ninjal.ac.jp/unidic_archive/cwj/3.1.1/unidic-cwj-3.1.1-full.zip";
+
+/// Where the UniDic sources are unpacked; too large to convert from memory.
+const UNIDIC_SOURCES_DIR: &str = "unidic_src";
+
+/// Raw SudachiDict releases: matrix.def.zip, and <version>/<edition>_lex.zip.
+const SUDACHI_RAW_URL: &str = "https://d2ej7fkh96fzlu.cloudfront.net/sudachidict-raw";
+
+/// Where the SudachiDict sources are unpacked.
+const SUDACHI_SOURCES_DIR: &str = "sudachi_src";
+
+/// Where the dictionary sources live inside a Mozc checkout.
+const MOZC_DICTIONARY_DIRS: &[&str] = &["src/data/dictionary_oss", "data/dictionary_oss"];
+
+/// Dictionary the Vibrato dictionary is converted from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Source {
+    /// Google Mozc's dictionary_oss
+    #[default]
+    Mozc,
+    /// UniDic for contemporary written Japanese (unidic-cwj), segmenting into
+    /// UniDic's short units
+    Unidic,
+    /// SudachiDict-core, with POS renamed to Mozc's
+    Sudachi,
+}
+
+/// What the compiled dictionaries are keyed by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LexiconKey {
+    /// Surface forms, for tokenizing text (system.dic.zst)
+    #[default]
+    Surface,
+    /// Also build conversion.dic.zst keyed by kana readings, for kana→kanji
+    /// conversion
+    Reading,
+}
+
+/// Options of a dictionary build; see the `mozc-dict-gen` flags of the same
+/// names for what each does.
+#[derive(Clone, Debug)]
+pub struct Builder {
+    source: Source,
+    source_dir: Option<PathBuf>,
+    mozc_ref: Option<String>,
+    sudachi_version: String,
+    download_cache: PathBuf,
+    work_dir: PathBuf,
+    output_dir: PathBuf,
+    key: LexiconKey,
+    compression: Compression,
+    extra_dictionaries: Vec<PathBuf>,
+    user_dictionaries: Vec<PathBuf>,
+    extra_lexicons: Vec<PathBuf>,
+    pos_map: Option<PathBuf>,
+    pos_filter: PosFilter,
+    cost_rules: Option<PathBuf>,
+    feature_layout: FeatureLayout,
+    feature_pos_map: Option<PathBuf>,
+    pronunciation: Pronunciation,
+    reading_script: Option<ReadingScript>,
+    single_kanji: bool,
+    reverse_lookup: bool,
+    zipcode: bool,
+    zipcode_src: Option<PathBuf>,
+    stats_report: Option<PathBuf>,
+    max_entries: Option<usize>,
+    max_size_bytes: Option<u64>,
+    in_memory: bool,
+    mecab: bool,
+    lindera: bool,
+    sqlite: bool,
+    jsonl: bool,
+    smoke_test: bool,
+    keep_intermediate: bool,
+    keep_sources: bool,
+    max_rejected_lines: usize,
+    warn_on_rejected_lines: bool,
+}
+
+/// Artifacts written from the Mozc sources besides the lexicon, with their
+/// entry counts.
+#[derive(Default)]
+struct MozcExtras {
+    suppression: Option<(PathBuf, usize)>,
+    single_kanji: Option<(PathBuf, usize)>,
+    symbols: Option<(PathBuf, usize)>,
+    /// Entries of the zip code lexicon written next to lex.csv
+    zipcode_entries: Option<usize>,
+    /// The zip code lexicon itself, when converted in memory
+    zipcode_lex: Option<Vec<u8>>,
+}
+
+/// The files a build wrote.
+#[derive(Clone, Debug)]
+pub struct BuildOutput {
+    /// The compiled system dictionary
+    pub dictionary: PathBuf,
+    /// The reading-keyed conversion dictionary ([`LexiconKey::Reading`])
+    pub conversion: Option<PathBuf>,
+    /// The zip code dictionary ([`Builder::zipcode`])
+    pub zipcode: Option<PathBuf>,
+    /// Mozc's suggestion filter and suppression words, if the sources have
+    /// them
+    pub suppression: Option<PathBuf>,
+    /// The reading → kanji FST ([`Builder::single_kanji`])
+    pub single_kanji: Option<PathBuf>,
+    /// The surface → readings FST ([`Builder::reverse_lookup`])
+    pub reverse_lookup: Option<PathBuf>,
+    /// Mozc's symbols and kaomoji by reading, if the sources have them
+    pub symbols: Option<PathBuf>,
+    /// mozc-dict-manifest.tsv
+    pub manifest: PathBuf,
+    /// manifest.json
+    pub bundle_manifest: PathBuf,
+    /// The statistics report ([`Builder::report`])
+    pub stats_report: Option<PathBuf>,
+    /// The MeCab dictionary sources ([`Builder::mecab`])
+    pub mecab: Option<PathBuf>,
+    /// The Lindera dictionary sources ([`Builder::lindera`])
+    pub lindera: Option<PathBuf>,
+    /// The SQLite export of the lexicon ([`Builder::sqlite`])
+    pub sqlite: Option<PathBuf>,
+    /// The JSON Lines export of the lexicon ([`Builder::jsonl`])
+    pub jsonl: Option<PathBuf>,
+    /// Entries compiled into the system dictionary, fewer than converted
+    /// if the lexicon was pruned
+    pub entries: usize,
+    /// Entries written and lines rejected converting the lexicon
+    pub report: Report,
+}
+
+impl BuildOutput {
+    /// Every file written, dictionaries first.
+    pub fn artifacts(&self) -> Vec<&Path> {
+        let mut paths = vec![self.dictionary.as_path()];
+        paths.extend(self.conversion.as_deref());
+        paths.extend(self.zipcode.as_deref());
+        paths.extend(self.suppression.as_deref());
+        paths.extend(self.single_kanji.as_deref());
+        paths.extend(self.reverse_lookup.as_deref());
+        paths.extend(self.symbols.as_deref());
+        paths.extend([self.manifest.as_path(), self.bundle_manifest.as_path()]);
+        paths
+    }
+}
+
+impl Builder {
+    /// A build of `source` into `output/`, downloading the sources into
+    /// `downloads/`.
+    pub fn new(source: Source) -> Self {
+        Self {
+            source,
+            source_dir: None,
+            mozc_ref: None,
+            sudachi_version: "20240409".to_string(),
+            download_cache: PathBuf::from("downloads"),
+            work_dir: PathBuf::from("."),
+            output_dir: PathBuf::from("output"),
+            key: LexiconKey::Surface,
+            compression: Compression::default(),
+            extra_dictionaries: Vec::new(),
+            user_dictionaries: Vec::new(),
+            extra_lexicons: Vec::new(),
+            pos_map: None,
+            pos_filter: PosFilter::default(),
+            cost_rules: None,
+            feature_layout: FeatureLayout::Mozc,
+            feature_pos_map: None,
+            pronunciation: Pronunciation::Estimated,
+            reading_script: None,
+            single_kanji: false,
+            reverse_lookup: false,
+            zipcode: false,
+            zipcode_src: None,
+            stats_report: None,
+            max_entries: None,
+            max_size_bytes: None,
+            in_memory: false,
+            mecab: false,
+            lindera: false,
+            sqlite: false,
+            jsonl: false,
+            smoke_test: false,
+            keep_intermediate: false,
+            keep_sources: false,
+            max_rejected_lines: 0,
+            warn_on_rejected_lines: false,
+        }
+    }
+
+    /// Reads the sources from a directory instead of downloading them: a
+    /// Mozc checkout or its dictionary_oss directory, or the unpacked UniDic
+    /// or SudachiDict files.
+    pub fn source_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.source_dir = Some(dir.into());
+        self
+    }
+
+    /// Mozc tag or commit to download instead of the tip of master.
+    pub fn mozc_ref(mut self, mozc_ref: impl Into<String>) -> Self {
+        self.mozc_ref = Some(mozc_ref.into());
+        self
+    }
+
+    /// SudachiDict release (date) to download.
+    pub fn sudachi_version(mut self, version: impl Into<String>) -> Self {
+        self.sudachi_version = version.into();
+        self
+    }
+
+    /// Directory downloads are cached in.
+    pub fn download_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.download_cache = dir.into();
+        self
+    }
+
+    /// Directory downloaded sources are unpacked or kept in (mozc_src/,
+    /// unidic_src/, sudachi_src/); the current directory by default.
+    pub fn work_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.work_dir = dir.into();
+        self
+    }
+
+    /// Directory the dictionaries and manifests are written to.
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = dir.into();
+        self
+    }
+
+    pub fn key(mut self, key: LexiconKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Adds a dictionary in Mozc's format, merged into the lexicon.
+    pub fn extra_dictionary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.extra_dictionaries.push(path.into());
+        self
+    }
+
+    /// Adds a user dictionary built into the system dictionary.
+    pub fn user_dictionary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.user_dictionaries.push(path.into());
+        self
+    }
+
+    /// Adds a lexicon in MeCab's IPAdic CSV format.
+    pub fn extra_lexicon(mut self, path: impl Into<PathBuf>) -> Self {
+        self.extra_lexicons.push(path.into());
+        self
+    }
+
+    /// POS map file for the extra lexicons.
+    pub fn pos_map(mut self, path: impl Into<PathBuf>) -> Self {
+        self.pos_map = Some(path.into());
+        self
+    }
+
+    /// Keeps only the Mozc entries whose POS `pos_filter` keeps (see
+    /// [`crate::pos_filter`]).
+    pub fn pos_filter(mut self, pos_filter: PosFilter) -> Self {
+        self.pos_filter = pos_filter;
+        self
+    }
+
+    /// Adjusts the costs of Mozc entries by the rules in the TOML file at
+    /// `path` (see [`crate::cost_rules`]).
+    pub fn cost_rules(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cost_rules = Some(path.into());
+        self
+    }
+
+    pub fn feature_layout(mut self, layout: FeatureLayout) -> Self {
+        self.feature_layout = layout;
+        self
+    }
+
+    /// POS map file replacing the built-in renames of the feature layout.
+    pub fn feature_pos_map(mut self, path: impl Into<PathBuf>) -> Self {
+        self.feature_pos_map = Some(path.into());
+        self
+    }
+
+    pub fn pronunciation(mut self, pronunciation: Pronunciation) -> Self {
+        self.pronunciation = pronunciation;
+        self
+    }
+
+    /// Writes readings and pronunciations in `script` instead of the feature
+    /// layout's, in the lexicon and the reading-keyed FSTs alike.
+    pub fn reading_script(mut self, script: ReadingScript) -> Self {
+        self.reading_script = Some(script);
+        self
+    }
+
+    /// Also writes Mozc's single-kanji list as a reading → kanji FST.
+    pub fn single_kanji(mut self, single_kanji: bool) -> Self {
+        self.single_kanji = single_kanji;
+        self
+    }
+
+    /// Also writes the lexicon's readings by surface as a reverse-lookup FST,
+    /// for reconversion (see [`crate::reverse`]).
+    pub fn reverse_lookup(mut self, reverse_lookup: bool) -> Self {
+        self.reverse_lookup = reverse_lookup;
+        self
+    }
+
+    /// Also builds a zip code dictionary from Japan Post's KEN_ALL data
+    /// (see [`zipcode`]).
+    pub fn zipcode(mut self, zipcode: bool) -> Self {
+        self.zipcode = zipcode;
+        self
+    }
+
+    /// Reads KEN_ALL from a local ken_all.zip or KEN_ALL.CSV instead of
+    /// downloading it; implies [`Builder::zipcode`].
+    pub fn zipcode_src(mut self, path: impl Into<PathBuf>) -> Self {
+        self.zipcode = true;
+        self.zipcode_src = Some(path.into());
+        self
+    }
+
+    /// Writes the statistics of the built lexicon and matrix to `path` as
+    /// JSON (see [`crate::stats`]).
+    pub fn report(mut self, path: impl Into<PathBuf>) -> Self {
+        self.stats_report = Some(path.into());
+        self
+    }
+
+    /// Prunes the lexicon to at most `max_entries` entries, keeping the
+    /// cheapest ones of every reading first (see [`crate::prune`]).
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Prunes the lexicon until the compressed system dictionary is at most
+    /// `max_size_bytes` bytes, recompiling with fewer entries while it is
+    /// over.
+    pub fn max_size_bytes(mut self, max_size_bytes: u64) -> Self {
+        self.max_size_bytes = Some(max_size_bytes);
+        self
+    }
+
+    /// Hands the converted lexicon, matrix, char.def and unk.def to the
+    /// Vibrato builder in memory instead of writing and re-reading them,
+    /// halving the disk traffic of a build at the cost of holding them in
+    /// RAM. Nothing is left to keep, so it excludes
+    /// [`Builder::keep_intermediate`].
+    pub fn in_memory(mut self, in_memory: bool) -> Self {
+        self.in_memory = in_memory;
+        self
+    }
+
+    /// Also writes the lexicon, matrix, char.def and unk.def with a dicrc to
+    /// `mecab/` in the output directory, for `mecab-dict-index` (see
+    /// [`crate::mecab`]).
+    pub fn mecab(mut self, mecab: bool) -> Self {
+        self.mecab = mecab;
+        self
+    }
+
+    /// Also writes the lexicon, matrix, char.def and unk.def with a
+    /// metadata.json to `lindera/` in the output directory, for `lindera
+    /// build` (see [`crate::lindera`]).
+    pub fn lindera(mut self, lindera: bool) -> Self {
+        self.lindera = lindera;
+        self
+    }
+
+    /// Also exports the lexicon to `lexicon.sqlite` in the output directory
+    /// (see [`crate::sqlite`]).
+    pub fn sqlite(mut self, sqlite: bool) -> Self {
+        self.sqlite = sqlite;
+        self
+    }
+
+    /// Also exports the lexicon to `lexicon.jsonl` in the output directory,
+    /// one JSON object per entry (see [`crate::export`]).
+    pub fn jsonl(mut self, jsonl: bool) -> Self {
+        self.jsonl = jsonl;
+        self
+    }
+
+    /// Reads the written system dictionary back and tokenizes a few
+    /// sentences with it, failing the build if they come out empty or
+    /// garbled (see [`crate::smoke`]).
+    pub fn smoke_test(mut self, smoke_test: bool) -> Self {
+        self.smoke_test = smoke_test;
+        self
+    }
+
+    /// Keeps lex.csv, matrix.def, char.def and unk.def; implies
+    /// [`Builder::keep_sources`].
+    pub fn keep_intermediate(mut self, keep: bool) -> Self {
+        self.keep_intermediate = keep;
+        self
+    }
+
+    /// Keeps the downloaded sources unpacked in the work directory.
+    pub fn keep_sources(mut self, keep: bool) -> Self {
+        self.keep_sources = keep;
+        self
+    }
+
+    /// Most lexicon lines that may be rejected as malformed; the build fails
+    /// when more are.
+    pub fn max_rejected_lines(mut self, lines: usize) -> Self {
+        self.max_rejected_lines = lines;
+        self
+    }
+
+    /// Warns instead of failing when more lines than `max_rejected_lines`
+    /// are rejected.
+    pub fn warn_on_rejected_lines(mut self, warn: bool) -> Self {
+        self.warn_on_rejected_lines = warn;
+        self
+    }
+
+    /// Runs the build, returning the files written.
+    pub fn build(&self) -> Result<BuildOutput> {
+        anyhow::ensure!(
+            self.key == LexiconKey::Surface || self.feature_layout == FeatureLayout::Mozc,
+            "--key reading needs --feature-layout mozc"
+        );
+        anyhow::ensure!(
+            self.stats_report.is_none() || self.feature_layout == FeatureLayout::Mozc,
+            "--report needs --feature-layout mozc"
+        );
+        let pruning = self.max_entries.is_some() || self.max_size_bytes.is_some();
+        anyhow::ensure!(
+            !pruning || self.feature_layout == FeatureLayout::Mozc,
+            "--max-entries and --max-size-bytes need --feature-layout mozc"
+        );
+        anyhow::ensure!(
+            !(self.in_memory && self.keep_intermediate),
+            "--in-memory and --keep-intermediate can't be combined: an in-memory build writes no intermediate files"
+        );
+        if self.source != Source::Mozc {
+            let mozc_only = !self.extra_dictionaries.is_empty()
+                || !self.user_dictionaries.is_empty()
+                || !self.extra_lexicons.is_empty()
+                || self.mozc_ref.is_some()
+                || !self.pos_filter.is_empty()
+                || self.cost_rules.is_some()
+                || self.feature_layout != FeatureLayout::Mozc
+                || self.feature_pos_map.is_some()
+                || self.pronunciation != Pronunciation::Estimated
+                || self.reading_script.is_some()
+                || self.single_kanji
+                || self.zipcode;
+            anyhow::ensure!(
+                !mozc_only,
+                "--extra-dictionary, --user-dict, --extra-lexicon, --mozc-src, --mozc-ref, --include-pos, --exclude-pos, --cost-rules, --feature-layout, --reading-as-pronunciation, --reading-script, --single-kanji and --with-zipcode need --source mozc"
+            );
+        }
+        let _build = tracing::info_span!("build", source = ?self.source, output_dir = ?self.output_dir).entered();
+        let output_dir = self.output_dir.as_path();
+        fs::create_dir_all(output_dir)?;
+        let mut intermediates: Vec<PathBuf> = INTERMEDIATE_FILES.iter().map(|name| output_dir.join(name)).collect();
+        let mut extras = MozcExtras::default();
+        let convert = tracing::info_span!("convert").entered();
+        let (report, mut inputs) = match self.source {
+            Source::Mozc => {
+                let (report, inputs, mozc_extras) = self.convert_mozc()?;
+                extras = mozc_extras;
+                (report, inputs)
+            }
+            Source::Unidic => {
+                let (report, inputs, unpacked) = self.convert_unidic()?;
+                intermediates.extend(unpacked);
+                (report, inputs)
+            }
+            Source::Sudachi => {
+                let (report, inputs, unpacked) = self.convert_sudachi()?;
+                intermediates.extend(unpacked);
+                (report, inputs)
+            }
+        };
+        drop(convert);
+        log_rejections(&report);
+        tracing::info!("Wrote {} lexicon entries", report.entries);
+        if report.duplicates > 0 {
+            tracing::info!("Skipped {} extra lexicon words the dictionaries already have", report.duplicates);
+        }
+        if report.pos_filtered > 0 {
+            tracing::info!("Left out {} entries by POS", report.pos_filtered);
+        }
+        for (rule, count) in &report.cost_adjustments {
+            tracing::info!("Cost rule {:?} adjusted {} entries", rule, count);
+        }
+        if report.rejected > self.max_rejected_lines {
+            let message =
+                format!("{} lexicon lines rejected, more than --max-rejected-lines {}", report.rejected, self.max_rejected_lines);
+            if !self.warn_on_rejected_lines {
+                anyhow::bail!("Quality gate failed: {}", message);
+            }
+            tracing::warn!("{}", message);
+        }
+
+        let budget = match (&inputs, pruning) {
+            (_, false) => None,
+            (Some(inputs), true) => Some(EntryBudget::read(&inputs.lex[..])?),
+            (None, true) => Some(EntryBudget::read(BufReader::new(File::open(output_dir.join("lex.csv"))?))?),
+        };
+        let dict_path = output_dir.join(self.compression.file_name("system.dic"));
+        let mut entries = report.entries;
+        let mut limit = self.max_entries;
+        for attempt in 1.. {
+            if let (Some(budget), Some(limit)) = (&budget, limit) {
+                let _prune = tracing::info_span!("prune", limit).entered();
+                let mut lex = Vec::new();
+                entries = budget.write(limit, &mut lex)?;
+                match &mut inputs {
+                    Some(inputs) => inputs.lex = lex,
+                    None => fs::write(output_dir.join("lex.csv"), lex)?,
+                }
+                tracing::info!("Pruned the lexicon to {} of {} entries", entries, budget.len());
+            }
+
+            tracing::info!("Compiling dictionary...");
+            let _compile = tracing::info_span!("compile", dictionary = "system", entries).entered();
+            let dict = match &inputs {
+                Some(inputs) => inputs.compile()?,
+                None => crate::compile_dir(output_dir)?,
+            };
+            crate::write_dictionary_as(&dict, BufWriter::new(File::create(&dict_path)?), self.compression)?;
+
+            let Some(max_size) = self.max_size_bytes else { break };
+            let size = fs::metadata(&dict_path)?.len();
+            if size <= max_size {
+                tracing::info!("{:?} is {} bytes, within --max-size-bytes {}", dict_path, size, max_size);
+                break;
+            }
+            anyhow::ensure!(
+                entries > 0 && attempt < prune::MAX_SIZE_ATTEMPTS,
+                "{:?} is still {} bytes with {} entries, over --max-size-bytes {}",
+                dict_path,
+                size,
+                entries,
+                max_size
+            );
+            tracing::info!("{:?} is {} bytes, over --max-size-bytes {}; pruning further", dict_path, size, max_size);
+            limit = Some(prune::next_limit(entries, size, max_size));
+        }
+        let (mut mecab_dir, mut lindera_dir, mut sqlite_path, mut jsonl_path) = (None, None, None, None);
+        let mut reverse_lookup = None;
+        if self.mecab || self.lindera || self.sqlite || self.jsonl || self.reverse_lookup {
+            let read;
+            let sources = match &inputs {
+                Some(inputs) => inputs,
+                None => {
+                    read = VibratoSources::read_dir(output_dir)?;
+                    &read
+                }
+            };
+            if self.mecab {
+                let dir = output_dir.join(mecab::MECAB_DIR);
+                mecab::write_dir(&dir, sources, self.feature_layout)?;
+                tracing::info!("Wrote MeCab dictionary sources to {:?}", dir);
+                mecab_dir = Some(dir);
+            }
+            if self.lindera {
+                let dir = output_dir.join(lindera::LINDERA_DIR);
+                lindera::write_dir(&dir, sources, self.feature_layout)?;
+                tracing::info!("Wrote Lindera dictionary sources to {:?}", dir);
+                lindera_dir = Some(dir);
+            }
+            if self.sqlite {
+                let path = output_dir.join(sqlite::SQLITE_FILE);
+                let _export = tracing::info_span!("export", format = "sqlite").entered();
+                let rows = sqlite::write_lexicon(&path, &sources.lex, self.feature_layout)?;
+                tracing::info!("Exported {} lexicon entries to {:?}", rows, path);
+                sqlite_path = Some(path);
+            }
+            if self.jsonl {
+                let path = output_dir.join(export::JSONL_FILE);
+                let _export = tracing::info_span!("export", format = "jsonl").entered();
+                let file = File::create(&path).with_context(|| format!("Failed to create {:?}", path))?;
+                let rows = export::write_jsonl(&sources.lex, self.feature_layout, BufWriter::new(file))?;
+                tracing::info!("Exported {} lexicon entries to {:?}", rows, path);
+                jsonl_path = Some(path);
+            }
+            if self.reverse_lookup {
+                let path = output_dir.join(reverse::REVERSE_FST);
+                let file = File::create(&path).with_context(|| format!("Failed to create {:?}", path))?;
+                let pairs = reverse::write_reverse_lookup(&sources.lex, self.feature_layout, BufWriter::new(file))?;
+                tracing::info!("Wrote {} surface/reading pairs to {:?}", pairs, path);
+                reverse_lookup = Some((path, pairs));
+            }
+        }
+        if self.smoke_test {
+            let _smoke = tracing::info_span!("smoke_test").entered();
+            tracing::info!("Smoke-testing {:?}...", dict_path);
+            smoke::check(crate::read_dictionary(&dict_path)?)?;
+        }
+        let mut artifacts = vec![(dict_path.clone(), entries)];
+        let mut conversion_path = None;
+        if self.key == LexiconKey::Reading {
+            tracing::info!("Compiling reading-keyed conversion dictionary...");
+            let _compile = tracing::info_span!("compile", dictionary = "conversion").entered();
+            let (dict, entries) = match &inputs {
+                Some(inputs) => conversion::compile_sources(inputs)?,
+                None => {
+                    intermediates.push(output_dir.join(conversion::CONVERSION_LEX));
+                    conversion::compile_dir(output_dir)?
+                }
+            };
+            let path = output_dir.join(self.compression.file_name(conversion::CONVERSION_DIC));
+            crate::write_dictionary_as(&dict, BufWriter::new(File::create(&path)?), self.compression)?;
+            tracing::info!("Wrote {} conversion entries to {:?}", entries, path);
+            artifacts.push((path.clone(), entries));
+            conversion_path = Some(path);
+        }
+        let mut zipcode_path = None;
+        if let Some(entries) = extras.zipcode_entries {
+            tracing::info!("Compiling zip code dictionary...");
+            let _compile = tracing::info_span!("compile", dictionary = "zipcode", entries).entered();
+            let dict = match (&inputs, &extras.zipcode_lex) {
+                (Some(inputs), Some(lex)) => inputs.compile_lex(&lex[..])?,
+                _ => {
+                    intermediates.push(output_dir.join(zipcode::ZIPCODE_LEX));
+                    zipcode::compile_dir(output_dir)?
+                }
+            };
+            let path = output_dir.join(self.compression.file_name(zipcode::ZIPCODE_DIC));
+            crate::write_dictionary_as(&dict, BufWriter::new(File::create(&path)?), self.compression)?;
+            tracing::info!("Wrote {} zip codes to {:?}", entries, path);
+            artifacts.push((path.clone(), entries));
+            zipcode_path = Some(path);
+        }
+        let manifests = tracing::info_span!("manifests").entered();
+        artifacts.extend(extras.suppression.iter().chain(&extras.single_kanji).chain(&extras.symbols).cloned());
+        artifacts.extend(reverse_lookup.clone());
+        let provenance = self.provenance(&report, entries)?;
+        let manifest_path = output_dir.join(MANIFEST_FILE);
+        write_manifest(&manifest_path, &provenance)?;
+        let manifest = BundleManifest {
+            built_at: bundle::build_timestamp()?,
+            provenance: provenance.into_iter().map(|(key, value)| (key.to_string(), value)).collect(),
+            artifacts: artifacts
+                .iter()
+                .map(|(path, entries)| Artifact::from_file(path, Some(*entries)))
+                .collect::<Result<_>>()?,
+        };
+        let bundle_manifest_path = output_dir.join(bundle::BUNDLE_MANIFEST);
+        manifest.write(BufWriter::new(File::create(&bundle_manifest_path)?))?;
+        drop(manifests);
+        if let Some(path) = &self.stats_report {
+            let _stats = tracing::info_span!("stats").entered();
+            let stats = match &inputs {
+                Some(inputs) => {
+                    let mut stats = DictStats::from_lex(&inputs.lex[..])?;
+                    stats.add_matrix(&ConnectionMatrix::read(&inputs.matrix[..])?);
+                    stats
+                }
+                None => DictStats::from_files(&output_dir.join("lex.csv"), &output_dir.join("matrix.def"))?,
+            };
+            stats.write(BufWriter::new(File::create(path)?))?;
+            tracing::info!("Wrote dictionary statistics to {:?}", path);
+        }
+
+        if self.keep_intermediate {
+            tracing::info!("Keeping intermediate files in {:?}", output_dir);
+        } else {
+            let _cleanup = tracing::info_span!("cleanup").entered();
+            let reclaimed = remove_intermediates(&intermediates)?;
+            tracing::info!("Removed intermediate files, reclaimed {:.1} MB", reclaimed as f64 / 1024.0 / 1024.0);
+        }
+
+        Ok(BuildOutput {
+            dictionary: dict_path,
+            conversion: conversion_path,
+            zipcode: zipcode_path,
+            suppression: extras.suppression.map(|(path, _)| path),
+            single_kanji: extras.single_kanji.map(|(path, _)| path),
+            reverse_lookup: reverse_lookup.map(|(path, _)| path),
+            symbols: extras.symbols.map(|(path, _)| path),
+            manifest: manifest_path,
+            bundle_manifest: bundle_manifest_path,
+            stats_report: self.stats_report.clone(),
+            mecab: mecab_dir,
+            lindera: lindera_dir,
+            sqlite: sqlite_path,
+            jsonl: jsonl_path,
+            entries,
+            report,
+        })
+    }
+
+    /// Converts `source` into the output directory, or into memory with
+    /// [`Builder::in_memory`].
+    fn convert(&self, source: &impl DictSource) -> Result<(Report, Option<VibratoSources>)> {
+        if self.in_memory {
+            let (inputs, report) = source.build()?;
+            Ok((report, Some(inputs)))
+        } else {
+            Ok((source.write_dir(&self.output_dir)?, None))
+        }
+    }
+
+    /// Converts the Mozc sources, returning the report, the builder inputs
+    /// if kept in memory and the other artifacts written from them.
+    fn convert_mozc(&self) -> Result<(Report, Option<VibratoSources>, MozcExtras)> {
+        let sources = match &self.source_dir {
+            Some(path) => {
+                let dir = find_dictionary_dir(path)?;
+                tracing::info!("Using local Mozc dictionary sources in {:?}", dir);
+                MozcSources::from_dir(&dir)?
+            }
+            None => {
+                let archive = download::fetch_cached(&mozc_url(self.mozc_ref.as_deref())?, &self.download_cache)?;
+                let sources = download::read_dictionary_sources(&archive)?;
+                if self.keep_sources || self.keep_intermediate {
+                    let dir = self.work_dir.join(SOURCES_DIR);
+                    sources.write_dir(&dir)?;
+                    tracing::info!("Wrote the Mozc dictionary sources to {:?}", dir);
+                }
+                sources
+            }
+        };
+
+        tracing::info!("Converting to Vibrato format...");
+        let filters = sources.filters.clone();
+        let single_kanji_list = sources.single_kanji.clone();
+        let symbol_files = sources.symbols.clone();
+        let id_def = sources.id_def.clone();
+        let mut pipeline = DictPipeline::new(sources)
+            .extra_dictionaries(self.extra_dictionaries.iter().cloned().map(Input::Path));
+        for path in &self.user_dictionaries {
+            pipeline = pipeline.user_dictionary(Input::Path(path.clone()));
+        }
+        for path in &self.extra_lexicons {
+            pipeline = pipeline.extra_lexicon(Input::Path(path.clone()));
+        }
+        if let Some(path) = &self.pos_map {
+            pipeline = pipeline.pos_map(read_pos_map(path)?);
+        }
+        if let Some(path) = &self.cost_rules {
+            pipeline = pipeline.cost_rules(CostRules::read(path)?);
+        }
+        let mut features = FeatureConversion::new(self.feature_layout);
+        if let Some(path) = &self.feature_pos_map {
+            features = features.with_pos_map(read_pos_map(path)?);
+        }
+        if let Some(script) = self.reading_script {
+            features = features.with_reading_script(script);
+        }
+        pipeline = pipeline
+            .features(features)
+            .pronunciation(self.pronunciation)
+            .pos_filter(self.pos_filter.clone());
+        for input in pipeline.dictionaries().chain(pipeline.user_dictionaries()).chain(pipeline.extra_lexicons()) {
+            tracing::info!("Processing {:?}", input.name());
+        }
+        let (report, inputs) = self.convert(&pipeline)?;
+
+        let mut extras = MozcExtras::default();
+        if !filters.is_empty() {
+            let path = self.output_dir.join(suppression::SUPPRESSION_FST);
+            let mut writer = BufWriter::new(File::create(&path)?);
+            let words = suppression::write_suppression(&filters, &mut writer)?;
+            writer.flush()?;
+            tracing::info!("Wrote {} suppressed words to {:?}", words, path);
+            extras.suppression = Some((path, words));
+        }
+        if self.single_kanji {
+            let input = single_kanji_list.context("single_kanji.tsv not found in the Mozc sources")?;
+            let path = self.output_dir.join(single_kanji::SINGLE_KANJI_FST);
+            let mut writer = BufWriter::new(File::create(&path)?);
+            let entries = single_kanji::write_single_kanji(&input, self.reading_script, &mut writer)?;
+            writer.flush()?;
+            tracing::info!("Wrote {} single-kanji candidates to {:?}", entries, path);
+            extras.single_kanji = Some((path, entries));
+        }
+        if !symbol_files.is_empty() {
+            let path = self.output_dir.join(symbols::SYMBOLS_FST);
+            let mut writer = BufWriter::new(File::create(&path)?);
+            let entries = symbols::write_symbols(&symbol_files, self.reading_script, &mut writer)?;
+            writer.flush()?;
+            tracing::info!("Wrote {} symbol and kaomoji candidates to {:?}", entries, path);
+            extras.symbols = Some((path, entries));
+        }
+        if self.zipcode {
+            let ken_all = match &self.zipcode_src {
+                Some(path) => path.clone(),
+                None => download::fetch_cached(zipcode::KEN_ALL_URL, &self.download_cache)?,
+            };
+            tracing::info!("Converting zip codes from {:?}...", ken_all);
+            let id_map = crate::read_id_def(id_def.open()?)?;
+            if self.in_memory {
+                let mut lex = Vec::new();
+                let entries = zipcode::read_ken_all_file(&ken_all)?;
+                extras.zipcode_entries = Some(zipcode::write_lex(&entries, &id_map, self.reading_script, &mut lex)?);
+                extras.zipcode_lex = Some(lex);
+            } else {
+                extras.zipcode_entries =
+                    Some(zipcode::write_lex_file(&ken_all, &id_map, self.reading_script, &self.output_dir)?);
+            }
+        }
+        Ok((report, inputs, extras))
+    }
+
+    /// Converts UniDic, returning the report, the builder inputs if kept in
+    /// memory and the unpacked sources to clean up with the intermediate
+    /// files.
+    fn convert_unidic(&self) -> Result<(Report, Option<VibratoSources>, Option<PathBuf>)> {
+        let (sources, unpacked) = match &self.source_dir {
+            Some(dir) => {
+                tracing::info!("Using local UniDic sources in {:?}", dir);
+                (UniDicSources::from_dir(dir)?, None)
+            }
+            None => {
+                let archive = download::fetch_cached(UNIDIC_URL, &self.download_cache)?;
+                let dir = self.work_dir.join(UNIDIC_SOURCES_DIR);
+                let sources = download::extract_unidic(&archive, &dir)?;
+                (sources, (!self.keep_sources).then_some(dir))
+            }
+        };
+        tracing::info!("Converting to Vibrato format...");
+        let (report, inputs) = self.convert(&sources)?;
+        Ok((report, inputs, unpacked))
+    }
+
+    /// Converts SudachiDict-core like [`Builder::convert_unidic`].
+    fn convert_sudachi(&self) -> Result<(Report, Option<VibratoSources>, Option<PathBuf>)> {
+        let (sources, unpacked) = match &self.source_dir {
+            Some(dir) => {
+                tracing::info!("Using local SudachiDict sources in {:?}", dir);
+                (SudachiSources::from_dir(dir)?, None)
+            }
+            None => {
+                let archives = sudachi_urls(&self.sudachi_version)?
+                    .iter()
+                    .map(|url| download::fetch_cached(url, &self.download_cache))
+                    .collect::<Result<Vec<_>>>()?;
+                let dir = self.work_dir.join(SUDACHI_SOURCES_DIR);
+                let sources = download::extract_sudachi(&archives, &dir)?;
+                (sources, (!self.keep_sources).then_some(dir))
+            }
+        };
+        tracing::info!("Converting to Vibrato format...");
+        let (report, inputs) = self.convert(&sources)?;
+        Ok((report, inputs, unpacked))
+    }
+
+    /// The sources and options of the build as manifest key-value pairs.
+    fn provenance(&self, report: &Report, entries: usize) -> Result<Vec<(&'static str, String)>> {
+        let mut lines = Vec::new();
+        match (self.source, &self.source_dir) {
+            (Source::Mozc, Some(src)) => lines.push(("mozc_src", src.display().to_string())),
+            (Source::Mozc, None) => {
+                lines.push(("mozc_ref", self.mozc_ref.as_deref().unwrap_or("master").to_string()));
+                lines.push(("mozc_url", mozc_url(self.mozc_ref.as_deref())?));
+            }
+            (Source::Unidic, Some(src)) => lines.push(("unidic_src", src.display().to_string())),
+            (Source::Unidic, None) => lines.push(("unidic_url", UNIDIC_URL.to_string())),
+            (Source::Sudachi, Some(src)) => lines.push(("sudachi_src", src.display().to_string())),
+            (Source::Sudachi, None) => {
+                for url in sudachi_urls(&self.sudachi_version)? {
+                    lines.push(("sudachi_url", url));
+                }
+            }
+        }
+        for extra in &self.extra_dictionaries {
+            lines.push(("extra_dictionary", extra.display().to_string()));
+        }
+        for user_dict in &self.user_dictionaries {
+            lines.push(("user_dictionary", user_dict.display().to_string()));
+        }
+        for lexicon in &self.extra_lexicons {
+            lines.push(("extra_lexicon", lexicon.display().to_string()));
+        }
+        if let Some(pos_map) = &self.pos_map {
+            lines.push(("pos_map", pos_map.display().to_string()));
+        }
+        for pattern in self.pos_filter.include() {
+            lines.push(("include_pos", pattern.to_string()));
+        }
+        for pattern in self.pos_filter.exclude() {
+            lines.push(("exclude_pos", pattern.to_string()));
+        }
+        if let Some(path) = &self.cost_rules {
+            lines.push(("cost_rules", path.display().to_string()));
+        }
+        for (rule, count) in &report.cost_adjustments {
+            lines.push(("cost_rule", format!("{}: {} entries", rule, count)));
+        }
+        if self.feature_layout != FeatureLayout::Mozc {
+            lines.push(("feature_layout", self.feature_layout.name().to_string()));
+        }
+        if let Some(pos_map) = &self.feature_pos_map {
+            lines.push(("feature_pos_map", pos_map.display().to_string()));
+        }
+        if self.pronunciation == Pronunciation::Reading {
+            lines.push(("pronunciation", "reading".to_string()));
+        }
+        if let Some(script) = self.reading_script {
+            lines.push(("reading_script", script.name().to_string()));
+        }
+        if self.key == LexiconKey::Reading {
+            lines.push(("key", "reading".to_string()));
+        }
+        if self.single_kanji {
+            lines.push(("single_kanji", "yes".to_string()));
+        }
+        if self.reverse_lookup {
+            lines.push(("reverse_lookup", "yes".to_string()));
+        }
+        match (&self.zipcode_src, self.zipcode) {
+            (Some(src), _) => lines.push(("zipcode_src", src.display().to_string())),
+            (None, true) => lines.push(("zipcode_url", zipcode::KEN_ALL_URL.to_string())),
+            (None, false) => {}
+        }
+        if self.compression != Compression::default() {
+            lines.push(("compression", self.compression.to_string()));
+        }
+        let formats: Vec<&str> = [(self.mecab, "mecab"), (self.lindera, "lindera")]
+            .into_iter()
+            .filter_map(|(enabled, format)| enabled.then_some(format))
+            .collect();
+        if !formats.is_empty() {
+            lines.push(("format", formats.join(",")));
+        }
+        let exports: Vec<&str> = [(self.jsonl, "jsonl"), (self.sqlite, "sqlite")]
+            .into_iter()
+            .filter_map(|(enabled, export)| enabled.then_some(export))
+            .collect();
+        if !exports.is_empty() {
+            lines.push(("export", exports.join(",")));
+        }
+        if let Some(max_entries) = self.max_entries {
+            lines.push(("max_entries", max_entries.to_string()));
+        }
+        if let Some(max_size_bytes) = self.max_size_bytes {
+            lines.push(("max_size_bytes", max_size_bytes.to_string()));
+        }
+        lines.push(("lexicon_entries", entries.to_string()));
+        Ok(lines)
+    }
+}
+
+fn read_pos_map(path: &Path) -> Result<PosMap> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    PosMap::read(BufReader::new(file)).with_context(|| format!("Invalid POS map {:?}", path))
+}
+
+fn log_rejections(report: &Report) {
+    for rejection in &report.rejections {
+        tracing::warn!(
+            "Rejected {:?} line {} ({}): {:?}",
+            rejection.source,
+            rejection.line_no,
+            rejection.reason,
+            rejection.line
+        );
+    }
+    if report.rejected > report.rejections.len() {
+        tracing::warn!("... {} more rejected lines", report.rejected - report.rejections.len());
+    }
+}
+
+/// Source archive URL for a Mozc tag or commit, or master if `mozc_ref` is
+/// not given.
+fn mozc_url(mozc_ref: Option<&str>) -> Result<String> {
+    let Some(mozc_ref) = mozc_ref else {
+        return Ok(MOZC_REPO_URL.to_string());
+    };
+    anyhow::ensure!(
+        !mozc_ref.is_empty()
+            && !mozc_ref.contains("..")
+            && mozc_ref.chars().all(|c| c.is_ascii_alphanumeric() || "._-/".contains(c)),
+        "Invalid --mozc-ref {:?}: expected a tag or commit",
+        mozc_ref
+    );
+    Ok(format!("{}/{}.tar.gz", MOZC_ARCHIVE_URL, mozc_ref))
+}
+
+/// The matrix and lexicon archives of a SudachiDict-core release.
+fn sudachi_urls(version: &str) -> Result<Vec<String>> {
+    anyhow::ensure!(
+        !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()),
+        "Invalid --sudachi-version {:?}: expected a release date such as 20240409",
+        version
+    );
+    let mut urls = vec![format!("{}/matrix.def.zip", SUDACHI_RAW_URL)];
+    urls.extend(["small_lex", "core_lex"].map(|name| format!("{}/{}/{}.zip", SUDACHI_RAW_URL, version, name)));
+    Ok(urls)
+}
+
+/// Writes which Mozc sources and extra dictionaries went into the build, so
+/// it can be reproduced.
+fn write_manifest(path: &Path, provenance: &[(&str, String)]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}\tv1", MANIFEST_MAGIC)?;
+    for (key, value) in provenance {
+        writeln!(writer, "{}\t{}", key, value)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Locates the dictionary sources under `path`, which may be a Mozc checkout
+/// or the dictionary_oss directory itself.
+fn find_dictionary_dir(path: &Path) -> Result<PathBuf> {
+    let dir = MOZC_DICTIONARY_DIRS
+        .iter()
+        .map(|sub| path.join(sub))
+        .find(|dir| dir.is_dir())
+        .unwrap_or_else(|| path.to_path_buf());
+    anyhow::ensure!(dir.join("id.def").is_file(), "id.def not found in {:?}", dir);
+    anyhow::ensure!(
+        CONNECTION_FILES.iter().any(|name| dir.join(name).is_file()),
+        "{} not found in {:?}",
+        CONNECTION_FILES.join(" or "),
+        dir
+    );
+    Ok(dir)
+}
+
+/// Removes the given files and directories, returning the number of bytes freed.
+fn remove_intermediates(paths: &[PathBuf]) -> Result<u64> {
+    let mut reclaimed = 0;
+    for path in paths {
+        if !path.exists() {
+            continue;
+        }
+        reclaimed += disk_usage(path)?;
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(reclaimed)
+}
+
+fn disk_usage(path: &Path) -> Result<u64> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += disk_usage(&entry?.path())?;
+    }
+    Ok(total)
+}
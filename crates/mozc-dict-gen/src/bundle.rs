@@ -0,0 +1,124 @@
+//! `output/manifest.json`, describing a build's artifacts so apps can tell
+//! whether the dictionary bundle they hold is current:
+//!
+//! ```json
+//! {
+//!   "manifest_version": 1,
+//!   "built_at": 1760000000,
+//!   "tools": { "mozc-dict-gen": "0.1.0", "vibrato": "0.5.2 (git 1a2b3c4d5e6f)" },
+//!   "provenance": { "mozc_ref": "master", "mozc_url": "...", "lexicon_entries": "1300000" },
+//!   "artifacts": [
+//!     { "file": "system.dic.zst", "size": 51234567, "sha256": "9f86d0...", "entries": 1300000 }
+//!   ]
+//! }
+//! ```
+//!
+//! `tools` has the Vibrato the dictionaries were compiled with, from the
+//! workspace's Cargo.lock (with the commit of a git checkout), since whether
+//! an app's Vibrato can load them depends on it.
+//! `provenance` holds the key-value lines of mozc-dict-manifest.tsv; keys
+//! given more than once (extra dictionaries, Sudachi URLs) hold an array.
+//! `built_at` is in Unix seconds, taken from `SOURCE_DATE_EPOCH` when set so
+//! reproducible builds produce identical manifests.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File name of the manifest in the output directory.
+pub const BUNDLE_MANIFEST: &str = "manifest.json";
+
+const MANIFEST_VERSION: u32 = 1;
+
+/// A file of the bundle, serialized as in the manifest.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Artifact {
+    pub file: String,
+    pub size: u64,
+    pub sha256: String,
+    /// Lexicon entries compiled into a dictionary
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entries: Option<usize>,
+}
+
+impl Artifact {
+    pub fn from_file(path: &Path, entries: Option<usize>) -> Result<Self> {
+        let file = path.file_name().with_context(|| format!("{:?} has no file name", path))?;
+        let reader = BufReader::new(File::open(path).with_context(|| format!("Failed to open {:?}", path))?);
+        Ok(Self {
+            file: file.to_string_lossy().into_owned(),
+            size: path.metadata()?.len(),
+            sha256: sha256_hex(reader)?,
+            entries,
+        })
+    }
+}
+
+/// Lowercase hex SHA-256 of everything `reader` yields.
+pub fn sha256_hex(mut reader: impl Read) -> Result<String> {
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        context.update(&buf[..n]);
+    }
+    Ok(context.finish().as_ref().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Build time in Unix seconds: `SOURCE_DATE_EPOCH` if set, else now.
+pub fn build_timestamp() -> Result<u64> {
+    match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => epoch.trim().parse().with_context(|| format!("Invalid SOURCE_DATE_EPOCH {:?}", epoch)),
+        Err(_) => Ok(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BundleManifest {
+    pub built_at: u64,
+    pub provenance: Vec<(String, String)>,
+    pub artifacts: Vec<Artifact>,
+}
+
+impl BundleManifest {
+    pub fn to_json(&self) -> Value {
+        let mut provenance = Map::new();
+        for (key, value) in &self.provenance {
+            match provenance.get_mut(key) {
+                Some(Value::Array(values)) => values.push(json!(value)),
+                Some(first) => *first = json!([first.take(), value]),
+                None => {
+                    provenance.insert(key.clone(), json!(value));
+                }
+            }
+        }
+        json!({
+            "manifest_version": MANIFEST_VERSION,
+            "built_at": self.built_at,
+            "tools": { env!("CARGO_PKG_NAME"): env!("CARGO_PKG_VERSION"), "vibrato": env!("VIBRATO_VERSION") },
+            "provenance": provenance,
+            "artifacts": self.artifacts,
+        })
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> Result<()> {
+        serde_json::to_writer_pretty(&mut writer, &self.to_json())?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Serialized as [`BundleManifest::to_json`], the manifest.json layout.
+impl Serialize for BundleManifest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.to_json().serialize(serializer)
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct ConnArgs {
+    /// Preceding word: a context ID or a POS prefix (e.g. 名詞,一般)
+    #[arg(long)]
+    left: String,
+
+    /// Following word: a context ID or a POS prefix (e.g. 助詞,格助詞)
+    #[arg(long)]
+    right: String,
+
+    /// Connection matrix to read (generate it with --keep-intermediate)
+    #[arg(long, default_value = "output/matrix.def")]
+    matrix: PathBuf,
+
+    /// Mozc id.def used to resolve POS names
+    #[arg(long, default_value = "mozc_src/id.def")]
+    id_def: PathBuf,
+
+    /// Maximum number of ID pairs to print
+    #[arg(long, default_value = "50")]
+    limit: usize,
+}
+
+pub fn run(args: &ConnArgs) -> Result<()> {
+    let id_map = mozc_dict_gen::read_id_def_file(&args.id_def)?;
+    let left_ids = resolve_ids(&args.left, &id_map)?;
+    let right_ids = resolve_ids(&args.right, &id_map)?;
+
+    let reader = BufReader::new(File::open(&args.matrix)?);
+    let mut costs = Vec::new();
+    for line in reader.lines().skip(1) {
+        let line = line?;
+        let mut parts = line.split_whitespace();
+        let (Some(left), Some(right), Some(cost)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+        let (left, right): (u16, u16) = (left.parse()?, right.parse()?);
+        if left_ids.contains(&left) && right_ids.contains(&right) {
+            costs.push((left, right, cost.parse::<i16>()?));
+        }
+    }
+
+    anyhow::ensure!(!costs.is_empty(), "No matching ID pairs found in {:?}", args.matrix);
+
+    costs.sort_by_key(|&(_, _, cost)| cost);
+    let unknown = "?".to_string();
+    println!("{:>5} {:>5} {:>6}  Left POS -> Right POS", "Left", "Right", "Cost");
+    for (left, right, cost) in costs.iter().take(args.limit) {
+        println!(
+            "{:>5} {:>5} {:>6}  {} -> {}",
+            left,
+            right,
+            cost,
+            id_map.get(left).unwrap_or(&unknown),
+            id_map.get(right).unwrap_or(&unknown)
+        );
+    }
+    if costs.len() > args.limit {
+        println!("... {} more (raise --limit to see them)", costs.len() - args.limit);
+    }
+
+    let sum: i64 = costs.iter().map(|&(_, _, cost)| cost as i64).sum();
+    println!(
+        "{} pairs: min {}, max {}, mean {:.1}",
+        costs.len(),
+        costs[0].2,
+        costs[costs.len() - 1].2,
+        sum as f64 / costs.len() as f64
+    );
+    Ok(())
+}
+
+/// Resolves a numeric ID as-is, or a POS prefix to every ID whose POS starts with it.
+pub(crate) fn resolve_ids(spec: &str, id_map: &HashMap<u16, String>) -> Result<BTreeSet<u16>> {
+    if let Ok(id) = spec.parse::<u16>() {
+        return Ok(BTreeSet::from([id]));
+    }
+    let ids: BTreeSet<u16> = id_map
+        .iter()
+        .filter(|(_, pos)| pos.starts_with(spec))
+        .map(|(&id, _)| id)
+        .collect();
+    anyhow::ensure!(!ids.is_empty(), "No POS in id.def starts with '{}'", spec);
+    Ok(ids)
+}
@@ -0,0 +1,98 @@
+//! Reading-keyed lexicons for kana→kanji conversion (`--key reading`).
+//!
+//! lex.csv is keyed by surface, as a tokenizer needs. An IME looks words up
+//! by what was typed, so the conversion lexicon swaps the key for the
+//! hiragana reading and keeps the surface as the first feature:
+//!
+//! ```text
+//! lex.csv             記者,1,1,4000,名詞,一般,*,*,*,*,*,きしゃ,きしゃ
+//! conversion_lex.csv  きしゃ,1,1,4000,記者,名詞,一般,*,*,*,*,*,きしゃ,きしゃ
+//! ```
+//!
+//! Tokenizing kana with the compiled conversion.dic.zst then yields the
+//! likeliest segmentation into words, each token's first feature being the
+//! conversion candidate. Context IDs and costs are unchanged, so the
+//! matrix, char.def and unk.def of the system dictionary are reused.
+//! Entries without a kana reading (`*`) can't be typed and are left out.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::{VibratoSources, POS_COLUMNS};
+
+/// File name of the reading-keyed lexicon next to lex.csv.
+pub const CONVERSION_LEX: &str = "conversion_lex.csv";
+
+/// File name of the compiled conversion dictionary, before the extension of
+/// its compression.
+pub const CONVERSION_DIC: &str = "conversion.dic";
+
+/// Column of the reading in a lex.csv row in Mozc's feature layout.
+const READING_COLUMN: usize = 4 + POS_COLUMNS;
+
+/// Rewrites lex.csv rows in Mozc's feature layout as reading-keyed rows,
+/// returning how many were written.
+pub fn write_reading_keyed(lex: impl Read, writer: impl Write) -> Result<usize> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(lex);
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .quote_style(csv::QuoteStyle::Necessary)
+        .from_writer(writer);
+
+    let mut written = 0;
+    for record in reader.records() {
+        let record = record.context("Unreadable lex.csv")?;
+        let Some(reading) = record.get(READING_COLUMN).filter(|reading| is_kana(reading)) else {
+            continue;
+        };
+        let mut row = vec![reading, &record[1], &record[2], &record[3], &record[0]];
+        row.extend(record.iter().skip(4));
+        writer.write_record(&row)?;
+        written += 1;
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+/// The in-memory builder inputs with a reading-keyed lexicon.
+pub fn reading_keyed(sources: &VibratoSources) -> Result<VibratoSources> {
+    let mut lex = Vec::new();
+    write_reading_keyed(&sources.lex[..], &mut lex)?;
+    Ok(VibratoSources {
+        lex,
+        ..sources.clone()
+    })
+}
+
+/// Compiles the reading-keyed lexicon of in-memory builder inputs,
+/// returning the dictionary and the number of entries.
+pub fn compile_sources(sources: &VibratoSources) -> Result<(vibrato::Dictionary, usize)> {
+    let mut lex = Vec::new();
+    let entries = write_reading_keyed(&sources.lex[..], &mut lex)?;
+    Ok((sources.compile_lex(&lex[..])?, entries))
+}
+
+/// Writes [`CONVERSION_LEX`] from the lex.csv in `dir` and compiles it
+/// with the other builder inputs there, returning the dictionary and the
+/// number of entries.
+pub fn compile_dir(dir: &Path) -> Result<(vibrato::Dictionary, usize)> {
+    let lex = BufReader::new(File::open(dir.join("lex.csv"))?);
+    let entries = write_reading_keyed(lex, BufWriter::new(File::create(dir.join(CONVERSION_LEX))?))?;
+    let dict = vibrato::SystemDictionaryBuilder::from_readers(
+        File::open(dir.join(CONVERSION_LEX))?,
+        File::open(dir.join("matrix.def"))?,
+        File::open(dir.join("char.def"))?,
+        File::open(dir.join("unk.def"))?,
+    )?;
+    Ok((dict, entries))
+}
+
+pub(crate) fn is_kana(reading: &str) -> bool {
+    !reading.is_empty() && reading.chars().all(|c| matches!(c, 'ぁ'..='ゖ' | 'ー' | 'ァ'..='ヶ'))
+}
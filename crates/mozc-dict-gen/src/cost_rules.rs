@@ -0,0 +1,137 @@
+//! Cost adjustment rules (`--cost-rules rules.toml`), applied to Mozc
+//! entries as the lexicon is converted:
+//!
+//! ```toml
+//! [[rule]]
+//! name = "single-character nouns"
+//! pos = "名詞"          # id.def POS prefix, whole fields
+//! surface = "^.$"       # regex on the surface
+//! add = 800
+//!
+//! [[rule]]
+//! name = "emoji"
+//! surface = "\\p{Extended_Pictographic}"
+//! multiply = 0.8
+//! ```
+//!
+//! A rule applies to entries matching all of its `pos`, `surface` and
+//! `reading` (regex on the hiragana reading) conditions; it needs at least
+//! one. The cost is multiplied by `multiply`, then `add` is added, and the
+//! result is clamped to the i16 costs of lex.csv. Rules apply in file order,
+//! every matching rule in turn, and how many entries each adjusted is
+//! reported in the manifests.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rule: Vec<RawRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    name: String,
+    pos: Option<String>,
+    surface: Option<String>,
+    reading: Option<String>,
+    add: Option<i32>,
+    multiply: Option<f64>,
+}
+
+/// One `[[rule]]` of the file.
+#[derive(Clone, Debug)]
+pub struct CostRule {
+    pub name: String,
+    pos: Option<String>,
+    surface: Option<Regex>,
+    reading: Option<Regex>,
+    add: i32,
+    multiply: f64,
+}
+
+impl CostRule {
+    pub fn matches(&self, pos: &str, surface: &str, reading: &str) -> bool {
+        let pos_matches = self.pos.as_deref().is_none_or(|prefix| {
+            pos == prefix || pos.strip_prefix(prefix).is_some_and(|rest| rest.starts_with(','))
+        });
+        pos_matches
+            && self.surface.as_ref().is_none_or(|regex| regex.is_match(surface))
+            && self.reading.as_ref().is_none_or(|regex| regex.is_match(reading))
+    }
+
+    pub fn adjust(&self, cost: i16) -> i16 {
+        let cost = (f64::from(cost) * self.multiply).round() + f64::from(self.add);
+        cost.clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16
+    }
+}
+
+/// The rules of a file, in order.
+#[derive(Clone, Debug, Default)]
+pub struct CostRules {
+    rules: Vec<CostRule>,
+}
+
+impl CostRules {
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: RulesFile = toml::from_str(text)?;
+        let mut rules = Vec::new();
+        for raw in file.rule {
+            let name = raw.name;
+            anyhow::ensure!(
+                raw.pos.is_some() || raw.surface.is_some() || raw.reading.is_some(),
+                "Rule {:?} matches every entry; give pos, surface or reading",
+                name
+            );
+            anyhow::ensure!(raw.add.is_some() || raw.multiply.is_some(), "Rule {:?} has no add or multiply", name);
+            let multiply = raw.multiply.unwrap_or(1.0);
+            anyhow::ensure!(multiply.is_finite() && multiply > 0.0, "Rule {:?}: multiply must be positive", name);
+            let regex = |pattern: Option<String>, field: &str| {
+                pattern
+                    .map(|pattern| Regex::new(&pattern))
+                    .transpose()
+                    .with_context(|| format!("Rule {:?}: invalid {} regex", name, field))
+            };
+            rules.push(CostRule {
+                pos: raw.pos,
+                surface: regex(raw.surface, "surface")?,
+                reading: regex(raw.reading, "reading")?,
+                add: raw.add.unwrap_or(0),
+                multiply,
+                name,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        Self::parse(&text).with_context(|| format!("Invalid cost rules {:?}", path))
+    }
+
+    pub fn rules(&self) -> &[CostRule] {
+        &self.rules
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The cost of an entry after every rule matching it, counting the
+    /// entries each rule adjusted in `adjusted` (one count per rule).
+    pub fn apply(&self, pos: &str, surface: &str, reading: &str, cost: i16, adjusted: &mut [usize]) -> i16 {
+        let mut cost = cost;
+        for (rule, count) in self.rules.iter().zip(adjusted) {
+            if rule.matches(pos, surface, reading) {
+                cost = rule.adjust(cost);
+                *count += 1;
+            }
+        }
+        cost
+    }
+}
@@ -0,0 +1,163 @@
+//! Differences between two lexicons (`mozc-dict-gen diff`), to review what
+//! an upstream Mozc change moved before shipping the new dictionary.
+//!
+//! Entries are matched on surface, reading and POS (as `stats` groups it),
+//! not on context IDs, which id.def renumbers between Mozc versions. An
+//! entry present on one side only is added or removed; one on both sides
+//! with another cost is changed. A key listed more than once in a lexicon
+//! (rare, e.g. under two conjugation forms) is compared at its lowest cost.
+//!
+//! Compiled dictionaries keep word features but not surfaces, so they cannot
+//! be listed back; diff the lex.csv of each build (`--keep-intermediate`).
+
+use anyhow::{Context, Result};
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::stats::pos_group;
+use crate::{GZIP_MAGIC, POS_COLUMNS, ZSTD_MAGIC};
+
+/// (surface, reading, POS)
+pub type EntryKey = (String, String, String);
+
+/// Lowest cost of each entry of a lex.csv.
+pub fn read_lex(reader: impl Read) -> Result<BTreeMap<EntryKey, i16>> {
+    let mut lex = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(reader);
+    let mut entries = BTreeMap::new();
+    for (line_no, record) in lex.records().enumerate() {
+        let record = record?;
+        // surface, left_id, right_id, cost, 7 POS columns, reading, pronunciation
+        if record.len() < 4 + POS_COLUMNS + 1 {
+            continue;
+        }
+        let cost: i16 = record[3].parse().with_context(|| format!("lex.csv line {}: bad cost", line_no + 1))?;
+        let key = (record[0].to_string(), record[4 + POS_COLUMNS].to_string(), pos_group(&record));
+        match entries.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(cost);
+            }
+            Entry::Occupied(mut entry) => {
+                let lowest = entry.get_mut();
+                *lowest = (*lowest).min(cost);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Reads lex.csv at `path`, refusing compiled dictionaries with a pointer to
+/// the lexicon they were built from.
+pub fn read_lex_file(path: &Path) -> Result<BTreeMap<EntryKey, i16>> {
+    let mut reader = BufReader::new(File::open(path).with_context(|| format!("Failed to open {:?}", path))?);
+    let mut head = [0; 4];
+    let read = reader.read(&mut head)?;
+    let head = &head[..read];
+    anyhow::ensure!(
+        !head.starts_with(&ZSTD_MAGIC) && !head.starts_with(&GZIP_MAGIC) && !head.contains(&0),
+        "{:?} is a compiled dictionary, which does not keep its surfaces; diff the lex.csv kept with --keep-intermediate",
+        path
+    );
+    read_lex(head.chain(reader)).with_context(|| format!("In {:?}", path))
+}
+
+/// An entry of a [`LexDiff`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffEntry {
+    pub surface: String,
+    pub reading: String,
+    pub pos: String,
+    /// Cost in the old lexicon, if it has the entry
+    pub old_cost: Option<i16>,
+    /// Cost in the new lexicon, if it has the entry
+    pub new_cost: Option<i16>,
+}
+
+impl DiffEntry {
+    /// New cost minus old cost, for changed entries.
+    pub fn cost_delta(&self) -> Option<i32> {
+        Some(self.new_cost? as i32 - self.old_cost? as i32)
+    }
+}
+
+/// Counts of one POS in a [`LexDiff`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PosDiff {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    /// Sum of the cost deltas of the changed entries
+    pub cost_delta: i64,
+}
+
+impl PosDiff {
+    pub fn mean_cost_delta(&self) -> f64 {
+        if self.changed == 0 {
+            0.0
+        } else {
+            self.cost_delta as f64 / self.changed as f64
+        }
+    }
+}
+
+/// Entries added, removed and changed between two lexicons, each sorted by
+/// POS, surface and reading.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LexDiff {
+    pub added: Vec<DiffEntry>,
+    pub removed: Vec<DiffEntry>,
+    pub changed: Vec<DiffEntry>,
+    /// Entries both lexicons have at the same cost
+    pub unchanged: usize,
+}
+
+impl LexDiff {
+    pub fn new(old: &BTreeMap<EntryKey, i16>, new: &BTreeMap<EntryKey, i16>) -> Self {
+        let entry = |(surface, reading, pos): &EntryKey, old_cost, new_cost| DiffEntry {
+            surface: surface.clone(),
+            reading: reading.clone(),
+            pos: pos.clone(),
+            old_cost,
+            new_cost,
+        };
+        let mut diff = Self::default();
+        for (key, &old_cost) in old {
+            match new.get(key) {
+                None => diff.removed.push(entry(key, Some(old_cost), None)),
+                Some(&new_cost) if new_cost == old_cost => diff.unchanged += 1,
+                Some(&new_cost) => diff.changed.push(entry(key, Some(old_cost), Some(new_cost))),
+            }
+        }
+        for (key, &new_cost) in new {
+            if !old.contains_key(key) {
+                diff.added.push(entry(key, None, Some(new_cost)));
+            }
+        }
+        for entries in [&mut diff.added, &mut diff.removed, &mut diff.changed] {
+            entries.sort_by(|a, b| (&a.pos, &a.surface, &a.reading).cmp(&(&b.pos, &b.surface, &b.reading)));
+        }
+        diff
+    }
+
+    /// Counts per POS.
+    pub fn by_pos(&self) -> BTreeMap<&str, PosDiff> {
+        let mut by_pos: BTreeMap<&str, PosDiff> = BTreeMap::new();
+        for entry in &self.added {
+            by_pos.entry(&entry.pos).or_default().added += 1;
+        }
+        for entry in &self.removed {
+            by_pos.entry(&entry.pos).or_default().removed += 1;
+        }
+        for entry in &self.changed {
+            let pos = by_pos.entry(&entry.pos).or_default();
+            pos.changed += 1;
+            pos.cost_delta += entry.cost_delta().unwrap_or_default() as i64;
+        }
+        by_pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
@@ -0,0 +1,257 @@
+//! Fetching the dictionary sources: cached downloads and reading or
+//! unpacking the release archives.
+
+use anyhow::{Context, Result};
+use crate::single_kanji::SINGLE_KANJI_FILE;
+use crate::symbols::{SYMBOL_DIRS, SYMBOL_FILES};
+use crate::sudachi::{self, SudachiSources};
+use crate::unidic::{self, UniDicSources};
+use crate::{Input, MozcSources, CONNECTION_FILES, FILTER_FILES};
+use reqwest::blocking::Client;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Validators saved next to a cached archive, one `name<TAB>value` per line.
+const HEADERS_SUFFIX: &str = ".headers";
+
+/// Validators of a cached download, sent back to revalidate it.
+#[derive(Debug, Default)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Validators {
+    fn read(path: &Path) -> Result<Self> {
+        let mut validators = Self::default();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            match line.split_once('\t') {
+                Some(("etag", value)) => validators.etag = Some(value.to_string()),
+                Some(("last_modified", value)) => validators.last_modified = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        Ok(validators)
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        if let Some(etag) = &self.etag {
+            writeln!(writer, "etag\t{}", etag)?;
+        }
+        if let Some(last_modified) = &self.last_modified {
+            writeln!(writer, "last_modified\t{}", last_modified)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Downloads `url` into `cache_dir`, reusing the cached copy when the server
+/// reports it unchanged (ETag / Last-Modified revalidation). If the server
+/// can't be reached, a cached copy is used as is.
+pub fn fetch_cached(url: &str, cache_dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(cache_dir)?;
+    let path = cache_dir.join(cache_file_name(url));
+    let headers_path = PathBuf::from(format!("{}{}", path.display(), HEADERS_SUFFIX));
+
+    let cached = if path.exists() && headers_path.exists() {
+        Some(Validators::read(&headers_path)?)
+    } else {
+        None
+    };
+
+    let mut request = Client::new().get(url);
+    if let Some(validators) = &cached {
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    if cached.is_some() {
+        tracing::info!("Checking {} for changes to cached {:?}...", url, path);
+    } else {
+        tracing::info!("Downloading {}...", url);
+    }
+    let response = match request.send() {
+        Ok(response) => response,
+        Err(e) if path.exists() => {
+            tracing::warn!("{} unreachable ({}), using cached {:?}", url, e, path);
+            return Ok(path);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if response.status() == StatusCode::NOT_MODIFIED && cached.is_some() {
+        tracing::info!("Cached {:?} is up to date", path);
+        return Ok(path);
+    }
+    let mut response = response.error_for_status()?;
+
+    let header = |name| response.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let validators = Validators {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+
+    // Download next to the cache entry and move it in place once complete,
+    // so an interrupted download never looks cached
+    let partial = PathBuf::from(format!("{}.part", path.display()));
+    let mut file = BufWriter::new(File::create(&partial)?);
+    let bytes = response.copy_to(&mut file)?;
+    file.flush()?;
+    drop(file);
+    fs::rename(&partial, &path)?;
+
+    if validators.is_empty() {
+        // Nothing to revalidate with; download again next time
+        let _ = fs::remove_file(&headers_path);
+    } else {
+        validators.write(&headers_path)?;
+    }
+    tracing::info!("Downloaded {:.1} MB to {:?}", bytes as f64 / 1024.0 / 1024.0, path);
+    Ok(path)
+}
+
+/// Cache file for `url`, named after the archive path so different refs
+/// don't overwrite each other.
+fn cache_file_name(url: &str) -> String {
+    let sanitize = |tail: &str| -> String {
+        tail.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || ".-_".contains(c) { c } else { '_' })
+            .collect()
+    };
+    match url.split_once("/archive/") {
+        Some((_, tail)) => format!("mozc-{}", sanitize(tail)),
+        // The path, as release archives elsewhere share file names across versions
+        None => sanitize(url.split_once("://").and_then(|(_, rest)| rest.split_once('/')).map_or(url, |(_, path)| path)),
+    }
+}
+
+/// Reads the dictionary sources (id.def, the connection file, dictionary*.txt,
+/// the filter lists, single_kanji.tsv and the symbol files) out of the Mozc
+/// source archive at `archive_path` in one pass over the gzip stream, without
+/// unpacking anything to disk. The tar
+/// order doesn't put id.def first, so the sources are held in memory (about
+/// 100 MB) until the conversion reads them.
+pub fn read_dictionary_sources(archive_path: &Path) -> Result<MozcSources> {
+    tracing::info!("Reading dictionary sources from {:?}...", archive_path);
+    let file = File::open(archive_path).with_context(|| format!("Failed to open {:?}", archive_path))?;
+    let tar = flate2::read::GzDecoder::new(BufReader::new(file));
+    let mut archive = tar::Archive::new(tar);
+
+    let mut id_def = None;
+    let mut connection: Option<Input> = None;
+    let mut dictionaries = Vec::new();
+    let mut filters = Vec::new();
+    let mut single_kanji = None;
+    let mut symbols = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?.into_owned();
+        if path.ends_with(Path::new("data/single_kanji").join(SINGLE_KANJI_FILE)) {
+            tracing::info!("Reading {:?}", path);
+            single_kanji = Some(Input::read(SINGLE_KANJI_FILE, entry)?);
+            continue;
+        }
+        let symbol_file = SYMBOL_FILES.into_iter().zip(SYMBOL_DIRS).find(|(name, dir)| {
+            path.ends_with(Path::new("data").join(dir.trim_start_matches("../")).join(name))
+        });
+        if let Some((name, _)) = symbol_file {
+            tracing::info!("Reading {:?}", path);
+            symbols.push(Input::read(name, entry)?);
+            continue;
+        }
+        if !path.parent().is_some_and(|dir| dir.ends_with("data/dictionary_oss")) {
+            continue;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let slot = match name.as_str() {
+            "id.def" => &mut id_def,
+            // Prefer the newer layout should an archive have both
+            _ if CONNECTION_FILES.contains(&name.as_str()) => {
+                if connection.as_ref().is_none_or(|c| c.name() != CONNECTION_FILES[0]) {
+                    tracing::info!("Reading {:?}", path);
+                    connection = Some(Input::read(name, entry)?);
+                }
+                continue;
+            }
+            _ if name.starts_with("dictionary") && name.ends_with(".txt") => {
+                tracing::info!("Reading {:?}", path);
+                dictionaries.push(Input::read(name, entry)?);
+                continue;
+            }
+            _ if FILTER_FILES.contains(&name.as_str()) => {
+                tracing::info!("Reading {:?}", path);
+                filters.push(Input::read(name, entry)?);
+                continue;
+            }
+            _ => continue,
+        };
+        tracing::info!("Reading {:?}", path);
+        *slot = Some(Input::read(name, entry)?);
+    }
+
+    // Keep lex.csv stable whatever order the archive lists the files in
+    dictionaries.sort_by_key(Input::name);
+    filters.sort_by_key(Input::name);
+    symbols.sort_by_key(|input| SYMBOL_FILES.iter().position(|name| *name == input.name()));
+    Ok(MozcSources {
+        id_def: id_def.context("id.def not found in the Mozc archive")?,
+        connection: connection.with_context(|| format!("{} not found in the Mozc archive", CONNECTION_FILES.join(" or ")))?,
+        dictionaries,
+        filters,
+        single_kanji,
+        symbols,
+    })
+}
+
+/// Unpacks the UniDic files (lex.csv, matrix.def, char.def, unk.def) of the
+/// zip at `archive_path` into `dir`, wherever the archive keeps them. They
+/// are too large to hold in memory (matrix.def alone is about 1 GB).
+pub fn extract_unidic(archive_path: &Path, dir: &Path) -> Result<UniDicSources> {
+    tracing::info!("Extracting UniDic from {:?}...", archive_path);
+    extract_zip(archive_path, dir, |name| unidic::is_lexicon(name) || unidic::DEF_FILES.contains(&name))?;
+    UniDicSources::from_dir(dir)
+}
+
+/// Unpacks the raw SudachiDict release (`archives`: the matrix.def zip and
+/// the lexicon zips) into `dir`.
+pub fn extract_sudachi(archives: &[PathBuf], dir: &Path) -> Result<SudachiSources> {
+    for archive in archives {
+        tracing::info!("Extracting SudachiDict from {:?}...", archive);
+        extract_zip(archive, dir, |name| name == "matrix.def" || sudachi::LEXICON_FILES.contains(&name))?;
+    }
+    SudachiSources::from_dir(dir)
+}
+
+/// Unpacks the files of the zip at `archive_path` whose names pass `wanted`
+/// into `dir`, dropping the directories they are in.
+fn extract_zip(archive_path: &Path, dir: &Path, wanted: impl Fn(&str) -> bool) -> Result<()> {
+    let file = File::open(archive_path).with_context(|| format!("Failed to open {:?}", archive_path))?;
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).context("Not a zip archive")?;
+    fs::create_dir_all(dir)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let Some(path) = entry.enclosed_name() else { continue };
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        if !wanted(&name) {
+            continue;
+        }
+        tracing::info!("Extracting {:?}", path);
+        let mut out = BufWriter::new(File::create(dir.join(&name))?);
+        std::io::copy(&mut entry, &mut out)?;
+        out.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Exports of the lexicon for tools that don't read MeCab's column
+//! conventions (`--export jsonl,sqlite`): the rows of lex.csv with named
+//! fields.
+//!
+//! Every export has the surface, reading, context IDs, cost and `pos` (the
+//! POS and conjugation fields, the first six features), which read the same
+//! whatever `--feature-layout` wrote. `output/lexicon.jsonl` also names each
+//! feature of the layout ([`FeatureLayout::feature_names`]), with columns
+//! past them (annotations) under `extra`:
+//!
+//! ```json
+//! {"surface":"記者","reading":"きしゃ","left_id":1,"right_id":1,"cost":4000,"pos":"名詞,一般,*,*,*,*","base_form":"*",…}
+//! ```
+//!
+//! See [`crate::sqlite`] for `output/lexicon.sqlite`.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::features::FeatureLayout;
+
+/// File name of the JSON Lines export in the output directory.
+pub const JSONL_FILE: &str = "lexicon.jsonl";
+
+/// Features making up `pos`: four POS fields, conjugation type and form.
+const POS_FEATURES: usize = 6;
+
+/// One lex.csv entry.
+#[derive(Serialize)]
+pub struct LexRow<'a> {
+    pub surface: &'a str,
+    pub reading: &'a str,
+    pub left_id: i64,
+    pub right_id: i64,
+    pub cost: i64,
+    pub pos: String,
+    #[serde(skip)]
+    pub features: Vec<&'a str>,
+}
+
+/// A JSON Lines object: the row, then its features by name.
+#[derive(Serialize)]
+struct JsonRow<'a> {
+    #[serde(flatten)]
+    row: &'a LexRow<'a>,
+    #[serde(flatten)]
+    named: BTreeMap<&'static str, &'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    extra: &'a [&'a str],
+}
+
+/// Calls `f` with every row of `lex`, features in `layout`, and returns
+/// how many there were.
+pub fn for_each_row(lex: &[u8], layout: FeatureLayout, mut f: impl FnMut(LexRow) -> Result<()>) -> Result<usize> {
+    let mut lex = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(lex);
+    let mut rows = 0;
+    for (line_no, record) in lex.records().enumerate() {
+        let record = record?;
+        let context = || format!("lex.csv line {}", line_no + 1);
+        anyhow::ensure!(record.len() > 4 + layout.reading_column(), "{}: too few columns", context());
+        let number = |i: usize| record[i].parse::<i64>().with_context(context);
+        let features: Vec<&str> = record.iter().skip(4).collect();
+        f(LexRow {
+            surface: &record[0],
+            reading: features[layout.reading_column()],
+            left_id: number(1)?,
+            right_id: number(2)?,
+            cost: number(3)?,
+            pos: features[..POS_FEATURES].join(","),
+            features,
+        })?;
+        rows += 1;
+    }
+    Ok(rows)
+}
+
+/// Writes `lex` as one JSON object per entry to `writer` and returns how
+/// many were written.
+pub fn write_jsonl(lex: &[u8], layout: FeatureLayout, mut writer: impl Write) -> Result<usize> {
+    let names = layout.feature_names();
+    let rows = for_each_row(lex, layout, |row| {
+        let named = names.iter().copied().zip(row.features.iter().copied());
+        let json = JsonRow {
+            row: &row,
+            // The reading is already a field of the row
+            named: named.filter(|(name, _)| *name != "reading").collect(),
+            extra: row.features.get(names.len()..).unwrap_or_default(),
+        };
+        serde_json::to_writer(&mut writer, &json)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(rows)
+}
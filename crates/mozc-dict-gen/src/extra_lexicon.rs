@@ -0,0 +1,208 @@
+//! Supplementary lexicons in MeCab's CSV format (`--extra-lexicon`), such as
+//! the mecab-ipadic-NEologd seed files, merged into the system dictionary.
+//!
+//! Lines are IPAdic-style CSV:
+//!
+//! ```text
+//! <surface>,<left>,<right>,<cost>,<6 POS columns>,<base form>,<reading>,<pronunciation>
+//! ```
+//!
+//! The IPAdic context IDs are meaningless against Mozc's matrix, so each
+//! entry gets the id.def ID of its POS instead. POS that id.def doesn't have
+//! are rewritten by a [`PosMap`]. Words an earlier dictionary already has
+//! (same reading and surface) are skipped, as are repeats within the
+//! lexicons.
+
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+use crate::overlay;
+use crate::{general_pos_id, IdMap, Input, Rejection, Report};
+
+const HEADER_MAGIC: &str = "#pos-map";
+const FORMAT_VERSION: &str = "v1";
+
+/// Fallbacks for POS Mozc's id.def lacks.
+pub const BUILTIN_POS_MAP: &str = include_str!("pos_map.tsv");
+
+/// Rewrites of MeCab POS prefixes to id.def POS prefixes. Text format (UTF-8,
+/// tab separated, `#` lines after the header are comments):
+///
+/// ```text
+/// #pos-map<TAB>v1
+/// <MeCab POS prefix><TAB><id.def POS prefix>
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PosMap {
+    rules: Vec<(String, String)>,
+}
+
+impl PosMap {
+    pub fn builtin() -> Self {
+        Self::read(BUILTIN_POS_MAP.as_bytes()).expect("Built-in POS map is valid")
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().context("Empty POS map")??;
+        let parts: Vec<&str> = header.split('\t').collect();
+        anyhow::ensure!(parts.first() == Some(&HEADER_MAGIC), "Not a POS map (missing {} header)", HEADER_MAGIC);
+        anyhow::ensure!(parts.get(1) == Some(&FORMAT_VERSION), "Unsupported POS map version {:?}", parts.get(1));
+
+        let mut rules = Vec::new();
+        for (line_no, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            let [from, to] = parts.as_slice() else {
+                anyhow::bail!("Line {}: expected 2 columns", line_no + 2);
+            };
+            rules.push((from.to_string(), to.to_string()));
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{}\t{}", HEADER_MAGIC, FORMAT_VERSION)?;
+        for (from, to) in &self.rules {
+            writeln!(writer, "{}\t{}", from, to)?;
+        }
+        Ok(())
+    }
+
+    /// `pos` with the longest matching rule prefix replaced by its target,
+    /// or as it is if no rule matches.
+    pub fn rewrite(&self, pos: &str) -> String {
+        let rule = self.matching_rules(pos).max_by_key(|(from, _)| from.len());
+        match rule {
+            Some((from, to)) => format!("{}{}", to, &pos[from.len()..]),
+            None => pos.to_string(),
+        }
+    }
+
+    /// The id.def ID for a MeCab POS: the POS itself if id.def has it, else
+    /// that of the first rule, longest prefix first, whose target id.def has.
+    pub fn resolve(&self, id_map: &IdMap, pos: &[&str]) -> Option<u16> {
+        // Trailing `*` fields match any id.def POS with the leading ones
+        let len = pos.iter().rposition(|f| *f != "*").map_or(0, |i| i + 1);
+        let pos = pos[..len].join(",");
+        if let Some((id, _)) = general_pos_id(id_map, &pos) {
+            return Some(id);
+        }
+        let mut rules: Vec<&(String, String)> = self.matching_rules(&pos).collect();
+        rules.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+        rules.iter().find_map(|(_, to)| general_pos_id(id_map, to)).map(|(id, _)| id)
+    }
+
+    /// Rules whose prefix is `pos` or its leading fields.
+    fn matching_rules<'a>(&'a self, pos: &'a str) -> impl Iterator<Item = &'a (String, String)> {
+        self.rules
+            .iter()
+            .filter(move |(from, _)| pos.strip_prefix(from.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with(',')))
+    }
+}
+
+/// Converts `lexicons` into Mozc's dictionary format, one per input, leaving
+/// out words `dictionaries` already have. Invalid lines are rejected into
+/// `report` and skipped words counted in its `duplicates`.
+pub fn to_mozc_dictionaries<'a>(
+    lexicons: &[Input],
+    dictionaries: impl IntoIterator<Item = &'a Input>,
+    id_map: &IdMap,
+    pos_map: &PosMap,
+    report: &mut Report,
+) -> Result<Vec<Input>> {
+    let mut entries = Vec::new();
+    let mut seen = HashSet::new();
+    for input in lexicons {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(input.open()?);
+        let mut lexicon = Vec::new();
+        for record in reader.records() {
+            let record = match record {
+                Ok(record) => record,
+                Err(err) => {
+                    let line_no = err.position().map_or(0, |p| p.line() as usize);
+                    report.reject(rejection(input, line_no, String::new(), format!("unreadable CSV: {}", err)));
+                    continue;
+                }
+            };
+            let line_no = record.position().map_or(0, |p| p.line() as usize);
+            let fields: Vec<&str> = record.iter().collect();
+            match parse_entry(&fields, id_map, pos_map) {
+                Ok((reading, id, cost, surface)) => {
+                    if seen.insert((reading.clone(), surface.to_string())) {
+                        lexicon.push((reading, id, cost, surface.to_string()));
+                    } else {
+                        report.duplicates += 1;
+                    }
+                }
+                Err(reason) => report.reject(rejection(input, line_no, fields.join(","), reason)),
+            }
+        }
+        entries.push((input.name(), lexicon));
+    }
+    if seen.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Only the lexicon words need remembering, however large the dictionaries
+    let mut known = HashSet::new();
+    for input in dictionaries {
+        for line in input.open()?.lines() {
+            let line = line?;
+            let parts: Vec<&str> = line.split('\t').collect();
+            if let [reading, _, _, _, surface, ..] = parts.as_slice() {
+                let key = (reading.to_string(), surface.to_string());
+                if seen.contains(&key) {
+                    known.insert(key);
+                }
+            }
+        }
+    }
+
+    let mut dictionaries = Vec::new();
+    for (name, lexicon) in entries {
+        let mut output = Vec::new();
+        for (reading, id, cost, surface) in lexicon {
+            if known.contains(&(reading.clone(), surface.clone())) {
+                report.duplicates += 1;
+                continue;
+            }
+            writeln!(output, "{}\t{}\t{}\t{}\t{}", reading, id, id, cost, surface)?;
+        }
+        dictionaries.push(Input::bytes(name, output));
+    }
+    Ok(dictionaries)
+}
+
+fn rejection(input: &Input, line_no: usize, line: String, reason: String) -> Rejection {
+    Rejection {
+        source: input.name(),
+        line_no,
+        line,
+        reason,
+    }
+}
+
+fn parse_entry<'a>(fields: &[&'a str], id_map: &IdMap, pos_map: &PosMap) -> Result<(String, u16, i16, &'a str), String> {
+    let [surface, _, _, cost, pos @ .., _, reading, _] = fields else {
+        return Err("expected surface, IDs, cost, POS, base form, reading and pronunciation".to_string());
+    };
+    if pos.len() != 6 {
+        return Err(format!("{} POS columns, expected 6", pos.len()));
+    }
+    if surface.is_empty() || surface.chars().any(char::is_control) {
+        return Err("empty surface or control characters".to_string());
+    }
+    overlay::check_term_length(surface, reading)?;
+    let reading = overlay::to_hiragana(reading).ok_or_else(|| format!("reading {:?} is not kana", reading))?;
+    let id = pos_map.resolve(id_map, pos).ok_or_else(|| format!("no context ID for POS {:?}", pos.join(",")))?;
+    let cost: i32 = cost.parse().map_err(|_| format!("bad cost {:?}", cost))?;
+    Ok((reading, id, cost.clamp(i16::MIN.into(), i16::MAX.into()) as i16, *surface))
+}
@@ -0,0 +1,191 @@
+//! Feature layouts lex.csv and unk.def can be written in. The tools in this
+//! repository read Mozc's layout; NLP tools that expect IPAdic or UniDic
+//! conventions mis-parse it, so `--feature-layout` rewrites the features:
+//!
+//! ```text
+//! mozc    pos1,pos2,pos3,pos4,cType,cForm,lexical form,reading,pronunciation   (hiragana)
+//! ipadic  pos1,pos2,pos3,pos4,cType,cForm,base form,reading,pronunciation      (katakana)
+//! unidic  pos1,pos2,pos3,pos4,cType,cForm,lForm,lemma,orth,pron                (katakana)
+//! ```
+//!
+//! Mozc's POS are IPAdic's, so the ipadic layout keeps them; the unidic
+//! layout renames them with a [`PosMap`] ([`BUILTIN_UNIDIC_POS_MAP`]).
+//! Conjugation types and forms are kept as they are. Annotation columns
+//! stay at the end. `--reading-script` overrides the script of the reading
+//! and pronunciation columns, e.g. katakana in Mozc's layout.
+
+use keyboard_formats::kana;
+
+use crate::extra_lexicon::PosMap;
+use crate::POS_COLUMNS;
+
+/// Mozc POS prefixes and the UniDic POS that replace them.
+pub const BUILTIN_UNIDIC_POS_MAP: &str = include_str!("unidic_pos_map.tsv");
+
+/// POS fields rewritten by the POS map; the two after them are the
+/// conjugation type and form.
+const POS_FIELDS: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum FeatureLayout {
+    /// Mozc's own layout, read by lookup, wiki-ngram and the demo keyboard
+    #[default]
+    Mozc,
+    /// IPAdic's 9 features: base form, katakana reading and pronunciation
+    Ipadic,
+    /// The leading 10 UniDic features, with UniDic POS names
+    Unidic,
+}
+
+impl FeatureLayout {
+    pub fn name(self) -> &'static str {
+        match self {
+            FeatureLayout::Mozc => "mozc",
+            FeatureLayout::Ipadic => "ipadic",
+            FeatureLayout::Unidic => "unidic",
+        }
+    }
+
+    /// Names of the features, as Lindera's schemas and the exports call them.
+    pub fn feature_names(self) -> &'static [&'static str] {
+        match self {
+            FeatureLayout::Mozc | FeatureLayout::Ipadic => &[
+                "major_pos",
+                "middle_pos",
+                "small_pos",
+                "fine_pos",
+                "conjugation_type",
+                "conjugation_form",
+                "base_form",
+                "reading",
+                "pronunciation",
+            ],
+            FeatureLayout::Unidic => &[
+                "major_pos",
+                "middle_pos",
+                "small_pos",
+                "fine_pos",
+                "conjugation_type",
+                "conjugation_form",
+                "reading_form",
+                "lexeme",
+                "orthographic_surface_form",
+                "pronunciation",
+            ],
+        }
+    }
+
+    /// Index of the reading among the features (lForm for UniDic).
+    pub fn reading_column(self) -> usize {
+        match self {
+            FeatureLayout::Mozc | FeatureLayout::Ipadic => POS_COLUMNS,
+            FeatureLayout::Unidic => POS_FIELDS + 2,
+        }
+    }
+}
+
+/// Script readings and pronunciations are written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReadingScript {
+    Hiragana,
+    Katakana,
+}
+
+impl ReadingScript {
+    pub fn name(self) -> &'static str {
+        match self {
+            ReadingScript::Hiragana => "hiragana",
+            ReadingScript::Katakana => "katakana",
+        }
+    }
+
+    /// `text` with its kana in this script (see [`keyboard_formats::kana`]).
+    pub fn convert(self, text: &str) -> String {
+        match self {
+            ReadingScript::Hiragana => kana::to_hiragana(text),
+            ReadingScript::Katakana => kana::to_katakana(text),
+        }
+    }
+}
+
+/// Rewrites Mozc features into a [`FeatureLayout`].
+#[derive(Clone, Debug, Default)]
+pub struct FeatureConversion {
+    layout: FeatureLayout,
+    pos_map: PosMap,
+    reading_script: Option<ReadingScript>,
+}
+
+impl FeatureConversion {
+    /// Conversion to `layout` with its built-in POS map.
+    pub fn new(layout: FeatureLayout) -> Self {
+        let pos_map = match layout {
+            FeatureLayout::Unidic => {
+                PosMap::read(BUILTIN_UNIDIC_POS_MAP.as_bytes()).expect("Built-in UniDic POS map is valid")
+            }
+            FeatureLayout::Mozc | FeatureLayout::Ipadic => PosMap::default(),
+        };
+        Self {
+            layout,
+            pos_map,
+            reading_script: None,
+        }
+    }
+
+    /// Replaces the built-in POS map.
+    pub fn with_pos_map(mut self, pos_map: PosMap) -> Self {
+        self.pos_map = pos_map;
+        self
+    }
+
+    /// Writes readings and pronunciations in `script` instead of the
+    /// layout's (Mozc's as they are, katakana for IPAdic and UniDic).
+    pub fn with_reading_script(mut self, script: ReadingScript) -> Self {
+        self.reading_script = Some(script);
+        self
+    }
+
+    pub fn layout(&self) -> FeatureLayout {
+        self.layout
+    }
+
+    /// Rewrites `features`, given in Mozc's layout (POS columns, reading,
+    /// pronunciation, then any annotation columns), into the layout.
+    /// `surface` is None for unknown words, which have no base form.
+    pub fn convert(&self, surface: Option<&str>, mut features: Vec<String>) -> Vec<String> {
+        if features.len() < POS_COLUMNS + 2 {
+            return features;
+        }
+        let layout_script = (self.layout != FeatureLayout::Mozc).then_some(ReadingScript::Katakana);
+        if let Some(script) = self.reading_script.or(layout_script) {
+            for column in &mut features[POS_COLUMNS..POS_COLUMNS + 2] {
+                *column = script.convert(column);
+            }
+        }
+        if self.layout == FeatureLayout::Mozc {
+            return features;
+        }
+        let extra = features.split_off(POS_COLUMNS + 2);
+        let pronunciation = features[POS_COLUMNS + 1].clone();
+        let reading = features[POS_COLUMNS].clone();
+        let base = match (features[POS_FIELDS + 2].as_str(), surface) {
+            ("*", Some(surface)) => surface.to_string(),
+            (lexical, _) => lexical.to_string(),
+        };
+
+        let pos = self.pos_map.rewrite(&features[..POS_FIELDS].join(","));
+        let mut out: Vec<String> = pos.split(',').map(str::to_string).collect();
+        out.resize(POS_FIELDS, "*".to_string());
+        out.extend(features[POS_FIELDS..POS_FIELDS + 2].iter().cloned());
+        match self.layout {
+            FeatureLayout::Ipadic => out.extend([base, reading, pronunciation]),
+            FeatureLayout::Mozc | FeatureLayout::Unidic => {
+                let orth = surface.map_or("*", |s| s).to_string();
+                out.extend([reading, base, orth, pronunciation]);
+            }
+        }
+        out.extend(extra);
+        out
+    }
+}
+
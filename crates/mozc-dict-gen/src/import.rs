@@ -0,0 +1,365 @@
+//! Import of customer term lists in the formats customers actually send:
+//! CSV, JSON and TSV exported from Excel, feeding the [`overlay`](crate::overlay)
+//! builder.
+//!
+//! CSV and TSV files may start with a header row naming the columns
+//! (`surface`, `reading`, `kind`, `cost`, or 表記, 読み, 種類, コスト); without
+//! one the columns are taken in that order, as in the plain overlay format.
+//! JSON files hold an array of objects with the same keys. UTF-8, UTF-16 with
+//! a BOM (Excel's "Unicode Text") and Shift_JIS (Excel's CSV) are accepted.
+//!
+//! Terms without a reading get one from the system dictionary, and terms the
+//! system dictionary already has are skipped; both are listed in the
+//! [`ImportReport`] for review.
+
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::overlay::{self, Term};
+use crate::{Input, Rejection};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TermFormat {
+    Tsv,
+    Csv,
+    Json,
+}
+
+impl TermFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "tsv" | "txt" => TermFormat::Tsv,
+            "csv" => TermFormat::Csv,
+            "json" => TermFormat::Json,
+            _ => return None,
+        })
+    }
+
+    /// Format named by the file extension; TSV if there's none.
+    pub fn from_path(path: &Path) -> Self {
+        path.extension().and_then(|e| Self::parse(&e.to_string_lossy())).unwrap_or(TermFormat::Tsv)
+    }
+}
+
+/// What the importer needs from the system dictionary.
+pub trait SystemLexicon {
+    /// Reading of `surface` as segmented by the dictionary, or None if any
+    /// part of it is an unknown word.
+    fn reading(&self, surface: &str) -> Option<String>;
+    /// Whether the dictionary has `surface` read as `reading` as one word.
+    fn contains(&self, surface: &str, reading: &str) -> bool;
+}
+
+impl SystemLexicon for vibrato::Tokenizer {
+    fn reading(&self, surface: &str) -> Option<String> {
+        let mut worker = self.new_worker();
+        worker.reset_sentence(surface);
+        worker.tokenize();
+        let mut reading = String::new();
+        for i in 0..worker.num_tokens() {
+            let token = worker.token(i);
+            if token.lex_type() == vibrato::dictionary::LexType::Unknown {
+                return None;
+            }
+            // Features: 7 POS columns, then reading and pronunciation
+            reading.push_str(token.feature().split(',').nth(7).filter(|r| *r != "*")?);
+        }
+        Some(reading).filter(|r| !r.is_empty())
+    }
+
+    fn contains(&self, surface: &str, reading: &str) -> bool {
+        let mut worker = self.new_worker();
+        worker.reset_sentence(surface);
+        worker.tokenize();
+        worker.num_tokens() == 1 && {
+            let token = worker.token(0);
+            token.lex_type() == vibrato::dictionary::LexType::System
+                && token.feature().split(',').nth(7) == Some(reading)
+        }
+    }
+}
+
+/// Result of an import, for the validation report.
+#[derive(Clone, Debug, Default)]
+pub struct ImportReport {
+    /// Name of the term list
+    pub source: String,
+    /// Terms passed on to the overlay
+    pub accepted: usize,
+    /// Every rejected row; unlike [`crate::Report`] nothing is left out
+    pub rejections: Vec<Rejection>,
+    /// Terms whose reading came from the system dictionary, by row
+    pub generated: Vec<(usize, Term)>,
+    /// Terms skipped because the system dictionary already has them, by row
+    pub in_system: Vec<(usize, Term)>,
+}
+
+impl ImportReport {
+    fn reject(&mut self, row: &Row, reason: impl Into<String>) {
+        self.rejections.push(Rejection {
+            source: self.source.clone(),
+            line_no: row.line_no,
+            line: row.line.clone(),
+            reason: reason.into(),
+        });
+    }
+
+    /// Writes the report for the people maintaining the term list.
+    pub fn write_text(&self, mut writer: impl Write) -> Result<()> {
+        writeln!(writer, "Term list: {}", self.source)?;
+        writeln!(writer, "Accepted: {}", self.accepted)?;
+        writeln!(writer, "Rejected: {}", self.rejections.len())?;
+        writeln!(writer, "Readings generated: {}", self.generated.len())?;
+        writeln!(writer, "Already in the system dictionary: {}", self.in_system.len())?;
+
+        if !self.rejections.is_empty() {
+            writeln!(writer, "\nRejected (fix and import again):")?;
+            for r in &self.rejections {
+                writeln!(writer, "  row {}: {}: {}", r.line_no, r.reason, r.line)?;
+            }
+        }
+        if !self.generated.is_empty() {
+            writeln!(writer, "\nReadings generated (check them, or add a reading column):")?;
+            for (row, term) in &self.generated {
+                writeln!(writer, "  row {}: {} → {}", row, term.surface, term.reading)?;
+            }
+        }
+        if !self.in_system.is_empty() {
+            writeln!(writer, "\nAlready in the system dictionary (skipped; give a cost to override):")?;
+            for (row, term) in &self.in_system {
+                writeln!(writer, "  row {}: {} ({})", row, term.surface, term.reading)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A row of the term list, with absent columns left empty.
+#[derive(Debug, Default)]
+struct Row {
+    /// Line in CSV and TSV files, 1-based index in the JSON array
+    line_no: usize,
+    line: String,
+    surface: String,
+    reading: String,
+    kind: String,
+    cost: String,
+    /// Why the row can't be read as a term at all
+    malformed: Option<&'static str>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Column {
+    Surface,
+    Reading,
+    Kind,
+    Cost,
+}
+
+impl Column {
+    /// Column order of files without a header
+    const POSITIONAL: [Column; 4] = [Column::Surface, Column::Reading, Column::Kind, Column::Cost];
+
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.trim().to_lowercase().as_str() {
+            "surface" | "word" | "表記" | "単語" => Column::Surface,
+            "reading" | "yomi" | "読み" | "よみ" => Column::Reading,
+            "kind" | "type" | "種類" | "種別" => Column::Kind,
+            "cost" | "コスト" => Column::Cost,
+            _ => return None,
+        })
+    }
+
+    fn set(self, row: &mut Row, value: &str) {
+        let value = value.trim().to_string();
+        match self {
+            Column::Surface => row.surface = value,
+            Column::Reading => row.reading = value,
+            Column::Kind => row.kind = value,
+            Column::Cost => row.cost = value,
+        }
+    }
+}
+
+/// Reads a term list in `format`. Rows that fail validation are rejected;
+/// a malformed file or header is an error. With `system`, missing readings
+/// are generated and terms it already has are skipped.
+pub fn import_terms(
+    input: &Input,
+    format: TermFormat,
+    system: Option<&dyn SystemLexicon>,
+) -> Result<(Vec<Term>, ImportReport)> {
+    let mut bytes = Vec::new();
+    input.open()?.read_to_end(&mut bytes)?;
+    let text = decode_text(&bytes);
+    let rows = match format {
+        TermFormat::Tsv => read_delimited(&text, b'\t')?,
+        TermFormat::Csv => read_delimited(&text, b',')?,
+        TermFormat::Json => read_json(&text)?,
+    };
+
+    let mut report = ImportReport {
+        source: input.name(),
+        ..Default::default()
+    };
+    let mut terms = Vec::new();
+    let mut seen = HashSet::new();
+    for row in rows {
+        if let Some(reason) = row.malformed.or(row.surface.is_empty().then_some("empty surface")) {
+            report.reject(&row, reason);
+            continue;
+        }
+        let mut generated = false;
+        let reading = if !row.reading.is_empty() {
+            row.reading.clone()
+        } else if let Some(kana) = overlay::to_hiragana(&row.surface) {
+            kana
+        } else if let Some(reading) = system.and_then(|s| s.reading(&row.surface)) {
+            generated = true;
+            reading
+        } else {
+            report.reject(&row, "no reading, and none found in the system dictionary");
+            continue;
+        };
+
+        let term = match overlay::parse_fields(&row.surface, &reading, Some(&row.kind), Some(&row.cost)) {
+            Ok(term) => term,
+            Err(reason) => {
+                report.reject(&row, reason);
+                continue;
+            }
+        };
+        if !seen.insert((term.surface.clone(), term.reading.clone())) {
+            report.reject(&row, "duplicate term");
+        } else if term.cost.is_none() && system.is_some_and(|s| s.contains(&term.surface, &term.reading)) {
+            report.in_system.push((row.line_no, term));
+        } else {
+            if generated {
+                report.generated.push((row.line_no, term.clone()));
+            }
+            terms.push(term);
+        }
+    }
+    report.accepted = terms.len();
+    Ok((terms, report))
+}
+
+/// Decodes a text file by its BOM, falling back to Shift_JIS when it isn't
+/// UTF-8, since that's what Excel writes as "CSV" on Japanese Windows.
+fn decode_text(bytes: &[u8]) -> String {
+    if let Some((encoding, bom_len)) = encoding_rs::Encoding::for_bom(bytes) {
+        return encoding.decode_without_bom_handling(&bytes[bom_len..]).0.into_owned();
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::SHIFT_JIS.decode(bytes).0.into_owned(),
+    }
+}
+
+fn read_delimited(text: &str, delimiter: u8) -> Result<Vec<Row>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .comment(Some(b'#'))
+        .from_reader(text.as_bytes());
+
+    let mut columns: Option<Vec<Option<Column>>> = None;
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        if record.iter().all(|field| field.trim().is_empty()) {
+            // Excel keeps rows that were only cleared
+            continue;
+        }
+        let columns = match &columns {
+            Some(columns) => columns,
+            None => {
+                let (header, is_header) = read_header(&record)?;
+                let columns = columns.insert(header);
+                if is_header {
+                    continue;
+                }
+                columns
+            }
+        };
+
+        let mut row = Row {
+            line_no: record.position().map_or(0, |p| p.line() as usize),
+            line: record.iter().collect::<Vec<_>>().join(&(delimiter as char).to_string()),
+            ..Default::default()
+        };
+        if record.iter().skip(columns.len()).any(|field| !field.trim().is_empty()) {
+            row.malformed = Some("more columns than the header");
+            rows.push(row);
+            continue;
+        }
+        for (column, field) in columns.iter().zip(record.iter()) {
+            if let Some(column) = column {
+                column.set(&mut row, field);
+            }
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Columns named by the first row, or the positional columns if it isn't a
+/// header. Blank header cells mark columns to ignore; a header naming
+/// unknown columns is an error.
+fn read_header(record: &csv::StringRecord) -> Result<(Vec<Option<Column>>, bool)> {
+    let names: Vec<&str> = record.iter().map(str::trim).collect();
+    if names.iter().all(|name| Column::parse(name).is_none()) {
+        return Ok((Column::POSITIONAL.iter().copied().map(Some).collect(), false));
+    }
+
+    let mut header = Vec::new();
+    for name in names {
+        match Column::parse(name) {
+            Some(column) if header.contains(&Some(column)) => bail!("Column {:?} appears twice in the header", name),
+            Some(column) => header.push(Some(column)),
+            None if name.is_empty() => header.push(None),
+            None => bail!("Unknown column {:?} in the header (expected surface, reading, kind, cost)", name),
+        }
+    }
+    if !header.contains(&Some(Column::Surface)) {
+        bail!("The header has no surface column");
+    }
+    Ok((header, true))
+}
+
+fn read_json(text: &str) -> Result<Vec<Row>> {
+    let serde_json::Value::Array(entries) = serde_json::from_str(text)? else {
+        bail!("Expected a JSON array of terms");
+    };
+
+    let mut rows = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let mut row = Row {
+            line_no: i + 1,
+            line: entry.to_string(),
+            ..Default::default()
+        };
+        let Some(fields) = entry.as_object() else {
+            row.malformed = Some("not a JSON object");
+            rows.push(row);
+            continue;
+        };
+        for (name, value) in fields {
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Number(n) => n.to_string(),
+                serde_json::Value::Null => String::new(),
+                _ => value.to_string(),
+            };
+            match Column::parse(name) {
+                Some(column) => column.set(&mut row, &value),
+                None => bail!("Unknown field {:?} in term {} (expected surface, reading, kind, cost)", name, i + 1),
+            }
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
@@ -0,0 +1,1045 @@
+//! Conversion of the Mozc dictionary sources into the text inputs of the
+//! Vibrato dictionary builder (lex.csv, matrix.def, char.def, unk.def).
+//!
+//! [`DictPipeline`] drives the conversion from [`MozcSources`]; other
+//! dictionaries such as [`unidic`] plug in through [`DictSource`]. A source
+//! converts either into files ([`DictSource::write_dir`]) or into memory
+//! ([`DictSource::build`]) for tests and tools that don't need the
+//! intermediate files. Small dictionaries can go all the way to a compiled
+//! system.dic.zst without touching disk ([`DictSource::write_dictionary`]).
+//! [`Builder`] runs a whole build, downloads and manifests included, as the
+//! `mozc-dict-gen` binary does.
+//!
+//! Builds are deterministic: the same sources and options produce
+//! byte-identical dictionaries and FSTs, so their checksums in the bundle
+//! manifest can be verified by rebuilding. Source files are taken in a fixed
+//! order (Mozc's dictionary*.txt sorted by name, whether read from a
+//! directory or the archive, then extra dictionaries in the order given) and
+//! lexicon entries keep their order within each file; nothing is written in
+//! hash map order. Only `built_at` in manifest.json varies, and it follows
+//! `SOURCE_DATE_EPOCH` when set.
+
+use anyhow::{Context, Result};
+use extra_lexicon::PosMap;
+use cost_rules::CostRules;
+use features::FeatureConversion;
+use pos_filter::PosFilter;
+use keyboard_formats::annotation::{self, Annotation, CandidateFlags};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub mod builder;
+pub mod bundle;
+pub mod conversion;
+pub mod cost_rules;
+pub mod diff;
+pub mod download;
+pub mod export;
+pub mod extra_lexicon;
+pub mod features;
+pub mod lindera;
+pub mod mecab;
+pub mod import;
+pub mod overlay;
+pub mod pos_filter;
+pub mod profile;
+pub mod pronunciation;
+pub mod prune;
+pub mod reverse;
+pub mod single_kanji;
+pub mod smoke;
+pub mod sqlite;
+pub mod stats;
+pub mod sudachi;
+pub mod suppression;
+pub mod symbols;
+pub mod unidic;
+pub mod user_dict;
+pub mod zipcode;
+
+pub use builder::{BuildOutput, Builder, LexiconKey, Source};
+/// The Vibrato the dictionaries are built and read with. Crates that
+/// tokenize with them use it from here, so there is one Vibrato to match.
+pub use vibrato;
+
+/// Text inputs to the Vibrato builder, in the order `from_readers` takes them.
+pub const INTERMEDIATE_FILES: &[&str] = &["lex.csv", "matrix.def", "char.def", "unk.def"];
+
+/// zstd level of the published system.dic.zst.
+pub const ZSTD_LEVEL: i32 = 19;
+
+/// zstd window of long-window mode (128 MiB), the most decoders accept
+/// without raising their window limit.
+const ZSTD_LONG_WINDOW_LOG: u32 = 27;
+
+/// First bytes of a zstd frame and of a gzip member.
+pub(crate) const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Rejected lexicon lines kept in a [`Report`]; the rest are only counted.
+pub const MAX_REPORTED_REJECTIONS: usize = 20;
+
+/// POS columns of a lex.csv entry, between the cost and the reading.
+pub const POS_COLUMNS: usize = 7;
+
+/// Context ID → comma separated POS, from Mozc's id.def.
+pub type IdMap = HashMap<u16, String>;
+
+/// An input file of the pipeline, on disk or in memory.
+#[derive(Clone, Debug)]
+pub enum Input {
+    Path(PathBuf),
+    Bytes { name: String, data: Vec<u8> },
+}
+
+impl Input {
+    pub fn bytes(name: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        Input::Bytes {
+            name: name.into(),
+            data: data.into(),
+        }
+    }
+
+    /// Reads `reader` to the end into an in-memory input.
+    pub fn read(name: impl Into<String>, mut reader: impl Read) -> Result<Self> {
+        let name = name.into();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).with_context(|| format!("Failed to read {}", name))?;
+        Ok(Input::Bytes { name, data })
+    }
+
+    /// File name used in messages.
+    pub fn name(&self) -> String {
+        match self {
+            Input::Path(path) => path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            Input::Bytes { name, .. } => name.clone(),
+        }
+    }
+
+    pub fn open(&self) -> Result<Box<dyn BufRead + '_>> {
+        Ok(match self {
+            Input::Path(path) => {
+                Box::new(BufReader::new(File::open(path).with_context(|| format!("Failed to open {:?}", path))?))
+            }
+            Input::Bytes { data, .. } => Box::new(&data[..]),
+        })
+    }
+}
+
+/// Names of the connection cost file, newest Mozc first; see
+/// [`ConnectionFormat`].
+pub const CONNECTION_FILES: [&str; 2] = ["connection_single_column.txt", "connection.txt"];
+
+/// Mozc's lists of words kept out of candidates; see [`suppression`].
+pub const FILTER_FILES: [&str; 2] = [suppression::SUGGESTION_FILTER_FILE, suppression::SUPPRESSION_WORD_FILE];
+
+/// The Mozc sources a dictionary is built from.
+#[derive(Clone, Debug)]
+pub struct MozcSources {
+    pub id_def: Input,
+    /// connection_single_column.txt, or connection.txt from older Mozc
+    pub connection: Input,
+    /// dictionary00.txt ... dictionary09.txt
+    pub dictionaries: Vec<Input>,
+    /// The [`FILTER_FILES`] the sources have
+    pub filters: Vec<Input>,
+    /// single_kanji.tsv, if the sources have it
+    pub single_kanji: Option<Input>,
+    /// The [`symbols::SYMBOL_FILES`] the sources have, in that order
+    pub symbols: Vec<Input>,
+}
+
+impl MozcSources {
+    /// Sources in a directory laid out like Mozc's data/dictionary_oss.
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let mut dictionaries = Vec::new();
+        for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name.starts_with("dictionary") && name.ends_with(".txt") {
+                dictionaries.push(path);
+            }
+        }
+        // read_dir order is platform dependent; keep lex.csv stable
+        dictionaries.sort();
+        let connection = CONNECTION_FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| dir.join(CONNECTION_FILES[0]));
+        Ok(Self {
+            id_def: Input::Path(dir.join("id.def")),
+            connection: Input::Path(connection),
+            dictionaries: dictionaries.into_iter().map(Input::Path).collect(),
+            filters: FILTER_FILES
+                .iter()
+                .map(|name| dir.join(name))
+                .filter(|path| path.is_file())
+                .map(Input::Path)
+                .collect(),
+            single_kanji: [dir.to_path_buf(), dir.join(single_kanji::SINGLE_KANJI_DIR)]
+                .iter()
+                .map(|dir| dir.join(single_kanji::SINGLE_KANJI_FILE))
+                .find(|path| path.is_file())
+                .map(Input::Path),
+            symbols: symbols::SYMBOL_FILES
+                .iter()
+                .zip(symbols::SYMBOL_DIRS)
+                .filter_map(|(name, sibling)| {
+                    [dir.join(name), dir.join(sibling).join(name)].into_iter().find(|path| path.is_file())
+                })
+                .map(Input::Path)
+                .collect(),
+        })
+    }
+
+    /// Writes the sources into `dir` under their names, laid out like
+    /// data/dictionary_oss so [`MozcSources::from_dir`] reads them back.
+    pub fn write_dir(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        let inputs = [&self.id_def, &self.connection].into_iter().chain(&self.dictionaries).chain(&self.filters);
+        for input in inputs.chain(&self.single_kanji).chain(&self.symbols) {
+            let path = dir.join(input.name());
+            let mut file = BufWriter::new(File::create(&path).with_context(|| format!("Failed to create {:?}", path))?);
+            std::io::copy(&mut input.open()?, &mut file)?;
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Sources read fully into memory from `id_def`, `connection` and
+    /// (name, reader) pairs of dictionaries, without filter lists,
+    /// single-kanji list or symbols.
+    pub fn from_readers<R: Read>(
+        id_def: impl Read,
+        connection: impl Read,
+        dictionaries: impl IntoIterator<Item = (String, R)>,
+    ) -> Result<Self> {
+        Ok(Self {
+            id_def: Input::read("id.def", id_def)?,
+            connection: Input::read("connection_single_column.txt", connection)?,
+            dictionaries: dictionaries
+                .into_iter()
+                .map(|(name, reader)| Input::read(name, reader))
+                .collect::<Result<_>>()?,
+            filters: Vec::new(),
+            single_kanji: None,
+            symbols: Vec::new(),
+        })
+    }
+}
+
+/// An input line that was skipped as malformed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rejection {
+    pub source: String,
+    /// 1-based
+    pub line_no: usize,
+    pub line: String,
+    pub reason: String,
+}
+
+/// What a pipeline run converted.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// Lexicon entries written to lex.csv
+    pub entries: usize,
+    /// Malformed lexicon lines skipped
+    pub rejected: usize,
+    /// The first [`MAX_REPORTED_REJECTIONS`] rejected lines
+    pub rejections: Vec<Rejection>,
+    /// Extra lexicon words skipped as another dictionary already has them
+    pub duplicates: usize,
+    /// Entries left out by the [`PosFilter`]
+    pub pos_filtered: usize,
+    /// Entries each rule of the [`CostRules`] adjusted, by rule name
+    pub cost_adjustments: Vec<(String, usize)>,
+    /// Word costs seen per left context ID, for calibrating unk.def
+    pub costs: PosCosts,
+}
+
+impl Report {
+    /// Counts a rejected line, keeping it if fewer than
+    /// [`MAX_REPORTED_REJECTIONS`] are kept so far.
+    pub fn reject(&mut self, rejection: Rejection) {
+        self.rejected += 1;
+        if self.rejections.len() < MAX_REPORTED_REJECTIONS {
+            self.rejections.push(rejection);
+        }
+    }
+
+    /// Adds the entries and rejections of `other`.
+    pub fn merge(&mut self, other: Report) {
+        self.entries += other.entries;
+        self.duplicates += other.duplicates;
+        self.pos_filtered += other.pos_filtered;
+        for (name, count) in other.cost_adjustments {
+            match self.cost_adjustments.iter_mut().find(|(known, _)| *known == name) {
+                Some((_, total)) => *total += count,
+                None => self.cost_adjustments.push((name, count)),
+            }
+        }
+        self.rejected += other.rejected - other.rejections.len();
+        for rejection in other.rejections {
+            self.reject(rejection);
+        }
+        for (id, costs) in other.costs.costs {
+            self.costs.costs.entry(id).or_default().extend(costs);
+        }
+    }
+}
+
+/// Word costs of the lexicon grouped by left context ID.
+#[derive(Clone, Debug, Default)]
+pub struct PosCosts {
+    costs: HashMap<u16, Vec<i16>>,
+}
+
+impl PosCosts {
+    pub fn record(&mut self, id: u16, cost: i16) {
+        self.costs.entry(id).or_default().push(cost);
+    }
+
+    /// Cost below which `fraction` of the words with context `id` fall, or
+    /// None if the lexicon has no such words.
+    pub fn percentile(&self, id: u16, fraction: f64) -> Option<i16> {
+        let mut costs = self.costs.get(&id)?.clone();
+        costs.sort_unstable();
+        let index = ((costs.len() - 1) as f64 * fraction.clamp(0.0, 1.0)).round() as usize;
+        Some(costs[index])
+    }
+}
+
+/// Vibrato builder inputs held in memory.
+#[derive(Clone, Debug, Default)]
+pub struct VibratoSources {
+    pub lex: Vec<u8>,
+    pub matrix: Vec<u8>,
+    pub char_def: Vec<u8>,
+    pub unk_def: Vec<u8>,
+}
+
+impl VibratoSources {
+    /// Reads the builder inputs from `dir`, under [`INTERMEDIATE_FILES`]
+    /// names.
+    pub fn read_dir(dir: &Path) -> Result<Self> {
+        let read = |name: &str| {
+            let path = dir.join(name);
+            fs::read(&path).with_context(|| format!("Failed to read {:?}", path))
+        };
+        Ok(Self {
+            lex: read("lex.csv")?,
+            matrix: read("matrix.def")?,
+            char_def: read("char.def")?,
+            unk_def: read("unk.def")?,
+        })
+    }
+
+    pub fn compile(&self) -> Result<vibrato::Dictionary> {
+        self.compile_lex(&self.lex[..])
+    }
+
+    /// Compiles another lexicon with these matrix, char.def and unk.def,
+    /// as the conversion and zip code dictionaries are.
+    pub fn compile_lex(&self, lex: impl Read) -> Result<vibrato::Dictionary> {
+        Ok(vibrato::SystemDictionaryBuilder::from_readers(
+            lex,
+            &self.matrix[..],
+            &self.char_def[..],
+            &self.unk_def[..],
+        )?)
+    }
+}
+
+/// How a compiled dictionary is compressed, written `zstd[:LEVEL[:long]]`,
+/// `gzip` or `none`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// zstd at a level (1-22), optionally in long-window mode
+    Zstd { level: i32, long: bool },
+    Gzip,
+    None,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Zstd {
+            level: ZSTD_LEVEL,
+            long: false,
+        }
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut parts = s.split(':');
+        let compression = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some("gzip"), None, ..) => Compression::Gzip,
+            (Some("none"), None, ..) => Compression::None,
+            (Some("zstd"), level, long, None) => {
+                let level = match level {
+                    Some(level) => level.parse().map_err(|_| format!("invalid zstd level '{}'", level))?,
+                    None => ZSTD_LEVEL,
+                };
+                if !zstd::compression_level_range().contains(&level) {
+                    return Err(format!("zstd level {} is out of range", level));
+                }
+                match long {
+                    None => Compression::Zstd { level, long: false },
+                    Some("long") => Compression::Zstd { level, long: true },
+                    Some(other) => return Err(format!("unknown zstd option '{}'", other)),
+                }
+            }
+            _ => return Err(format!("expected zstd[:LEVEL[:long]], gzip or none, got '{}'", s)),
+        };
+        Ok(compression)
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::Zstd { level, long: false } => write!(f, "zstd:{}", level),
+            Compression::Zstd { level, long: true } => write!(f, "zstd:{}:long", level),
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::None => write!(f, "none"),
+        }
+    }
+}
+
+impl Compression {
+    /// `name` (system.dic) with the extension of the compression.
+    pub fn file_name(self, name: &str) -> String {
+        match self {
+            Compression::Zstd { .. } => format!("{}.zst", name),
+            Compression::Gzip => format!("{}.gz", name),
+            Compression::None => name.to_string(),
+        }
+    }
+}
+
+/// Writes `dict` zstd-compressed, as published in system.dic.zst.
+pub fn write_compressed(dict: &vibrato::Dictionary, writer: impl Write) -> Result<()> {
+    write_dictionary_as(dict, writer, Compression::default())
+}
+
+/// Writes `dict` with `compression`.
+pub fn write_dictionary_as(dict: &vibrato::Dictionary, mut writer: impl Write, compression: Compression) -> Result<()> {
+    match compression {
+        Compression::Zstd { level, long } => {
+            let mut encoder = zstd::Encoder::new(writer, level)?;
+            if long {
+                encoder.long_distance_matching(true)?;
+                encoder.window_log(ZSTD_LONG_WINDOW_LOG)?;
+            }
+            dict.write(&mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::best());
+            dict.write(&mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        Compression::None => {
+            dict.write(&mut writer)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a compiled dictionary in any of the [`Compression`] formats, told
+/// apart by their magic bytes. The file is memory-mapped, so an uncompressed
+/// dictionary (`--compression none`) is deserialized straight from the page
+/// cache, with no decompression or copy into a buffer first.
+pub fn read_dictionary(path: &Path) -> Result<vibrato::Dictionary> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    // Safety: the dictionary is only read while mapped; it is not expected to
+    // change under us, as with any other read-only input of the build.
+    let bytes = unsafe { memmap2::Mmap::map(&file)? };
+    let dict = if bytes.starts_with(&ZSTD_MAGIC) {
+        vibrato::Dictionary::read(zstd::Decoder::with_buffer(&bytes[..])?)
+    } else if bytes.starts_with(&GZIP_MAGIC) {
+        vibrato::Dictionary::read(flate2::read::GzDecoder::new(&bytes[..]))
+    } else {
+        vibrato::Dictionary::read(&bytes[..])
+    };
+    dict.with_context(|| format!("Failed to read the dictionary {:?}", path))
+}
+
+/// The compiled dictionary `name` (system.dic) in `dir`, in whichever
+/// [`Compression`] it was written: `name.zst`, `name.gz` or `name` itself.
+pub fn find_dictionary(dir: &Path, name: &str) -> Option<PathBuf> {
+    [Compression::default(), Compression::Gzip, Compression::None]
+        .into_iter()
+        .map(|compression| dir.join(compression.file_name(name)))
+        .find(|path| path.exists())
+}
+
+/// Compiles the builder inputs previously written to `dir` by
+/// [`DictSource::write_dir`].
+pub fn compile_dir(dir: &Path) -> Result<vibrato::Dictionary> {
+    Ok(vibrato::SystemDictionaryBuilder::from_readers(
+        File::open(dir.join("lex.csv"))?,
+        File::open(dir.join("matrix.def"))?,
+        File::open(dir.join("char.def"))?,
+        File::open(dir.join("unk.def"))?,
+    )?)
+}
+
+/// A dictionary the Vibrato builder inputs can be made from.
+pub trait DictSource {
+    /// Writes lex.csv, matrix.def, char.def and unk.def. lex.csv features
+    /// are [`POS_COLUMNS`] POS columns, then the hiragana reading and
+    /// pronunciation, whatever the source's own layout. Annotation columns
+    /// and id.def POS fields past the POS columns may follow.
+    fn write(&self, lex: impl Write, matrix: impl Write, char_def: impl Write, unk_def: impl Write) -> Result<Report>;
+
+    /// Writes the builder inputs to `dir` under [`INTERMEDIATE_FILES`] names.
+    fn write_dir(&self, dir: &Path) -> Result<Report> {
+        fs::create_dir_all(dir)?;
+        let create = |name: &str| -> Result<BufWriter<File>> { Ok(BufWriter::new(File::create(dir.join(name))?)) };
+        self.write(create("lex.csv")?, create("matrix.def")?, create("char.def")?, create("unk.def")?)
+    }
+
+    /// Builds the builder inputs in memory.
+    fn build(&self) -> Result<(VibratoSources, Report)> {
+        let mut out = VibratoSources::default();
+        let report = self.write(&mut out.lex, &mut out.matrix, &mut out.char_def, &mut out.unk_def)?;
+        Ok((out, report))
+    }
+
+    /// Converts and compiles in memory, for dictionaries small enough that
+    /// the intermediate files aren't worth writing.
+    fn compile(&self) -> Result<(vibrato::Dictionary, Report)> {
+        let (sources, report) = self.build()?;
+        Ok((sources.compile()?, report))
+    }
+
+    /// Converts, compiles and writes the zstd-compressed dictionary to
+    /// `writer` without touching disk.
+    fn write_dictionary(&self, writer: impl Write) -> Result<Report> {
+        let (dict, report) = self.compile()?;
+        write_compressed(&dict, writer)?;
+        Ok(report)
+    }
+}
+
+/// Converts [`MozcSources`], plus any extra dictionaries in Mozc's format,
+/// into Vibrato builder inputs.
+pub struct DictPipeline {
+    sources: MozcSources,
+    extra_dictionaries: Vec<Input>,
+    user_dictionaries: Vec<Input>,
+    extra_lexicons: Vec<Input>,
+    pos_map: PosMap,
+    pos_filter: PosFilter,
+    cost_rules: CostRules,
+    features: FeatureConversion,
+    pronunciation: Pronunciation,
+}
+
+impl DictPipeline {
+    pub fn new(sources: MozcSources) -> Self {
+        Self {
+            sources,
+            extra_dictionaries: Vec::new(),
+            user_dictionaries: Vec::new(),
+            extra_lexicons: Vec::new(),
+            pos_map: PosMap::builtin(),
+            pos_filter: PosFilter::default(),
+            cost_rules: CostRules::default(),
+            features: FeatureConversion::default(),
+            pronunciation: Pronunciation::default(),
+        }
+    }
+
+    /// Writes lex.csv and unk.def features in another layout than Mozc's;
+    /// see [`features`].
+    pub fn features(mut self, features: FeatureConversion) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// How the lex.csv pronunciation column is filled; see [`Pronunciation`].
+    pub fn pronunciation(mut self, pronunciation: Pronunciation) -> Self {
+        self.pronunciation = pronunciation;
+        self
+    }
+
+    /// Adds a lexicon in MeCab's CSV format (see [`extra_lexicon`]), merged
+    /// after every other dictionary without the words those already have.
+    pub fn extra_lexicon(mut self, input: Input) -> Self {
+        self.extra_lexicons.push(input);
+        self
+    }
+
+    /// Replaces the built-in [`PosMap`] for extra lexicons.
+    pub fn pos_map(mut self, pos_map: PosMap) -> Self {
+        self.pos_map = pos_map;
+        self
+    }
+
+    /// Keeps only the entries whose POS `pos_filter` keeps, whichever
+    /// dictionary they come from; see [`pos_filter`].
+    pub fn pos_filter(mut self, pos_filter: PosFilter) -> Self {
+        self.pos_filter = pos_filter;
+        self
+    }
+
+    /// Adjusts the costs of the entries matching `cost_rules`, whichever
+    /// dictionary they come from; see [`cost_rules`].
+    pub fn cost_rules(mut self, cost_rules: CostRules) -> Self {
+        self.cost_rules = cost_rules;
+        self
+    }
+
+    /// Adds a user dictionary (reading, surface, POS name, optional cost;
+    /// see [`user_dict`]), merged last with IDs resolved from id.def.
+    pub fn user_dictionary(mut self, input: Input) -> Self {
+        self.user_dictionaries.push(input);
+        self
+    }
+
+    /// Adds a dictionary in Mozc's format (reading, left ID, right ID, cost,
+    /// surface), merged after the Mozc dictionaries.
+    pub fn extra_dictionary(mut self, input: Input) -> Self {
+        self.extra_dictionaries.push(input);
+        self
+    }
+
+    pub fn extra_dictionaries(mut self, inputs: impl IntoIterator<Item = Input>) -> Self {
+        self.extra_dictionaries.extend(inputs);
+        self
+    }
+
+    /// Every lexicon input in Mozc's format, Mozc's dictionaries first. User
+    /// dictionaries are not included until converted by [`Self::write`].
+    pub fn dictionaries(&self) -> impl Iterator<Item = &Input> {
+        self.sources.dictionaries.iter().chain(&self.extra_dictionaries)
+    }
+
+    pub fn user_dictionaries(&self) -> impl Iterator<Item = &Input> {
+        self.user_dictionaries.iter()
+    }
+
+    pub fn extra_lexicons(&self) -> impl Iterator<Item = &Input> {
+        self.extra_lexicons.iter()
+    }
+
+}
+
+impl DictSource for DictPipeline {
+    fn write(&self, lex: impl Write, matrix: impl Write, char_def: impl Write, unk_def: impl Write) -> Result<Report> {
+        let id_map = read_id_def(self.sources.id_def.open()?)?;
+        let connection = ConnectionMatrix::read(self.sources.connection.open()?)
+            .and_then(|connection| connection.validate_ids(&id_map).map(|_| connection))
+            .with_context(|| format!("Invalid connection matrix {}", self.sources.connection.name()))?;
+        connection.write_matrix_def(matrix)?;
+        let mut extra_report = Report::default();
+        let user_dictionaries = self
+            .user_dictionaries
+            .iter()
+            .map(|input| user_dict::to_mozc_dictionary(input, &id_map, &mut extra_report))
+            .collect::<Result<Vec<_>>>()?;
+        let extra_lexicons = extra_lexicon::to_mozc_dictionaries(
+            &self.extra_lexicons,
+            self.dictionaries().chain(&user_dictionaries),
+            &id_map,
+            &self.pos_map,
+            &mut extra_report,
+        )?;
+        let dictionaries = self.dictionaries().chain(&user_dictionaries).chain(&extra_lexicons);
+        let mut report = convert_lexicon(
+            dictionaries,
+            lex,
+            &id_map,
+            &self.pos_filter,
+            &self.cost_rules,
+            &self.features,
+            self.pronunciation,
+        )?;
+        report.merge(extra_report);
+        generate_char_def(char_def)?;
+        generate_unk_def(unk_def, &id_map, &report.costs, &self.features)?;
+        Ok(report)
+    }
+}
+
+/// Reads id.def: a context ID, a space, then the POS as comma separated
+/// fields, [`POS_COLUMNS`] of them in Mozc's id.def; any past those are kept
+/// too (see [`extra_pos_fields`]). Everything after the first space is the
+/// POS, so fields containing spaces are kept whole.
+pub fn read_id_def(reader: impl BufRead) -> Result<IdMap> {
+    let mut map = HashMap::new();
+
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, pos) = line
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .with_context(|| format!("id.def line {}: expected an ID and a POS, found {:?}", line_no + 1, line))?;
+        let id: u16 = id.parse().with_context(|| format!("id.def line {}: bad ID {:?}", line_no + 1, id))?;
+        map.insert(id, pos.trim_start().to_string());
+    }
+    Ok(map)
+}
+
+/// The first [`POS_COLUMNS`] fields of an id.def POS, padded with `*`.
+pub fn pos_columns(pos: &str) -> Vec<&str> {
+    let mut columns: Vec<&str> = pos.split(',').collect();
+    columns.resize(POS_COLUMNS, "*");
+    columns
+}
+
+/// The fields of an id.def POS past [`POS_COLUMNS`]. lex.csv has them last,
+/// after the annotation columns.
+pub fn extra_pos_fields(pos: &str) -> Vec<&str> {
+    pos.split(',').skip(POS_COLUMNS).collect()
+}
+
+pub fn read_id_def_file(path: &Path) -> Result<IdMap> {
+    read_id_def(BufReader::new(File::open(path).with_context(|| format!("Failed to open {:?}", path))?))
+}
+
+/// Layouts of Mozc's connection cost file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionFormat {
+    /// connection_single_column.txt (Mozc 1.x onwards): the matrix size on
+    /// the first line, then size² costs row by row (left ID major). Some
+    /// releases have one more line between the two, which is not a cost; it
+    /// is recognised by the line count (size² + 1 numbers) or by not being a
+    /// number.
+    SingleColumn,
+    /// connection.txt of older Mozc releases, laid out like MeCab's
+    /// matrix.def: `<size> <size>`, then one `<left> <right> <cost>` line per
+    /// cell.
+    Matrix,
+}
+
+impl ConnectionFormat {
+    /// Tells the formats apart by the first line: one number or two.
+    pub fn detect(header: &str) -> Option<Self> {
+        let fields: Vec<&str> = header.split_whitespace().collect();
+        match fields.as_slice() {
+            [size] if size.parse::<u16>().is_ok() => Some(ConnectionFormat::SingleColumn),
+            [left, right] if left.parse::<u16>().is_ok() && left == right => Some(ConnectionFormat::Matrix),
+            _ => None,
+        }
+    }
+}
+
+/// A validated connection cost matrix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionMatrix {
+    pub format: ConnectionFormat,
+    pub size: u16,
+    /// size² costs, left ID major
+    pub costs: Vec<i16>,
+}
+
+impl ConnectionMatrix {
+    /// Reads either [`ConnectionFormat`]. Any line count or cell that doesn't
+    /// fit the header is an error, so a truncated or mismatched file never
+    /// yields a corrupt matrix.
+    pub fn read(reader: impl BufRead) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().context("Empty connection file")??;
+        let format = ConnectionFormat::detect(&header)
+            .with_context(|| format!("Line 1: expected the matrix size, found {:?}", header))?;
+        let size: u16 = header.split_whitespace().next().unwrap_or_default().parse()?;
+        anyhow::ensure!(size > 0, "Line 1: matrix size is 0");
+        let costs = match format {
+            ConnectionFormat::SingleColumn => read_single_column(lines, size)?,
+            ConnectionFormat::Matrix => read_matrix_cells(lines, size)?,
+        };
+        Ok(Self { format, size, costs })
+    }
+
+    /// Checks that the matrix covers exactly the context IDs of id.def.
+    pub fn validate_ids(&self, id_map: &IdMap) -> Result<()> {
+        let ids = id_map.keys().max().map_or(0, |max| *max as usize + 1);
+        anyhow::ensure!(
+            ids == self.size as usize,
+            "The connection matrix is {}x{} but id.def has {} context IDs; are they from the same Mozc version?",
+            self.size,
+            self.size,
+            ids
+        );
+        Ok(())
+    }
+
+    /// Writes matrix.def, the format Vibrato reads.
+    pub fn write_matrix_def(&self, writer: impl Write) -> Result<()> {
+        let mut writer = BufWriter::new(writer);
+        writeln!(writer, "{} {}", self.size, self.size)?;
+        for (i, cost) in self.costs.iter().enumerate() {
+            writeln!(writer, "{} {} {}", i / self.size as usize, i % self.size as usize, cost)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn read_single_column(lines: impl Iterator<Item = io::Result<String>>, size: u16) -> Result<Vec<i16>> {
+    let cells = size as usize * size as usize;
+    let mut metadata = None;
+    let mut costs: Vec<i16> = Vec::with_capacity(cells + 1);
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        let value = line.trim();
+        if value.is_empty() {
+            continue;
+        }
+        match value.parse() {
+            Ok(cost) => costs.push(cost),
+            Err(_) if costs.is_empty() && metadata.is_none() => metadata = Some(value.to_string()),
+            Err(_) => anyhow::bail!("Line {}: {:?} is not a connection cost", i + 2, value),
+        }
+        anyhow::ensure!(
+            costs.len() <= cells + 1,
+            "More than {} costs after the header (size {}): not a {}x{} matrix",
+            cells + 1,
+            size,
+            size,
+            size
+        );
+    }
+
+    match costs.len() {
+        n if n == cells => Ok(costs),
+        n if n == cells + 1 && metadata.is_none() => Ok(costs.split_off(1)),
+        n => anyhow::bail!(
+            "Found {} costs for a {}x{} matrix, expected {} ({} squared), or {} with a metadata line",
+            n,
+            size,
+            size,
+            cells,
+            size,
+            cells + 1
+        ),
+    }
+}
+
+fn read_matrix_cells(lines: impl Iterator<Item = io::Result<String>>, size: u16) -> Result<Vec<i16>> {
+    let mut cells: Vec<Option<i16>> = vec![None; size as usize * size as usize];
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_no = i + 2;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [left, right, cost] = fields.as_slice() else {
+            anyhow::bail!("Line {}: expected <left> <right> <cost>, found {:?}", line_no, line);
+        };
+        let parse_id = |id: &str| id.parse::<u16>().ok().filter(|id| *id < size);
+        let (Some(left), Some(right)) = (parse_id(left), parse_id(right)) else {
+            anyhow::bail!("Line {}: context ID out of range for a {}x{} matrix", line_no, size, size);
+        };
+        let cost = cost.parse().with_context(|| format!("Line {}: {:?} is not a connection cost", line_no, cost))?;
+        let cell = &mut cells[left as usize * size as usize + right as usize];
+        anyhow::ensure!(cell.is_none(), "Line {}: cell {} {} given twice", line_no, left, right);
+        *cell = Some(cost);
+    }
+
+    let missing = cells.iter().filter(|c| c.is_none()).count();
+    anyhow::ensure!(missing == 0, "{} of {} cells of the {}x{} matrix are missing", missing, cells.len(), size, size);
+    Ok(cells.into_iter().flatten().collect())
+}
+
+/// Converts a Mozc connection file of either [`ConnectionFormat`] into
+/// matrix.def.
+pub fn convert_matrix(reader: impl BufRead, writer: impl Write) -> Result<ConnectionFormat> {
+    let matrix = ConnectionMatrix::read(reader)?;
+    matrix.write_matrix_def(writer)?;
+    Ok(matrix.format)
+}
+
+/// What the pronunciation column of lex.csv holds for Mozc dictionaries,
+/// which only give readings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pronunciation {
+    /// Estimated from the reading by [`pronunciation::estimate`]
+    #[default]
+    Estimated,
+    /// The reading as it is, as before pronunciations were estimated
+    Reading,
+}
+
+/// Converts dictionaries in Mozc's format into a single lex.csv. Malformed
+/// lines are skipped and counted in the report, as are entries `pos_filter`
+/// leaves out and entries `cost_rules` adjust.
+pub fn convert_lexicon<'a>(
+    dictionaries: impl IntoIterator<Item = &'a Input>,
+    writer: impl Write,
+    id_map: &IdMap,
+    pos_filter: &PosFilter,
+    cost_rules: &CostRules,
+    conversion: &FeatureConversion,
+    pronunciation: Pronunciation,
+) -> Result<Report> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .quote_style(csv::QuoteStyle::Necessary)
+        .from_writer(writer);
+
+    let mut report = Report::default();
+    let mut adjusted = vec![0; cost_rules.rules().len()];
+    for input in dictionaries {
+        for (line_no, line) in input.open()?.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            // Mozc format: reading, left_id, right_id, cost, surface, notes...
+            // Example: あいあんと	1852	271	7271	アイアンと
+            let parsed = match parts.as_slice() {
+                [reading, left_id, right_id, cost, surface, ..] => {
+                    match (left_id.parse::<u16>(), right_id.parse::<u16>(), cost.parse::<i16>()) {
+                        (Ok(left_id), Ok(right_id), Ok(cost)) => Ok((*reading, left_id, right_id, cost, *surface)),
+                        _ => Err("unparsable ID or cost"),
+                    }
+                }
+                _ => Err("fewer than 5 columns"),
+            };
+            let (reading, left_id, right_id, cost, surface) = match parsed {
+                Ok(parsed) => parsed,
+                Err(reason) => {
+                    report.reject(Rejection {
+                        source: input.name(),
+                        line_no: line_no + 1,
+                        line: line.clone(),
+                        reason: reason.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            // MeCab format: surface, left, right, cost, pos, ...
+            // We use the POS string from id_map for left_id
+            let pos_str = id_map.get(&left_id).map(|s| s.as_str()).unwrap_or("Unk");
+            if !pos_filter.keeps(pos_str) {
+                report.pos_filtered += 1;
+                continue;
+            }
+            let cost = cost_rules.apply(pos_str, surface, reading, cost, &mut adjusted);
+
+            let mut feature_columns: Vec<String> = pos_columns(pos_str).into_iter().map(str::to_string).collect();
+
+            // MeCab has reading and pronunciation at the end; Mozc only gives
+            // the reading, so the pronunciation is estimated from it
+            feature_columns.push(reading.to_string());
+            feature_columns.push(match pronunciation {
+                Pronunciation::Estimated => pronunciation::estimate(reading, pos_str),
+                Pronunciation::Reading => reading.to_string(),
+            });
+
+            // Candidate annotations, from annotation=/flags= notes and the word itself
+            let mut annotation = Annotation::from_mozc_columns(parts[5..].iter().copied());
+            if pos_str.starts_with("記号") {
+                annotation.flags.insert(CandidateFlags::SYMBOL);
+            }
+            if annotation::is_emoji(surface) {
+                annotation.flags.insert(CandidateFlags::EMOJI);
+            }
+            let annotation_columns = annotation.columns();
+            let extra_pos = extra_pos_fields(pos_str);
+            if annotation_columns.is_some() || !extra_pos.is_empty() {
+                feature_columns.extend(annotation_columns.unwrap_or_else(|| ["*".to_string(), "*".to_string()]));
+            }
+            feature_columns.extend(extra_pos.into_iter().map(str::to_string));
+
+            let mut record = vec![surface.to_string(), left_id.to_string(), right_id.to_string(), cost.to_string()];
+            record.extend(conversion.convert(Some(surface), feature_columns));
+            writer.write_record(&record)?;
+            report.entries += 1;
+            report.costs.record(left_id, cost);
+        }
+    }
+    writer.flush()?;
+    report.cost_adjustments = cost_rules.rules().iter().map(|rule| rule.name.clone()).zip(adjusted).collect();
+    Ok(report)
+}
+
+/// IPADIC-grade character categories; Mozc itself ships no char.def.
+pub const CHAR_DEF: &str = include_str!("char.def");
+
+pub fn generate_char_def(mut writer: impl Write) -> Result<()> {
+    writer.write_all(CHAR_DEF.as_bytes())?;
+    Ok(())
+}
+
+/// Unknown-word entries per char.def category as (category, POS prefix in
+/// id.def). Categories may propose several POS, as in IPADIC's unk.def;
+/// katakana and alphabet runs are often names as well as common nouns.
+const UNKNOWN_WORDS: &[(&str, &str)] = &[
+    ("DEFAULT", "名詞,一般"),
+    ("SPACE", "記号,空白"),
+    ("KANJI", "名詞,一般"),
+    ("KANJI", "名詞,固有名詞,一般"),
+    ("SYMBOL", "記号,一般"),
+    ("NUMERIC", "名詞,数"),
+    ("ALPHA", "名詞,一般"),
+    ("ALPHA", "名詞,固有名詞,一般"),
+    ("HIRAGANA", "名詞,一般"),
+    ("KATAKANA", "名詞,一般"),
+    ("KATAKANA", "名詞,固有名詞,一般"),
+    ("KANJINUMERIC", "名詞,数"),
+    ("GREEK", "名詞,一般"),
+    ("CYRILLIC", "名詞,一般"),
+];
+
+/// Unknown words cost as much as the costliest known words of their POS, so
+/// a dictionary word wins whenever one fits.
+const UNKNOWN_COST_PERCENTILE: f64 = 0.9;
+/// Cost of an unknown word whose POS has no words in the lexicon.
+const DEFAULT_UNKNOWN_COST: i16 = 5000;
+
+/// Context ID of the most general POS whose leading fields are `prefix`
+/// (`名詞,固有名詞`): one whose remaining columns are all `*` if there is one,
+/// the lowest ID otherwise.
+pub fn general_pos_id<'a>(id_map: &'a IdMap, prefix: &str) -> Option<(u16, &'a str)> {
+    let mut matches: Vec<(u16, &str)> = id_map
+        .iter()
+        .filter(|(_, pos)| pos.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with(',')))
+        .map(|(id, pos)| (*id, pos.as_str()))
+        .collect();
+    matches.sort();
+    let general = matches
+        .iter()
+        .find(|(_, pos)| pos[prefix.len()..].split(',').all(|c| c.is_empty() || c == "*"));
+    general.or(matches.first()).copied()
+}
+
+pub fn generate_unk_def(
+    mut writer: impl Write,
+    id_map: &IdMap,
+    costs: &PosCosts,
+    conversion: &FeatureConversion,
+) -> Result<()> {
+    for (category, prefix) in UNKNOWN_WORDS {
+        let (id, pos) = general_pos_id(id_map, prefix).unwrap_or((0, prefix));
+        let cost = if *category == "SPACE" {
+            0
+        } else {
+            costs.percentile(id, UNKNOWN_COST_PERCENTILE).unwrap_or(DEFAULT_UNKNOWN_COST)
+        };
+        // Features: the POS columns, then reading and pronunciation
+        let mut features: Vec<String> = pos_columns(pos).into_iter().map(str::to_string).collect();
+        features.extend(["*".to_string(), "*".to_string()]);
+        let features = conversion.convert(None, features);
+        writeln!(writer, "{},{},{},{},{}", category, id, id, cost, features.join(","))?;
+    }
+    Ok(())
+}
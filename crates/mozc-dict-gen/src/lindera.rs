@@ -0,0 +1,66 @@
+//! Lindera dictionary sources (`--format lindera`), so the search stack
+//! tokenizes with the same lexicon as the keyboard.
+//!
+//! Lindera builds its dictionaries from MeCab's source formats, so lex.csv,
+//! matrix.def, char.def and unk.def are written to `output/lindera/` as
+//! they are, with a `metadata.json` naming the feature columns of the
+//! layout and the UTF-8 encoding. Compile with:
+//!
+//! ```bash
+//! lindera build --src output/lindera --dest output/lindera-dic --metadata output/lindera/metadata.json
+//! ```
+//!
+//! Words Lindera adds without context IDs (simple user dictionary rows) get
+//! those of unk.def's `DEFAULT` row, the general noun.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::features::FeatureLayout;
+use crate::VibratoSources;
+
+/// Directory of the Lindera sources in the output directory.
+pub const LINDERA_DIR: &str = "lindera";
+
+/// Columns of the leading lex.csv fields, before the features.
+const ENTRY_FIELDS: [&str; 4] = ["surface", "left_context_id", "right_context_id", "cost"];
+
+/// metadata.json for the sources: the column schema of `layout`, and the
+/// context IDs and cost of unk.def's `DEFAULT` row as the defaults.
+pub fn metadata(sources: &VibratoSources, layout: FeatureLayout) -> Result<serde_json::Value> {
+    let unk_def = std::str::from_utf8(&sources.unk_def).context("unk.def is not UTF-8")?;
+    let default = unk_def.lines().find(|line| line.starts_with("DEFAULT,")).context("unk.def has no DEFAULT row")?;
+    let fields: Vec<&str> = default.split(',').collect();
+    let number = |i: usize| {
+        let field = fields.get(i).and_then(|field| field.parse::<i32>().ok());
+        field.with_context(|| format!("Bad unk.def row {:?}", default))
+    };
+    let schema: Vec<&str> = ENTRY_FIELDS.iter().chain(layout.feature_names()).copied().collect();
+    Ok(serde_json::json!({
+        "name": format!("mozc-{}", layout.name()),
+        "encoding": "UTF-8",
+        "compress_algorithm": "deflate",
+        "default_word_cost": number(3)?,
+        "default_left_context_id": number(1)?,
+        "default_right_context_id": number(2)?,
+        "default_field_value": "*",
+        // Annotation columns may follow the features
+        "flexible_csv": true,
+        "skip_invalid_cost_or_id": false,
+        "normalize_details": false,
+        "dictionary_schema": { "name": layout.name(), "fields": schema },
+    }))
+}
+
+/// Writes the sources and metadata.json to `dir`.
+pub fn write_dir(dir: &Path, sources: &VibratoSources, layout: FeatureLayout) -> Result<()> {
+    let metadata = metadata(sources, layout)?;
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("lex.csv"), &sources.lex)?;
+    fs::write(dir.join("matrix.def"), &sources.matrix)?;
+    fs::write(dir.join("char.def"), &sources.char_def)?;
+    fs::write(dir.join("unk.def"), &sources.unk_def)?;
+    fs::write(dir.join("metadata.json"), serde_json::to_string_pretty(&metadata)? + "\n")?;
+    Ok(())
+}
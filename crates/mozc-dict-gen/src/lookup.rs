@@ -0,0 +1,75 @@
+use anyhow::Result;
+use mozc_dict_gen::POS_COLUMNS;
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct LookupArgs {
+    /// Exact surface form to match
+    #[arg(long)]
+    surface: Option<String>,
+
+    /// Exact reading to match
+    #[arg(long)]
+    reading: Option<String>,
+
+    /// POS prefix to match, comma separated (e.g. 名詞,固有名詞)
+    #[arg(long)]
+    pos: Option<String>,
+
+    /// Lexicon to scan (generate it with --keep-intermediate)
+    #[arg(long, default_value = "output/lex.csv")]
+    lex: PathBuf,
+
+    /// Maximum number of entries to print
+    #[arg(long, default_value = "50")]
+    limit: usize,
+}
+
+pub fn run(args: &LookupArgs) -> Result<()> {
+    anyhow::ensure!(
+        args.surface.is_some() || args.reading.is_some() || args.pos.is_some(),
+        "Specify at least one of --surface, --reading or --pos"
+    );
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(&args.lex)
+        .map_err(|e| anyhow::anyhow!("Failed to open {:?} (run with --keep-intermediate to keep it): {}", args.lex, e))?;
+
+    println!("{:<16} {:<16} {:>5} {:>5} {:>6}  POS", "Surface", "Reading", "Left", "Right", "Cost");
+
+    let mut matches = 0;
+    for record in reader.records() {
+        let record = record?;
+        // surface, left_id, right_id, cost, 7 POS columns, reading, pronunciation
+        if record.len() < 4 + POS_COLUMNS + 1 {
+            continue;
+        }
+        let surface = &record[0];
+        let reading = &record[4 + POS_COLUMNS];
+        let pos: Vec<&str> = (4..4 + POS_COLUMNS).map(|i| &record[i]).collect();
+        let pos = pos.join(",");
+
+        if args.surface.as_deref().is_some_and(|s| s != surface)
+            || args.reading.as_deref().is_some_and(|r| r != reading)
+            || args.pos.as_deref().is_some_and(|p| !pos.starts_with(p))
+        {
+            continue;
+        }
+
+        matches += 1;
+        if matches <= args.limit {
+            println!(
+                "{:<16} {:<16} {:>5} {:>5} {:>6}  {}",
+                surface, reading, &record[1], &record[2], &record[3], pos
+            );
+        }
+    }
+
+    if matches > args.limit {
+        println!("... {} more (raise --limit to see them)", matches - args.limit);
+    }
+    println!("{} matching entries", matches);
+    Ok(())
+}
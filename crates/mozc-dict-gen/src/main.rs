@@ -1,269 +1,538 @@
 use anyhow::Result;
-use std::collections::HashMap;
+use clap::{Parser, Subcommand};
+use mozc_dict_gen::builder::MANIFEST_FILE;
+use mozc_dict_gen::bundle;
+use mozc_dict_gen::diff::{self, LexDiff};
+use mozc_dict_gen::features::{FeatureLayout, ReadingScript};
+use mozc_dict_gen::import::{self, SystemLexicon, TermFormat};
+use mozc_dict_gen::pos_filter::{PosFilter, PosPattern};
+use mozc_dict_gen::profile::Profile;
+use mozc_dict_gen::stats::DictStats;
+use mozc_dict_gen::{overlay, read_dictionary, Builder, Compression, Input, LexiconKey, Pronunciation, Source};
+use std::ffi::OsString;
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::path::Path;
+use std::io::BufWriter;
+use std::path::PathBuf;
 
-const MOZC_REPO_URL: &str = "https://github.com/google/mozc/archive/refs/heads/master.tar.gz";
+mod conn;
+mod lookup;
+mod whatif;
 
-fn main() -> Result<()> {
-    println!("Downloading Mozc source...");
-    let response = reqwest::blocking::get(MOZC_REPO_URL)?;
-    let bytes = response.bytes()?;
+#[derive(Parser, Debug)]
+#[command(name = "mozc-dict-gen")]
+#[command(about = "Generate a Vibrato dictionary from the Google Mozc dictionary (or UniDic, SudachiDict)")]
+#[command(args_conflicts_with_subcommands = true)]
+#[command(args_override_self = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    println!("Extracting dictionary files...");
-    let tar = flate2::read::GzDecoder::new(&bytes[..]);
-    let mut archive = tar::Archive::new(tar);
+    #[command(flatten)]
+    build: BuildArgs,
+}
 
-    let mozc_src_dir = Path::new("mozc_src");
-    if !mozc_src_dir.exists() {
-        fs::create_dir(mozc_src_dir)?;
-    }
+/// What a build writes besides the Vibrato dictionary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum DictFormat {
+    /// The Vibrato dictionary only
+    Vibrato,
+    /// Also the MeCab dictionary sources
+    Mecab,
+    /// Also the Lindera dictionary sources
+    Lindera,
+}
 
-    for entry in archive.entries()? {
-        let mut entry = entry?;
-        let path = entry.path()?;
-        let path_str = path.to_string_lossy();
-
-        if path_str.contains("data/dictionary_oss/dictionary") && path_str.ends_with(".txt") {
-            println!("Extracting {:?}", path);
-            entry.unpack(mozc_src_dir.join(path.file_name().unwrap()))?;
-        } else if path_str.contains("data/dictionary_oss/connection_single_column.txt") {
-            println!("Extracting {:?}", path);
-            entry.unpack(mozc_src_dir.join(path.file_name().unwrap()))?;
-        } else if path_str.contains("data/dictionary_oss/id.def") {
-            println!("Extracting {:?}", path);
-            entry.unpack(mozc_src_dir.join(path.file_name().unwrap()))?;
-        }
-    }
+/// Forms of the lexicon written for other tools.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum Export {
+    /// lexicon.jsonl
+    Jsonl,
+    /// lexicon.sqlite
+    Sqlite,
+}
 
-    println!("Converting to Vibrato format...");
-    let output_dir = Path::new("output");
-    if !output_dir.exists() {
-        fs::create_dir(output_dir)?;
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Find lexicon entries by surface, reading, or POS
+    Lookup(lookup::LookupArgs),
+    /// Show connection costs between context IDs or POS groups
+    Conn(conn::ConnArgs),
+    /// Compare segmentations before and after applying cost overrides, without writing a dictionary
+    Whatif(whatif::WhatIfArgs),
+    /// Build a user-lexicon overlay from a customer term list
+    Overlay(OverlayArgs),
+    /// Report entries per POS, cost and reading-length distributions,
+    /// duplicate surface/reading pairs and matrix density as JSON
+    Stats(StatsArgs),
+    /// Compare two lexicons: entries added, removed and changed, by POS
+    Diff(DiffArgs),
+}
 
-    let id_map = read_id_def(&mozc_src_dir.join("id.def"))?;
-    
-    println!("Generating matrix.def...");
-    convert_matrix(&mozc_src_dir.join("connection_single_column.txt"), &output_dir.join("matrix.def"))?;
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// Lexicon of the old build (lex.csv, kept with --keep-intermediate)
+    old: PathBuf,
 
-    println!("Generating lex.csv...");
-    convert_lexicon(mozc_src_dir, &output_dir.join("lex.csv"), &id_map)?;
+    /// Lexicon of the new build
+    new: PathBuf,
 
-    println!("Generating char.def...");
-    generate_char_def(&output_dir.join("char.def"))?;
+    /// POS prefix to compare, comma separated (e.g. 名詞,固有名詞)
+    #[arg(long)]
+    pos: Option<String>,
 
-    println!("Generating unk.def...");
-    generate_unk_def(&output_dir.join("unk.def"), &id_map)?;
+    /// Maximum number of added, removed and changed entries each to list
+    #[arg(long, default_value = "20")]
+    limit: usize,
+}
 
-    println!("Compiling dictionary...");
-    let dict = vibrato::SystemDictionaryBuilder::from_readers(
-        File::open(output_dir.join("lex.csv"))?,
-        File::open(output_dir.join("matrix.def"))?,
-        File::open(output_dir.join("char.def"))?,
-        File::open(output_dir.join("unk.def"))?,
-    )?;
+#[derive(clap::Args, Debug)]
+struct StatsArgs {
+    /// Lexicon to read (generate it with --keep-intermediate)
+    #[arg(long, default_value = "output/lex.csv")]
+    lex: PathBuf,
 
-    let mut f = File::create(output_dir.join("system.dic.zst"))?;
-    let mut encoder = zstd::Encoder::new(&mut f, 19)?;
-    dict.write(&mut encoder)?;
-    encoder.finish()?;
+    /// Connection matrix to read (generate it with --keep-intermediate)
+    #[arg(long, default_value = "output/matrix.def")]
+    matrix: PathBuf,
 
-    println!("Done. Dictionary generated at output/system.dic.zst");
-    Ok(())
+    /// Write the report here instead of to stdout
+    #[arg(long)]
+    output: Option<PathBuf>,
 }
 
-fn read_id_def(path: &Path) -> Result<HashMap<u16, String>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let mut map = HashMap::new();
-
-    for line in reader.lines() {
-        let line = line?;
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 2 {
-            let id: u16 = parts[0].parse()?;
-            let pos = parts[1].to_string();
-            map.insert(id, pos);
-        }
+#[derive(clap::Args, Debug)]
+struct OverlayArgs {
+    /// Term list: surface, reading, optional kind (noun, proper, organization,
+    /// person, place) and optional cost, as TSV, CSV (optionally with a header
+    /// row) or a JSON array of objects
+    #[arg(long)]
+    terms: PathBuf,
+
+    /// Format of the term list (tsv, csv, json); guessed from the extension if
+    /// omitted
+    #[arg(long)]
+    format: Option<String>,
+
+    /// Write the validation report here instead of to stdout
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Mozc id.def used to assign context IDs
+    #[arg(long, default_value = "mozc_src/id.def")]
+    id_def: PathBuf,
+
+    /// Output path of the overlay (Vibrato user lexicon CSV)
+    #[arg(long, default_value = "output/overlay.csv")]
+    output: PathBuf,
+
+    /// System dictionary used to generate missing readings, skip terms it
+    /// already has and check that the overlay loads on top of it
+    #[arg(long)]
+    system_dict: Option<PathBuf>,
+
+    /// Fail if any term is rejected
+    #[arg(long)]
+    strict: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct BuildArgs {
+    /// Dictionary to convert
+    #[arg(long, value_enum, default_value = "mozc")]
+    source: Source,
+
+    /// `reading` also builds a reading-keyed conversion.dic.zst next to
+    /// system.dic.zst
+    #[arg(long, value_enum, default_value = "surface")]
+    key: LexiconKey,
+
+    /// Compression of the compiled dictionaries: `zstd:LEVEL` (1-22, add
+    /// `:long` for long-window mode), `gzip` or `none`; the file extension
+    /// follows (system.dic.zst, system.dic.gz, system.dic)
+    #[arg(long, default_value = "zstd:19")]
+    compression: Compression,
+
+    /// Read lex.csv, matrix.def, char.def and unk.def from an unpacked
+    /// unidic-cwj directory instead of downloading it (--source unidic)
+    #[arg(long)]
+    unidic_src: Option<PathBuf>,
+
+    /// Read matrix.def, small_lex.csv and core_lex.csv from a directory
+    /// instead of downloading them (--source sudachi)
+    #[arg(long)]
+    sudachi_src: Option<PathBuf>,
+
+    /// SudachiDict release (date) to download
+    #[arg(long, default_value = "20240409")]
+    sudachi_version: String,
+
+    /// Keep intermediate files (lex.csv, matrix.def, char.def, unk.def) instead of
+    /// removing them after the dictionary is compiled; implies --keep-sources
+    #[arg(long)]
+    keep_intermediate: bool,
+
+    /// Pass the converted lexicon and matrix to the dictionary compiler in memory instead
+    /// of writing and re-reading intermediate files (needs RAM for them; not with
+    /// --keep-intermediate)
+    #[arg(long, conflicts_with = "keep_intermediate")]
+    in_memory: bool,
+
+    /// Prune the lexicon to at most this many entries, keeping the cheapest entries of every
+    /// reading first, for a small (e.g. mobile) dictionary variant
+    #[arg(long)]
+    max_entries: Option<usize>,
+
+    /// Prune the lexicon until the compressed system dictionary is at most this many bytes,
+    /// recompiling with fewer entries while it is over
+    #[arg(long)]
+    max_size_bytes: Option<u64>,
+
+    /// Dictionary formats, comma separated: `mecab` also writes lex.csv, matrix.def, char.def,
+    /// unk.def and a dicrc to output/mecab/ for mecab-dict-index, `lindera` the same with a
+    /// metadata.json to output/lindera/ for `lindera build`; the Vibrato dictionary is always built
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "vibrato")]
+    format: Vec<DictFormat>,
+
+    /// Exports, comma separated: `jsonl` writes the lexicon to output/lexicon.jsonl, one JSON
+    /// object with named fields per entry, `sqlite` to output/lexicon.sqlite with indexed
+    /// surface, reading, left_id, right_id, cost and pos columns
+    #[arg(long, value_enum, value_delimiter = ',')]
+    export: Vec<Export>,
+
+    /// After compiling, load system.dic back and tokenize a few sentences (東京に行きます,
+    /// dates, digits, ASCII), failing the build if they come out empty or garbled
+    #[arg(long)]
+    smoke_test: bool,
+
+    /// Build with the flags of a product target (see mozc_dict_gen::profile); flags given
+    /// explicitly override the profile's
+    #[arg(long, value_enum)]
+    profile: Option<Profile>,
+
+    /// Write the dictionary sources read from the Mozc archive to mozc_src/,
+    /// where tools such as input-data-gen look for id.def
+    #[arg(long)]
+    keep_sources: bool,
+
+    /// Additional dictionary in Mozc's format (reading, left ID, right ID, cost, surface)
+    /// merged into the lexicon, e.g. from input-data-gen (repeatable)
+    #[arg(long = "extra-dictionary")]
+    extra_dictionary: Vec<PathBuf>,
+
+    /// User dictionary (reading, surface, POS name such as 名詞 or 組織, optional
+    /// cost; tab separated) built into the system dictionary with context IDs
+    /// from id.def (repeatable)
+    #[arg(long = "user-dict")]
+    user_dict: Vec<PathBuf>,
+
+    /// Lexicon in MeCab's IPAdic CSV format, such as mecab-ipadic-NEologd
+    /// seed files, merged with context IDs from id.def; words already in
+    /// the other dictionaries are skipped (repeatable)
+    #[arg(long = "extra-lexicon")]
+    extra_lexicon: Vec<PathBuf>,
+
+    /// Keep only lexicon entries whose id.def POS matches this pattern: comma separated
+    /// fields appearing in a row in the POS, `*` matching any text, e.g. 名詞 or
+    /// 固有名詞,人名 (repeatable)
+    #[arg(long = "include-pos")]
+    include_pos: Vec<PosPattern>,
+
+    /// Leave out lexicon entries whose id.def POS matches this pattern, e.g. 記号
+    /// (repeatable)
+    #[arg(long = "exclude-pos")]
+    exclude_pos: Vec<PosPattern>,
+
+    /// TOML file of cost adjustments (multiply and/or add) for entries matched by POS
+    /// prefix and surface or reading regex, applied as the lexicon is converted
+    #[arg(long)]
+    cost_rules: Option<PathBuf>,
+
+    /// POS map (`#pos-map` header) replacing the built-in fallbacks for
+    /// extra lexicon POS id.def lacks
+    #[arg(long, requires = "extra_lexicon")]
+    pos_map: Option<PathBuf>,
+
+    /// Feature layout of lex.csv and unk.def, for NLP tools expecting IPAdic or
+    /// UniDic conventions; the tools in this repository read the mozc layout
+    #[arg(long, value_enum, default_value = "mozc")]
+    feature_layout: FeatureLayout,
+
+    /// POS map (`#pos-map` header) replacing the built-in POS renames of
+    /// --feature-layout
+    #[arg(long)]
+    feature_pos_map: Option<PathBuf>,
+
+    /// Copy readings into the pronunciation column as they are instead of
+    /// estimating pronunciations (long vowels, は/へ/を particles)
+    #[arg(long)]
+    reading_as_pronunciation: bool,
+
+    /// Write readings and pronunciations in this script, in lex.csv and the
+    /// reading-keyed FSTs (default: hiragana, katakana for --feature-layout
+    /// ipadic and unidic)
+    #[arg(long, value_enum)]
+    reading_script: Option<ReadingScript>,
+
+    /// Also write output/single_kanji.fst, Mozc's single-kanji candidates by
+    /// reading, for offering kanji one at a time when conversion fails
+    #[arg(long)]
+    single_kanji: bool,
+
+    /// Also write output/reverse.fst, the readings and costs of each surface
+    /// in the lexicon, for reconversion of committed text
+    #[arg(long)]
+    reverse_lookup: bool,
+
+    /// Also build output/zipcode.dic.zst, Japan Post's postal codes (KEN_ALL)
+    /// keyed by the code as Mozc types them (100-0001) with the address as
+    /// the first feature
+    #[arg(long)]
+    with_zipcode: bool,
+
+    /// Read KEN_ALL from a local ken_all.zip or KEN_ALL.CSV instead of
+    /// downloading it; implies --with-zipcode
+    #[arg(long)]
+    zipcode_src: Option<PathBuf>,
+
+    /// Write the statistics of the built dictionary (as `stats` reports
+    /// them) to this JSON file
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Read dictionary*.txt, the connection file and id.def from an
+    /// existing Mozc checkout (or its dictionary_oss directory) instead of
+    /// downloading the source
+    #[arg(long, conflicts_with = "mozc_ref")]
+    mozc_src: Option<PathBuf>,
+
+    /// Directory the Mozc source archive is cached in, revalidated with the
+    /// server before each build
+    #[arg(long, default_value = "downloads")]
+    download_cache: PathBuf,
+
+    /// Mozc tag or commit to download instead of the tip of master, so
+    /// rebuilds produce the same dictionary
+    #[arg(long)]
+    mozc_ref: Option<String>,
+
+    /// Most lexicon lines that may be rejected as malformed (too few columns,
+    /// unparsable IDs or costs); the build fails when more are
+    #[arg(long, default_value = "0")]
+    max_rejected_lines: usize,
+
+    /// Warn instead of failing when more lines than --max-rejected-lines are
+    /// rejected
+    #[arg(long)]
+    warn_on_rejected_lines: bool,
+}
+
+fn main() -> Result<()> {
+    let _telemetry = telemetry::init("mozc-dict-gen")?;
+    let cli = parse_cli();
+    match cli.command {
+        Some(Command::Lookup(args)) => lookup::run(&args),
+        Some(Command::Conn(args)) => conn::run(&args),
+        Some(Command::Whatif(args)) => whatif::run(&args),
+        Some(Command::Overlay(args)) => build_overlay(&args),
+        Some(Command::Stats(args)) => print_stats(&args),
+        Some(Command::Diff(args)) => print_diff(&args),
+        None => build(&cli.build),
     }
-    Ok(map)
 }
 
-fn convert_matrix(input_path: &Path, output_path: &Path) -> Result<()> {
-    let input_file = File::open(input_path)?;
-    let reader = BufReader::new(input_file);
-    let mut lines = reader.lines();
-
-    let output_file = File::create(output_path)?;
-    let mut writer = BufWriter::new(output_file);
-
-    // First line is size
-    let first_line = lines.next().ok_or_else(|| anyhow::anyhow!("Empty matrix file"))??;
-    let size: u16 = first_line.trim().parse()?;
-
-    writeln!(writer, "{} {}", size, size)?;
-
-    let mut count = 0;
-    for (i, line) in lines.enumerate() {
-        let line = line?;
-        if i == 0 { continue; } // Skip the second line if it's 0/metadata? Wait, let's verify.
-        // Based on inspection:
-        // Line 1: 2671
-        // Line 2: 0
-        // Line 3: 6022
-        // If I skip line 2, and read N*N lines.
-        // Let's assume line 2 is NOT a cost if it's 0 and followed by costs.
-        // But 0 is a valid cost.
-        // Let's assume the file contains N*N lines of costs AFTER the header.
-        // If there are extra lines, we need to handle them.
-        // Actually, let's just read all lines and see.
-        
-        // Re-reading logic:
-        // The file has 7134243 lines.
-        // 1 header line.
-        // 7134242 remaining lines.
-        // 2671 * 2671 = 7134241.
-        // So there is 1 extra line.
-        // Likely line 2 is metadata or padding.
-        // Let's skip line 2.
-        
-        let cost: i16 = line.trim().parse()?;
-        
-        // Calculate left and right ID
-        // The index in the matrix is count.
-        // row = count / size
-        // col = count % size
-        
-        let left_id = count / size as usize;
-        let right_id = count % size as usize;
-        
-        writeln!(writer, "{} {} {}", left_id, right_id, cost)?;
-        
-        count += 1;
-        if count >= (size as usize * size as usize) {
-            break;
-        }
+/// Parses the command line, again with the flags of `--profile` in front if
+/// one is given, so the flags given after them win.
+fn parse_cli() -> Cli {
+    let cli = Cli::parse();
+    let Some(profile) = cli.build.profile else {
+        return cli;
+    };
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    args.splice(1..1, profile.args().iter().map(OsString::from));
+    Cli::parse_from(args)
+}
+
+fn build(args: &BuildArgs) -> Result<()> {
+    anyhow::ensure!(args.unidic_src.is_none() || args.source == Source::Unidic, "--unidic-src needs --source unidic");
+    anyhow::ensure!(args.sudachi_src.is_none() || args.source == Source::Sudachi, "--sudachi-src needs --source sudachi");
+    let mut builder = Builder::new(args.source)
+        .sudachi_version(&args.sudachi_version)
+        .download_cache(&args.download_cache)
+        .key(args.key)
+        .compression(args.compression)
+        .feature_layout(args.feature_layout)
+        .single_kanji(args.single_kanji)
+        .reverse_lookup(args.reverse_lookup)
+        .zipcode(args.with_zipcode)
+        .pos_filter(PosFilter::new(args.include_pos.clone(), args.exclude_pos.clone()))
+        .in_memory(args.in_memory)
+        .mecab(args.format.contains(&DictFormat::Mecab))
+        .lindera(args.format.contains(&DictFormat::Lindera))
+        .sqlite(args.export.contains(&Export::Sqlite))
+        .jsonl(args.export.contains(&Export::Jsonl))
+        .smoke_test(args.smoke_test)
+        .keep_intermediate(args.keep_intermediate)
+        .keep_sources(args.keep_sources)
+        .max_rejected_lines(args.max_rejected_lines)
+        .warn_on_rejected_lines(args.warn_on_rejected_lines);
+    let source_dir = match args.source {
+        Source::Mozc => &args.mozc_src,
+        Source::Unidic => &args.unidic_src,
+        Source::Sudachi => &args.sudachi_src,
+    };
+    if let Some(dir) = source_dir {
+        builder = builder.source_dir(dir);
+    }
+    if let Some(path) = &args.zipcode_src {
+        builder = builder.zipcode_src(path);
+    }
+    if let Some(path) = &args.cost_rules {
+        builder = builder.cost_rules(path);
+    }
+    if let Some(max_entries) = args.max_entries {
+        builder = builder.max_entries(max_entries);
+    }
+    if let Some(max_size_bytes) = args.max_size_bytes {
+        builder = builder.max_size_bytes(max_size_bytes);
+    }
+    if let Some(path) = &args.report {
+        builder = builder.report(path);
+    }
+    if let Some(mozc_ref) = &args.mozc_ref {
+        builder = builder.mozc_ref(mozc_ref);
+    }
+    for path in &args.extra_dictionary {
+        builder = builder.extra_dictionary(path);
+    }
+    for path in &args.user_dict {
+        builder = builder.user_dictionary(path);
     }
-    
+    for path in &args.extra_lexicon {
+        builder = builder.extra_lexicon(path);
+    }
+    if let Some(path) = &args.pos_map {
+        builder = builder.pos_map(path);
+    }
+    if let Some(path) = &args.feature_pos_map {
+        builder = builder.feature_pos_map(path);
+    }
+    if args.reading_as_pronunciation {
+        builder = builder.pronunciation(Pronunciation::Reading);
+    }
+    if let Some(script) = args.reading_script {
+        builder = builder.reading_script(script);
+    }
+    let output = builder.build()?;
+
+    println!(
+        "Done. Dictionary generated at {:?}, sources recorded in output/{} and checksums in output/{}",
+        output.dictionary,
+        MANIFEST_FILE,
+        bundle::BUNDLE_MANIFEST
+    );
     Ok(())
 }
 
-fn convert_lexicon(src_dir: &Path, output_path: &Path, id_map: &HashMap<u16, String>) -> Result<()> {
-    let output_file = File::create(output_path)?;
-    let mut writer = csv::WriterBuilder::new()
-        .has_headers(false)
-        .quote_style(csv::QuoteStyle::Necessary)
-        .from_writer(output_file);
-
-    for entry in fs::read_dir(src_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if let Some(name) = path.file_name() {
-            let name_str = name.to_string_lossy();
-            if name_str.starts_with("dictionary") && name_str.ends_with(".txt") {
-                println!("Processing {:?}", name);
-                let file = File::open(&path)?;
-                let reader = BufReader::new(file);
-                for line in reader.lines() {
-                    let line = line?;
-                    let parts: Vec<&str> = line.split('\t').collect();
-                    // Mozc format: reading, left_id, right_id, cost, surface, ...
-                    // Example: あいあんと	1852	271	7271	アイアンと
-                    if parts.len() >= 5 {
-                        let reading = parts[0];
-                        let left_id: u16 = parts[1].parse()?;
-                        let right_id: u16 = parts[2].parse()?;
-                        let cost: i16 = parts[3].parse()?;
-                        let surface = parts[4];
-                        
-                        // MeCab format: surface, left, right, cost, pos, ...
-                        // We use the POS string from id_map for left_id
-                        let pos_str = id_map.get(&left_id).map(|s| s.as_str()).unwrap_or("Unk,*,*,*,*,*,*");
-                        
-                        // We need to split pos_str into columns
-                        let pos_parts: Vec<&str> = pos_str.split(',').collect();
-                        
-                        let mut record = vec![surface.to_string(), left_id.to_string(), right_id.to_string(), cost.to_string()];
-                        
-                        // Ensure exactly 7 POS fields
-                        for i in 0..7 {
-                            if i < pos_parts.len() {
-                                record.push(pos_parts[i].to_string());
-                            } else {
-                                record.push("*".to_string());
-                            }
-                        }
-                        
-                        // Add reading and pronunciation if available, or use reading for both
-                        // MeCab standard has reading and pronunciation at the end.
-                        // Mozc gives us reading.
-                        record.push(reading.to_string()); // Reading
-                        record.push(reading.to_string()); // Pronunciation (approx)
-                        
-                        writer.write_record(&record)?;
-                    }
-                }
-            }
+fn build_overlay(args: &OverlayArgs) -> Result<()> {
+    let format = match &args.format {
+        Some(name) => TermFormat::parse(name).ok_or_else(|| anyhow::anyhow!("Unknown term list format {:?}", name))?,
+        None => TermFormat::from_path(&args.terms),
+    };
+    let tokenizer = match &args.system_dict {
+        Some(path) => Some(vibrato::Tokenizer::new(read_dictionary(path)?)),
+        None => None,
+    };
+    let system = tokenizer.as_ref().map(|t| t as &dyn SystemLexicon);
+    let (terms, report) = import::import_terms(&Input::Path(args.terms.clone()), format, system)?;
+
+    match &args.report {
+        Some(path) => {
+            report.write_text(BufWriter::new(File::create(path)?))?;
+            println!("Wrote the validation report to {:?}", path);
         }
+        None => report.write_text(std::io::stdout().lock())?,
+    }
+    if args.strict && !report.rejections.is_empty() {
+        anyhow::bail!("{} terms rejected", report.rejections.len());
+    }
+    anyhow::ensure!(!terms.is_empty(), "No valid terms in {:?}", args.terms);
+
+    let id_map = mozc_dict_gen::read_id_def_file(&args.id_def)?;
+    let mut lexicon = Vec::new();
+    overlay::write_user_lexicon(&terms, &id_map, &mut lexicon)?;
+
+    if let Some(path) = &args.system_dict {
+        // The tokenizer owns the first copy
+        let dict = read_dictionary(path)?;
+        dict.reset_user_lexicon_from_reader(Some(&lexicon[..]))?;
+        println!("Overlay loads on top of {:?}", path);
     }
+
+    if let Some(parent) = args.output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&args.output, &lexicon)?;
+    println!("Wrote {} terms to {:?} ({} rejected)", terms.len(), args.output, report.rejections.len());
     Ok(())
 }
 
-fn generate_char_def(output_path: &Path) -> Result<()> {
-    let mut file = File::create(output_path)?;
-    // Minimal char.def based on IPADIC/Vibrato defaults
-    writeln!(file, "DEFAULT 0 1 0")?;
-    writeln!(file, "SPACE 0 1 0")?;
-    writeln!(file, "KANJI 0 0 0")?;
-    writeln!(file, "SYMBOL 0 1 0")?;
-    writeln!(file, "NUMERIC 0 1 0")?;
-    writeln!(file, "ALPHA 0 1 0")?;
-    writeln!(file, "HIRAGANA 0 1 0")?;
-    writeln!(file, "KATAKANA 0 1 0")?;
-    writeln!(file, "KANJINUMERIC 0 1 0")?;
-    writeln!(file, "GREEK 0 1 0")?;
-    writeln!(file, "CYRILLIC 0 1 0")?;
-    
-    writeln!(file, "0x0020 SPACE")?;
-    writeln!(file, "0x0009 SPACE")?;
-    writeln!(file, "0x000D SPACE")?;
-    writeln!(file, "0x000A SPACE")?;
-    
-    writeln!(file, "0x0030..0x0039 NUMERIC")?;
-    writeln!(file, "0x0041..0x005A ALPHA")?;
-    writeln!(file, "0x0061..0x007A ALPHA")?;
-    writeln!(file, "0x3041..0x309F HIRAGANA")?;
-    writeln!(file, "0x30A1..0x30FF KATAKANA")?;
-    writeln!(file, "0x4E00..0x9FFF KANJI")?;
-    
+fn print_stats(args: &StatsArgs) -> Result<()> {
+    let stats = DictStats::from_files(&args.lex, &args.matrix)?;
+    match &args.output {
+        Some(path) => {
+            stats.write(BufWriter::new(File::create(path)?))?;
+            println!("Wrote statistics of {} entries to {:?}", stats.entries, path);
+        }
+        None => stats.write(std::io::stdout().lock())?,
+    }
     Ok(())
 }
 
-fn generate_unk_def(output_path: &Path, id_map: &HashMap<u16, String>) -> Result<()> {
-    let mut file = File::create(output_path)?;
-    // We need to find valid IDs for categories.
-    // For simplicity, we'll just pick the first ID that looks like a Noun, etc.
-    // Or just use ID 0 if we don't care about UNK handling details for now.
-    // But Vibrato might require valid IDs.
-    
-    // Let's try to find a "Noun,General" ID.
-    let noun_id = id_map.iter().find(|(_, v)| v.starts_with("名詞,一般")).map(|(k, _)| *k).unwrap_or(0);
-    let space_id = id_map.iter().find(|(_, v)| v.contains("空白")).map(|(k, _)| *k).unwrap_or(0);
-    
-    // Format: Category, LeftID, RightID, Cost, Features...
-    writeln!(file, "DEFAULT,{},{},5000,名詞,一般,*,*,*,*,*,*,*", noun_id, noun_id)?;
-    writeln!(file, "SPACE,{},{},0,記号,空白,*,*,*,*,*,*,*", space_id, space_id)?;
-    writeln!(file, "KANJI,{},{},5000,名詞,一般,*,*,*,*,*,*,*", noun_id, noun_id)?;
-    writeln!(file, "ALPHA,{},{},5000,名詞,一般,*,*,*,*,*,*,*", noun_id, noun_id)?;
-    writeln!(file, "NUMERIC,{},{},5000,名詞,数,*,*,*,*,*,*,*", noun_id, noun_id)?;
-    writeln!(file, "HIRAGANA,{},{},5000,名詞,一般,*,*,*,*,*,*,*", noun_id, noun_id)?;
-    writeln!(file, "KATAKANA,{},{},5000,名詞,一般,*,*,*,*,*,*,*", noun_id, noun_id)?;
-    writeln!(file, "KANJINUMERIC,{},{},5000,名詞,数,*,*,*,*,*,*,*", noun_id, noun_id)?;
-    writeln!(file, "GREEK,{},{},5000,名詞,一般,*,*,*,*,*,*,*", noun_id, noun_id)?;
-    writeln!(file, "CYRILLIC,{},{},5000,名詞,一般,*,*,*,*,*,*,*", noun_id, noun_id)?;
-    writeln!(file, "SYMBOL,{},{},5000,記号,一般,*,*,*,*,*,*,*", noun_id, noun_id)?;
-    
+fn print_diff(args: &DiffArgs) -> Result<()> {
+    let read = |path| -> Result<_> {
+        let mut entries = diff::read_lex_file(path)?;
+        if let Some(prefix) = &args.pos {
+            entries.retain(|(_, _, pos), _| pos.starts_with(prefix.as_str()));
+        }
+        Ok(entries)
+    };
+    let diff = LexDiff::new(&read(&args.old)?, &read(&args.new)?);
+
+    println!("{:<32} {:>8} {:>8} {:>8} {:>10}", "POS", "Added", "Removed", "Changed", "Mean delta");
+    for (pos, counts) in diff.by_pos() {
+        println!(
+            "{:<32} {:>8} {:>8} {:>8} {:>+10.1}",
+            pos,
+            counts.added,
+            counts.removed,
+            counts.changed,
+            counts.mean_cost_delta()
+        );
+    }
+    println!(
+        "{} added, {} removed, {} changed, {} unchanged",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len(),
+        diff.unchanged
+    );
+
+    let sections = [("Added", '+', &diff.added), ("Removed", '-', &diff.removed), ("Changed", '~', &diff.changed)];
+    for (title, sign, entries) in sections {
+        if entries.is_empty() {
+            continue;
+        }
+        println!("\n{}:", title);
+        for entry in entries.iter().take(args.limit) {
+            let cost = match (entry.old_cost, entry.new_cost, entry.cost_delta()) {
+                (Some(old), Some(new), Some(delta)) => format!("{} -> {} ({:+})", old, new, delta),
+                (old, new, _) => old.or(new).unwrap_or_default().to_string(),
+            };
+            println!("{} {:<16} {:<16} {:<24} {}", sign, entry.surface, entry.reading, entry.pos, cost);
+        }
+        if entries.len() > args.limit {
+            println!("... {} more (raise --limit to see them)", entries.len() - args.limit);
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,63 @@
+//! MeCab dictionary sources (`--format mecab`), for tooling that still runs
+//! classic MeCab off the same lexicon as the Vibrato dictionary.
+//!
+//! Vibrato reads MeCab's source formats, so lex.csv, matrix.def, char.def
+//! and unk.def are written to `output/mecab/` as they are, with a dicrc
+//! for the feature layout. Everything is UTF-8, which the dicrc declares;
+//! compile with:
+//!
+//! ```bash
+//! mecab-dict-index -d output/mecab -o output/mecab-dic -f utf-8 -t utf-8
+//! ```
+//!
+//! The features stay in the `--feature-layout` order; `ipadic` is what most
+//! MeCab tooling expects, and Mozc's layout has the same columns with
+//! hiragana readings.
+
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+use crate::features::FeatureLayout;
+use crate::VibratoSources;
+
+/// Directory of the MeCab sources in the output directory.
+pub const MECAB_DIR: &str = "mecab";
+
+/// dicrc for lexicons in `layout`: BOS/EOS features of the same width and
+/// output formats reading the layout's reading column.
+pub fn dicrc(layout: FeatureLayout) -> String {
+    let columns = layout.feature_names().len();
+    let bos = std::iter::once("BOS/EOS").chain(std::iter::repeat_n("*", columns - 1)).collect::<Vec<_>>().join(",");
+    format!(
+        "; Generated by mozc-dict-gen ({} feature layout)\n\
+         cost-factor = 800\n\
+         bos-feature = {}\n\
+         eval-size = 8\n\
+         unk-eval-size = 4\n\
+         config-charset = utf-8\n\
+         \n\
+         ; yomi\n\
+         node-format-yomi = %pS%f[{}]\n\
+         unk-format-yomi = %M\n\
+         eos-format-yomi = \\n\n\
+         \n\
+         ; simple\n\
+         node-format-simple = %m\\t%F-[0,1,2,3]\\n\n\
+         eos-format-simple = EOS\\n\n",
+        layout.name(),
+        bos,
+        layout.reading_column()
+    )
+}
+
+/// Writes the sources and dicrc to `dir`.
+pub fn write_dir(dir: &Path, sources: &VibratoSources, layout: FeatureLayout) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("lex.csv"), &sources.lex)?;
+    fs::write(dir.join("matrix.def"), &sources.matrix)?;
+    fs::write(dir.join("char.def"), &sources.char_def)?;
+    fs::write(dir.join("unk.def"), &sources.unk_def)?;
+    fs::write(dir.join("dicrc"), dicrc(layout))?;
+    Ok(())
+}
@@ -0,0 +1,205 @@
+//! Overlay dictionaries of a customer's own vocabulary (product names,
+//! departments, people) for enterprise keyboard deployments.
+//!
+//! A term list is validated, each term gets a POS and cost from simple
+//! heuristics, and the result is written as a Vibrato user lexicon that is
+//! applied on top of the system dictionary at load time, so an overlay is
+//! rebuilt in milliseconds without recompiling the system dictionary.
+//!
+//! Term list format (UTF-8, tab separated, `#` starts a comment line):
+//!
+//! ```text
+//! <surface><TAB><reading>[<TAB><kind>[<TAB><cost>]]
+//! ```
+//!
+//! `kind` is one of `noun`, `proper` (default), `organization`, `person`,
+//! `place`; readings may be written in hiragana or katakana.
+
+use anyhow::Result;
+use keyboard_formats::kana;
+use keyboard_formats::text_length::LengthUnit;
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+use crate::{pos_columns, IdMap, Input, Rejection, Report};
+
+/// Longest surface or reading accepted, in characters.
+pub const MAX_TERM_CHARS: usize = 64;
+
+/// Cost of a one-kana term; each further kana lowers it by
+/// [`LENGTH_BONUS`], since long terms are rarely substrings of other words
+/// and must outcompete their own segmentation into shorter words.
+const LENGTH_BONUS: i16 = 250;
+/// Length bonus stops growing past this many kana.
+const MAX_BONUS_CHARS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TermKind {
+    Noun,
+    Proper,
+    Organization,
+    Person,
+    Place,
+}
+
+impl TermKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "noun" => TermKind::Noun,
+            "proper" => TermKind::Proper,
+            "organization" | "org" | "company" => TermKind::Organization,
+            "person" => TermKind::Person,
+            "place" => TermKind::Place,
+            _ => return None,
+        })
+    }
+
+    /// POS prefix in Mozc's id.def.
+    pub fn pos_prefix(self) -> &'static str {
+        match self {
+            TermKind::Noun => "名詞,一般",
+            TermKind::Proper => "名詞,固有名詞,一般",
+            TermKind::Organization => "名詞,固有名詞,組織",
+            TermKind::Person => "名詞,固有名詞,人名,一般",
+            TermKind::Place => "名詞,固有名詞,地域,一般",
+        }
+    }
+
+    /// Cost of a one-kana term, in the range of Mozc's own nouns.
+    fn base_cost(self) -> i16 {
+        match self {
+            TermKind::Noun => 5000,
+            TermKind::Proper | TermKind::Organization => 5500,
+            TermKind::Person | TermKind::Place => 6000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Term {
+    pub surface: String,
+    /// Hiragana
+    pub reading: String,
+    pub kind: TermKind,
+    /// Explicit cost overriding the heuristic
+    pub cost: Option<i16>,
+}
+
+impl Term {
+    pub fn cost(&self) -> i16 {
+        self.cost.unwrap_or_else(|| {
+            let bonus_chars = self.reading.chars().count().saturating_sub(1).min(MAX_BONUS_CHARS);
+            self.kind.base_cost() - LENGTH_BONUS * bonus_chars as i16
+        })
+    }
+}
+
+/// Reads a term list, skipping malformed and duplicate lines. Skipped lines
+/// are listed in the report; `entries` counts the accepted terms.
+pub fn read_terms(input: &Input) -> Result<(Vec<Term>, Report)> {
+    let mut terms = Vec::new();
+    let mut report = Report::default();
+    let mut seen = HashSet::new();
+
+    for (line_no, line) in input.open()?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_term(&line) {
+            Ok(term) if !seen.insert((term.surface.clone(), term.reading.clone())) => report.reject(Rejection {
+                source: input.name(),
+                line_no: line_no + 1,
+                line,
+                reason: "duplicate term".to_string(),
+            }),
+            Ok(term) => terms.push(term),
+            Err(reason) => report.reject(Rejection {
+                source: input.name(),
+                line_no: line_no + 1,
+                line,
+                reason,
+            }),
+        }
+    }
+    report.entries = terms.len();
+    Ok((terms, report))
+}
+
+fn parse_term(line: &str) -> Result<Term, String> {
+    let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+    match fields.as_slice() {
+        [surface, reading, ..] if fields.len() <= 4 => {
+            parse_fields(surface, reading, fields.get(2).copied(), fields.get(3).copied())
+        }
+        _ => Err("expected 2 to 4 tab-separated columns".to_string()),
+    }
+}
+
+/// Rejects a surface or reading longer than [`MAX_TERM_CHARS`] characters
+/// (not bytes, which would allow a third as much kana as ASCII).
+pub(crate) fn check_term_length(surface: &str, reading: &str) -> Result<(), String> {
+    if [surface, reading].iter().any(|text| LengthUnit::Chars.length(text) > MAX_TERM_CHARS) {
+        return Err(format!("longer than {} characters", MAX_TERM_CHARS));
+    }
+    Ok(())
+}
+
+/// Validates the columns of a term; `kind` and `cost` may be empty or missing.
+pub(crate) fn parse_fields(surface: &str, reading: &str, kind: Option<&str>, cost: Option<&str>) -> Result<Term, String> {
+    if surface.is_empty() || surface.chars().any(char::is_control) {
+        return Err("empty surface or control characters".to_string());
+    }
+    check_term_length(surface, reading)?;
+    let reading = to_hiragana(reading).ok_or_else(|| format!("reading {:?} is not kana", reading))?;
+
+    let kind = match kind.filter(|k| !k.is_empty()) {
+        Some(name) => TermKind::parse(name).ok_or_else(|| format!("unknown kind {:?}", name))?,
+        None => TermKind::Proper,
+    };
+    let cost = match cost.filter(|c| !c.is_empty()) {
+        Some(cost) => Some(cost.parse::<i16>().map_err(|_| format!("bad cost {:?}", cost))?),
+        None => None,
+    };
+
+    Ok(Term {
+        surface: surface.to_string(),
+        reading,
+        kind,
+        cost,
+    })
+}
+
+/// Hiragana form of a kana reading (see [`kana::to_hiragana`]), or None if
+/// it contains anything else.
+pub(crate) fn to_hiragana(reading: &str) -> Option<String> {
+    let hiragana = kana::to_hiragana(reading);
+    let is_kana = !hiragana.is_empty() && hiragana.chars().all(|c| matches!(c, 'ぁ'..='ゖ' | 'ー'));
+    is_kana.then_some(hiragana)
+}
+
+/// Writes `terms` as a Vibrato user lexicon (lex.csv rows), with context IDs
+/// of the first id.def POS matching each term's kind.
+pub fn write_user_lexicon(terms: &[Term], id_map: &IdMap, writer: impl Write) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .quote_style(csv::QuoteStyle::Necessary)
+        .from_writer(writer);
+
+    for term in terms {
+        let prefix = term.kind.pos_prefix();
+        let (id, pos) = id_map
+            .iter()
+            .filter(|(_, pos)| pos.starts_with(prefix))
+            .min_by_key(|(id, _)| **id)
+            .ok_or_else(|| anyhow::anyhow!("No POS starting with {} in id.def", prefix))?;
+
+        let mut record = vec![term.surface.clone(), id.to_string(), id.to_string(), term.cost().to_string()];
+        record.extend(pos_columns(pos).into_iter().map(str::to_string));
+        record.push(term.reading.clone());
+        record.push(term.reading.clone());
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
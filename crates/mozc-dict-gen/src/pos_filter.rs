@@ -0,0 +1,101 @@
+//! POS filters for lexicon conversion (`--include-pos`, `--exclude-pos`),
+//! for products that want a different vocabulary than the whole of Mozc's.
+//!
+//! A pattern is a comma separated run of POS fields, matched against the
+//! id.def POS of each entry's left context ID. It matches when its fields
+//! appear one after another anywhere in the POS, and `*` in a field matches
+//! any text, so `記号` matches every symbol, `固有名詞,人名` every personal
+//! name and `動詞,*,*,*,五段*` the godan verbs:
+//!
+//! ```text
+//! 名詞,固有名詞,人名,姓,*,*,*   matched by 固有名詞,人名 and by 人名
+//! ```
+//!
+//! With include patterns only entries matching one of them are kept; entries
+//! matching an exclude pattern are dropped either way.
+
+use std::str::FromStr;
+
+/// One `--include-pos` or `--exclude-pos` pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PosPattern {
+    fields: Vec<String>,
+}
+
+impl FromStr for PosPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let fields: Vec<String> = s.split(',').map(|field| field.trim().to_string()).collect();
+        if fields.iter().any(String::is_empty) {
+            return Err(format!("empty POS field in '{}'", s));
+        }
+        Ok(Self { fields })
+    }
+}
+
+impl std::fmt::Display for PosPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.fields.join(","))
+    }
+}
+
+impl PosPattern {
+    /// Whether the fields of the pattern appear consecutively in `pos`.
+    pub fn matches(&self, pos: &str) -> bool {
+        let pos: Vec<&str> = pos.split(',').collect();
+        pos.windows(self.fields.len())
+            .any(|window| window.iter().zip(&self.fields).all(|(field, pattern)| glob_match(pattern, field)))
+    }
+}
+
+/// Matches `text` against `pattern`, where `*` matches any text.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let Some((first, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(mut text) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = rest.split('*').collect();
+    let last = parts.pop().unwrap_or_default();
+    for part in parts {
+        match text.find(part) {
+            Some(at) => text = &text[at + part.len()..],
+            None => return false,
+        }
+    }
+    text.len() >= last.len() && text.ends_with(last)
+}
+
+/// The entries a lexicon conversion keeps, by POS.
+#[derive(Clone, Debug, Default)]
+pub struct PosFilter {
+    include: Vec<PosPattern>,
+    exclude: Vec<PosPattern>,
+}
+
+impl PosFilter {
+    pub fn new(include: Vec<PosPattern>, exclude: Vec<PosPattern>) -> Self {
+        Self { include, exclude }
+    }
+
+    pub fn include(&self) -> &[PosPattern] {
+        &self.include
+    }
+
+    pub fn exclude(&self) -> &[PosPattern] {
+        &self.exclude
+    }
+
+    /// Whether the filter keeps everything.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether entries of the id.def POS `pos` are kept.
+    pub fn keeps(&self, pos: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(pos));
+        included && !self.exclude.iter().any(|pattern| pattern.matches(pos))
+    }
+}
@@ -0,0 +1,50 @@
+//! Build profiles (`--profile mobile|desktop|server`): the flags each
+//! product target builds with, kept here instead of in every target's build
+//! script.
+//!
+//! A profile is a list of command-line flags parsed in front of the ones
+//! given, so a flag given explicitly overrides the profile's value. wiki-ngram
+//! has profiles of the same names for its thresholds.
+
+/// Product target a dictionary is built for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Profile {
+    /// Pruned to 150k entries within the mobile size budget, with reading
+    /// conversion and single kanji
+    Mobile,
+    /// The full lexicon with reading conversion, single kanji and zip codes
+    Desktop,
+    /// Desktop's dictionaries uncompressed, for memory-mapping at startup
+    Server,
+}
+
+impl Profile {
+    /// The flags of the profile.
+    pub fn args(self) -> &'static [&'static str] {
+        match self {
+            Profile::Mobile => &[
+                "--key=reading",
+                "--single-kanji",
+                "--max-entries=150000",
+                // 45 MiB, the mobile budget of system.dic.zst in size_budgets.tsv
+                "--max-size-bytes=47185920",
+                "--compression=zstd:19",
+                "--smoke-test",
+            ],
+            Profile::Desktop => &[
+                "--key=reading",
+                "--single-kanji",
+                "--with-zipcode",
+                "--compression=zstd:22:long",
+                "--smoke-test",
+            ],
+            Profile::Server => &[
+                "--key=reading",
+                "--single-kanji",
+                "--with-zipcode",
+                "--compression=none",
+                "--smoke-test",
+            ],
+        }
+    }
+}
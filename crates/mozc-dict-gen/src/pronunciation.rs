@@ -0,0 +1,54 @@
+//! Pronunciations estimated from readings, for the pronunciation column of
+//! lex.csv. Mozc dictionaries only give readings; tools that tell the two
+//! apart (speech synthesis, pronunciation lookups) expect the spoken form,
+//! as UniDic's pron writes it:
+//!
+//! ```text
+//! とうきょう  -> とーきょー   long o written おう
+//! せんせい    -> せんせー     long e written えい
+//! は (助詞)   -> わ           particles read differently from their kana
+//! ```
+//!
+//! Vowels spanning a conjugation or word boundary can't be told from long
+//! vowels by the reading alone; the final う of verbs (思う, 言う) and the
+//! い of adjectives (可愛い) are kept as written.
+
+/// Particles whose kana differs from how they are spoken.
+const PARTICLES: [(&str, &str); 3] = [("は", "わ"), ("へ", "え"), ("を", "お")];
+
+/// The pronunciation of `reading` (hiragana) for a word of POS `pos`
+/// (id.def-style, comma separated).
+pub fn estimate(reading: &str, pos: &str) -> String {
+    if pos.starts_with("助詞") {
+        if let Some((_, spoken)) = PARTICLES.iter().find(|(kana, _)| *kana == reading) {
+            return spoken.to_string();
+        }
+    }
+
+    let chars: Vec<char> = reading.chars().collect();
+    let verb = pos.starts_with("動詞");
+    let mut out = String::with_capacity(reading.len());
+    for (i, &c) in chars.iter().enumerate() {
+        let last = i + 1 == chars.len();
+        let lengthens = match (i.checked_sub(1).and_then(|p| vowel(chars[p])), c) {
+            (Some('o'), 'う') | (Some('u'), 'う') => !(verb && last),
+            (Some('e'), 'い') => true,
+            (Some('a'), 'あ') | (Some('e'), 'え') | (Some('o'), 'お') => true,
+            _ => false,
+        };
+        out.push(if lengthens { 'ー' } else { c });
+    }
+    out
+}
+
+/// The vowel a hiragana ends with, or None for ん, っ, ー and non-kana.
+fn vowel(c: char) -> Option<char> {
+    const ROWS: [(char, &str); 5] = [
+        ('a', "あかさたなはまやらわがざだばぱぁゃゎ"),
+        ('i', "いきしちにひみりぎじぢびぴぃ"),
+        ('u', "うくすつぬふむゆるぐずづぶぷぅゅゔ"),
+        ('e', "えけせてねへめれげぜでべぺぇ"),
+        ('o', "おこそとのほもよろをごぞどぼぽぉょ"),
+    ];
+    ROWS.iter().find(|(_, kana)| kana.contains(c)).map(|(vowel, _)| *vowel)
+}
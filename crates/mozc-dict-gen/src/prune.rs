@@ -0,0 +1,96 @@
+//! Entry budgets for small dictionary variants (`--max-entries`,
+//! `--max-size-bytes`).
+//!
+//! A mobile keyboard can't ship the full lexicon, so lex.csv is cut down to
+//! the entries that matter most before it is compiled. Entries are ranked
+//! by their place among the entries of the same reading, then by cost: every
+//! reading keeps its cheapest (most frequent) word before any reading gets
+//! a second one, so pruning drops rare homophones rather than whole
+//! readings. The kept rows are written in their original order.
+//!
+//! A size budget can't be checked before compiling; the builder prunes to
+//! an estimate, compiles, and shrinks the estimate until the compressed
+//! dictionary fits (see [`next_limit`]).
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use crate::POS_COLUMNS;
+
+/// Column of the reading in a lex.csv row in Mozc's feature layout.
+const READING_COLUMN: usize = 4 + POS_COLUMNS;
+
+/// Attempts at compiling under a size budget before giving up.
+pub const MAX_SIZE_ATTEMPTS: usize = 8;
+
+/// The rows of a lexicon, ranked for pruning.
+pub struct EntryBudget {
+    rows: Vec<csv::StringRecord>,
+    /// Row indices, the entries to keep first at the front
+    ranked: Vec<usize>,
+}
+
+impl EntryBudget {
+    /// Reads and ranks lex.csv rows in Mozc's feature layout. Rows without
+    /// a reading are grouped by surface.
+    pub fn read(lex: impl Read) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(lex);
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            rows.push(record.context("Unreadable lex.csv")?);
+        }
+
+        let mut by_reading: HashMap<&str, Vec<(i32, usize)>> = HashMap::new();
+        for (i, row) in rows.iter().enumerate() {
+            let reading = row.get(READING_COLUMN).filter(|reading| *reading != "*").unwrap_or(&row[0]);
+            let cost = row.get(3).and_then(|cost| cost.parse().ok()).unwrap_or(i32::MAX);
+            by_reading.entry(reading).or_default().push((cost, i));
+        }
+        let mut keys = Vec::with_capacity(rows.len());
+        for mut entries in by_reading.into_values() {
+            entries.sort_unstable();
+            keys.extend(entries.into_iter().enumerate().map(|(place, (cost, i))| (place, cost, i)));
+        }
+        keys.sort_unstable();
+        let ranked = keys.into_iter().map(|(_, _, i)| i).collect();
+        Ok(Self { rows, ranked })
+    }
+
+    /// Entries of the full lexicon.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Writes the `max_entries` highest-ranked rows, returning how many were
+    /// written.
+    pub fn write(&self, max_entries: usize, writer: impl Write) -> Result<usize> {
+        let mut kept: Vec<usize> = self.ranked.iter().take(max_entries).copied().collect();
+        kept.sort_unstable();
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .quote_style(csv::QuoteStyle::Necessary)
+            .from_writer(writer);
+        for &i in &kept {
+            writer.write_record(&self.rows[i])?;
+        }
+        writer.flush()?;
+        Ok(kept.len())
+    }
+}
+
+/// The entries to try next when `entries` compiled to `size` bytes, over
+/// `max_size`: scaled down by the overshoot with some margin, and always
+/// fewer than before.
+pub fn next_limit(entries: usize, size: u64, max_size: u64) -> usize {
+    let estimate = (entries as f64 * max_size as f64 / size as f64 * 0.95) as usize;
+    estimate.min(entries.saturating_sub(1))
+}
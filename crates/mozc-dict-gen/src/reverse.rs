@@ -0,0 +1,34 @@
+//! The reverse-lookup FST (`--reverse-lookup`): each surface of the lexicon
+//! with its readings and costs, written as `output/reverse.fst` for
+//! reconversion of committed text (see [`keyboard_formats::reverse_lookup`]).
+//!
+//! It is written from the same lexicon as system.dic, after any pruning, so
+//! reconversion offers the readings the system dictionary converts.
+//! Entries without a kana reading (`*`) and surfaces that can't be keys
+//! (blank, or with control characters) are left out.
+
+use anyhow::Result;
+use keyboard_formats::reverse_lookup;
+use std::io::Write;
+
+use crate::conversion;
+use crate::export;
+use crate::features::FeatureLayout;
+
+/// File name of the FST in the output directory.
+pub const REVERSE_FST: &str = "reverse.fst";
+
+/// Writes the reverse-lookup FST of `lex`, features in `layout`, returning
+/// the number of (surface, reading) pairs.
+pub fn write_reverse_lookup(lex: &[u8], layout: FeatureLayout, writer: impl Write) -> Result<usize> {
+    let mut entries = Vec::new();
+    export::for_each_row(lex, layout, |row| {
+        let surface = row.surface;
+        if conversion::is_kana(row.reading) && !surface.trim().is_empty() && !surface.chars().any(char::is_control) {
+            entries.push((surface.to_string(), row.reading.to_string(), row.cost));
+        }
+        Ok(())
+    })?;
+    let entries = entries.iter().map(|(surface, reading, cost)| (surface.as_str(), reading.as_str(), *cost));
+    reverse_lookup::write_fst(entries, writer)
+}
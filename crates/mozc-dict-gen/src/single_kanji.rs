@@ -0,0 +1,54 @@
+//! Mozc's single-kanji list (data/single_kanji/single_kanji.tsv), written as
+//! `output/single_kanji.fst` by `--single-kanji` (see
+//! [`keyboard_formats::single_kanji`]).
+//!
+//! Each line gives a reading and its kanji, most common first, with `#`
+//! comment lines:
+//!
+//! ```text
+//! あい<TAB>愛相藍哀挨逢
+//! ```
+
+use anyhow::{Context, Result};
+use keyboard_formats::single_kanji;
+use std::io::{BufRead, Write};
+
+use crate::features::ReadingScript;
+use crate::Input;
+
+pub const SINGLE_KANJI_FILE: &str = "single_kanji.tsv";
+
+/// Where the list lives relative to dictionary_oss in a Mozc checkout.
+pub const SINGLE_KANJI_DIR: &str = "../single_kanji";
+
+/// File name of the compiled list in the output directory.
+pub const SINGLE_KANJI_FST: &str = "single_kanji.fst";
+
+/// The (reading, kanji) lines of single_kanji.tsv.
+pub fn read_single_kanji(input: &Input) -> Result<Vec<(String, String)>> {
+    let mut readings = Vec::new();
+    for (line_no, line) in input.open()?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (reading, kanji) = line
+            .split_once('\t')
+            .with_context(|| format!("{} line {}: expected a reading and kanji", input.name(), line_no + 1))?;
+        readings.push((reading.to_string(), kanji.trim().to_string()));
+    }
+    Ok(readings)
+}
+
+/// Writes the single-kanji FST from single_kanji.tsv, readings in `script`
+/// (hiragana, as in the list, when None), returning the number of
+/// (reading, kanji) pairs.
+pub fn write_single_kanji(input: &Input, script: Option<ReadingScript>, writer: impl Write) -> Result<usize> {
+    let mut readings = read_single_kanji(input)?;
+    if let Some(script) = script {
+        for (reading, _) in &mut readings {
+            *reading = script.convert(reading);
+        }
+    }
+    single_kanji::write_fst(readings.iter().map(|(reading, kanji)| (reading.as_str(), kanji.as_str())), writer)
+}
@@ -0,0 +1,68 @@
+//! Post-build smoke test (`--smoke-test`): the written system dictionary is
+//! read back and a few sentences tokenized, so a broken unk.def, char.def
+//! or matrix fails the build rather than the downstream tests.
+//!
+//! A sentence fails when it yields no tokens, when the token surfaces don't
+//! spell it back, when it splits into more tokens than it has words (an
+//! unk.def that stopped grouping digits or letters), or when words every
+//! dictionary has come out as unknown words.
+
+use anyhow::Result;
+use vibrato::dictionary::LexType;
+
+pub struct SmokeSentence {
+    pub text: &'static str,
+    /// Every token must come from the lexicon, not unk.def
+    pub in_lexicon: bool,
+    pub max_tokens: usize,
+}
+
+/// Common words, then digits and ASCII left to unk.def.
+pub const SENTENCES: &[SmokeSentence] = &[
+    SmokeSentence { text: "東京に行きます", in_lexicon: true, max_tokens: 6 },
+    SmokeSentence { text: "今日はいい天気です", in_lexicon: true, max_tokens: 7 },
+    SmokeSentence { text: "2024年10月16日", in_lexicon: false, max_tokens: 8 },
+    SmokeSentence { text: "1234567890", in_lexicon: false, max_tokens: 3 },
+    SmokeSentence { text: "Hello world", in_lexicon: false, max_tokens: 4 },
+    SmokeSentence { text: "iPhoneを買いました", in_lexicon: false, max_tokens: 6 },
+];
+
+impl SmokeSentence {
+    /// Checks the segmentation of the sentence, as (surface, unknown word)
+    /// pairs, returning what is wrong with it.
+    pub fn check(&self, tokens: &[(&str, bool)]) -> Result<(), String> {
+        let segmentation: Vec<&str> = tokens.iter().map(|(surface, _)| *surface).collect();
+        if tokens.is_empty() {
+            return Err("no tokens".to_string());
+        }
+        if segmentation.concat() != self.text {
+            return Err(format!("the tokens {} don't spell the sentence", segmentation.join("/")));
+        }
+        if tokens.len() > self.max_tokens {
+            return Err(format!("{} tokens, more than {}: {}", tokens.len(), self.max_tokens, segmentation.join("/")));
+        }
+        let unknown: Vec<&str> = tokens.iter().filter(|(_, unknown)| *unknown).map(|(surface, _)| *surface).collect();
+        if self.in_lexicon && !unknown.is_empty() {
+            return Err(format!("{} not found in the lexicon", unknown.join(", ")));
+        }
+        Ok(())
+    }
+}
+
+/// Tokenizes [`SENTENCES`] with `dict`, listing every sentence that fails.
+pub fn check(dict: vibrato::Dictionary) -> Result<()> {
+    let tokenizer = vibrato::Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+    let mut failures = Vec::new();
+    for sentence in SENTENCES {
+        worker.reset_sentence(sentence.text);
+        worker.tokenize();
+        let tokens: Vec<(&str, bool)> =
+            worker.token_iter().map(|token| (token.surface(), token.lex_type() == LexType::Unknown)).collect();
+        if let Err(problem) = sentence.check(&tokens) {
+            failures.push(format!("{}: {}", sentence.text, problem));
+        }
+    }
+    anyhow::ensure!(failures.is_empty(), "Smoke test failed:\n  {}", failures.join("\n  "));
+    Ok(())
+}
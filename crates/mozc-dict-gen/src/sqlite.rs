@@ -0,0 +1,68 @@
+//! SQLite export of the lexicon (`--export sqlite`), for analysts who want
+//! to query the dictionary without parsing lex.csv:
+//!
+//! ```sql
+//! SELECT surface, cost FROM lexicon WHERE reading = 'きしゃ' ORDER BY cost;
+//! SELECT pos, count(*) FROM lexicon GROUP BY pos;
+//! ```
+//!
+//! `output/lexicon.sqlite` has one `lexicon` row per lex.csv entry with the
+//! columns of [`crate::export`], every one indexed, and `features` keeping
+//! the whole feature list.
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+use crate::export;
+use crate::features::FeatureLayout;
+
+/// File name of the export in the output directory.
+pub const SQLITE_FILE: &str = "lexicon.sqlite";
+
+const SCHEMA: &str = "
+CREATE TABLE lexicon (
+    id INTEGER PRIMARY KEY,
+    surface TEXT NOT NULL,
+    reading TEXT NOT NULL,
+    left_id INTEGER NOT NULL,
+    right_id INTEGER NOT NULL,
+    cost INTEGER NOT NULL,
+    pos TEXT NOT NULL,
+    features TEXT NOT NULL
+);
+";
+
+const INDEXES: &str = "
+CREATE INDEX lexicon_surface ON lexicon (surface);
+CREATE INDEX lexicon_reading ON lexicon (reading);
+CREATE INDEX lexicon_left_id ON lexicon (left_id);
+CREATE INDEX lexicon_right_id ON lexicon (right_id);
+CREATE INDEX lexicon_cost ON lexicon (cost);
+CREATE INDEX lexicon_pos ON lexicon (pos);
+";
+
+/// Writes the lex.csv rows of `lex`, features in `layout`, to a new
+/// database at `path` and returns how many were written.
+pub fn write_lexicon(path: &Path, lex: &[u8], layout: FeatureLayout) -> Result<usize> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let mut db = Connection::open(path).with_context(|| format!("Failed to create {:?}", path))?;
+    db.execute_batch(SCHEMA)?;
+    let tx = db.transaction()?;
+    let rows = {
+        let mut insert = tx.prepare(
+            "INSERT INTO lexicon (surface, reading, left_id, right_id, cost, pos, features)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        export::for_each_row(lex, layout, |row| {
+            let features = row.features.join(",");
+            insert.execute(params![row.surface, row.reading, row.left_id, row.right_id, row.cost, row.pos, features])?;
+            Ok(())
+        })?
+    };
+    tx.execute_batch(INDEXES)?;
+    tx.commit()?;
+    Ok(rows)
+}
@@ -0,0 +1,180 @@
+//! Statistics of a built dictionary (`mozc-dict-gen stats`, or `--report`
+//! during a build) as JSON, for comparing builds across Mozc versions:
+//!
+//! ```json
+//! {
+//!   "report_version": 1,
+//!   "entries": 1300000,
+//!   "pos": { "名詞,一般": 400000, "名詞,固有名詞,人名,姓": 30000, ... },
+//!   "cost": { "min": -1000, "max": 12000, "mean": 6123.4,
+//!             "histogram": [{ "from": 5000, "to": 5999, "count": 180000 }, ...] },
+//!   "reading_length": { "1": 9000, "2": 120000, ... },
+//!   "duplicates": { "pairs": 20000, "extra_entries": 24000,
+//!                   "top": [{ "surface": "を", "reading": "を", "count": 4 }, ...] },
+//!   "matrix": { "size": 2672, "nonzero": 6800000, "density": 0.95, "min": -3000, "max": 10000 }
+//! }
+//! ```
+//!
+//! POS are the leading POS columns of lex.csv without their `*` fields, and
+//! reading lengths are in characters. A duplicate is a (surface, reading)
+//! pair with more than one entry, usually under different POS.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+
+use crate::{ConnectionMatrix, POS_COLUMNS};
+
+const REPORT_VERSION: u32 = 1;
+
+/// Width of a cost histogram bucket.
+pub const COST_BUCKET: i32 = 1000;
+
+/// Duplicate pairs listed in the report, most entries first.
+pub const TOP_DUPLICATES: usize = 20;
+
+/// POS fields counted; the ones after them are conjugations and forms.
+const POS_FIELDS: usize = 4;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DictStats {
+    pub entries: usize,
+    pub pos: BTreeMap<String, usize>,
+    /// Entries per cost bucket, by the bucket's lowest cost
+    pub cost_histogram: BTreeMap<i32, usize>,
+    pub cost_min: Option<i16>,
+    pub cost_max: Option<i16>,
+    cost_sum: i64,
+    /// Entries per reading length in characters
+    pub reading_length: BTreeMap<usize, usize>,
+    /// (surface, reading) pairs with more than one entry and their counts
+    pub duplicates: Vec<(String, String, usize)>,
+    pub matrix: Option<MatrixStats>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatrixStats {
+    pub size: u16,
+    /// Cells with a cost other than 0
+    pub nonzero: usize,
+    pub min: i16,
+    pub max: i16,
+}
+
+/// The leading POS columns of a lex.csv record without their `*` fields,
+/// such as `名詞,固有名詞,地域`.
+pub(crate) fn pos_group(record: &csv::StringRecord) -> String {
+    let pos: Vec<&str> = (4..4 + POS_FIELDS).map(|i| &record[i]).filter(|field| *field != "*").collect();
+    pos.join(",")
+}
+
+impl MatrixStats {
+    pub fn density(&self) -> f64 {
+        self.nonzero as f64 / (self.size as f64 * self.size as f64)
+    }
+}
+
+impl DictStats {
+    /// Reads lex.csv in the mozc feature layout; lines too short for one are
+    /// skipped, as lookup does.
+    pub fn from_lex(reader: impl Read) -> Result<Self> {
+        let mut lex = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(reader);
+        let mut stats = Self::default();
+        let mut pairs: HashMap<(String, String), usize> = HashMap::new();
+        for (line_no, record) in lex.records().enumerate() {
+            let record = record?;
+            // surface, left_id, right_id, cost, 7 POS columns, reading, pronunciation
+            if record.len() < 4 + POS_COLUMNS + 1 {
+                continue;
+            }
+            let cost: i16 = record[3].parse().with_context(|| format!("lex.csv line {}: bad cost", line_no + 1))?;
+            let reading = &record[4 + POS_COLUMNS];
+
+            stats.entries += 1;
+            *stats.pos.entry(pos_group(&record)).or_default() += 1;
+            *stats.cost_histogram.entry((cost as i32).div_euclid(COST_BUCKET) * COST_BUCKET).or_default() += 1;
+            stats.cost_min = Some(stats.cost_min.map_or(cost, |min| min.min(cost)));
+            stats.cost_max = Some(stats.cost_max.map_or(cost, |max| max.max(cost)));
+            stats.cost_sum += cost as i64;
+            *stats.reading_length.entry(reading.chars().count()).or_default() += 1;
+            *pairs.entry((record[0].to_string(), reading.to_string())).or_default() += 1;
+        }
+        stats.duplicates = pairs
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|((surface, reading), count)| (surface, reading, count))
+            .collect();
+        stats.duplicates.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| (&a.0, &a.1).cmp(&(&b.0, &b.1))));
+        Ok(stats)
+    }
+
+    /// Adds the density and cost range of matrix.def.
+    pub fn add_matrix(&mut self, matrix: &ConnectionMatrix) {
+        self.matrix = Some(MatrixStats {
+            size: matrix.size,
+            nonzero: matrix.costs.iter().filter(|cost| **cost != 0).count(),
+            min: matrix.costs.iter().copied().min().unwrap_or_default(),
+            max: matrix.costs.iter().copied().max().unwrap_or_default(),
+        });
+    }
+
+    /// Reads lex.csv and matrix.def from a build's output directory (kept
+    /// with --keep-intermediate).
+    pub fn from_files(lex: &Path, matrix: &Path) -> Result<Self> {
+        let file = File::open(lex).with_context(|| format!("Failed to open {:?}", lex))?;
+        let mut stats = Self::from_lex(BufReader::new(file))?;
+        let file = File::open(matrix).with_context(|| format!("Failed to open {:?}", matrix))?;
+        stats.add_matrix(&ConnectionMatrix::read(BufReader::new(file)).with_context(|| format!("In {:?}", matrix))?);
+        Ok(stats)
+    }
+
+    /// Extra entries of the duplicate pairs, beyond one per pair.
+    pub fn duplicate_entries(&self) -> usize {
+        self.duplicates.iter().map(|(_, _, count)| count - 1).sum()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mean = if self.entries == 0 { 0.0 } else { self.cost_sum as f64 / self.entries as f64 };
+        let histogram: Vec<Value> = self
+            .cost_histogram
+            .iter()
+            .map(|(from, count)| json!({ "from": from, "to": from + COST_BUCKET - 1, "count": count }))
+            .collect();
+        let reading_length: Map<String, Value> =
+            self.reading_length.iter().map(|(length, count)| (length.to_string(), json!(count))).collect();
+        let top: Vec<Value> = self
+            .duplicates
+            .iter()
+            .take(TOP_DUPLICATES)
+            .map(|(surface, reading, count)| json!({ "surface": surface, "reading": reading, "count": count }))
+            .collect();
+        let mut report = json!({
+            "report_version": REPORT_VERSION,
+            "entries": self.entries,
+            "pos": self.pos,
+            "cost": { "min": self.cost_min, "max": self.cost_max, "mean": mean, "histogram": histogram },
+            "reading_length": reading_length,
+            "duplicates": { "pairs": self.duplicates.len(), "extra_entries": self.duplicate_entries(), "top": top },
+        });
+        if let Some(matrix) = &self.matrix {
+            report["matrix"] = json!({
+                "size": matrix.size,
+                "nonzero": matrix.nonzero,
+                "density": matrix.density(),
+                "min": matrix.min,
+                "max": matrix.max,
+            });
+        }
+        report
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> Result<()> {
+        serde_json::to_writer_pretty(&mut writer, &self.to_json())?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! SudachiDict as a [`DictSource`], for its coverage of modern web text.
+//!
+//! The raw SudachiDict release is a MeCab-style matrix.def and lexicon CSVs
+//! (small_lex.csv, plus core_lex.csv for the core edition) with 18 or more
+//! columns:
+//!
+//! ```text
+//! <trie surface>,<left>,<right>,<cost>,<surface>,<pos1>..<pos6>,<reading>,<normalized form>,
+//! <dictionary form ID>,<split type>,<A split>,<B split>,...
+//! ```
+//!
+//! Sudachi's POS follow UniDic (名詞,普通名詞,一般); they are renamed to the
+//! IPAdic-style set of Mozc's id.def with a [`PosMap`]
+//! ([`BUILTIN_POS_MAP`]) so lex.csv reads the same whichever source it came
+//! from. Entries with context ID -1 only exist as parts of splits and are
+//! left out. Sudachi ships no char.def for MeCab, so [`crate::CHAR_DEF`] is
+//! used and unk.def is generated as for Mozc, from the POS of the converted
+//! context IDs.
+
+use anyhow::{Context, Result};
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::extra_lexicon::PosMap;
+use crate::features::FeatureConversion;
+use crate::overlay;
+use crate::{generate_char_def, generate_unk_def, pos_columns, DictSource, IdMap, Input, Rejection, Report};
+
+/// Sudachi (UniDic-style) POS prefixes and the id.def-style POS replacing
+/// them.
+pub const BUILTIN_POS_MAP: &str = include_str!("sudachi_pos_map.tsv");
+
+/// Lexicon files of the core edition, in the order they are merged.
+pub const LEXICON_FILES: [&str; 2] = ["small_lex.csv", "core_lex.csv"];
+
+/// Leading POS fields renamed by the POS map; the two after them are the
+/// conjugation type and form.
+const POS_FIELDS: usize = 4;
+const COLUMNS: usize = 18;
+
+/// The SudachiDict files a dictionary is built from.
+#[derive(Clone, Debug)]
+pub struct SudachiSources {
+    pub lexicons: Vec<Input>,
+    pub matrix: Input,
+}
+
+impl SudachiSources {
+    /// Sources in a directory holding matrix.def and the lexicon CSVs.
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let lexicons: Vec<Input> =
+            LEXICON_FILES.iter().map(|name| dir.join(name)).filter(|path| path.is_file()).map(Input::Path).collect();
+        anyhow::ensure!(!lexicons.is_empty(), "None of {} found in {:?}", LEXICON_FILES.join(", "), dir);
+        anyhow::ensure!(dir.join("matrix.def").is_file(), "matrix.def not found in {:?}", dir);
+        Ok(Self {
+            lexicons,
+            matrix: Input::Path(dir.join("matrix.def")),
+        })
+    }
+}
+
+impl DictSource for SudachiSources {
+    fn write(&self, lex: impl Write, mut matrix: impl Write, char_def: impl Write, unk_def: impl Write) -> Result<Report> {
+        let pos_map = PosMap::read(BUILTIN_POS_MAP.as_bytes()).expect("Built-in Sudachi POS map is valid");
+        let (report, id_map) = convert_lexicons(&self.lexicons, lex, &pos_map)?;
+        io::copy(&mut self.matrix.open()?, &mut matrix)?;
+        matrix.flush()?;
+        generate_char_def(char_def)?;
+        generate_unk_def(unk_def, &id_map, &report.costs, &FeatureConversion::default())?;
+        Ok(report)
+    }
+}
+
+/// Writes the lexicons as lex.csv, returning the report and the POS of each
+/// context ID for unk.def.
+fn convert_lexicons(lexicons: &[Input], writer: impl Write, pos_map: &PosMap) -> Result<(Report, IdMap)> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .quote_style(csv::QuoteStyle::Necessary)
+        .from_writer(writer);
+
+    let mut report = Report::default();
+    let mut id_map = IdMap::new();
+    for input in lexicons {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(input.open()?);
+        for record in reader.records() {
+            let record = record.with_context(|| format!("Unreadable CSV in {}", input.name()))?;
+            let fields: Vec<&str> = record.iter().collect();
+            let line_no = record.position().map_or(0, |p| p.line() as usize);
+            if fields.get(1) == Some(&"-1") {
+                continue;
+            }
+            let (left_id, right_id, cost) = match parse_ids(&fields) {
+                Ok(parsed) => parsed,
+                Err(reason) => {
+                    report.reject(Rejection {
+                        source: input.name(),
+                        line_no,
+                        line: fields.join(","),
+                        reason,
+                    });
+                    continue;
+                }
+            };
+
+            let surface = if fields[4].is_empty() { fields[0] } else { fields[4] };
+            let pos = normalize_pos(pos_map, &fields[5..11]);
+            let reading = overlay::to_hiragana(fields[11]).unwrap_or_else(|| fields[11].to_string());
+            id_map.entry(left_id).or_insert_with(|| pos.clone());
+
+            let mut record = vec![surface.to_string(), left_id.to_string(), right_id.to_string(), cost.to_string()];
+            record.extend(pos_columns(&pos).into_iter().map(str::to_string));
+            record.push(reading.clone());
+            record.push(reading);
+            writer.write_record(&record)?;
+            report.entries += 1;
+            report.costs.record(left_id, cost);
+        }
+    }
+    writer.flush()?;
+    Ok((report, id_map))
+}
+
+fn parse_ids(fields: &[&str]) -> Result<(u16, u16, i16), String> {
+    if fields.len() < COLUMNS {
+        return Err(format!("{} columns, expected at least {}", fields.len(), COLUMNS));
+    }
+    match (fields[1].parse(), fields[2].parse(), fields[3].parse()) {
+        (Ok(left_id), Ok(right_id), Ok(cost)) => Ok((left_id, right_id, cost)),
+        _ => Err("unparsable ID or cost".to_string()),
+    }
+}
+
+/// The six Sudachi POS fields as id.def-style POS columns: the renamed POS
+/// padded to four fields, then the conjugation type and form.
+fn normalize_pos(pos_map: &PosMap, pos: &[&str]) -> String {
+    let renamed = pos_map.rewrite(&pos[..POS_FIELDS].join(","));
+    let mut fields: Vec<&str> = renamed.split(',').collect();
+    fields.resize(POS_FIELDS, "*");
+    fields.extend(&pos[POS_FIELDS..]);
+    fields.join(",")
+}
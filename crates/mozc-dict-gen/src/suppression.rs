@@ -0,0 +1,56 @@
+//! Mozc's lists of words kept out of candidates, written as
+//! `output/suppression.fst` (see [`keyboard_formats::suppression`]) so
+//! keyboards filter predictions as Mozc does.
+//!
+//! Both are UTF-8 with `#` comment lines:
+//!
+//! ```text
+//! suggestion_filter.txt   <surface>                 never predicted
+//! suppression_word.txt    <reading><TAB><surface>   never offered
+//! ```
+//!
+//! A suppression_word.txt line without a surface suppresses a reading
+//! rather than a word and is left out, as the list is keyed by surface.
+
+use anyhow::Result;
+use keyboard_formats::suppression::{self as format, SUGGESTION_FILTERED, SUPPRESSED};
+use std::io::{BufRead, Write};
+
+use crate::Input;
+
+pub const SUGGESTION_FILTER_FILE: &str = "suggestion_filter.txt";
+pub const SUPPRESSION_WORD_FILE: &str = "suppression_word.txt";
+
+/// File name of the compiled list in the output directory.
+pub const SUPPRESSION_FST: &str = "suppression.fst";
+
+/// The surfaces listed in a filter file and their flag, which follows the
+/// file name.
+pub fn read_filter(input: &Input) -> Result<Vec<(String, u64)>> {
+    let flag = if input.name() == SUPPRESSION_WORD_FILE { SUPPRESSED } else { SUGGESTION_FILTERED };
+    let mut words = Vec::new();
+    for line in input.open()?.lines() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let surface = match line.split_once('\t') {
+            Some((_, surface)) => surface.trim(),
+            None => line.trim(),
+        };
+        if !surface.is_empty() {
+            words.push((surface.to_string(), flag));
+        }
+    }
+    Ok(words)
+}
+
+/// Writes the suppression FST from `filters`, returning the number of
+/// surfaces.
+pub fn write_suppression(filters: &[Input], writer: impl Write) -> Result<usize> {
+    let mut words = Vec::new();
+    for input in filters {
+        words.extend(read_filter(input)?);
+    }
+    format::write_fst(words, writer)
+}
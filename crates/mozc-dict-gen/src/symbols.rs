@@ -0,0 +1,77 @@
+//! Mozc's symbol and kaomoji dictionaries (data/symbol/symbol.tsv and
+//! data/emoticon/emoticon.tsv), written as `output/symbols.fst` (see
+//! [`keyboard_formats::symbols`]).
+//!
+//! Both are tab separated with space-separated readings, `#` comment lines
+//! and a column header line:
+//!
+//! ```text
+//! symbol.tsv     <POS><TAB><symbol><TAB><readings><TAB>...
+//! emoticon.tsv   <kaomoji><TAB><readings><TAB>...
+//! ```
+
+use anyhow::{Context, Result};
+use keyboard_formats::symbols::{self as format, SymbolKind};
+use std::io::{BufRead, Write};
+
+use crate::features::ReadingScript;
+use crate::Input;
+
+pub const SYMBOL_FILE: &str = "symbol.tsv";
+pub const EMOTICON_FILE: &str = "emoticon.tsv";
+
+/// Where the files live relative to dictionary_oss in a Mozc checkout, in
+/// the order of [`SYMBOL_FILES`].
+pub const SYMBOL_DIRS: [&str; 2] = ["../symbol", "../emoticon"];
+
+/// Symbols before kaomoji, so a reading both have offers symbols first.
+pub const SYMBOL_FILES: [&str; 2] = [SYMBOL_FILE, EMOTICON_FILE];
+
+/// File name of the compiled dictionary in the output directory.
+pub const SYMBOLS_FST: &str = "symbols.fst";
+
+/// The (reading, text) pairs of a symbol file and their kind, which follows
+/// the file name.
+pub fn read_symbols(input: &Input) -> Result<Vec<(String, String, SymbolKind)>> {
+    let (kind, text_column) =
+        if input.name() == EMOTICON_FILE { (SymbolKind::Kaomoji, 0) } else { (SymbolKind::Symbol, 1) };
+    let mut symbols = Vec::new();
+    for (line_no, line) in input.open()?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let (text, readings) = fields
+            .get(text_column)
+            .zip(fields.get(text_column + 1))
+            .with_context(|| format!("{} line {}: expected a symbol and readings", input.name(), line_no + 1))?;
+        // Column names (POS, CHAR, READING...) rather than kana
+        if readings.chars().all(|c| c.is_ascii_uppercase() || c == '_') {
+            continue;
+        }
+        if text.is_empty() {
+            continue;
+        }
+        for reading in readings.split(' ').filter(|reading| !reading.is_empty()) {
+            symbols.push((reading.to_string(), text.to_string(), kind));
+        }
+    }
+    Ok(symbols)
+}
+
+/// Writes the symbol FST from `inputs`, readings in `script` (hiragana, as
+/// in the files, when None), returning the number of (reading, symbol)
+/// pairs.
+pub fn write_symbols(inputs: &[Input], script: Option<ReadingScript>, writer: impl Write) -> Result<usize> {
+    let mut symbols = Vec::new();
+    for input in inputs {
+        symbols.extend(read_symbols(input)?);
+    }
+    if let Some(script) = script {
+        for (reading, _, _) in &mut symbols {
+            *reading = script.convert(reading);
+        }
+    }
+    format::write_fst(symbols.iter().map(|(reading, text, kind)| (reading.as_str(), text.as_str(), *kind)), writer)
+}
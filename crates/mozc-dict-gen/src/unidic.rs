@@ -0,0 +1,162 @@
+//! UniDic (unidic-cwj, the contemporary written Japanese edition) as a
+//! [`DictSource`], for segmenting with UniDic's short units instead of
+//! Mozc's words.
+//!
+//! UniDic already ships MeCab's text format, so matrix.def and char.def are
+//! copied as they are. Its lex.csv and unk.def carry 29 features:
+//!
+//! ```text
+//! pos1,pos2,pos3,pos4,cType,cForm,lForm,lemma,orth,pron,orthBase,pronBase,goshu,
+//! iType,iForm,fType,fForm,iConType,fConType,type,kana,kanaBase,form,formBase,...
+//! ```
+//!
+//! which are rearranged into the layout every source writes: the four POS
+//! fields, conjugation type and form, the lemma, then the hiragana reading
+//! (`kana`) and pronunciation (`pron`).
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::overlay;
+use crate::{DictSource, Input, Rejection, Report, POS_COLUMNS};
+
+/// Feature columns used from UniDic entries.
+const POS_FIELDS: usize = 6;
+const LEMMA_FIELD: usize = 7;
+const PRON_FIELD: usize = 9;
+const KANA_FIELD: usize = 20;
+
+/// UniDic files besides the lexicon, whose name varies by release.
+pub const DEF_FILES: [&str; 3] = ["matrix.def", "char.def", "unk.def"];
+
+/// The UniDic files a dictionary is built from.
+#[derive(Clone, Debug)]
+pub struct UniDicSources {
+    /// lex.csv (lex_3_1.csv in the 3.1 releases)
+    pub lex: Input,
+    pub matrix: Input,
+    pub char_def: Input,
+    pub unk_def: Input,
+}
+
+impl UniDicSources {
+    /// Sources in an unpacked unidic-cwj directory.
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let mut lexicons: Vec<PathBuf> = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read {:?}", dir))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| is_lexicon(&path.file_name().unwrap_or_default().to_string_lossy()))
+            .collect();
+        // read_dir order is platform dependent; pick the same one every time
+        lexicons.sort();
+        let lex = lexicons.into_iter().next().with_context(|| format!("No lex.csv in {:?}", dir))?;
+        for name in DEF_FILES {
+            anyhow::ensure!(dir.join(name).is_file(), "{} not found in {:?}", name, dir);
+        }
+        Ok(Self {
+            lex: Input::Path(lex),
+            matrix: Input::Path(dir.join("matrix.def")),
+            char_def: Input::Path(dir.join("char.def")),
+            unk_def: Input::Path(dir.join("unk.def")),
+        })
+    }
+}
+
+/// Whether `name` is a UniDic lexicon file name (lex.csv, lex_3_1.csv).
+pub fn is_lexicon(name: &str) -> bool {
+    name.starts_with("lex") && name.ends_with(".csv")
+}
+
+impl DictSource for UniDicSources {
+    fn write(
+        &self,
+        lex: impl Write,
+        mut matrix: impl Write,
+        mut char_def: impl Write,
+        unk_def: impl Write,
+    ) -> Result<Report> {
+        let report = convert_entries(&self.lex, lex, true)?;
+        convert_entries(&self.unk_def, unk_def, false)?;
+        io::copy(&mut self.matrix.open()?, &mut matrix)?;
+        io::copy(&mut self.char_def.open()?, &mut char_def)?;
+        matrix.flush()?;
+        char_def.flush()?;
+        Ok(report)
+    }
+}
+
+/// Rewrites the features of lex.csv or unk.def entries; unk.def has only
+/// the POS fields, so the rest become `*`.
+fn convert_entries(input: &Input, writer: impl Write, lexicon: bool) -> Result<Report> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(input.open()?);
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .quote_style(csv::QuoteStyle::Necessary)
+        .from_writer(writer);
+
+    let mut report = Report::default();
+    for record in reader.records() {
+        let record = record.with_context(|| format!("Unreadable CSV in {}", input.name()))?;
+        let fields: Vec<&str> = record.iter().collect();
+        let line_no = record.position().map_or(0, |p| p.line() as usize);
+        let parsed = match fields.as_slice() {
+            [surface, left_id, right_id, cost, features @ ..] if !features.is_empty() => {
+                match (left_id.parse::<u16>(), right_id.parse::<u16>(), cost.parse::<i16>()) {
+                    _ if lexicon && features.len() <= KANA_FIELD => {
+                        Err(format!("{} features, expected at least {}", features.len(), KANA_FIELD + 1))
+                    }
+                    (Ok(left_id), Ok(right_id), Ok(cost)) => Ok((*surface, left_id, right_id, cost, features)),
+                    _ => Err("unparsable ID or cost".to_string()),
+                }
+            }
+            _ => Err("fewer than 5 columns".to_string()),
+        };
+        let (surface, left_id, right_id, cost, features) = match parsed {
+            Ok(parsed) => parsed,
+            Err(reason) => {
+                anyhow::ensure!(lexicon, "{} line {}: {}", input.name(), line_no, reason);
+                report.reject(Rejection {
+                    source: input.name(),
+                    line_no,
+                    line: fields.join(","),
+                    reason,
+                });
+                continue;
+            }
+        };
+
+        let mut record = vec![surface.to_string(), left_id.to_string(), right_id.to_string(), cost.to_string()];
+        let mut pos: Vec<&str> = features.iter().take(POS_FIELDS).copied().collect();
+        pos.resize(POS_FIELDS, "*");
+        pos.push(features.get(LEMMA_FIELD).map_or("*", |lemma| base_lemma(lemma)));
+        pos.resize(POS_COLUMNS, "*");
+        record.extend(pos.into_iter().map(str::to_string));
+        for field in [KANA_FIELD, PRON_FIELD] {
+            let kana = features.get(field).copied().unwrap_or("*");
+            record.push(overlay::to_hiragana(kana).unwrap_or_else(|| kana.to_string()));
+        }
+        writer.write_record(&record)?;
+
+        if lexicon {
+            report.entries += 1;
+            report.costs.record(left_id, cost);
+        }
+    }
+    writer.flush()?;
+    Ok(report)
+}
+
+/// UniDic lemmas of loanwords carry their origin (ページ-page); keep the
+/// Japanese part, as Mozc's base forms have no such suffix.
+fn base_lemma(lemma: &str) -> &str {
+    match lemma.split_once('-') {
+        Some((base, _)) if !base.is_empty() => base,
+        _ => lemma,
+    }
+}
@@ -0,0 +1,88 @@
+//! User dictionaries built into the system dictionary (`--user-dict`), for
+//! product vocabulary that every user of a build should have.
+//!
+//! Format (UTF-8, tab separated, `#` starts a comment line):
+//!
+//! ```text
+//! <reading><TAB><surface><TAB><POS>[<TAB><cost>]
+//! ```
+//!
+//! The POS is a Mozc user-dictionary POS name (名詞, 固有名詞, 人名, 組織, ...;
+//! see [`POS_NAMES`]) or leading fields of an id.def POS
+//! (`名詞,固有名詞,組織`). Readings may be hiragana or katakana.
+
+use anyhow::Result;
+use std::io::{BufRead, Write};
+
+use crate::overlay;
+use crate::{general_pos_id, IdMap, Input, Rejection, Report};
+
+/// Cost of entries without one: about the cheaper half of Mozc's common
+/// nouns, so a registered word beats most homophones without overriding
+/// particles and frequent words.
+pub const DEFAULT_COST: i16 = 4000;
+
+/// Mozc user-dictionary POS names and the id.def POS they stand for.
+pub const POS_NAMES: &[(&str, &str)] = &[
+    ("名詞", "名詞,一般"),
+    ("短縮よみ", "名詞,一般"),
+    ("固有名詞", "名詞,固有名詞,一般"),
+    ("人名", "名詞,固有名詞,人名,一般"),
+    ("姓", "名詞,固有名詞,人名,姓"),
+    ("名", "名詞,固有名詞,人名,名"),
+    ("組織", "名詞,固有名詞,組織"),
+    ("地名", "名詞,固有名詞,地域,一般"),
+    ("名詞サ変", "名詞,サ変接続"),
+    ("名詞形動", "名詞,形容動詞語幹"),
+    ("副詞", "副詞,一般"),
+    ("感動詞", "感動詞"),
+    ("記号", "記号,一般"),
+    ("顔文字", "記号,一般"),
+];
+
+/// Converts a user dictionary into Mozc's dictionary format, named after
+/// `input`, for merging with the other dictionaries. Invalid lines are
+/// rejected into `report`.
+pub fn to_mozc_dictionary(input: &Input, id_map: &IdMap, report: &mut Report) -> Result<Input> {
+    let mut output = Vec::new();
+    for (line_no, line) in input.open()?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_entry(&line, id_map) {
+            Ok((reading, id, cost, surface)) => writeln!(output, "{}\t{}\t{}\t{}\t{}", reading, id, id, cost, surface)?,
+            Err(reason) => report.reject(Rejection {
+                source: input.name(),
+                line_no: line_no + 1,
+                line,
+                reason,
+            }),
+        }
+    }
+    Ok(Input::bytes(input.name(), output))
+}
+
+fn parse_entry<'a>(line: &'a str, id_map: &IdMap) -> Result<(String, u16, i16, &'a str), String> {
+    let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+    let [reading, surface, pos, rest @ ..] = fields.as_slice() else {
+        return Err("expected reading, surface, POS and an optional cost".to_string());
+    };
+    if rest.len() > 1 {
+        return Err("more than 4 columns".to_string());
+    }
+
+    if surface.is_empty() || surface.chars().any(char::is_control) {
+        return Err("empty surface or control characters".to_string());
+    }
+    overlay::check_term_length(surface, reading)?;
+    let reading = overlay::to_hiragana(reading).ok_or_else(|| format!("reading {:?} is not kana", reading))?;
+
+    let prefix = POS_NAMES.iter().find(|(name, _)| name == pos).map_or(*pos, |(_, prefix)| *prefix);
+    let (id, _) = general_pos_id(id_map, prefix).ok_or_else(|| format!("no POS {:?} in id.def", pos))?;
+    let cost = match rest.first().filter(|c| !c.is_empty()) {
+        Some(cost) => cost.parse().map_err(|_| format!("bad cost {:?}", cost))?,
+        None => DEFAULT_COST,
+    };
+    Ok((reading, id, cost, surface))
+}
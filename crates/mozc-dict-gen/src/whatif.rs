@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use vibrato::{SystemDictionaryBuilder, Tokenizer};
+
+#[derive(clap::Args, Debug)]
+pub struct WhatIfArgs {
+    /// Overlay of cost overrides. Tab separated lines of either
+    /// `entry <surface> <reading> <cost>` or `conn <left id|pos> <right id|pos> <cost>`
+    #[arg(long)]
+    overlay: PathBuf,
+
+    /// Sentence to tokenize; repeatable. Reads sentences from stdin if omitted
+    #[arg(long)]
+    text: Vec<String>,
+
+    /// Directory holding lex.csv, matrix.def, char.def and unk.def (kept with --keep-intermediate)
+    #[arg(long, default_value = "output")]
+    dict_dir: PathBuf,
+
+    /// Mozc id.def used to resolve POS names in connection overrides
+    #[arg(long, default_value = "mozc_src/id.def")]
+    id_def: PathBuf,
+}
+
+#[derive(Default)]
+struct Overlay {
+    /// (surface, reading) -> cost
+    entries: HashMap<(String, String), i16>,
+    /// (left id, right id) -> cost
+    connections: HashMap<(u16, u16), i16>,
+}
+
+pub fn run(args: &WhatIfArgs) -> Result<()> {
+    let overlay = read_overlay(args)?;
+    println!(
+        "Overlay: {} entry overrides, {} connection overrides",
+        overlay.entries.len(),
+        overlay.connections.len()
+    );
+
+    println!("Compiling baseline and modified dictionaries in memory...");
+    let lex = fs::read(args.dict_dir.join("lex.csv"))?;
+    let matrix = fs::read(args.dict_dir.join("matrix.def"))?;
+    let char_def = fs::read(args.dict_dir.join("char.def"))?;
+    let unk_def = fs::read(args.dict_dir.join("unk.def"))?;
+
+    let baseline = SystemDictionaryBuilder::from_readers(&lex[..], &matrix[..], &char_def[..], &unk_def[..])?;
+    let (lex, applied) = apply_entry_overrides(&lex, &overlay)?;
+    let matrix = apply_connection_overrides(&matrix, &overlay)?;
+    println!("Applied {} entry overrides to lex.csv", applied);
+    let modified = SystemDictionaryBuilder::from_readers(&lex[..], &matrix[..], &char_def[..], &unk_def[..])?;
+
+    let baseline = Tokenizer::new(baseline);
+    let modified = Tokenizer::new(modified);
+
+    if !args.text.is_empty() {
+        for text in &args.text {
+            compare(&baseline, &modified, text);
+        }
+        return Ok(());
+    }
+
+    // Interactive mode: one sentence per line until EOF
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        let text = line.trim();
+        if !text.is_empty() {
+            compare(&baseline, &modified, text);
+        }
+    }
+    Ok(())
+}
+
+fn compare(baseline: &Tokenizer, modified: &Tokenizer, text: &str) {
+    println!("{}", text);
+    println!("  before: {}", segment(baseline, text));
+    println!("  after:  {}", segment(modified, text));
+}
+
+fn read_overlay(args: &WhatIfArgs) -> Result<Overlay> {
+    let mut overlay = Overlay::default();
+    let mut id_map = None;
+
+    let reader = BufReader::new(File::open(&args.overlay)?);
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split('\t').collect();
+        let context = || format!("{:?} line {}: {}", args.overlay, line_no + 1, line);
+        match parts.as_slice() {
+            ["entry", surface, reading, cost] => {
+                let cost: i16 = cost.parse().with_context(context)?;
+                overlay.entries.insert((surface.to_string(), reading.to_string()), cost);
+            }
+            ["conn", left, right, cost] => {
+                let cost: i16 = cost.parse().with_context(context)?;
+                if id_map.is_none() {
+                    id_map = Some(mozc_dict_gen::read_id_def_file(&args.id_def)?);
+                }
+                let id_map = id_map.as_ref().unwrap();
+                let lefts: BTreeSet<u16> = crate::conn::resolve_ids(left, id_map)?;
+                let rights: BTreeSet<u16> = crate::conn::resolve_ids(right, id_map)?;
+                for &l in &lefts {
+                    for &r in &rights {
+                        overlay.connections.insert((l, r), cost);
+                    }
+                }
+            }
+            _ => anyhow::bail!("Malformed overlay line ({})", context()),
+        }
+    }
+    Ok(overlay)
+}
+
+/// Rewrites the cost column of lex.csv rows matching an entry override.
+fn apply_entry_overrides(lex: &[u8], overlay: &Overlay) -> Result<(Vec<u8>, usize)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(lex);
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .quote_style(csv::QuoteStyle::Necessary)
+        .from_writer(Vec::new());
+
+    let mut applied = 0;
+    for record in reader.records() {
+        let record = record?;
+        // surface, left_id, right_id, cost, 7 POS columns, reading, pronunciation
+        let key = (record[0].to_string(), record.get(11).unwrap_or("").to_string());
+        match overlay.entries.get(&key) {
+            Some(cost) => {
+                let mut fields: Vec<String> = record.iter().map(|f| f.to_string()).collect();
+                fields[3] = cost.to_string();
+                writer.write_record(&fields)?;
+                applied += 1;
+            }
+            None => writer.write_record(&record)?,
+        }
+    }
+    Ok((writer.into_inner()?, applied))
+}
+
+/// Rewrites matrix.def costs for overridden (left, right) pairs.
+fn apply_connection_overrides(matrix: &[u8], overlay: &Overlay) -> Result<Vec<u8>> {
+    if overlay.connections.is_empty() {
+        return Ok(matrix.to_vec());
+    }
+    let mut out = Vec::with_capacity(matrix.len());
+    for (i, line) in matrix.lines().enumerate() {
+        let line = line?;
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if i > 0 && parts.len() == 3 {
+            let pair = (parts[0].parse()?, parts[1].parse()?);
+            if let Some(cost) = overlay.connections.get(&pair) {
+                writeln!(out, "{} {} {}", parts[0], parts[1], cost)?;
+                continue;
+            }
+        }
+        writeln!(out, "{}", line)?;
+    }
+    Ok(out)
+}
+
+fn segment(tokenizer: &Tokenizer, text: &str) -> String {
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence(text);
+    worker.tokenize();
+    let mut surfaces = Vec::new();
+    let mut total_cost = 0;
+    for i in 0..worker.num_tokens() {
+        let token = worker.token(i);
+        surfaces.push(token.surface().to_string());
+        total_cost = token.total_cost();
+    }
+    format!("{}  (cost {})", surfaces.join(" | "), total_cost)
+}
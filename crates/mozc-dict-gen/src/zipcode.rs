@@ -0,0 +1,142 @@
+//! Postal-code dictionary (`--with-zipcode`): Japan Post's KEN_ALL data as
+//! a Vibrato dictionary keyed by the code, as Mozc builds its zip code
+//! dictionary, so typing `100-0001` converts to 東京都千代田区千代田.
+//!
+//! KEN_ALL.CSV is Shift_JIS, one row per code and town, with the town's
+//! reading in half-width katakana. Rows are cleaned as Mozc does (see
+//! [`places::read_ken_all`], which input-data-gen `places` shares): notes in
+//! parentheses are dropped, towns split over several rows are joined, and
+//! placeholders for "the whole city" (以下に掲載がない場合, ...一円, ...の次に
+//! 番地がくる場合) leave the address at the city. Rows are written in the
+//! conversion layout, the code (with Mozc's hyphen) as the key and the
+//! address as the first feature:
+//!
+//! ```text
+//! 100-0001,1847,1847,5000,東京都千代田区千代田,名詞,固有名詞,地域,一般,*,*,*,とうきようとちよだくちよだ,...
+//! ```
+//!
+//! The matrix, char.def and unk.def of the system dictionary are reused.
+
+use anyhow::{Context, Result};
+use keyboard_formats::places::{self, Place};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::features::ReadingScript;
+use crate::{general_pos_id, pos_columns, IdMap};
+
+/// Japan Post's KEN_ALL data, a zip holding KEN_ALL.CSV.
+pub const KEN_ALL_URL: &str = "https://www.post.japanpost.jp/zipcode/dl/kogaki/zip/ken_all.zip";
+
+pub const KEN_ALL_FILE: &str = "KEN_ALL.CSV";
+
+/// File name of the code-keyed lexicon next to lex.csv.
+pub const ZIPCODE_LEX: &str = "zipcode_lex.csv";
+
+/// File name of the compiled dictionary, before the extension of its
+/// compression.
+pub const ZIPCODE_DIC: &str = "zipcode.dic";
+
+/// POS of the entries, as Mozc's zip code dictionary uses.
+const ZIPCODE_POS: &str = "名詞,固有名詞,地域,一般";
+
+/// Cost of every entry; they only compete with each other.
+const ZIPCODE_COST: i16 = 5000;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ZipCode {
+    /// `100-0001`
+    pub code: String,
+    pub address: String,
+    /// Hiragana, with small kana written large as KEN_ALL gives them
+    pub reading: String,
+}
+
+/// Reads KEN_ALL.CSV (Shift_JIS) into one entry per distinct code and
+/// address, sorted. Rows are cleaned by [`places::read_ken_all`].
+pub fn read_ken_all(mut reader: impl Read) -> Result<Vec<ZipCode>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let (text, _, had_errors) = encoding_rs::SHIFT_JIS.decode(&bytes);
+    anyhow::ensure!(!had_errors, "{} is not valid Shift_JIS", KEN_ALL_FILE);
+    let table = places::read_ken_all(&text).with_context(|| format!("Failed to read {}", KEN_ALL_FILE))?;
+    let entries: BTreeSet<ZipCode> = table.iter().map(entry).collect();
+    Ok(entries.into_iter().collect())
+}
+
+fn entry(place: &Place) -> ZipCode {
+    let code = &place.postal_code;
+    ZipCode {
+        code: match code.split_at_checked(3) {
+            Some((head, tail)) if !tail.is_empty() => format!("{}-{}", head, tail),
+            _ => code.to_string(),
+        },
+        address: format!("{}{}{}", place.prefecture.surface, place.city.surface, place.town.surface),
+        reading: format!("{}{}{}", place.prefecture.reading, place.city.reading, place.town.reading),
+    }
+}
+
+/// Reads KEN_ALL from `path`: Japan Post's zip, or KEN_ALL.CSV itself.
+pub fn read_ken_all_file(path: &Path) -> Result<Vec<ZipCode>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    if !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip")) {
+        return read_ken_all(BufReader::new(file));
+    }
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).context("Not a zip archive")?;
+    let name = archive
+        .file_names()
+        .find(|name| name.eq_ignore_ascii_case(KEN_ALL_FILE))
+        .with_context(|| format!("{} not found in {:?}", KEN_ALL_FILE, path))?
+        .to_string();
+    let entries = read_ken_all(archive.by_name(&name)?)?;
+    Ok(entries)
+}
+
+/// Writes the entries as code-keyed lexicon rows with the context ID of a
+/// place name in `id_map`, readings in `script` (hiragana when None),
+/// returning how many were written.
+pub fn write_lex(
+    entries: &[ZipCode],
+    id_map: &IdMap,
+    script: Option<ReadingScript>,
+    writer: impl Write,
+) -> Result<usize> {
+    let (id, pos) = general_pos_id(id_map, ZIPCODE_POS).with_context(|| format!("No POS {} in id.def", ZIPCODE_POS))?;
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .quote_style(csv::QuoteStyle::Necessary)
+        .from_writer(writer);
+    let (id, cost) = (id.to_string(), ZIPCODE_COST.to_string());
+    for entry in entries {
+        let mut row = vec![entry.code.as_str(), &id, &id, &cost, &entry.address];
+        row.extend(pos_columns(pos));
+        let reading = script.map_or_else(|| entry.reading.clone(), |script| script.convert(&entry.reading));
+        row.extend([reading.as_str(), &reading]);
+        writer.write_record(&row)?;
+    }
+    writer.flush()?;
+    Ok(entries.len())
+}
+
+/// Compiles [`ZIPCODE_LEX`] in `dir` with the other builder inputs there.
+pub fn compile_dir(dir: &Path) -> Result<vibrato::Dictionary> {
+    Ok(vibrato::SystemDictionaryBuilder::from_readers(
+        File::open(dir.join(ZIPCODE_LEX))?,
+        File::open(dir.join("matrix.def"))?,
+        File::open(dir.join("char.def"))?,
+        File::open(dir.join("unk.def"))?,
+    )?)
+}
+
+/// Writes [`ZIPCODE_LEX`] into `dir` from KEN_ALL at `path`, returning the
+/// number of entries.
+pub fn write_lex_file(path: &Path, id_map: &IdMap, script: Option<ReadingScript>, dir: &Path) -> Result<usize> {
+    let entries = read_ken_all_file(path)?;
+    let mut writer = BufWriter::new(File::create(dir.join(ZIPCODE_LEX))?);
+    let written = write_lex(&entries, id_map, script, &mut writer)?;
+    writer.flush()?;
+    Ok(written)
+}
@@ -0,0 +1,80 @@
+//! Tracing setup shared by the forge binaries and the demo keyboard.
+//!
+//! Pipelines open a span per stage (download, tokenize, write, compile...)
+//! and the runtime one per prediction query. [`init`] prints events to
+//! stderr, filtered by `RUST_LOG` (warnings and errors when unset),
+//! and logs span durations when they close. Records of crates still using
+//! `log` are forwarded.
+//!
+//! With the `otlp` feature and `OTEL_EXPORTER_OTLP_ENDPOINT` set, spans at
+//! info level and above are also exported over OTLP/HTTP, whatever
+//! `RUST_LOG` says; the other `OTEL_EXPORTER_OTLP_*` variables configure
+//! the exporter as usual.
+
+use anyhow::Result;
+use std::io::IsTerminal;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Flushes exported spans when dropped; keep it alive until `main` returns.
+pub struct Telemetry {
+    #[cfg(feature = "otlp")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+/// Installs the global subscriber, naming the process `service` in exported
+/// traces.
+pub fn init(service: &'static str) -> Result<Telemetry> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn"));
+    let stderr = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_span_events(FmtSpan::CLOSE)
+        .with_filter(filter);
+    let registry = tracing_subscriber::registry().with(stderr);
+
+    #[cfg(feature = "otlp")]
+    let (registry, provider) = {
+        let provider = match std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            Some(_) => Some(otlp_provider(service)?),
+            None => None,
+        };
+        let layer = provider.as_ref().map(|provider| {
+            use opentelemetry::trace::TracerProvider;
+            tracing_opentelemetry::layer()
+                .with_tracer(provider.tracer(service))
+                .with_filter(tracing_subscriber::filter::LevelFilter::INFO)
+        });
+        (registry.with(layer), provider)
+    };
+    #[cfg(not(feature = "otlp"))]
+    let _ = service;
+
+    registry.try_init()?;
+    Ok(Telemetry {
+        #[cfg(feature = "otlp")]
+        provider,
+    })
+}
+
+#[cfg(feature = "otlp")]
+fn otlp_provider(service: &'static str) -> Result<opentelemetry_sdk::trace::SdkTracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder().with_http().build()?;
+    Ok(opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(opentelemetry_sdk::Resource::builder().with_service_name(service).build())
+        .build())
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! Compares ways of storing the n-gram model for next-word prediction: the
+//! fst::Map the forge ships, a prediction index (context → its top next
+//! words, precomputed) and a word trie. Each is built from the same counts
+//! and asked the same contexts; the report gives size, build time and query
+//! latency.
+//!
+//! Usage: cargo run --release -p test-resources --example bench_ngram_formats -- [counts.tsv]
+//!
+//! `counts.tsv` is a wiki-ngram shard file (`#ngram-counts` header); without
+//! one, counts are generated from a fixed pseudo-random corpus.
+
+use anyhow::Result;
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use wiki_ngram::histogram::CountHistogram;
+use wiki_ngram::ngram::{self, FilterConfig, FrequencyComparison, NgramCounts, Smoothing};
+use wiki_ngram::shard;
+use wiki_ngram::stage::{FrequencyFilter, NgramFilter};
+
+/// Predictions asked for per query, as the demo keyboard shows.
+const LIMIT: usize = 5;
+
+/// Next words the prediction index keeps per context.
+const INDEX_TOP_K: usize = 8;
+
+/// Contexts queried, sampled evenly from all contexts in the model.
+const QUERIES: usize = 5000;
+
+/// Each format stores (key, score) entries with space-joined keys and
+/// answers "best next words after this context".
+trait NgramStore: Sized {
+    const NAME: &'static str;
+
+    fn build(entries: &[(String, u64)]) -> Result<Self>;
+
+    /// Bytes the format takes, serialized or (for in-memory formats) as
+    /// laid out in memory.
+    fn size(&self) -> usize;
+
+    /// Up to `limit` words following `context`, best first, ties by word.
+    fn predict(&self, context: &str, limit: usize) -> Vec<(String, u64)>;
+}
+
+/// The shipped format: every n-gram a key, prediction by prefix search.
+struct FstMap {
+    map: Map<Vec<u8>>,
+}
+
+impl NgramStore for FstMap {
+    const NAME: &'static str = "fst-map";
+
+    fn build(entries: &[(String, u64)]) -> Result<Self> {
+        let mut builder = MapBuilder::memory();
+        for (key, score) in entries {
+            builder.insert(key, *score)?;
+        }
+        Ok(Self {
+            map: Map::new(builder.into_inner()?)?,
+        })
+    }
+
+    fn size(&self) -> usize {
+        self.map.as_fst().as_bytes().len()
+    }
+
+    fn predict(&self, context: &str, limit: usize) -> Vec<(String, u64)> {
+        let prefix = format!("{} ", context);
+        let mut stream = self.map.search(Str::new(&prefix).starts_with()).into_stream();
+        let mut predictions = Vec::new();
+        while let Some((key, score)) = stream.next() {
+            let next = String::from_utf8_lossy(&key[prefix.len()..]);
+            if !next.contains(' ') {
+                predictions.push((next.into_owned(), score));
+            }
+        }
+        rank(&mut predictions, limit);
+        predictions
+    }
+}
+
+/// Context → offset of its top next words in a packed table
+/// (`count u8`, then per word `score u32`, `len u8`, UTF-8 bytes).
+struct PredictionIndex {
+    contexts: Map<Vec<u8>>,
+    table: Vec<u8>,
+}
+
+impl NgramStore for PredictionIndex {
+    const NAME: &'static str = "prediction-index";
+
+    fn build(entries: &[(String, u64)]) -> Result<Self> {
+        let mut by_context: BTreeMap<&str, Vec<(String, u64)>> = BTreeMap::new();
+        for (key, score) in entries {
+            if let Some((context, next)) = key.rsplit_once(' ') {
+                by_context.entry(context).or_default().push((next.to_string(), *score));
+            }
+        }
+        let mut builder = MapBuilder::memory();
+        let mut table = Vec::new();
+        for (context, mut next) in by_context {
+            rank(&mut next, INDEX_TOP_K);
+            builder.insert(context, table.len() as u64)?;
+            table.push(next.len() as u8);
+            for (word, score) in next {
+                table.extend_from_slice(&(score as u32).to_le_bytes());
+                table.push(word.len() as u8);
+                table.extend_from_slice(word.as_bytes());
+            }
+        }
+        Ok(Self {
+            contexts: Map::new(builder.into_inner()?)?,
+            table,
+        })
+    }
+
+    fn size(&self) -> usize {
+        self.contexts.as_fst().as_bytes().len() + self.table.len()
+    }
+
+    fn predict(&self, context: &str, limit: usize) -> Vec<(String, u64)> {
+        let Some(offset) = self.contexts.get(context) else { return Vec::new() };
+        let mut at = offset as usize;
+        let count = self.table[at] as usize;
+        at += 1;
+        let mut predictions = Vec::with_capacity(count.min(limit));
+        for _ in 0..count.min(limit) {
+            let score = u32::from_le_bytes(self.table[at..at + 4].try_into().unwrap()) as u64;
+            let len = self.table[at + 4] as usize;
+            at += 5;
+            predictions.push((String::from_utf8_lossy(&self.table[at..at + len]).into_owned(), score));
+            at += len;
+        }
+        predictions
+    }
+}
+
+/// Words interned to IDs, n-grams as paths of word IDs from the root.
+struct WordTrie {
+    words: Vec<String>,
+    ids: HashMap<String, u32>,
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Default)]
+struct TrieNode {
+    /// (word ID, node index), sorted by word ID
+    children: Vec<(u32, u32)>,
+    /// Score of the n-gram ending here, if it is in the model
+    score: Option<u64>,
+}
+
+impl WordTrie {
+    fn child(&self, node: u32, word: u32) -> Option<u32> {
+        let children = &self.nodes[node as usize].children;
+        children.binary_search_by_key(&word, |&(w, _)| w).ok().map(|i| children[i].1)
+    }
+}
+
+impl NgramStore for WordTrie {
+    const NAME: &'static str = "word-trie";
+
+    fn build(entries: &[(String, u64)]) -> Result<Self> {
+        let mut trie = Self {
+            words: Vec::new(),
+            ids: HashMap::new(),
+            nodes: vec![TrieNode::default()],
+        };
+        for (key, score) in entries {
+            let mut node = 0;
+            for word in key.split(' ') {
+                let id = *trie.ids.entry(word.to_string()).or_insert_with(|| {
+                    trie.words.push(word.to_string());
+                    trie.words.len() as u32 - 1
+                });
+                node = match trie.child(node, id) {
+                    Some(child) => child,
+                    None => {
+                        let child = trie.nodes.len() as u32;
+                        trie.nodes.push(TrieNode::default());
+                        let children = &mut trie.nodes[node as usize].children;
+                        let at = children.partition_point(|&(w, _)| w < id);
+                        children.insert(at, (id, child));
+                        child
+                    }
+                };
+            }
+            trie.nodes[node as usize].score = Some(*score);
+        }
+        Ok(trie)
+    }
+
+    fn size(&self) -> usize {
+        let words: usize = self.words.iter().map(|word| word.len() + 24 + 4 + 24).sum();
+        let nodes: usize = self.nodes.iter().map(|node| 24 + 16 + node.children.len() * 8).sum();
+        words + nodes
+    }
+
+    fn predict(&self, context: &str, limit: usize) -> Vec<(String, u64)> {
+        let mut node = 0;
+        for word in context.split(' ') {
+            let Some(child) = self.ids.get(word).and_then(|&id| self.child(node, id)) else { return Vec::new() };
+            node = child;
+        }
+        let mut predictions: Vec<(String, u64)> = self.nodes[node as usize]
+            .children
+            .iter()
+            .filter_map(|&(word, child)| Some((self.words[word as usize].clone(), self.nodes[child as usize].score?)))
+            .collect();
+        rank(&mut predictions, limit);
+        predictions
+    }
+}
+
+fn rank(predictions: &mut Vec<(String, u64)>, limit: usize) {
+    predictions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    predictions.truncate(limit);
+}
+
+struct Row {
+    name: &'static str,
+    size: usize,
+    build: Duration,
+    p50: Duration,
+    p95: Duration,
+    agrees: usize,
+}
+
+fn bench<S: NgramStore>(entries: &[(String, u64)], contexts: &[&str], reference: &[Vec<(String, u64)>]) -> Result<Row> {
+    let start = Instant::now();
+    let store = S::build(entries)?;
+    let build = start.elapsed();
+
+    let mut latencies = Vec::with_capacity(contexts.len());
+    let mut agrees = 0;
+    for (context, expected) in contexts.iter().zip(reference) {
+        let start = Instant::now();
+        let predictions = store.predict(context, LIMIT);
+        latencies.push(start.elapsed());
+        agrees += usize::from(&predictions == expected);
+    }
+    latencies.sort();
+    let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p) as usize];
+    Ok(Row {
+        name: S::NAME,
+        size: store.size(),
+        build,
+        p50: percentile(0.5),
+        p95: percentile(0.95),
+        agrees,
+    })
+}
+
+/// Counts of a fixed pseudo-random corpus with a Zipf-like vocabulary.
+fn generated_counts() -> NgramCounts {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let mut counts = NgramCounts::default();
+    for _ in 0..100_000 {
+        let len = 3 + (next() % 8) as usize;
+        let words: Vec<String> = (0..len)
+            .map(|_| {
+                // Squaring a uniform draw skews it towards frequent words
+                let u = (next() % 1_000_000) as f64 / 1_000_000.0;
+                format!("w{}", (u * u * 3000.0) as u32)
+            })
+            .collect();
+        ngram::extract_ngrams_from_tokens(&words, 3, 1, &mut counts);
+    }
+    counts
+}
+
+fn main() -> Result<()> {
+    let counts = match std::env::args().nth(1) {
+        Some(path) => shard::merge_shards(&[path.into()])?,
+        None => generated_counts(),
+    };
+    let filter = FrequencyFilter {
+        config: FilterConfig {
+            min_frequency: 2,
+            comparison: FrequencyComparison::Ge,
+            per_order_min_frequency: Default::default(),
+            drop_mass_fraction: None,
+            rare_discount: None,
+        },
+        smoothing: Smoothing::LogCount,
+    };
+    let entries = filter.filter(&counts, &CountHistogram::from_counts(&counts));
+
+    let mut all_contexts: Vec<&str> =
+        entries.iter().filter_map(|(key, _)| key.rsplit_once(' ')).map(|(context, _)| context).collect();
+    all_contexts.sort_unstable();
+    all_contexts.dedup();
+    let step = (all_contexts.len() / QUERIES).max(1);
+    let contexts: Vec<&str> = all_contexts.iter().step_by(step).copied().collect();
+    let reference = {
+        let fst = FstMap::build(&entries)?;
+        contexts.iter().map(|context| fst.predict(context, LIMIT)).collect::<Vec<_>>()
+    };
+
+    println!("{} n-grams, {} contexts queried, top {}", entries.len(), contexts.len(), LIMIT);
+    println!(
+        "{:<18} | {:>10} | {:>10} | {:>9} | {:>9} | {:>8}",
+        "Format", "Size (KB)", "Build (ms)", "p50 (µs)", "p95 (µs)", "Agrees"
+    );
+    println!("{:-<18}-+-{:->10}-+-{:->10}-+-{:->9}-+-{:->9}-+-{:->8}", "", "", "", "", "", "");
+    let rows = [
+        bench::<FstMap>(&entries, &contexts, &reference)?,
+        bench::<PredictionIndex>(&entries, &contexts, &reference)?,
+        bench::<WordTrie>(&entries, &contexts, &reference)?,
+    ];
+    for row in rows {
+        println!(
+            "{:<18} | {:>10.1} | {:>10.1} | {:>9.2} | {:>9.2} | {:>7.1}%",
+            row.name,
+            row.size as f64 / 1024.0,
+            row.build.as_secs_f64() * 1000.0,
+            row.p50.as_secs_f64() * 1e6,
+            row.p95.as_secs_f64() * 1e6,
+            row.agrees as f64 * 100.0 / contexts.len() as f64
+        );
+    }
+    println!("word-trie size is its in-memory layout; the others are serialized bytes.");
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! The tokenizer's best path through `output/system.dic.zst` for a text,
+//! with word and connection costs. Vibrato doesn't expose its lattice; the
+//! conversion lattice of a reading, every node included, is dumped by
+//! `demo-keyboard --script <file> --dump-lattice dot|json`.
+
+use anyhow::Result;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use mozc_dict_gen::vibrato::{Dictionary, Tokenizer};
+
+struct Node {
+    surface: String,
+    feature: String,
+    left_id: u16,
+    right_id: u16,
+    word_cost: i16,
+    /// Connection cost from the previous node (BOS for the first token)
+    conn_cost: i32,
+    total_cost: i32,
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: cargo run -p test-resources --example dump_path -- <text> [dot|json]");
+        return Ok(());
+    }
+
+    let text = &args[1];
+    let format = args.get(2).map(|s| s.as_str()).unwrap_or("dot");
+
+    let file = File::open("output/system.dic.zst")?;
+    let decoder = zstd::stream::read::Decoder::new(BufReader::new(file))?;
+    let tokenizer = Tokenizer::new(Dictionary::read(decoder)?);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence(text);
+    worker.tokenize();
+
+    // Vibrato only exposes the best path, so connection costs are recovered
+    // from the accumulated path cost: total[i] = total[i-1] + conn + word.
+    let mut nodes = Vec::new();
+    let mut prev_total = 0;
+    for i in 0..worker.num_tokens() {
+        let t = worker.token(i);
+        nodes.push(Node {
+            surface: t.surface().to_string(),
+            feature: t.feature().to_string(),
+            left_id: t.left_id(),
+            right_id: t.right_id(),
+            word_cost: t.word_cost(),
+            conn_cost: t.total_cost() - prev_total - t.word_cost() as i32,
+            total_cost: t.total_cost(),
+        });
+        prev_total = t.total_cost();
+    }
+
+    match format {
+        "json" => print_json(text, &nodes)?,
+        _ => print_dot(&nodes),
+    }
+    Ok(())
+}
+
+fn print_dot(nodes: &[Node]) {
+    println!("digraph path {{");
+    println!("  rankdir=LR;");
+    println!("  node [shape=box];");
+    println!("  bos [label=\"BOS\"];");
+    for (i, n) in nodes.iter().enumerate() {
+        // Debug formatting quotes and escapes the label as DOT reads it
+        let label = format!("{}\n{}\nL{} R{} word={}", n.surface, n.feature, n.left_id, n.right_id, n.word_cost);
+        println!("  n{} [label={:?}];", i, label);
+        let from = if i == 0 { "bos".to_string() } else { format!("n{}", i - 1) };
+        println!("  {} -> n{} [label=\"conn={} total={}\"];", from, i, n.conn_cost, n.total_cost);
+    }
+    println!("}}");
+}
+
+fn print_json(text: &str, nodes: &[Node]) -> Result<()> {
+    let path: Vec<serde_json::Value> = nodes
+        .iter()
+        .map(|n| {
+            serde_json::json!({
+                "surface": n.surface,
+                "feature": n.feature,
+                "left_id": n.left_id,
+                "right_id": n.right_id,
+                "word_cost": n.word_cost,
+                "conn_cost": n.conn_cost,
+                "total_cost": n.total_cost,
+            })
+        })
+        .collect();
+    let json = serde_json::json!({ "input": text, "path": path });
+    println!("{}", serde_json::to_string_pretty(&json)?);
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::fs::File;
 use std::io::BufReader;
-use vibrato::Dictionary;
-use vibrato::Tokenizer;
+use mozc_dict_gen::vibrato::Dictionary;
+use mozc_dict_gen::vibrato::Tokenizer;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let file = File::open("output/system.dic.zst")?;
@@ -0,0 +1,27 @@
+use keyboard_formats::annotation::{is_emoji, Annotation, CandidateFlags};
+
+#[test]
+fn test_columns_round_trip() {
+    let annotation = Annotation {
+        text: Some("【顔文字】, 古い".to_string()),
+        flags: CandidateFlags::parse("slang|emoji|future"),
+    };
+    let [text, flags] = annotation.columns().unwrap();
+    assert_eq!(text, "【顔文字】， 古い", "Commas would split the feature");
+    assert_eq!(flags, "emoji|slang", "Unknown flags are dropped");
+    assert_eq!(Annotation::from_columns(Some(&text), Some(&flags)).flags, annotation.flags);
+
+    assert_eq!(Annotation::default().columns(), None);
+    assert!(Annotation::from_columns(Some("*"), Some("*")).is_empty());
+    assert_eq!(annotation.to_string(), "【顔文字】, 古い (emoji, slang)");
+}
+
+#[test]
+fn test_is_emoji() {
+    assert!(is_emoji("😀"));
+    assert!(is_emoji("👨‍👩‍👧"));
+    assert!(is_emoji("☀\u{FE0F}"));
+    assert!(!is_emoji("☀"), "Text-style symbols are not emoji");
+    assert!(!is_emoji("😀です"));
+    assert!(!is_emoji(""));
+}
@@ -0,0 +1,40 @@
+use keyboard_formats::annotation::CandidateFlags;
+use keyboard_formats::candidate_filter::{Modes, DEMOTE_COST};
+
+#[test]
+fn test_builtin_modes() {
+    let modes = Modes::builtin();
+    let kid_safe = modes.filter(["kid-safe"]).unwrap();
+    assert!(!kid_safe.allows(CandidateFlags::VULGAR));
+    assert!(!kid_safe.allows(CandidateFlags::parse("slang|emoji")));
+    assert!(kid_safe.allows(CandidateFlags::EMOJI));
+    assert!(modes.filter(["no-swearing"]).is_err());
+
+    let none = modes.filter([]).unwrap();
+    assert!(none.is_empty());
+    assert_eq!(none.penalty(CandidateFlags::VULGAR), Some(0));
+}
+
+#[test]
+fn test_hiding_wins_over_demoting() {
+    let modes = Modes::builtin();
+    let formal = modes.filter(["formal"]).unwrap();
+    assert_eq!(formal.penalty(CandidateFlags::SLANG), Some(DEMOTE_COST));
+    assert_eq!(formal.penalty(CandidateFlags::SYMBOL), Some(0));
+
+    let strict = modes.filter(["formal", "no-slang"]).unwrap();
+    assert_eq!(strict.penalty(CandidateFlags::SLANG), None);
+    assert_eq!(strict.penalty(CandidateFlags::EMOJI), Some(DEMOTE_COST));
+}
+
+#[test]
+fn test_modes_round_trip() {
+    let text = "#candidate-filters\tv1\n# custom\nno-symbols\tsymbol\tdemote\n";
+    let modes = Modes::read(text.as_bytes()).unwrap();
+    let mut out = Vec::new();
+    modes.write(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "#candidate-filters\tv1\nno-symbols\tsymbol\tdemote\n");
+
+    assert!(Modes::read("#candidate-filters\tv1\nx\tsymbol\tdrop\n".as_bytes()).is_err());
+    assert!(Modes::read("#candidate-filters\tv1\nx\tunknown\thide\n".as_bytes()).is_err());
+}
@@ -0,0 +1,65 @@
+use fst::{Map, MapBuilder};
+use keyboard_formats::completion::Completer;
+
+fn completer() -> Completer<Vec<u8>> {
+    let mut words = vec![("てんき", 6000), ("てがみ", 5000), ("きょう", 7000), ("つくえ", 4000), ("ちず", 3000)];
+    words.sort();
+    let mut builder = MapBuilder::memory();
+    for (word, score) in words {
+        builder.insert(word, score).unwrap();
+    }
+    Completer::new(Map::new(builder.into_inner().unwrap()).unwrap())
+}
+
+fn readings(kana: &str, next: &[&str]) -> Vec<(String, usize)> {
+    completer()
+        .complete(kana, next, 10)
+        .into_iter()
+        .map(|c| (c.reading, c.start))
+        .collect()
+}
+
+#[test]
+fn test_completes_trailing_word() {
+    let start = "きょうの".len();
+    assert_eq!(
+        readings("きょうのて", &[]),
+        vec![("てんき".to_string(), start), ("てがみ".to_string(), start)]
+    );
+}
+
+#[test]
+fn test_pending_romaji() {
+    // "kyouno" + pending "t": た, ち, つ, て, と could follow
+    let next = ["た", "ち", "つ", "て", "と", "っ"];
+    let words: Vec<String> = readings("きょうの", &next).into_iter().map(|(r, _)| r).collect();
+    assert_eq!(words, vec!["てんき", "てがみ", "つくえ", "ちず"]);
+}
+
+#[test]
+fn test_nothing_to_complete() {
+    assert!(readings("", &[]).is_empty());
+    assert!(readings("ぬ", &[]).is_empty());
+}
+
+#[test]
+fn test_context_reranks_completions() {
+    let surface = |reading: &str| match reading {
+        "てんき" => "天気".to_string(),
+        "てがみ" => "手紙".to_string(),
+        _ => reading.to_string(),
+    };
+    let ranked = completer().complete_in_context("きょうのて", &[], 10, 1, surface, |start| {
+        assert_eq!(start, "きょうの".len(), "The context is the input before the word");
+        // The model expects 手紙 after きょうの strongly enough to beat the more frequent reading
+        |_: &str, surface: &str| if surface == "手紙" { 1.5 } else { 0.0 }
+    });
+    let words: Vec<(&str, &str, f64)> =
+        ranked.iter().map(|c| (c.surface.as_str(), c.reading.as_str(), c.score)).collect();
+    assert_eq!(words, [("手紙", "てがみ", 6.5)]);
+
+    let unranked = completer().complete_in_context("きょうのて", &[], 10, 5, surface, |_| |_: &str, _: &str| 0.0);
+    let words: Vec<&str> = unranked.iter().map(|c| c.surface.as_str()).collect();
+    assert_eq!(words, ["天気", "手紙"], "Reading frequency decides without context");
+    assert!(completer().complete_in_context("ぬ", &[], 10, 5, surface, |_| |_: &str, _: &str| 0.0).is_empty());
+}
@@ -0,0 +1,50 @@
+use keyboard_formats::confusion::{ConfusionModel, DefaultCosts};
+
+fn model() -> ConfusionModel {
+    let mut m = ConfusionModel::new("romaji", DefaultCosts::default());
+    m.set_substitution("a", "s", 1000);
+    m.set_substitution("shi", "si", 200);
+    m.set_substitution("nn", "n", 500);
+    m
+}
+
+#[test]
+fn test_exact_match_is_free() {
+    assert_eq!(model().distance("konnichiha", "konnichiha"), 0);
+}
+
+#[test]
+fn test_adjacent_key_cheaper_than_default() {
+    let m = model();
+    assert_eq!(m.distance("ame", "sme"), 1000);
+    assert_eq!(m.distance("ame", "pme"), DefaultCosts::default().substitution);
+}
+
+#[test]
+fn test_multi_character_rules() {
+    let m = model();
+    assert_eq!(m.distance("shinbun", "sinbun"), 200);
+    assert_eq!(m.distance("konnichiha", "konichiha"), 500);
+}
+
+#[test]
+fn test_transposition() {
+    let m = model();
+    assert_eq!(m.distance("arigatou", "arigaotu"), DefaultCosts::default().transposition);
+}
+
+#[test]
+fn test_text_roundtrip() {
+    let mut m = model();
+    m.set_insertion('q', 2500);
+    m.set_deletion('u', 1500);
+
+    let mut buf = Vec::new();
+    m.write(&mut buf).unwrap();
+    let read = ConfusionModel::read(&buf[..]).unwrap();
+
+    assert_eq!(read.unit, "romaji");
+    assert_eq!(read.distance("shinbun", "sinbun"), 200);
+    assert_eq!(read.insertion_cost('q'), 2500);
+    assert_eq!(read.deletion_cost('u'), 1500);
+}
@@ -0,0 +1,158 @@
+//! Conversion accuracy of a built conversion.dic over the curated pairs in
+//! fixtures/conversion_accuracy.tsv, as exact matches and as characters
+//! right (one minus the edit distance over the expected length). Needs the
+//! artifact, so it is ignored by default and run nightly:
+//!
+//! ```bash
+//! cargo run -p mozc-dict-gen --release -- --key reading
+//! cargo test -p test-resources --test conversion_accuracy_test -- --ignored --nocapture
+//! ```
+//!
+//! Set CONVERSION_MIN_EXACT_MATCH (0.0-1.0) to fail below an exact-match
+//! rate.
+
+use mozc_dict_gen::vibrato::dictionary::LexType;
+use mozc_dict_gen::vibrato::tokenizer::worker::Worker;
+use mozc_dict_gen::vibrato::Tokenizer;
+use std::path::PathBuf;
+
+/// Mismatches printed with the report.
+const SHOWN_MISMATCHES: usize = 30;
+
+/// CONVERSION_DICT_PATH, else output/conversion.dic.zst.
+fn get_conversion_dict_path() -> PathBuf {
+    std::env::var("CONVERSION_DICT_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("../../output/conversion.dic.zst"))
+}
+
+/// (reading, expected) pairs of the fixture.
+fn read_cases() -> Vec<(&'static str, &'static str)> {
+    include_str!("fixtures/conversion_accuracy.tsv")
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.split_once('\t').expect("reading<TAB>expected"))
+        .collect()
+}
+
+/// Joins the candidates of the best segmentation of `kana`; a token the
+/// dictionary doesn't have stays as typed.
+fn convert(worker: &mut Worker, kana: &str) -> String {
+    worker.reset_sentence(kana);
+    worker.tokenize();
+    worker
+        .token_iter()
+        .map(|token| match token.lex_type() {
+            LexType::Unknown => token.surface().to_string(),
+            _ => token.feature().split(',').next().unwrap_or_default().to_string(),
+        })
+        .collect()
+}
+
+/// Levenshtein distance in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[derive(Debug, Default)]
+struct Score {
+    cases: usize,
+    exact: usize,
+    /// Characters of the expected conversions
+    chars: usize,
+    /// Edits from the conversions to the expected ones
+    errors: usize,
+}
+
+impl Score {
+    fn add(&mut self, expected: &str, actual: &str) {
+        self.cases += 1;
+        self.exact += usize::from(expected == actual);
+        self.chars += expected.chars().count();
+        self.errors += edit_distance(actual, expected).min(expected.chars().count());
+    }
+
+    fn exact_match(&self) -> f64 {
+        self.exact as f64 / self.cases.max(1) as f64
+    }
+
+    fn char_accuracy(&self) -> f64 {
+        1.0 - self.errors as f64 / self.chars.max(1) as f64
+    }
+}
+
+#[test]
+fn test_accuracy_scoring() {
+    assert_eq!(edit_distance("今日は", "今日は"), 0);
+    assert_eq!(edit_distance("今日わ", "今日は"), 1);
+    assert_eq!(edit_distance("", "今日"), 2);
+
+    let mut score = Score::default();
+    score.add("今日は", "今日は");
+    score.add("明日", "あした");
+    assert_eq!((score.cases, score.exact, score.chars, score.errors), (2, 1, 5, 2), "Errors cap at the expected length");
+    assert_eq!(score.exact_match(), 0.5);
+    assert!((score.char_accuracy() - 0.6).abs() < 1e-9);
+}
+
+#[test]
+fn test_accuracy_fixture_is_well_formed() {
+    let cases = read_cases();
+    assert!(cases.len() >= 300, "Only {} pairs", cases.len());
+    let mut readings = std::collections::HashSet::new();
+    for (reading, expected) in cases {
+        assert!(reading.chars().all(|c| matches!(c, 'ぁ'..='ゖ' | 'ー')), "Not hiragana: {}", reading);
+        assert!(!expected.is_empty() && !expected.contains('\t'), "Bad expectation for {}", reading);
+        assert!(readings.insert(reading), "Listed twice: {}", reading);
+    }
+}
+
+#[test]
+#[ignore = "needs output/conversion.dic.zst from mozc-dict-gen --key reading"]
+fn test_conversion_accuracy() {
+    let path = get_conversion_dict_path();
+    let dict = mozc_dict_gen::read_dictionary(&path)
+        .unwrap_or_else(|e| panic!("Failed to read {:?} ({}); build it with --key reading", path, e));
+    let tokenizer = Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+
+    let mut score = Score::default();
+    let mut mismatches = Vec::new();
+    for (reading, expected) in read_cases() {
+        let actual = convert(&mut worker, reading);
+        score.add(expected, &actual);
+        if actual != expected {
+            mismatches.push((reading, expected, actual));
+        }
+    }
+    println!(
+        "Conversion accuracy over {} pairs: exact match {:.1}%, characters {:.1}%",
+        score.cases,
+        score.exact_match() * 100.0,
+        score.char_accuracy() * 100.0
+    );
+    for (reading, expected, actual) in mismatches.iter().take(SHOWN_MISMATCHES) {
+        println!("  {} → {} (expected {})", reading, actual, expected);
+    }
+
+    if let Ok(min) = std::env::var("CONVERSION_MIN_EXACT_MATCH") {
+        let min: f64 = min.parse().expect("CONVERSION_MIN_EXACT_MATCH must be a number");
+        assert!(
+            score.exact_match() >= min,
+            "Exact match {:.3} is below CONVERSION_MIN_EXACT_MATCH {}",
+            score.exact_match(),
+            min
+        );
+    }
+}
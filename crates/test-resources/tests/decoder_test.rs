@@ -0,0 +1,59 @@
+use fst::{Map, MapBuilder};
+use keyboard_formats::confusion::{ConfusionModel, DefaultCosts};
+use keyboard_formats::decoder::{BeamDecoder, TouchModel, WordTrie};
+use keyboard_formats::layout::{Key, Layout};
+
+fn trie(words: &[(&str, u64)]) -> WordTrie<Vec<u8>> {
+    let mut words = words.to_vec();
+    words.sort();
+    let mut builder = MapBuilder::memory();
+    for (word, score) in words {
+        builder.insert(word, score).unwrap();
+    }
+    WordTrie::new(Map::new(builder.into_inner().unwrap()).unwrap())
+}
+
+#[test]
+fn test_fuzzy_typing() {
+    let mut model = ConfusionModel::new("romaji", DefaultCosts::default());
+    model.set_substitution("shi", "si", 200);
+    model.set_substitution("a", "s", 1000);
+    let words = trie(&[("shinbun", 5000), ("shinpai", 6000), ("asa", 4000)]);
+
+    let typed: Vec<char> = "sinbun".chars().collect();
+    let results = BeamDecoder::default().decode(&model, &words, &typed);
+    assert_eq!(results[0].text, "shinbun", "{:?}", results);
+
+    // Adjacent-key slip (s for a) plus a transposition
+    let typed: Vec<char> = "sas".chars().collect();
+    let results = BeamDecoder::default().decode(&model, &words, &typed);
+    assert_eq!(results[0].text, "asa", "{:?}", results);
+}
+
+#[test]
+fn test_swipe_decoding() {
+    let mut layout = Layout::new("test", "kana");
+    for (output, x) in [("あ", 0.5), ("か", 1.5), ("さ", 2.5), ("た", 3.5)] {
+        layout.push(Key {
+            output: output.to_string(),
+            x,
+            y: 0.5,
+            width: 1.0,
+            height: 1.0,
+        });
+    }
+    let touch = TouchModel {
+        layout: &layout,
+        sigma: 0.5,
+        radius: 1.5,
+        skip: Some(-2.0),
+    };
+    let words = trie(&[("あさ", 5000), ("かさ", 5000), ("あかさ", 3000)]);
+
+    // Starts near あ, passes over か on the way, ends on さ
+    let points = [(0.6, 0.5), (1.5, 0.6), (2.4, 0.5)];
+    let results = BeamDecoder::default().decode(&touch, &words, &points);
+    let texts: Vec<&str> = results.iter().map(|h| h.text.as_str()).collect();
+    assert_eq!(texts[0], "あさ", "{:?}", results);
+    assert!(texts.contains(&"あかさ"), "{:?}", results);
+}
@@ -0,0 +1,135 @@
+use clap::Parser;
+use demo_keyboard::converter::{Converter, Lattice};
+use demo_keyboard::romaji;
+use demo_keyboard::session::SessionContext;
+use demo_keyboard::Args;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const LEX: &str = "今日,1,1,100,名詞,一般,*,*,*,*,*,きょう,キョー
+京,1,1,3000,名詞,一般,*,*,*,*,*,きょう,キョー
+は,2,2,50,助詞,係助詞,*,*,*,*,*,は,ワ
+晴れ,1,1,200,名詞,一般,*,*,*,*,*,はれ,ハレ
+";
+
+/// Writes the artifacts the demo reads into a fresh directory.
+fn artifacts(name: &str, lex: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("demo-keyboard-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("lex.csv"), lex).unwrap();
+    let cells: String = (0..9).map(|i| format!("{} {} 0\n", i / 3, i % 3)).collect();
+    fs::write(dir.join("matrix.def"), format!("3 3\n{}", cells)).unwrap();
+    let mut ngrams = fst::MapBuilder::memory();
+    for (key, score) in [("は 晴れ", 400), ("今日 は", 500), ("今日 は 晴れ", 300)] {
+        ngrams.insert(key, score).unwrap();
+    }
+    fs::write(dir.join("ngrams.fst"), ngrams.into_inner().unwrap()).unwrap();
+    fs::write(dir.join("script.txt"), "kyouha\n").unwrap();
+    dir
+}
+
+fn args(dir: &Path, extra: &[&str]) -> Args {
+    let path = |name: &str| dir.join(name).into_os_string();
+    let flags = [
+        ("--lex", "lex.csv"),
+        ("--matrix", "matrix.def"),
+        ("--ngrams", "ngrams.fst"),
+        ("--script", "script.txt"),
+    ];
+    let argv = std::iter::once("demo-keyboard".into())
+        .chain(flags.iter().flat_map(|&(flag, name)| [flag.into(), path(name)]))
+        .chain(extra.iter().map(|&arg| arg.into()));
+    Args::parse_from(argv)
+}
+
+#[test]
+fn test_script_mode_converts_and_predicts() {
+    let dir = artifacts("script", LEX);
+    let mut out = Vec::new();
+    demo_keyboard::run(&args(&dir, &[]), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "input\tkyouha",
+            "kana\tきょうは",
+            "conversion\t今日は",
+            "segments\t今日/きょう は/は",
+            "predictions\t晴れ",
+            "prediction_details\t晴れ/はれ/名詞,一般/ngrams/3",
+            "completions\t",
+            "",
+        ]
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_context_ids_outside_the_matrix_are_rejected() {
+    let dir = artifacts("ids", &format!("{}雨,7,7,100,名詞,一般,*,*,*,*,*,あめ,アメ\n", LEX));
+    let error = demo_keyboard::run(&args(&dir, &[]), Vec::new()).unwrap_err();
+    assert!(format!("{:#}", error).contains("context IDs 7 7 are outside the 3x3 matrix"), "{:#}", error);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_incremental_lattice_matches_a_full_recompute() {
+    let homophones = "端,1,1,900,名詞,一般,*,*,*,*,*,はし,ハシ\n箸,1,1,400,名詞,一般,*,*,*,*,*,はし,ハシ\n";
+    let lex = format!("{}{}", LEX, homophones);
+    let dir = artifacts("lattice", &lex);
+    let converter = Converter::load(&dir.join("lex.csv"), &dir.join("matrix.def")).unwrap();
+    let full = |romaji: &str| {
+        let mut lattice = Lattice::default();
+        converter.extend(&mut lattice, romaji::to_kana_final(romaji).chars(), |_, _| 0.0);
+        let segments = lattice.best_path(lattice.len());
+        segments.into_iter().map(|s| (s.surface, s.reading)).collect::<Vec<_>>()
+    };
+
+    // Typing, then Backspace into a settled kana (は) and a pending one (n)
+    let mut session = SessionContext::new();
+    let keys = "kyouhahashin<<<<<<hare<<<<nnkyouhannde<<<<<<ttta";
+    for key in keys.chars() {
+        match key {
+            '<' => session.pop(&converter),
+            c => session.push(&converter, c),
+        }
+        let incremental: Vec<_> = session.segments().into_iter().map(|s| (s.surface, s.reading)).collect();
+        assert_eq!(incremental, full(session.romaji()), "After {:?}", session.romaji());
+        assert_eq!(session.kana(), romaji::to_kana_final(session.romaji()));
+        let (settled, pending) = romaji::to_kana(session.romaji());
+        assert_eq!(session.composing(), (settled, pending.as_str()));
+    }
+    assert_eq!(session.romaji(), "kyouhannkyouttta");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_lattice_dump_lists_every_node_and_the_chosen_path() {
+    let dir = artifacts("dump", LEX);
+    let mut out = Vec::new();
+    demo_keyboard::run(&args(&dir, &["--dump-lattice", "json"]), &mut out).unwrap();
+    let dump: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(dump["input"], "きょうは");
+    let nodes = dump["nodes"].as_array().unwrap();
+    let node = |surface: &str| nodes.iter().find(|node| node["surface"] == surface).unwrap();
+    let span = |node: &serde_json::Value| (node["start"].as_u64().unwrap(), node["end"].as_u64().unwrap());
+
+    assert_eq!(span(node("京")), (0, 3));
+    assert_eq!(node("京")["path_cost"], 3000);
+    assert_eq!(node("京")["chosen"], false, "Loses to 今日");
+    assert_eq!(node("は")["path_cost"], 150);
+    let chosen: Vec<&str> =
+        nodes.iter().filter(|node| node["chosen"] == true).map(|node| node["surface"].as_str().unwrap()).collect();
+    assert_eq!(chosen, ["今日", "は"]);
+    // Unconverted kana keep paths alive through き and きょ
+    assert_eq!(node("ょ")["word_cost"], 20000);
+    assert_eq!(node("ょ")["best"], true);
+
+    let mut out = Vec::new();
+    demo_keyboard::run(&args(&dir, &["--dump-lattice", "dot"]), &mut out).unwrap();
+    let dot = String::from_utf8(out).unwrap();
+    assert!(dot.starts_with("digraph lattice {"), "{}", dot);
+    assert!(dot.contains("[label=\"今日\\nきょう\\nL1 R1 word=100\", style=bold]"), "{}", dot);
+    fs::remove_dir_all(dir).unwrap();
+}
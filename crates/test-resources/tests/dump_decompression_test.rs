@@ -0,0 +1,117 @@
+//! Parallel decompression of bz2 dumps (wiki_ngram::bz2) against the
+//! sequential decoder, and dumps in other compressions.
+
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use wiki_ngram::bz2::{self, DumpCompression, ParallelDecoder, Piece};
+use wiki_ngram::seekable;
+
+/// Text of several 100 kB bz2 blocks at level 1.
+fn sample_text() -> Vec<u8> {
+    (0..60_000u64).flat_map(|i| format!("<text>{} {}</text>\n", i, i * i % 9973).into_bytes()).collect()
+}
+
+fn compress(data: &[u8], level: u32) -> Vec<u8> {
+    let mut encoder = BzEncoder::new(Vec::new(), Compression::new(level));
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn read_all(mut reader: impl Read) -> Vec<u8> {
+    let mut out = Vec::new();
+    reader.read_to_end(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn test_blocks_decompress_in_parallel() {
+    let text = sample_text();
+    let compressed = compress(&text, 1);
+    let blocks = bz2::find_blocks(&compressed, 3);
+    assert!(blocks.len() >= 5, "Only {} blocks", blocks.len());
+    assert_eq!(blocks, bz2::find_blocks(&compressed, 1), "The scan doesn't depend on the threads");
+    assert!(matches!(&blocks[0], Piece::Block(bits) if bits.start == 32), "The first block follows the BZh9 header");
+
+    for threads in [1, 4] {
+        let decoder = ParallelDecoder::new(Arc::new(compressed.clone()), blocks.clone(), threads);
+        assert!(read_all(decoder) == text, "{} threads decompressed other bytes", threads);
+    }
+}
+
+#[test]
+fn test_multistream_dump_splits_by_its_index() {
+    let dir = std::env::temp_dir().join(format!("bz2-multistream-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let text = sample_text();
+    let (first, second) = text.split_at(text.len() / 3);
+    let mut dump = compress(first, 9);
+    let second_offset = dump.len();
+    dump.extend(compress(second, 9));
+    let dump_path = dir.join("jawiki-latest-pages-articles-multistream.xml.bz2");
+    std::fs::write(&dump_path, &dump).unwrap();
+    let index_path = bz2::index_path(&dump_path).unwrap();
+    assert_eq!(index_path.file_name().unwrap(), "jawiki-latest-pages-articles-multistream-index.txt.bz2");
+    std::fs::write(&index_path, compress(format!("{}:10:記事\n{}:11:次\n", second_offset, second_offset).as_bytes(), 9))
+        .unwrap();
+
+    let pieces = bz2::read_index(std::fs::File::open(&index_path).unwrap(), dump.len()).unwrap();
+    let parallel = read_all(bz2::open_dump(&dump_path, 4).unwrap());
+    let sequential = read_all(bz2::open_dump(&dump_path, 1).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(pieces, [Piece::Streams(0..dump.len())], "Streams under 1 MiB are merged");
+    assert!(parallel == text && sequential == text);
+}
+
+#[test]
+fn test_dump_compression_is_detected() {
+    let dir = std::env::temp_dir().join(format!("dump-compression-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let text = sample_text();
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    gzip.write_all(&text).unwrap();
+    let dumps = [
+        (DumpCompression::Bz2, compress(&text, 1)),
+        (DumpCompression::Zstd, zstd::encode_all(&text[..], 3).unwrap()),
+        (DumpCompression::Gzip, gzip.finish().unwrap()),
+        (DumpCompression::None, text.clone()),
+    ];
+    for (compression, dump) in dumps {
+        assert_eq!(DumpCompression::detect(&dump), compression);
+        // Named .bz2 whatever the compression, as only the content counts
+        let path = dir.join(format!("{:?}.xml.bz2", compression));
+        std::fs::write(&path, dump).unwrap();
+        assert!(read_all(bz2::open_dump(&path, 4).unwrap()) == text, "{:?} read other bytes", compression);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_prepared_dump_decompresses_by_frames() {
+    let dir = std::env::temp_dir().join(format!("dump-seekable-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let text: Vec<u8> =
+        (0..400_000u64).flat_map(|i| format!("<page>{}</page>\n", i * 7919 % 100_003).into_bytes()).collect();
+    let mut dump = Vec::new();
+    let frames = seekable::write_seekable(&text[..], &mut dump, 3, 2).unwrap();
+    assert!(frames.len() >= 2, "Only {} frames", frames.len());
+    assert_eq!(seekable::read_seek_table(&dump).unwrap(), frames);
+    assert!(seekable::read_seek_table(&zstd::encode_all(&text[..], 3).unwrap()).is_none());
+
+    // Every frame holds whole pages
+    let mut start = 0;
+    for frame in &frames {
+        let end = start + frame.decompressed as usize;
+        assert!(text[start..end].starts_with(b"<page>") && text[start..end].ends_with(b"</page>\n"));
+        start = end;
+    }
+
+    let path = dir.join("jawiki-latest-pages-articles.xml.zst");
+    std::fs::write(&path, &dump).unwrap();
+    let parallel = read_all(bz2::open_dump(&path, 4).unwrap());
+    let sequential = read_all(bz2::open_dump(&path, 1).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(parallel == text && sequential == text, "The seek table is skipped by plain zstd readers");
+}
@@ -0,0 +1,46 @@
+use keyboard_formats::eras::{Date, Era, EraTable};
+
+fn table() -> EraTable {
+    EraTable::new(vec![
+        Era {
+            name: "令和".to_string(),
+            reading: "れいわ".to_string(),
+            start: Date::new(2019, 5, 1),
+            end: None,
+        },
+        Era {
+            name: "平成".to_string(),
+            reading: "へいせい".to_string(),
+            start: Date::new(1989, 1, 8),
+            end: Some(Date::new(2019, 4, 30)),
+        },
+    ])
+}
+
+#[test]
+fn test_era_year_to_gregorian() {
+    let table = table();
+    assert_eq!(table.find("れいわ").unwrap().to_gregorian(6), Some(2024));
+    assert_eq!(table.find("平成").unwrap().to_gregorian(31), Some(2019));
+    assert_eq!(table.find("平成").unwrap().to_gregorian(32), None, "平成 ended in its 31st year");
+}
+
+#[test]
+fn test_transition_year_has_both_eras() {
+    let table = table();
+    let years: Vec<(&str, i32)> = table.era_years(2019).into_iter().map(|(e, y)| (e.name.as_str(), y)).collect();
+    assert_eq!(years, vec![("平成", 31), ("令和", 1)]);
+
+    let (era, year) = table.era_at(Date::new(2019, 4, 30)).unwrap();
+    assert_eq!((era.name.as_str(), year), ("平成", 31));
+    let (era, year) = table.era_at(Date::new(2019, 5, 1)).unwrap();
+    assert_eq!((era.name.as_str(), year), ("令和", 1));
+}
+
+#[test]
+fn test_text_roundtrip() {
+    let table = table();
+    let mut buf = Vec::new();
+    table.write(&mut buf).unwrap();
+    assert_eq!(EraTable::read(&buf[..]).unwrap(), table);
+}
@@ -0,0 +1,138 @@
+use clap::Parser;
+use input_data_gen::contractions::{self, ContractionsArgs};
+use input_data_gen::flick::{self, FlickArgs};
+use input_data_gen::names::{self, NamesArgs};
+use keyboard_formats::confusion::{ConfusionModel, DefaultCosts};
+use keyboard_formats::mapping::MappingTable;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+/// A fresh directory for the artifacts of one test.
+fn output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("input-data-gen-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Parses the flags of a subcommand as the binary does.
+#[derive(Parser)]
+struct Subcommand<T: clap::Args> {
+    #[command(flatten)]
+    args: T,
+}
+
+fn parse<T: clap::Args>(argv: &[&str]) -> T {
+    Subcommand::<T>::parse_from(std::iter::once("input-data-gen").chain(argv.iter().copied())).args
+}
+
+#[test]
+fn test_flick_confusion_costs() {
+    let path = output_dir("flick").join("flick-confusion.tsv");
+    let args: FlickArgs = parse(&["--output", path.to_str().unwrap(), "--neighbor-key-cost", "1400"]);
+    flick::run(&args).unwrap();
+    let model = ConfusionModel::read(BufReader::new(fs::File::open(&path).unwrap())).unwrap();
+    let cost = |intended, typed| model.substitution_cost(intended, typed);
+
+    assert_eq!(cost("い", "う"), 1000, "Left flick read as up");
+    assert_eq!(cost("い", "え"), 2000, "Left flick read as right");
+    assert_eq!(cost("あ", "い"), 1200, "Tap read as a flick");
+    assert_eq!(cost("や", "ゆ"), 1200, "や's key has no left flick");
+    assert_eq!(cost("き", "し"), 1400, "Left flick on the next key");
+    assert_eq!(cost("き", "ち"), DefaultCosts::default().substitution, "Diagonal keys are not neighbors");
+    assert_eq!(cost("が", "か"), 800, "Modifier press left out");
+    assert_eq!(cost("か", "が"), 1600, "Stray modifier press");
+    assert_eq!(model.distance("がっこう", "かつこう"), 1600);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_contractions_from_rules_and_corpus_phrases() {
+    let dir = output_dir("contractions");
+    let ngrams: BTreeMap<&str, u64> = BTreeMap::from([
+        ("帰っ/かえっ て/て いる/いる", 300),
+        ("食べ/たべ なけれ/なけれ ば/ば", 200),
+        ("言う/いう のだ/のだ", 100),
+        // ている starts inside a token
+        ("捨て/すて いる/いる", 400),
+    ]);
+    let mut fst = fst::MapBuilder::memory();
+    fst.extend_iter(ngrams).unwrap();
+    fs::write(dir.join("ngrams.fst"), fst.into_inner().unwrap()).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    let args: ContractionsArgs = parse(&[
+        "--ngrams",
+        &path("ngrams.fst"),
+        "--output",
+        &path("contractions.tsv"),
+        "--max-phrases",
+        "2",
+    ]);
+    contractions::run(&args).unwrap();
+    let table = MappingTable::read(BufReader::new(fs::File::open(path("contractions.tsv")).unwrap())).unwrap();
+    let targets = |from| {
+        table
+            .lookup(from)
+            .map(|m| (m.to.as_str(), m.to_reading.as_str(), m.label.as_str()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(targets("帰っている"), [("帰ってる", "かえってる", "contracted")]);
+    assert_eq!(targets("帰ってる"), [("帰っている", "かえっている", "expanded")]);
+    assert!(targets("食べなければ").contains(&("食べなきゃ", "たべなきゃ", "contracted")));
+    assert_eq!(targets("なきゃ"), [("なければ", "なければ", "expanded")], "Rules are mapped without a corpus");
+    assert!(!table.contains("言うのだ"), "Over --max-phrases");
+    assert!(!table.contains("捨ている"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+const JMNEDICT: &str = "<JMnedict>
+<entry><k_ele><keb>山田</keb></k_ele><r_ele><reb>ヤマダ</reb></r_ele><trans><name_type>&surname;</name_type></trans></entry>
+<entry><k_ele><keb>太郎</keb></k_ele><r_ele><reb>たろう</reb></r_ele><trans><name_type>&masc;</name_type></trans></entry>
+<entry><k_ele><keb>中島</keb></k_ele><r_ele><reb>なかじま</reb></r_ele><r_ele><reb>なかしま</reb></r_ele>
+<trans><name_type>&surname;</name_type></trans></entry>
+<entry><k_ele><keb>河野</keb></k_ele><k_ele><keb>川野</keb></k_ele>
+<r_ele><reb>こうの</reb><re_restr>河野</re_restr></r_ele><r_ele><reb>かわの</reb></r_ele>
+<trans><name_type>&surname;</name_type></trans></entry>
+<entry><k_ele><keb>東京</keb></k_ele><r_ele><reb>とうきょう</reb></r_ele><trans><name_type>&place;</name_type></trans></entry>
+</JMnedict>
+";
+
+#[test]
+fn test_name_dictionary_kinds_readings_and_costs() {
+    let dir = output_dir("names");
+    fs::write(dir.join("JMnedict.xml"), JMNEDICT).unwrap();
+    fs::write(dir.join("titles.txt"), "山田太郎\n山田太郎_(政治家)\n中島太郎\n東京\n").unwrap();
+    fs::write(dir.join("lex.csv"), "太老,1,1,5000,名詞,一般,*,*,*,*,*,たろう,タロー\n").unwrap();
+    fs::write(dir.join("id.def"), "1851 名詞,固有名詞,人名,姓,*,*,*\n1852 名詞,固有名詞,人名,名,*,*,*\n").unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    let args: NamesArgs = parse(&[
+        "--jmnedict",
+        &path("JMnedict.xml"),
+        "--wiki-titles",
+        &path("titles.txt"),
+        "--lex",
+        &path("lex.csv"),
+        "--id-def",
+        &path("id.def"),
+        "--output",
+        &path("names.txt"),
+    ]);
+    names::run(&args).unwrap();
+
+    // Costs fall by 400 per log unit of title evidence (山田 in two titles,
+    // 太郎 in three); 太郎 is kept within 300 of the noun たろう
+    assert_eq!(
+        fs::read_to_string(path("names.txt")).unwrap(),
+        "なかじま\t1851\t1851\t6722\t中島\tfamily\tprimary
+なかしま\t1851\t1851\t7222\t中島\tfamily\talternative
+たろう\t1852\t1852\t5300\t太郎\tgiven\tprimary
+やまだ\t1851\t1851\t6560\t山田\tfamily\tprimary
+かわの\t1851\t1851\t7000\t川野\tfamily\tprimary
+こうの\t1851\t1851\t7000\t河野\tfamily\tprimary
+かわの\t1851\t1851\t7500\t河野\tfamily\talternative
+"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
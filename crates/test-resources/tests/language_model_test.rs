@@ -0,0 +1,65 @@
+use fst::{Map, MapBuilder};
+use keyboard_formats::language_model::{Model, BACKOFF_FACTOR};
+
+/// Builds an FST from (key, count) pairs, scored like wiki-ngram (ln(count) * 1000).
+fn fst(entries: &[(&str, u64)]) -> Map<Vec<u8>> {
+    let mut entries = entries.to_vec();
+    entries.sort();
+    let mut builder = MapBuilder::memory();
+    for (key, count) in entries {
+        builder.insert(key, ((count as f64).ln() * 1000.0) as u64).unwrap();
+    }
+    Map::new(builder.into_inner().unwrap()).unwrap()
+}
+
+fn model() -> Model<Vec<u8>> {
+    let vocab = fst(&[("今日", 100), ("は", 400), ("晴れ", 20), ("雨", 40), ("明日", 40)]);
+    let ngrams = fst(&[("今日 は", 80), ("は 晴れ", 10), ("は 雨", 30), ("今日 は 晴れ", 8)]);
+    Model::new(ngrams, vocab, 3)
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 0.01, "expected {}, got {}", expected, actual);
+}
+
+#[test]
+fn test_uses_longest_history() {
+    let m = model();
+    // P(晴れ | 今日 は) = 8 / 80
+    assert_close(m.token_score(&["今日", "は"], "晴れ"), (8.0f64 / 80.0).ln());
+    // P(は | 今日) = 80 / 100
+    assert_close(m.token_score(&["今日"], "は"), (80.0f64 / 100.0).ln());
+}
+
+#[test]
+fn test_backs_off_to_shorter_history() {
+    let m = model();
+    // No 今日 は 雨 trigram: 0.4 * P(雨 | は) = 0.4 * 30 / 400
+    assert_close(m.token_score(&["今日", "は"], "雨"), (BACKOFF_FACTOR * 30.0 / 400.0).ln());
+}
+
+#[test]
+fn test_sentence_score_ranks_hypotheses() {
+    let m = model();
+    let likely = m.score(&["今日", "は", "晴れ"]);
+    let unlikely = m.score(&["今日", "は", "明日"]);
+    let unknown = m.score(&["今日", "は", "霙"]);
+    assert!(likely > unlikely, "{} vs {}", likely, unlikely);
+    assert!(unlikely > unknown, "{} vs {}", unlikely, unknown);
+    assert_eq!(m.score(&[]), 0.0);
+}
+
+#[test]
+fn test_token_probabilities_sum_to_one() {
+    let m = model();
+    let vocab = ["今日", "は", "晴れ", "雨", "明日"];
+    for history in [&["今日", "は"][..], &["は"], &["明日"], &[]] {
+        // Unknown words share one slot, whichever of them is asked for
+        let sum: f64 = vocab.iter().chain(&["霙"]).map(|word| m.token_probability(history, word)).sum();
+        assert!((sum - 1.0).abs() < 1e-9, "{:?}: {}", history, sum);
+    }
+    // (8 - 0.75 + 0.75 * P(晴れ | は)) / 8, the only trigram after 今日 は
+    let after_ha = m.token_probability(&["は"], "晴れ");
+    assert_close(m.token_probability(&["今日", "は"], "晴れ"), (8.0 - 0.75 + 0.75 * after_ha) / 8.0);
+    assert!(m.token_probability(&["今日", "は"], "雨") < m.token_probability(&["は"], "雨"));
+}
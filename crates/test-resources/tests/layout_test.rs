@@ -0,0 +1,43 @@
+use keyboard_formats::layout::{Key, Layout};
+
+fn key(output: &str, x: f64, y: f64) -> Key {
+    Key {
+        output: output.to_string(),
+        x,
+        y,
+        width: 1.0,
+        height: 1.0,
+    }
+}
+
+fn layout() -> Layout {
+    let mut layout = Layout::new("kana50", "kana");
+    layout.push(key("あ", 0.5, 0.5));
+    layout.push(key("い", 0.5, 1.5));
+    layout.push(key("か", 1.5, 0.5));
+    layout.push(key("が", 1.5, 0.5));
+    layout
+}
+
+#[test]
+fn test_round_trip() {
+    let layout = layout();
+    let mut buf = Vec::new();
+    layout.write(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf).starts_with("#layout\tv1\tname=kana50\tinput=kana\n"));
+
+    let read = Layout::read(&buf[..]).unwrap();
+    assert_eq!(read, layout);
+}
+
+#[test]
+fn test_path_and_nearest() {
+    let layout = layout();
+    assert_eq!(layout.path(["あ", "が"]), Some(vec![(0.5, 0.5), (1.5, 0.5)]));
+    assert_eq!(layout.path(["あ", "ん"]), None);
+
+    let nearest = layout.nearest(0.6, 1.2);
+    assert_eq!(nearest[0].output, "い");
+    assert!(nearest[0].contains(0.6, 1.2));
+    assert!(!nearest[1].contains(0.6, 1.2));
+}
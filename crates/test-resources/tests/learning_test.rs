@@ -0,0 +1,40 @@
+use keyboard_formats::learning::ShortTermMemory;
+use keyboard_formats::user_history::Decay;
+
+fn memory() -> ShortTermMemory {
+    ShortTermMemory::new(Decay { half_life_secs: 600 }, 3000.0)
+}
+
+#[test]
+fn test_committed_word_is_boosted_immediately() {
+    let mut m = memory();
+    assert_eq!(m.boost("きしゃ", "記者", 0), 0.0);
+
+    m.commit("きしゃ", "記者", 0);
+    assert!((m.boost("きしゃ", "記者", 0) - 3000.0).abs() < 1e-6);
+    assert_eq!(m.boost("きしゃ", "汽車", 0), 0.0, "Other surfaces of the reading are not boosted");
+    assert!(m.boost("きしゃ", "記者", 600) < m.boost("きしゃ", "記者", 0), "Boost should fade");
+}
+
+#[test]
+fn test_rerank_moves_learned_prediction_up() {
+    let mut m = memory();
+    m.commit("てんき", "天気", 0);
+
+    let mut predictions = vec![("電気".to_string(), 9000), ("天気".to_string(), 7000)];
+    m.rerank(&mut predictions, 0);
+    assert_eq!(predictions[0], ("天気".to_string(), 10000));
+    assert_eq!(predictions[1], ("電気".to_string(), 9000));
+}
+
+#[test]
+fn test_memory_is_saved_as_user_history() {
+    let mut m = memory();
+    m.commit("きしゃ", "記者", 100);
+
+    let mut buf = Vec::new();
+    m.history().write(&mut buf).unwrap();
+    let text = String::from_utf8(buf).unwrap();
+    assert!(text.starts_with("#user-history\tv1\thalf_life_secs=600\n"), "{}", text);
+    assert!(text.contains("きしゃ\t記者\t1\t100"), "{}", text);
+}
@@ -0,0 +1,29 @@
+use keyboard_formats::mapping::{Mapping, MappingTable};
+
+fn mapping(from: &str, to: &str, label: &str) -> Mapping {
+    Mapping::new(from, "", to, "", label)
+}
+
+#[test]
+fn test_lookup_and_dedup() {
+    let mut table = MappingTable::new("keigo");
+    table.push(mapping("行く", "いらっしゃる", "honorific"));
+    table.push(mapping("行く", "参る", "humble"));
+    table.push(mapping("行く", "参る", "humble"));
+
+    assert_eq!(table.len(), 2);
+    let labels: Vec<&str> = table.lookup("行く").map(|m| m.label.as_str()).collect();
+    assert_eq!(labels, vec!["honorific", "humble"]);
+    assert_eq!(table.lookup("来る").count(), 0);
+}
+
+#[test]
+fn test_text_roundtrip() {
+    let mut table = MappingTable::new("keigo");
+    table.push(Mapping::new("言う", "いう", "おっしゃる", "おっしゃる", "honorific"));
+
+    let mut buf = Vec::new();
+    table.write(&mut buf).unwrap();
+    let read = MappingTable::read(&buf[..]).unwrap();
+    assert_eq!(read, table);
+}
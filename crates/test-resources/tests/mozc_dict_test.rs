@@ -1,24 +1,28 @@
-use std::fs::File;
-use std::io::BufReader;
-use vibrato::{Dictionary, Tokenizer};
+use mozc_dict_gen::vibrato::{Dictionary, Tokenizer};
 
 use std::path::PathBuf;
 
+/// MOZC_DICT_PATH, else an uncompressed output/system.dic (memory-mapped,
+/// built with `--compression none`) if there is one, else system.dic.zst.
 fn get_dict_path() -> PathBuf {
-    std::env::var("MOZC_DICT_PATH")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| PathBuf::from("../../output/system.dic.zst"))
+    std::env::var("MOZC_DICT_PATH").map(PathBuf::from).unwrap_or_else(|_| {
+        let uncompressed = PathBuf::from("../../output/system.dic");
+        if uncompressed.exists() {
+            uncompressed
+        } else {
+            PathBuf::from("../../output/system.dic.zst")
+        }
+    })
+}
+
+fn load_dictionary() -> Dictionary {
+    mozc_dict_gen::read_dictionary(&get_dict_path())
+        .expect("Failed to read dictionary. Set MOZC_DICT_PATH env var or run 'cargo run -p mozc-dict-gen --release' first.")
 }
 
 #[test]
 fn test_load_mozc_dictionary() {
-    let file = File::open(get_dict_path())
-        .expect("Failed to open dictionary file. Set MOZC_DICT_PATH env var or run 'cargo run -p mozc-dict-gen --release' first.");
-    let reader = BufReader::new(file);
-    let decoder = zstd::stream::read::Decoder::new(reader)
-        .expect("Failed to create zstd decoder");
-    let _dict = Dictionary::read(decoder)
-        .expect("Failed to read dictionary");
+    let _dict = load_dictionary();
     
     // If we got here, the dictionary loaded successfully
     assert!(true);
@@ -26,13 +30,7 @@ fn test_load_mozc_dictionary() {
 
 #[test]
 fn test_tokenize_common_phrases() {
-    let file = File::open(get_dict_path())
-        .expect("Failed to open dictionary file. Set MOZC_DICT_PATH env var or run 'cargo run -p mozc-dict-gen --release' first.");
-    let reader = BufReader::new(file);
-    let decoder = zstd::stream::read::Decoder::new(reader)
-        .expect("Failed to create zstd decoder");
-    let dict = Dictionary::read(decoder)
-        .expect("Failed to read dictionary");
+    let dict = load_dictionary();
     
     let tokenizer = Tokenizer::new(dict);
     let mut worker = tokenizer.new_worker();
@@ -58,15 +56,14 @@ fn test_tokenize_common_phrases() {
     assert!(worker.num_tokens() >= 3, "Should tokenize '東京に行きます' into multiple tokens");
 }
 
+#[test]
+fn test_smoke_sentences() {
+    mozc_dict_gen::smoke::check(load_dictionary()).unwrap();
+}
+
 #[test]
 fn test_tokenize_edge_cases() {
-    let file = File::open(get_dict_path())
-        .expect("Failed to open dictionary file. Set MOZC_DICT_PATH env var or run 'cargo run -p mozc-dict-gen --release' first.");
-    let reader = BufReader::new(file);
-    let decoder = zstd::stream::read::Decoder::new(reader)
-        .expect("Failed to create zstd decoder");
-    let dict = Dictionary::read(decoder)
-        .expect("Failed to read dictionary");
+    let dict = load_dictionary();
     
     let tokenizer = Tokenizer::new(dict);
     let mut worker = tokenizer.new_worker();
@@ -89,13 +86,7 @@ fn test_tokenize_edge_cases() {
 
 #[test]
 fn test_tokenization_produces_features() {
-    let file = File::open(get_dict_path())
-        .expect("Failed to open dictionary file. Set MOZC_DICT_PATH env var or run 'cargo run -p mozc-dict-gen --release' first.");
-    let reader = BufReader::new(file);
-    let decoder = zstd::stream::read::Decoder::new(reader)
-        .expect("Failed to create zstd decoder");
-    let dict = Dictionary::read(decoder)
-        .expect("Failed to read dictionary");
+    let dict = load_dictionary();
     
     let tokenizer = Tokenizer::new(dict);
     let mut worker = tokenizer.new_worker();
@@ -0,0 +1,981 @@
+use keyboard_formats::kana;
+use keyboard_formats::reverse_lookup::ReverseLookup;
+use keyboard_formats::single_kanji::SingleKanji;
+use keyboard_formats::suppression::{SuppressionList, SUGGESTION_FILTERED, SUPPRESSED};
+use keyboard_formats::symbols::{SymbolKind, Symbols};
+use mozc_dict_gen::bundle::{self, Artifact, BundleManifest};
+use mozc_dict_gen::conversion;
+use mozc_dict_gen::cost_rules::CostRules;
+use mozc_dict_gen::diff::{self, DiffEntry, LexDiff, PosDiff};
+use mozc_dict_gen::export;
+use mozc_dict_gen::extra_lexicon::PosMap;
+use mozc_dict_gen::features::{FeatureConversion, FeatureLayout, ReadingScript};
+use mozc_dict_gen::lindera;
+use mozc_dict_gen::mecab;
+use mozc_dict_gen::pos_filter::{PosFilter, PosPattern};
+use mozc_dict_gen::prune::{self, EntryBudget};
+use mozc_dict_gen::reverse;
+use mozc_dict_gen::single_kanji;
+use mozc_dict_gen::sqlite;
+use mozc_dict_gen::smoke::{self, SmokeSentence};
+use mozc_dict_gen::stats::DictStats;
+use mozc_dict_gen::sudachi::SudachiSources;
+use mozc_dict_gen::suppression;
+use mozc_dict_gen::symbols;
+use mozc_dict_gen::unidic::UniDicSources;
+use mozc_dict_gen::vibrato;
+use mozc_dict_gen::zipcode;
+use mozc_dict_gen::{
+    convert_matrix, read_dictionary, read_id_def, write_dictionary_as, Builder, Compression, ConnectionFormat,
+    ConnectionMatrix, DictPipeline, DictSource, Input, LexiconKey, MozcSources, Pronunciation, Source,
+};
+use std::collections::BTreeMap;
+use std::io::Read;
+
+fn sources() -> MozcSources {
+    MozcSources {
+        id_def: Input::bytes("id.def", "0 BOS/EOS,*,*,*,*,*,*\n1 名詞,一般,*,*,*,*,*\n"),
+        // Size, the non-cost line, then size * size costs
+        connection: Input::bytes("connection_single_column.txt", "2\n0\n0\n10\n20\n30\n"),
+        dictionaries: vec![Input::bytes("dictionary00.txt", "きしゃ\t1\t1\t4000\t記者\nbroken line\n")],
+        filters: Vec::new(),
+        single_kanji: None,
+        symbols: Vec::new(),
+    }
+}
+
+#[test]
+fn test_build_in_memory() {
+    let (out, report) = DictPipeline::new(sources()).build().unwrap();
+
+    assert_eq!(String::from_utf8(out.matrix).unwrap(), "2 2\n0 0 0\n0 1 10\n1 0 20\n1 1 30\n");
+    assert_eq!(
+        String::from_utf8(out.lex).unwrap(),
+        "記者,1,1,4000,名詞,一般,*,*,*,*,*,きしゃ,きしゃ\n"
+    );
+    assert!(String::from_utf8(out.unk_def).unwrap().starts_with("DEFAULT,1,1,4000,"));
+    assert_eq!(report.entries, 1);
+    assert_eq!(report.rejected, 1);
+    assert_eq!(report.rejections[0].source, "dictionary00.txt");
+    assert_eq!(report.rejections[0].line_no, 2);
+}
+
+#[test]
+fn test_pos_filter_leaves_out_entries_by_pos() {
+    let pattern = |s: &str| s.parse::<PosPattern>().unwrap();
+    let name = "名詞,固有名詞,人名,姓,*,*,*";
+    assert!(pattern("固有名詞,人名").matches(name));
+    assert!(pattern("名詞,*,人*").matches(name));
+    assert!(!pattern("人名,固有名詞").matches(name), "Fields match in order");
+    assert!(!pattern("名").matches(name), "Whole fields only, without a *");
+    assert!("記号,,".parse::<PosPattern>().is_err());
+
+    let mut mozc = sources();
+    mozc.id_def = Input::bytes("id.def", "0 BOS/EOS,*,*,*,*,*,*\n1 名詞,一般,*,*,*,*,*\n2 記号,一般,*,*,*,*,*\n");
+    mozc.connection = Input::bytes("connection_single_column.txt", "3\n0\n0\n0\n0\n0\n0\n0\n0\n0\n");
+    mozc.dictionaries = vec![Input::bytes("dictionary00.txt", "きしゃ\t1\t1\t4000\t記者\nまる\t2\t2\t3000\t○\n")];
+    let (out, report) =
+        DictPipeline::new(mozc).pos_filter(PosFilter::new(Vec::new(), vec![pattern("記号")])).build().unwrap();
+    assert_eq!((report.entries, report.pos_filtered), (1, 1));
+    assert!(String::from_utf8(out.lex).unwrap().starts_with("記者,"));
+}
+
+#[test]
+fn test_cost_rules_adjust_matching_entries() {
+    let rules = CostRules::parse(
+        r#"
+        [[rule]]
+        name = "single-character nouns"
+        pos = "名詞"
+        surface = "^.$"
+        add = 800
+
+        [[rule]]
+        name = "emoji"
+        surface = "\\p{Extended_Pictographic}"
+        multiply = 0.5
+        "#,
+    )
+    .unwrap();
+    let mut mozc = sources();
+    mozc.dictionaries =
+        vec![Input::bytes("dictionary00.txt", "きしゃ\t1\t1\t4000\t記者\nき\t1\t1\t4000\t木\nはーと\t1\t1\t6000\t❤\n")];
+    let (out, report) = DictPipeline::new(mozc).cost_rules(rules).build().unwrap();
+    let costs: Vec<String> = String::from_utf8(out.lex)
+        .unwrap()
+        .lines()
+        .map(|line| line.split(',').take(4).collect::<Vec<_>>().join(","))
+        .collect();
+    assert_eq!(costs, ["記者,1,1,4000", "木,1,1,4800", "❤,1,1,3400"], "❤ is one character too, then halved");
+    assert_eq!(report.cost_adjustments, [("single-character nouns".to_string(), 2), ("emoji".to_string(), 1)]);
+
+    assert!(CostRules::parse("[[rule]]\nname = \"all\"\nadd = 1\n").is_err(), "No condition");
+    assert!(CostRules::parse("[[rule]]\nname = \"x\"\npos = \"名詞\"\n").is_err(), "No adjustment");
+    assert!(CostRules::parse("[[rule]]\nname = \"x\"\nsurface = \"(\"\nadd = 1\n").is_err(), "Bad regex");
+}
+
+#[test]
+fn test_mecab_sources_have_a_dicrc_for_the_layout() {
+    let (out, _) = DictPipeline::new(sources()).build().unwrap();
+    let dir = std::env::temp_dir().join(format!("mecab-sources-{}", std::process::id()));
+    mecab::write_dir(&dir, &out, FeatureLayout::Mozc).unwrap();
+    assert_eq!(std::fs::read(dir.join("lex.csv")).unwrap(), out.lex);
+    let dicrc = std::fs::read_to_string(dir.join("dicrc")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(dicrc.contains("config-charset = utf-8\n"));
+    assert!(dicrc.contains("bos-feature = BOS/EOS,*,*,*,*,*,*,*,*\n"), "As wide as the 9 features");
+    assert!(dicrc.contains("node-format-yomi = %pS%f[7]\n"), "The reading column");
+    assert!(mecab::dicrc(FeatureLayout::Unidic).contains("%f[6]"));
+}
+
+#[test]
+fn test_lindera_metadata_follows_the_layout() {
+    let (out, _) = DictPipeline::new(sources()).build().unwrap();
+    let metadata = lindera::metadata(&out, FeatureLayout::Ipadic).unwrap();
+    assert_eq!(metadata["encoding"], "UTF-8");
+    let fields = metadata["dictionary_schema"]["fields"].as_array().unwrap();
+    assert_eq!(fields.len(), 4 + 9, "surface, IDs and cost, then the 9 IPAdic features");
+    assert_eq!((&fields[0], &fields[11]), (&"surface".into(), &"reading".into()));
+    // The general noun of unk.def's DEFAULT row
+    assert_eq!((&metadata["default_left_context_id"], &metadata["default_word_cost"]), (&1.into(), &4000.into()));
+}
+
+#[test]
+fn test_sqlite_export_reads_the_same_in_every_layout() {
+    let dir = std::env::temp_dir().join(format!("sqlite-export-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (layout, reading) in [(FeatureLayout::Mozc, "きしゃ"), (FeatureLayout::Unidic, "キシャ")] {
+        let (out, _) = DictPipeline::new(sources()).features(FeatureConversion::new(layout)).build().unwrap();
+        let path = dir.join(sqlite::SQLITE_FILE);
+        assert_eq!(sqlite::write_lexicon(&path, &out.lex, layout).unwrap(), 1);
+        let db = rusqlite::Connection::open(&path).unwrap();
+        let row: (String, i64, String) = db
+            .query_row("SELECT reading, cost, pos FROM lexicon WHERE surface = '記者'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(row.0, reading);
+        assert_eq!(row.1, 4000);
+        assert!(row.2.ends_with(",*,*"), "POS and conjugation: {}", row.2);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_jsonl_export_names_the_fields() {
+    let (out, _) = DictPipeline::new(sources()).build().unwrap();
+    let mut jsonl = Vec::new();
+    assert_eq!(export::write_jsonl(&out.lex, FeatureLayout::Mozc, &mut jsonl).unwrap(), 1);
+    let jsonl = String::from_utf8(jsonl).unwrap();
+    assert_eq!(jsonl.lines().count(), 1);
+    let entry: serde_json::Value = serde_json::from_str(jsonl.trim_end()).unwrap();
+    assert_eq!(entry["surface"], "記者");
+    assert_eq!(entry["reading"], "きしゃ");
+    assert_eq!(entry["cost"], 4000);
+    assert_eq!(entry["pos"], "名詞,一般,*,*,*,*");
+    assert_eq!((&entry["major_pos"], &entry["pronunciation"]), (&"名詞".into(), &"きしゃ".into()));
+    assert!(entry.get("extra").is_none(), "No columns past the features");
+    assert!(!jsonl.contains("\"reading\":\"きしゃ\",\"reading\""));
+}
+
+#[test]
+fn test_extra_dictionaries_follow_mozc() {
+    let pipeline = DictPipeline::new(sources()).extra_dictionary(Input::bytes("names.txt", "たろう\t1\t1\t5000\t太郎\n"));
+    let names: Vec<String> = pipeline.dictionaries().map(Input::name).collect();
+    assert_eq!(names, ["dictionary00.txt", "names.txt"]);
+
+    let (out, report) = pipeline.build().unwrap();
+    assert_eq!(report.entries, 2);
+    assert!(String::from_utf8(out.lex).unwrap().ends_with("太郎,1,1,5000,名詞,一般,*,*,*,*,*,たろう,たろー\n"));
+}
+
+#[test]
+fn test_write_dictionary_without_files() {
+    let sources = MozcSources::from_readers(
+        "0 BOS/EOS,*,*,*,*,*,*\n1 名詞,一般,*,*,*,*,*\n".as_bytes(),
+        "2\n0\n0\n10\n20\n30\n".as_bytes(),
+        [("tenant.txt".to_string(), "きしゃ\t1\t1\t4000\t記者\n".as_bytes())],
+    )
+    .unwrap();
+
+    let mut compressed = Vec::new();
+    let report = DictPipeline::new(sources).write_dictionary(&mut compressed).unwrap();
+    assert_eq!(report.entries, 1);
+
+    let decoder = zstd::Decoder::new(&compressed[..]).unwrap();
+    vibrato::Dictionary::read(decoder).expect("Compiled dictionary should load");
+}
+
+#[test]
+fn test_output_compression() {
+    assert_eq!("zstd:3".parse(), Ok(Compression::Zstd { level: 3, long: false }));
+    assert_eq!("zstd:22:long".parse(), Ok(Compression::Zstd { level: 22, long: true }));
+    assert_eq!("zstd".parse(), Ok(Compression::default()));
+    assert!("zstd:99".parse::<Compression>().is_err());
+    assert!("brotli".parse::<Compression>().is_err());
+    assert_eq!(Compression::Gzip.file_name("system.dic"), "system.dic.gz");
+    assert_eq!(Compression::None.file_name("system.dic"), "system.dic");
+    assert_eq!(Compression::Zstd { level: 22, long: true }.to_string(), "zstd:22:long");
+
+    let (dict, _) = DictPipeline::new(sources()).compile().unwrap();
+    let write = |compression| {
+        let mut out = Vec::new();
+        write_dictionary_as(&dict, &mut out, compression).unwrap();
+        out
+    };
+    let plain = write(Compression::None);
+    vibrato::Dictionary::read(&plain[..]).expect("Uncompressed dictionary should load");
+    let mut gunzipped = Vec::new();
+    flate2::read::GzDecoder::new(&write(Compression::Gzip)[..]).read_to_end(&mut gunzipped).unwrap();
+    assert_eq!(gunzipped, plain);
+    let long = write(Compression::Zstd { level: 3, long: true });
+    assert_eq!(zstd::decode_all(&long[..]).unwrap(), plain);
+}
+
+#[test]
+fn test_read_dictionary_in_every_compression() {
+    let (dict, _) = DictPipeline::new(sources()).compile().unwrap();
+    let dir = std::env::temp_dir().join(format!("read-dictionary-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for compression in [Compression::None, Compression::Gzip, Compression::Zstd { level: 3, long: true }] {
+        let path = dir.join(compression.file_name("system.dic"));
+        write_dictionary_as(&dict, std::fs::File::create(&path).unwrap(), compression).unwrap();
+        read_dictionary(&path).unwrap_or_else(|e| panic!("{} dictionary should load: {:#}", compression, e));
+    }
+    assert!(read_dictionary(&dir.join("missing.dic")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Categories of `c` in char.def: the last mapping line covering it wins.
+fn char_categories(char_def: &str, c: char) -> Vec<String> {
+    let mut categories = vec!["DEFAULT".to_string()];
+    for line in char_def.lines() {
+        let line = line.split('#').next().unwrap().trim();
+        let mut fields = line.split_whitespace();
+        let Some(range) = fields.next().filter(|f| f.starts_with("0x")) else { continue };
+        let (low, high) = range.split_once("..").unwrap_or((range, range));
+        let parse = |hex: &str| u32::from_str_radix(&hex[2..], 16).unwrap();
+        if (parse(low)..=parse(high)).contains(&(c as u32)) {
+            categories = fields.map(str::to_string).collect();
+        }
+    }
+    categories
+}
+
+#[test]
+fn test_char_def_covers_common_blocks() {
+    let char_def = mozc_dict_gen::CHAR_DEF;
+    for (c, category) in [
+        ('ｱ', "KATAKANA"),
+        ('ﾞ', "KATAKANA"),
+        ('Ａ', "ALPHA"),
+        ('１', "NUMERIC"),
+        ('！', "SYMBOL"),
+        ('㐀', "KANJI"),
+        ('々', "KANJI"),
+        ('一', "KANJINUMERIC"),
+        ('α', "GREEK"),
+        ('Ж', "CYRILLIC"),
+        ('é', "ALPHA"),
+        ('ー', "KATAKANA"),
+        ('　', "SPACE"),
+        ('。', "SYMBOL"),
+    ] {
+        assert_eq!(char_categories(char_def, c)[0], category, "Category of {:?}", c);
+    }
+}
+
+#[test]
+fn test_every_char_category_has_unknown_entry() {
+    let (out, _) = DictPipeline::new(sources()).build().unwrap();
+    let unk_def = String::from_utf8(out.unk_def).unwrap();
+    let char_def = String::from_utf8(out.char_def).unwrap();
+    for line in char_def.lines().map(|l| l.split('#').next().unwrap().trim()) {
+        let Some(category) = line.split_whitespace().next().filter(|f| !f.starts_with("0x")) else { continue };
+        assert!(unk_def.lines().any(|l| l.starts_with(&format!("{},", category))), "No unk.def entry for {}", category);
+    }
+}
+
+#[test]
+fn test_unknown_words_use_pos_from_id_def() {
+    let mut sources = sources();
+    sources.id_def = Input::bytes(
+        "id.def",
+        "0 BOS/EOS,*,*,*,*,*,*\n1 名詞,一般,*,*,*,*,*\n2 名詞,数,*,*,*,*,*\n3 記号,空白,*,*,*,*,*\n\
+         4 記号,一般,*,*,*,*,*\n5 名詞,固有名詞,一般,*,*,*,*\n6 名詞,一般,*,*,*,*,する\n",
+    );
+    sources.connection = Input::bytes("connection_single_column.txt", format!("7\n{}", "0\n".repeat(49)));
+    sources.dictionaries = vec![Input::bytes("dictionary00.txt", "いち\t2\t2\t3000\t一\nに\t2\t2\t3500\t二\n")];
+    let (out, _) = DictPipeline::new(sources).build().unwrap();
+    let unk_def = String::from_utf8(out.unk_def).unwrap();
+
+    let entry = |category: &str| unk_def.lines().find(|l| l.starts_with(&format!("{},", category))).unwrap().to_string();
+    assert_eq!(entry("NUMERIC"), "NUMERIC,2,2,3500,名詞,数,*,*,*,*,*,*,*");
+    assert_eq!(entry("SPACE"), "SPACE,3,3,0,記号,空白,*,*,*,*,*,*,*");
+    assert!(entry("SYMBOL").starts_with("SYMBOL,4,4,5000,記号,一般,"));
+    assert!(entry("DEFAULT").starts_with("DEFAULT,1,1,"), "The general noun POS should win over 6");
+    assert!(unk_def.lines().any(|l| l.starts_with("KATAKANA,5,5,")), "Katakana runs may be proper nouns");
+}
+
+#[test]
+fn test_annotations_follow_the_pronunciation() {
+    let mut sources = sources();
+    sources.dictionaries = vec![Input::bytes(
+        "dictionary00.txt",
+        "きしゃ\t1\t1\t4000\t記者\tprimary\n\
+         かお\t1\t1\t6000\t(^_^)\tannotation=【顔文字】\tflags=slang\n\
+         えがお\t1\t1\t6000\t😀\n",
+    )];
+    let (out, _) = DictPipeline::new(sources).build().unwrap();
+    let lex = String::from_utf8(out.lex).unwrap();
+    let lines: Vec<&str> = lex.lines().collect();
+    assert_eq!(lines[0], "記者,1,1,4000,名詞,一般,*,*,*,*,*,きしゃ,きしゃ", "Other notes are dropped");
+    assert_eq!(lines[1], "(^_^),1,1,6000,名詞,一般,*,*,*,*,*,かお,かお,【顔文字】,slang");
+    assert_eq!(lines[2], "😀,1,1,6000,名詞,一般,*,*,*,*,*,えがお,えがお,*,emoji");
+}
+
+#[test]
+fn test_convert_matrix_detects_the_metadata_line() {
+    let convert = |text: &str| {
+        let mut out = Vec::new();
+        convert_matrix(text.as_bytes(), &mut out).map(|_| String::from_utf8(out).unwrap())
+    };
+    let expected = "2 2\n0 0 0\n0 1 10\n1 0 20\n1 1 30\n";
+    assert_eq!(convert("2\n0\n10\n20\n30\n").unwrap(), expected, "Plain single-column matrix");
+    assert_eq!(convert("2\n5\n0\n10\n20\n30\n").unwrap(), expected, "Numeric metadata line");
+    assert_eq!(convert("2\nversion 1\n0\n10\n20\n30\n\n").unwrap(), expected, "Text metadata line");
+
+    let err = convert("2\n0\n10\n20\n").unwrap_err().to_string();
+    assert!(err.contains("Found 3 costs for a 2x2 matrix, expected 4"), "{}", err);
+    assert!(convert("2\n0\n10\n20\n30\n40\n50\n").is_err());
+    assert!(convert("2\n0\n10\nx\n30\n").unwrap_err().to_string().starts_with("Line 4:"));
+}
+
+#[test]
+fn test_connection_files_of_every_mozc_version() {
+    let fixtures = [
+        (include_str!("fixtures/connection/connection_single_column.txt"), ConnectionFormat::SingleColumn),
+        (include_str!("fixtures/connection/connection_single_column_with_count.txt"), ConnectionFormat::SingleColumn),
+        (include_str!("fixtures/connection/connection.txt"), ConnectionFormat::Matrix),
+    ];
+    let id_map = read_id_def(include_str!("fixtures/connection/id.def").as_bytes()).unwrap();
+    for (text, format) in fixtures {
+        let matrix = ConnectionMatrix::read(text.as_bytes()).unwrap();
+        assert_eq!(matrix.format, format);
+        assert_eq!(matrix.size, 3);
+        assert_eq!(matrix.costs, [0, -200, 150, -200, 0, 3000, 150, 3000, 0]);
+        matrix.validate_ids(&id_map).unwrap();
+    }
+
+    let read = |text: &str| ConnectionMatrix::read(text.as_bytes()).unwrap_err().to_string();
+    assert!(read("2 2\n0 0 0\n0 1 10\n1 0 20\n").contains("1 of 4 cells"));
+    assert!(read("2 2\n0 0 0\n0 0 10\n").starts_with("Line 3: cell 0 0 given twice"));
+    assert!(read("2 2\n0 2 0\n").starts_with("Line 2: context ID out of range"));
+    assert!(read("2 3\n").starts_with("Line 1:"), "Mozc matrices are square");
+
+    let mut sources = sources();
+    sources.id_def = Input::bytes("id.def", include_str!("fixtures/connection/id.def"));
+    let err = DictPipeline::new(sources).build().unwrap_err();
+    assert!(format!("{:#}", err).contains("is 2x2 but id.def has 3 context IDs"), "{:#}", err);
+}
+
+#[test]
+fn test_id_def_keeps_the_whole_pos() {
+    let id_map = read_id_def("0 BOS/EOS,*,*,*,*,*,*\r\n\n12 動詞,自立,*,*,サ変・スル,連用形,する (古)\n".as_bytes()).unwrap();
+    assert_eq!(id_map[&12], "動詞,自立,*,*,サ変・スル,連用形,する (古)");
+
+    assert_eq!(read_id_def("1 a,b,c,d,e,f,g,h\n".as_bytes()).unwrap()[&1], "a,b,c,d,e,f,g,h");
+    let err = read_id_def("1 名詞\nx 名詞\n".as_bytes()).unwrap_err().to_string();
+    assert!(err.contains("line 2"), "{}", err);
+
+    let mut sources = sources();
+    sources.id_def = Input::bytes("id.def", "0 BOS/EOS\n1 名詞,固有名詞,組織\n");
+    let (out, _) = DictPipeline::new(sources).build().unwrap();
+    assert!(String::from_utf8(out.lex).unwrap().starts_with("記者,1,1,4000,名詞,固有名詞,組織,*,*,*,*,きしゃ,きしゃ\n"));
+
+    // Fields past the seventh follow the (empty) annotation columns
+    let mut sources = self::sources();
+    sources.id_def = Input::bytes("id.def", "0 BOS/EOS\n1 名詞,一般,*,*,*,*,*,外来,和語\n");
+    let (out, _) = DictPipeline::new(sources).build().unwrap();
+    let lex = String::from_utf8(out.lex).unwrap();
+    assert!(lex.starts_with("記者,1,1,4000,名詞,一般,*,*,*,*,*,きしゃ,きしゃ,*,*,外来,和語\n"), "{}", lex);
+}
+
+#[test]
+fn test_sources_round_trip_through_a_directory() {
+    let dir = std::env::temp_dir().join(format!("mozc-sources-{}", std::process::id()));
+    sources().write_dir(&dir).unwrap();
+    let read_back = MozcSources::from_dir(&dir).unwrap();
+    let (expected, _) = DictPipeline::new(sources()).build().unwrap();
+    let (actual, _) = DictPipeline::new(read_back).build().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(actual.lex, expected.lex);
+    assert_eq!(actual.matrix, expected.matrix);
+}
+
+#[test]
+fn test_user_dictionary_is_merged_with_resolved_ids() {
+    let mut sources = sources();
+    sources.id_def = Input::bytes(
+        "id.def",
+        "0 BOS/EOS,*,*,*,*,*,*\n1 名詞,一般,*,*,*,*,*\n2 名詞,固有名詞,組織,*,*,*,*\n3 名詞,固有名詞,一般,*,*,*,*\n",
+    );
+    let user_dict = "# product names\n\
+                     ろこも\tロコモ\t組織\t3000\n\
+                     フォージ\tForge\t名詞,固有名詞,一般\n\
+                     しゃないびん\t社内便\t名詞\n\
+                     ばつ\t×\t動詞\n\
+                     ok\tOK\t名詞\n";
+    sources.connection = Input::bytes("connection_single_column.txt", format!("4\n{}", "0\n".repeat(16)));
+    let pipeline = DictPipeline::new(sources).user_dictionary(Input::bytes("my_words.tsv", user_dict));
+    let (out, report) = pipeline.build().unwrap();
+
+    let lex = String::from_utf8(out.lex).unwrap();
+    let lines: Vec<&str> = lex.lines().collect();
+    assert_eq!(lines[1], "ロコモ,2,2,3000,名詞,固有名詞,組織,*,*,*,*,ろこも,ろこも");
+    assert_eq!(lines[2], "Forge,3,3,4000,名詞,固有名詞,一般,*,*,*,*,ふぉーじ,ふぉーじ");
+    assert_eq!(lines[3], "社内便,1,1,4000,名詞,一般,*,*,*,*,*,しゃないびん,しゃないびん");
+    assert_eq!(report.entries, 4);
+
+    let reasons: Vec<(&str, usize, &str)> =
+        report.rejections.iter().map(|r| (r.source.as_str(), r.line_no, r.reason.as_str())).collect();
+    assert_eq!(
+        reasons,
+        [
+            ("dictionary00.txt", 2, "fewer than 5 columns"),
+            ("my_words.tsv", 5, "no POS \"動詞\" in id.def"),
+            ("my_words.tsv", 6, "reading \"ok\" is not kana"),
+        ]
+    );
+}
+
+#[test]
+fn test_extra_lexicon_is_remapped_and_deduplicated() {
+    let mut sources = sources();
+    sources.id_def = Input::bytes(
+        "id.def",
+        "0 BOS/EOS,*,*,*,*,*,*\n1 名詞,一般,*,*,*,*,*\n2 名詞,固有名詞,一般,*,*,*,*\n3 感動詞,*,*,*,*,*,*\n",
+    );
+    sources.connection = Input::bytes("connection_single_column.txt", format!("4\n{}", "0\n".repeat(16)));
+    let neologd = "ゆるキャラ,1288,1288,5000,名詞,固有名詞,一般,*,*,*,ゆるキャラ,ユルキャラ,ユルキャラ\n\
+                   記者,1285,1285,4000,名詞,一般,*,*,*,*,記者,キシャ,キシャ\n\
+                   \"ロコ,モ\",1293,1293,6000,名詞,固有名詞,組織,*,*,*,ロコモ,ロコモ,ロコモ\n\
+                   ええと,2,2,5000,フィラー,*,*,*,*,*,ええと,エエト,エート\n\
+                   ゆるキャラ,1288,1288,4000,名詞,固有名詞,一般,*,*,*,ゆるキャラ,ユルキャラ,ユルキャラ\n\
+                   走る,772,772,5000,動詞,自立,*,*,五段・ラ行,基本形,走る,ハシル,ハシル\n";
+    let pipeline = DictPipeline::new(sources).extra_lexicon(Input::bytes("neologd.csv", neologd));
+    let (out, report) = pipeline.build().unwrap();
+
+    let lex = String::from_utf8(out.lex).unwrap();
+    let lines: Vec<&str> = lex.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "記者,1,1,4000,名詞,一般,*,*,*,*,*,きしゃ,きしゃ",
+            "ゆるキャラ,2,2,5000,名詞,固有名詞,一般,*,*,*,*,ゆるきゃら,ゆるきゃら",
+            "\"ロコ,モ\",2,2,6000,名詞,固有名詞,一般,*,*,*,*,ろこも,ろこも",
+            "ええと,3,3,5000,感動詞,*,*,*,*,*,*,ええと,えーと",
+        ]
+    );
+    assert_eq!(report.duplicates, 2, "記者 is in Mozc's dictionary and ゆるキャラ is given twice");
+    let last = report.rejections.last().unwrap();
+    assert_eq!((last.source.as_str(), last.line_no), ("neologd.csv", 6));
+    assert!(last.reason.contains("動詞,自立"), "{}", last.reason);
+
+    let pos_map = PosMap::read("#pos-map\tv1\n動詞\t名詞,一般\n".as_bytes()).unwrap();
+    let pipeline = pipeline.pos_map(pos_map);
+    let (out, _) = pipeline.build().unwrap();
+    assert!(String::from_utf8(out.lex).unwrap().contains("\n走る,1,1,5000,名詞,一般,"));
+    assert!(PosMap::read("#pos-map\tv2\n".as_bytes()).is_err());
+}
+
+#[test]
+fn test_pronunciation_is_estimated_from_the_reading() {
+    let mut sources = sources();
+    sources.id_def = Input::bytes(
+        "id.def",
+        "0 BOS/EOS,*,*,*,*,*,*\n1 名詞,一般,*,*,*,*,*\n2 助詞,係助詞,*,*,*,*,*\n3 動詞,自立,*,*,五段・ワ行促音便,基本形,*\n",
+    );
+    sources.connection = Input::bytes("connection_single_column.txt", format!("4\n{}", "0\n".repeat(16)));
+    sources.dictionaries = vec![Input::bytes(
+        "dictionary00.txt",
+        "とうきょう\t1\t1\t3000\t東京\nせんせい\t1\t1\t3000\t先生\nは\t2\t2\t100\tは\nおもう\t3\t3\t3000\t思う\n",
+    )];
+    let pronunciations = |pipeline: DictPipeline| -> Vec<String> {
+        let (out, _) = pipeline.build().unwrap();
+        let lex = String::from_utf8(out.lex).unwrap();
+        lex.lines().map(|line| line.rsplit(',').next().unwrap().to_string()).collect()
+    };
+
+    let estimated = pronunciations(DictPipeline::new(sources.clone()));
+    assert_eq!(estimated, ["とーきょー", "せんせー", "わ", "おもう"], "The final う of verbs is kept");
+    let copied = pronunciations(DictPipeline::new(sources).pronunciation(Pronunciation::Reading));
+    assert_eq!(copied, ["とうきょう", "せんせい", "は", "おもう"]);
+}
+
+#[test]
+fn test_bundle_manifest_lists_checksums() {
+    assert_eq!(
+        bundle::sha256_hex("abc".as_bytes()).unwrap(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    let dir = std::env::temp_dir().join(format!("bundle-manifest-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("system.dic.zst"), "abc").unwrap();
+
+    let manifest = BundleManifest {
+        built_at: 1700000000,
+        provenance: [("mozc_ref", "master"), ("extra_dictionary", "a.txt"), ("extra_dictionary", "b.txt")]
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .to_vec(),
+        artifacts: vec![Artifact::from_file(&dir.join("system.dic.zst"), Some(42)).unwrap()],
+    };
+    let json = manifest.to_json();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(json["built_at"], 1700000000);
+    assert!(json["tools"]["vibrato"].as_str().is_some_and(|version| !version.is_empty()));
+    assert_eq!(json["provenance"]["mozc_ref"], "master");
+    assert_eq!(json["provenance"]["extra_dictionary"], serde_json::json!(["a.txt", "b.txt"]));
+    let artifact = &json["artifacts"][0];
+    assert_eq!(artifact["file"], "system.dic.zst");
+    assert_eq!((artifact["size"].as_u64(), artifact["entries"].as_u64()), (Some(3), Some(42)));
+    assert_eq!(artifact["sha256"], "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+}
+
+#[test]
+fn test_builder_writes_the_artifacts() {
+    let dir = std::env::temp_dir().join(format!("mozc-builder-{}", std::process::id()));
+    sources().write_dir(&dir.join("dictionary_oss")).unwrap();
+    let builder = Builder::new(Source::Mozc)
+        .source_dir(dir.join("dictionary_oss"))
+        .output_dir(dir.join("output"))
+        .key(LexiconKey::Reading)
+        .compression(Compression::None);
+    let error = builder.build().unwrap_err().to_string();
+    assert!(error.contains("1 lexicon lines rejected"), "The broken line fails the build: {}", error);
+    assert!(builder.clone().warn_on_rejected_lines(true).build().is_ok(), "Only a warning when opted in");
+
+    let output = builder.max_rejected_lines(1).build().unwrap();
+    let names: Vec<_> = output.artifacts().iter().map(|path| path.file_name().unwrap().to_owned()).collect();
+    let manifest = std::fs::read_to_string(&output.manifest).unwrap();
+    let intermediates_left = dir.join("output").join("lex.csv").exists();
+    let dict = vibrato::Dictionary::read(std::fs::File::open(&output.dictionary).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(names, ["system.dic", "conversion.dic", "mozc-dict-manifest.tsv", "manifest.json"]);
+    assert_eq!((output.report.entries, output.report.rejected), (1, 1));
+    assert!(manifest.contains("key\treading\ncompression\tnone\nlexicon_entries\t1\n"), "{}", manifest);
+    assert!(!intermediates_left);
+    dict.unwrap();
+}
+
+#[test]
+fn test_identical_sources_build_identical_bytes() {
+    let dir = std::env::temp_dir().join(format!("mozc-deterministic-{}", std::process::id()));
+    let mut sources = sources();
+    sources.dictionaries = vec![
+        Input::bytes("dictionary01.txt", "きしゃ\t1\t1\t4500\t汽車\n"),
+        Input::bytes("dictionary00.txt", "きしゃ\t1\t1\t4000\t記者\n"),
+    ];
+    let build = |sources: &MozcSources, name: &str| {
+        sources.write_dir(&dir.join(name)).unwrap();
+        let output = Builder::new(Source::Mozc)
+            .source_dir(dir.join(name))
+            .output_dir(dir.join(name).join("output"))
+            .key(LexiconKey::Reading)
+            .max_rejected_lines(1)
+            .build()
+            .unwrap();
+        let artifacts: Vec<Vec<u8>> = [&output.dictionary, output.conversion.as_ref().unwrap()]
+            .iter()
+            .map(|path| std::fs::read(path).unwrap())
+            .collect();
+        artifacts
+    };
+    let first = build(&sources, "first");
+    // The same files, written in the other order
+    sources.dictionaries.reverse();
+    let second = build(&sources, "second");
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(first == second, "Rebuilding changed the dictionary bytes");
+}
+
+#[test]
+fn test_in_memory_build_matches_the_file_build() {
+    let dir = std::env::temp_dir().join(format!("mozc-in-memory-{}", std::process::id()));
+    sources().write_dir(&dir.join("mozc")).unwrap();
+    let build = |name: &str, in_memory: bool| {
+        let output = Builder::new(Source::Mozc)
+            .source_dir(dir.join("mozc"))
+            .output_dir(dir.join(name))
+            .key(LexiconKey::Reading)
+            .report(dir.join(name).join("stats.json"))
+            .in_memory(in_memory)
+            .max_rejected_lines(1)
+            .build()
+            .unwrap();
+        let files = [&output.dictionary, output.conversion.as_ref().unwrap(), output.stats_report.as_ref().unwrap()];
+        files.map(|path| std::fs::read(path).unwrap())
+    };
+    let on_disk = build("on-disk", false);
+    let in_memory = build("in-memory", true);
+    let leftovers: Vec<_> = std::fs::read_dir(dir.join("in-memory"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".csv") || name.ends_with(".def"))
+        .collect();
+    let combined = Builder::new(Source::Mozc).in_memory(true).keep_intermediate(true).build();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(on_disk == in_memory, "The in-memory build wrote other bytes");
+    assert!(leftovers.is_empty(), "Intermediate files written: {:?}", leftovers);
+    assert!(combined.is_err(), "Nothing to keep in memory");
+}
+
+#[test]
+fn test_pruning_keeps_the_cheapest_entry_of_every_reading_first() {
+    let lex = "記者,1,1,4000,名詞,一般,*,*,*,*,*,きしゃ,きしゃ\n\
+               汽車,1,1,5000,名詞,一般,*,*,*,*,*,きしゃ,きしゃ\n\
+               貴社,1,1,4500,名詞,一般,*,*,*,*,*,きしゃ,きしゃ\n\
+               東京,1,1,6000,名詞,一般,*,*,*,*,*,とうきょう,とーきょー\n";
+    let budget = EntryBudget::read(lex.as_bytes()).unwrap();
+    assert_eq!(budget.len(), 4);
+    let pruned = |max_entries| {
+        let mut out = Vec::new();
+        let written = budget.write(max_entries, &mut out).unwrap();
+        let surfaces: Vec<String> = String::from_utf8(out).unwrap().lines().map(|line| line[..6].to_string()).collect();
+        assert_eq!(written, surfaces.len());
+        surfaces
+    };
+    assert_eq!(pruned(2), ["記者", "東京"], "The rarer homophones go before a whole reading");
+    assert_eq!(pruned(3), ["記者", "貴社", "東京"], "Kept rows stay in lexicon order");
+    assert_eq!(pruned(10).len(), 4);
+
+    assert_eq!(prune::next_limit(1000, 200, 100), 475);
+    assert_eq!(prune::next_limit(1000, 1001, 1000), 949);
+    assert_eq!(prune::next_limit(1, 10_000, 1), 0);
+}
+
+#[test]
+fn test_build_with_an_entry_budget() {
+    let dir = std::env::temp_dir().join(format!("mozc-max-entries-{}", std::process::id()));
+    let mut mozc = sources();
+    mozc.dictionaries = vec![Input::bytes(
+        "dictionary00.txt",
+        "きしゃ\t1\t1\t4000\t記者\nきしゃ\t1\t1\t5000\t汽車\nとうきょう\t1\t1\t3000\t東京\n",
+    )];
+    mozc.write_dir(&dir.join("mozc")).unwrap();
+    let output = Builder::new(Source::Mozc)
+        .source_dir(dir.join("mozc"))
+        .output_dir(dir.join("output"))
+        .max_entries(2)
+        .build()
+        .unwrap();
+    let manifest = std::fs::read_to_string(&output.manifest).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((output.report.entries, output.entries), (3, 2));
+    assert!(manifest.contains("max_entries\t2\n"), "{}", manifest);
+    assert!(manifest.contains("lexicon_entries\t2\n"), "{}", manifest);
+}
+
+#[test]
+fn test_smoke_test_rejects_garbled_segmentations() {
+    let sentence = SmokeSentence { text: "東京に行きます", in_lexicon: true, max_tokens: 6 };
+    assert_eq!(sentence.check(&[("東京", false), ("に", false), ("行き", false), ("ます", false)]), Ok(()));
+    assert!(sentence.check(&[]).is_err(), "No tokens");
+    assert!(sentence.check(&[("東京", false), ("に", false)]).is_err(), "Tokens missing text");
+    let chars: Vec<(&str, bool)> = ["東", "京", "に", "行", "き", "ま", "す"].map(|c| (c, false)).to_vec();
+    assert!(sentence.check(&chars).is_err(), "One token per character");
+    let unknown = sentence.check(&[("東京", true), ("に", false), ("行き", false), ("ます", false)]);
+    assert_eq!(unknown, Err("東京 not found in the lexicon".to_string()));
+
+    let digits = SmokeSentence { text: "1234", in_lexicon: false, max_tokens: 3 };
+    assert_eq!(digits.check(&[("1234", true)]), Ok(()), "Unknown words are fine outside the lexicon sentences");
+    assert!(smoke::SENTENCES.iter().all(|sentence| sentence.max_tokens <= sentence.text.chars().count()));
+}
+
+#[test]
+fn test_mozc_filter_lists_become_the_suppression_list() {
+    let filters = [
+        Input::bytes("suggestion_filter.txt", "# comment\nBadWord\nくそ\n"),
+        Input::bytes("suppression_word.txt", "# reading\tsurface\nくそ\tくそ\nあほ\t\n"),
+    ];
+    let mut fst = Vec::new();
+    assert_eq!(suppression::write_suppression(&filters, &mut fst).unwrap(), 2, "Reading-only lines are left out");
+    let list = SuppressionList::new(fst::Map::new(fst).unwrap());
+
+    assert_eq!(list.flags("くそ"), SUGGESTION_FILTERED | SUPPRESSED);
+    assert!(!list.allows_prediction("badword") && list.allows_candidate("BADWORD"));
+    assert!(list.allows_prediction("あほ") && list.allows_prediction("記者"));
+}
+
+#[test]
+fn test_reading_script_overrides_the_layout() {
+    let lex = |conversion: FeatureConversion| {
+        let (out, _) = DictPipeline::new(sources()).features(conversion).build().unwrap();
+        String::from_utf8(out.lex).unwrap()
+    };
+    let katakana = FeatureConversion::new(FeatureLayout::Mozc).with_reading_script(ReadingScript::Katakana);
+    assert_eq!(lex(katakana), "記者,1,1,4000,名詞,一般,*,*,*,*,*,キシャ,キシャ\n");
+    let hiragana = FeatureConversion::new(FeatureLayout::Unidic).with_reading_script(ReadingScript::Hiragana);
+    let unidic = lex(hiragana);
+    assert!(unidic.contains(",きしゃ,") && !unidic.contains("キシャ"), "{}", unidic);
+
+    assert_eq!(kana::to_katakana("ゔぁいおりん・いすゞ"), "ヴァイオリン・イスヾ");
+    assert_eq!(kana::to_hiragana("ヵ月ヶ所 ﾃｽﾄ"), "ゕ月ゖ所 てすと", "Half-width katakana is folded");
+    // ヷ has no hiragana letter, so it takes a combining voiced mark
+    assert_eq!(kana::to_hiragana("ヷイン"), "わ\u{3099}いん");
+    assert_eq!(kana::to_katakana(&kana::to_hiragana("ヴィヷルディ")), "ヴィヷルディ");
+}
+
+#[test]
+fn test_half_width_kana_fold_with_their_marks() {
+    assert_eq!(kana::fold_half_width("ｶﾞｯｺｳ ﾊﾟﾝ ﾋﾞﾙ ｳﾞｧ ﾜﾞ"), "ガッコウ パン ビル ヴァ ヷ");
+    assert_eq!(kana::to_hiragana("ﾄｳｷﾖｳﾄ ﾁﾖﾀﾞｸ"), "とうきようと ちよだく");
+    // ッ and voiced kana take no voiced mark, and カ no semi-voiced one
+    assert_eq!(kana::fold_half_width("ｯﾞ ｶﾞﾞ ｶﾟ ﾞ"), "ッ゛ ガ゛ カ゜ ゛");
+    assert_eq!(kana::to_katakana("ﾃﾞﾝｷ でんき"), "デンキ デンキ");
+}
+
+#[test]
+fn test_single_kanji_candidates_keep_mozc_order() {
+    let tsv = Input::bytes("single_kanji.tsv", "# reading\tkanji\nあい\t愛相藍\nかん\t間感\nあい\t哀愛\n");
+    let mut fst = Vec::new();
+    assert_eq!(single_kanji::write_single_kanji(&tsv, None, &mut fst).unwrap(), 6);
+    let list = SingleKanji::new(fst::Map::new(fst).unwrap());
+
+    assert_eq!(list.candidates("あい"), ["愛", "相", "藍", "哀"]);
+    assert_eq!(list.candidates("かん"), ["間", "感"]);
+    assert!(list.candidates("か").is_empty(), "Readings are matched whole");
+}
+
+#[test]
+fn test_reverse_lookup_lists_readings_cheapest_first() {
+    let lex = "\
+生,1,1,5000,名詞,一般,*,*,*,*,*,なま,なま
+生,2,2,-400,名詞,一般,*,*,*,*,*,せい,せい
+生,3,3,4500,名詞,一般,*,*,*,*,*,せい,せい
+生,4,4,-200,名詞,一般,*,*,*,*,*,き,き
+生 ビール,1,1,6000,名詞,一般,*,*,*,*,*,なまびーる,なまびーる
+α,1,1,3000,記号,一般,*,*,*,*,*,*,*
+";
+    let mut fst = Vec::new();
+    assert_eq!(reverse::write_reverse_lookup(lex.as_bytes(), FeatureLayout::Mozc, &mut fst).unwrap(), 4);
+    let lookup = ReverseLookup::new(fst::Map::new(fst).unwrap());
+
+    let readings = lookup.readings("生");
+    let pairs: Vec<(&str, i64)> = readings.iter().map(|r| (r.reading.as_str(), r.cost)).collect();
+    assert_eq!(
+        pairs,
+        [("せい", -400), ("き", -200), ("なま", 5000)],
+        "Negative costs keep their order; only the cheapest cost of a pair, not 生 ビール"
+    );
+    assert_eq!(lookup.readings("生 ビール")[0].reading, "なまびーる");
+    assert!(lookup.readings("α").is_empty(), "Entries without a reading are left out");
+}
+
+#[test]
+fn test_symbols_and_kaomoji_by_reading() {
+    let inputs = [
+        Input::bytes(
+            "symbol.tsv",
+            "POS\tCHAR\tREADING\tDESCRIPTION\n記号\t→\tやじるし みぎ\t右矢印\n記号\t←\tやじるし ひだり\t左矢印\n",
+        ),
+        Input::bytes("emoticon.tsv", "# kaomoji\n(^_^)\tかお にこにこ\n( ´ ▽ ` )\tかお\n"),
+    ];
+    let mut fst = Vec::new();
+    assert_eq!(symbols::write_symbols(&inputs, None, &mut fst).unwrap(), 7);
+    let symbols = Symbols::new(fst::Map::new(fst).unwrap());
+
+    let texts = |reading| symbols.candidates(reading).into_iter().map(|symbol| symbol.text).collect::<Vec<_>>();
+    assert_eq!(texts("やじるし"), ["→", "←"]);
+    assert_eq!(texts("かお"), ["(^_^)", "( ´ ▽ ` )"]);
+    assert_eq!(symbols.candidates("みぎ")[0].kind, SymbolKind::Symbol);
+    assert_eq!(symbols.candidates("にこにこ")[0].kind, SymbolKind::Kaomoji);
+    assert!(symbols.candidates("READING").is_empty(), "The header line is skipped");
+}
+
+#[test]
+fn test_zipcode_dictionary_from_ken_all() {
+    let ken_all = [
+        r#"13101,"100  ","1000000","ﾄｳｷﾖｳﾄ","ﾁﾖﾀﾞｸ","ｲｶﾆｹｲｻｲｶﾞﾅｲﾊﾞｱｲ","東京都","千代田区","以下に掲載がない場合",0,0,0,0,0,0"#,
+        r#"13101,"100  ","1000001","ﾄｳｷﾖｳﾄ","ﾁﾖﾀﾞｸ","ﾁﾖﾀﾞ","東京都","千代田区","千代田",0,0,0,0,0,0"#,
+        r#"01101,"060  ","0600042","ﾎｯｶｲﾄﾞｳ","ｻｯﾎﾟﾛｼﾁﾕｳｵｳｸ","ｵｵﾄﾞｵﾘﾆｼ(1","北海道","札幌市中央区","大通西（１",0,0,1,0,0,0"#,
+        r#"01101,"060  ","0600042","ﾎｯｶｲﾄﾞｳ","ｻｯﾎﾟﾛｼﾁﾕｳｵｳｸ","-19ﾁﾖｳﾒ)","北海道","札幌市中央区","～１９丁目）",0,0,1,0,0,0"#,
+        r#"13361,"10001","1000101","ﾄｳｷﾖｳﾄ","ｵｵｼﾏﾏﾁ","ｵｵｼﾏﾏﾁｲﾁｴﾝ","東京都","大島町","大島町一円",0,0,0,0,0,0"#,
+    ]
+    .join("\r\n");
+    let (shift_jis, _, _) = encoding_rs::SHIFT_JIS.encode(&ken_all);
+    let entries = zipcode::read_ken_all(&shift_jis[..]).unwrap();
+    let addresses: Vec<(&str, &str, &str)> =
+        entries.iter().map(|e| (e.code.as_str(), e.address.as_str(), e.reading.as_str())).collect();
+    assert_eq!(
+        addresses,
+        [
+            ("060-0042", "北海道札幌市中央区大通西", "ほっかいどうさっぽろしちゆうおうくおおどおりにし"),
+            ("100-0000", "東京都千代田区", "とうきようとちよだく"),
+            ("100-0001", "東京都千代田区千代田", "とうきようとちよだくちよだ"),
+            ("100-0101", "東京都大島町", "とうきようとおおしままち"),
+        ]
+    );
+
+    let id_map = read_id_def("0 BOS/EOS,*,*,*,*,*,*\n7 名詞,固有名詞,地域,一般,*,*,*\n".as_bytes()).unwrap();
+    let mut lex = Vec::new();
+    assert_eq!(zipcode::write_lex(&entries[2..3], &id_map, None, &mut lex).unwrap(), 1);
+    assert_eq!(
+        String::from_utf8(lex).unwrap(),
+        "100-0001,7,7,5000,東京都千代田区千代田,名詞,固有名詞,地域,一般,*,*,*,とうきようとちよだくちよだ,とうきようとちよだくちよだ\n"
+    );
+}
+
+#[test]
+fn test_dictionary_statistics_report() {
+    let mut sources = sources();
+    sources.dictionaries = vec![Input::bytes(
+        "dictionary00.txt",
+        "きしゃ\t1\t1\t4000\t記者\nきしゃ\t1\t1\t4500\t記者\nきしゃ\t1\t1\t5200\t汽車\nき\t1\t1\t-20\t木\n",
+    )];
+    let (out, _) = DictPipeline::new(sources).build().unwrap();
+    let mut stats = DictStats::from_lex(&out.lex[..]).unwrap();
+    stats.add_matrix(&ConnectionMatrix::read(&out.matrix[..]).unwrap());
+
+    assert_eq!(stats.entries, 4);
+    assert_eq!(stats.pos.get("名詞,一般"), Some(&4));
+    assert_eq!(stats.cost_histogram, BTreeMap::from([(-1000, 1), (4000, 2), (5000, 1)]));
+    assert_eq!(stats.reading_length.get(&3), Some(&3));
+    assert_eq!(stats.duplicates, [("記者".to_string(), "きしゃ".to_string(), 2)]);
+
+    let report = stats.to_json();
+    assert_eq!(report["cost"]["min"], -20);
+    assert_eq!(report["duplicates"]["extra_entries"], 1);
+    // Three of the four cells of the 2x2 matrix are non-zero
+    assert_eq!(report["matrix"]["density"], 0.75);
+}
+
+#[test]
+fn test_lexicon_diff_by_pos() {
+    let lex = |dictionary: &str| {
+        let mut sources = sources();
+        sources.dictionaries = vec![Input::bytes("dictionary00.txt", dictionary)];
+        let (out, _) = DictPipeline::new(sources).build().unwrap();
+        diff::read_lex(&out.lex[..]).unwrap()
+    };
+    let old = lex("きしゃ\t1\t1\t4000\t記者\nきしゃ\t1\t1\t5200\t汽車\nき\t1\t1\t3000\t木\n");
+    let new = lex("きしゃ\t1\t1\t4500\t記者\nき\t1\t1\t3000\t木\nき\t1\t1\t3500\t気\n");
+    let diff = LexDiff::new(&old, &new);
+
+    let surfaces = |entries: &[DiffEntry]| entries.iter().map(|e| e.surface.clone()).collect::<Vec<_>>();
+    assert_eq!(surfaces(&diff.added), ["気"]);
+    assert_eq!(surfaces(&diff.removed), ["汽車"]);
+    assert_eq!(surfaces(&diff.changed), ["記者"]);
+    assert_eq!(diff.changed[0].cost_delta(), Some(500));
+    assert_eq!(diff.unchanged, 1);
+    let by_pos = diff.by_pos();
+    assert_eq!(by_pos["名詞,一般"], PosDiff { added: 1, removed: 1, changed: 1, cost_delta: 500 });
+    assert!(LexDiff::new(&old, &old).is_empty());
+}
+
+#[test]
+fn test_reading_keyed_conversion_lexicon() {
+    let mut sources = sources();
+    sources.dictionaries = vec![Input::bytes("dictionary00.txt", "きしゃ\t1\t1\t4000\t記者\nきしゃ\t1\t1\t4500\t汽車\n")];
+    let (out, _) = DictPipeline::new(sources).build().unwrap();
+    let keyed = conversion::reading_keyed(&out).unwrap();
+
+    assert_eq!(
+        String::from_utf8(keyed.lex.clone()).unwrap(),
+        "きしゃ,1,1,4000,記者,名詞,一般,*,*,*,*,*,きしゃ,きしゃ\nきしゃ,1,1,4500,汽車,名詞,一般,*,*,*,*,*,きしゃ,きしゃ\n"
+    );
+    assert_eq!(keyed.unk_def, out.unk_def);
+    keyed.compile().unwrap();
+
+    let mut lex = Vec::new();
+    let unidic_symbol = "、,5,5,100,補助記号,読点,*,*,*,*,、,*,*\n";
+    assert_eq!(conversion::write_reading_keyed(unidic_symbol.as_bytes(), &mut lex).unwrap(), 0, "Untypable entries are left out");
+}
+
+#[test]
+fn test_unidic_features_match_the_mozc_layout() {
+    let sources = UniDicSources {
+        lex: Input::bytes(
+            "lex_3_1.csv",
+            "東京,1,1,3000,名詞,固有名詞,地名,一般,*,*,トウキョウ,東京,東京,トーキョー,東京,トーキョー,固,*,*,*,*,*,*,\
+             体,トウキョウ,トウキョウ,トウキョウ,トウキョウ,0,*,*,7158,26057\n\
+             ページ,2,2,4000,名詞,普通名詞,一般,*,*,*,ページ,ページ-page,ページ,ページ,ページ,ページ,外,*,*,*,*,*,*,\
+             体,ページ,ページ,ページ,ページ,0,*,*,8935,32539\n\
+             壊れ,1,1,10,名詞\n",
+        ),
+        matrix: Input::bytes("matrix.def", "3 3\n0 0 0\n"),
+        char_def: Input::bytes("char.def", "DEFAULT 0 1 0\n"),
+        unk_def: Input::bytes("unk.def", "DEFAULT,2,2,4769,補助記号,一般,*,*,*,*\n"),
+    };
+    let (out, report) = sources.build().unwrap();
+
+    let lex = String::from_utf8(out.lex).unwrap();
+    let lines: Vec<&str> = lex.lines().collect();
+    assert_eq!(lines, ["東京,1,1,3000,名詞,固有名詞,地名,一般,*,*,東京,とうきょう,とーきょー", "ページ,2,2,4000,名詞,普通名詞,一般,*,*,*,ページ,ぺーじ,ぺーじ"]);
+    assert_eq!(String::from_utf8(out.unk_def).unwrap(), "DEFAULT,2,2,4769,補助記号,一般,*,*,*,*,*,*,*\n");
+    assert_eq!(out.matrix, b"3 3\n0 0 0\n");
+    assert_eq!((report.entries, report.rejected), (2, 1));
+    assert_eq!(report.rejections[0].line_no, 3);
+}
+
+#[test]
+fn test_ipadic_and_unidic_feature_layouts() {
+    let mut sources = sources();
+    sources.id_def = Input::bytes("id.def", "0 BOS/EOS,*,*,*,*,*,*\n1 名詞,一般,*,*,*,*,*\n2 動詞,自立,*,*,一段,基本形,*\n");
+    sources.connection = Input::bytes("connection_single_column.txt", format!("3\n{}", "0\n".repeat(9)));
+    sources.dictionaries = vec![Input::bytes("dictionary00.txt", "きしゃ\t1\t1\t4000\t記者\nたべる\t2\t2\t4000\t食べる\tflags=slang\n")];
+    let build = |layout| {
+        let pipeline = DictPipeline::new(sources.clone()).features(FeatureConversion::new(layout));
+        let (out, _) = pipeline.build().unwrap();
+        (String::from_utf8(out.lex).unwrap(), String::from_utf8(out.unk_def).unwrap())
+    };
+
+    let (lex, unk_def) = build(FeatureLayout::Ipadic);
+    assert_eq!(
+        lex,
+        "記者,1,1,4000,名詞,一般,*,*,*,*,記者,キシャ,キシャ\n食べる,2,2,4000,動詞,自立,*,*,一段,基本形,食べる,タベル,タベル,*,slang\n"
+    );
+    assert!(unk_def.contains("\nKANJI,1,1,4000,名詞,一般,*,*,*,*,*,*,*\n"), "{}", unk_def);
+
+    let (lex, _) = build(FeatureLayout::Unidic);
+    assert!(lex.starts_with("記者,1,1,4000,名詞,普通名詞,一般,*,*,*,キシャ,記者,記者,キシャ\n"), "{}", lex);
+    assert!(lex.contains("\n食べる,2,2,4000,動詞,一般,*,*,一段,基本形,タベル,食べる,食べる,タベル,*,slang\n"), "{}", lex);
+
+    let (lex, _) = build(FeatureLayout::Mozc);
+    assert!(lex.starts_with("記者,1,1,4000,名詞,一般,*,*,*,*,*,きしゃ,きしゃ\n"));
+}
+
+#[test]
+fn test_sudachi_pos_is_normalized() {
+    let sources = SudachiSources {
+        lexicons: vec![Input::bytes(
+            "small_lex.csv",
+            "東京,3,3,3003,東京,名詞,固有名詞,地名,一般,*,*,トウキョウ,東京,*,A,*,*,*,*\n\
+             東京都,-1,-1,0,東京都,名詞,固有名詞,地名,一般,*,*,トウキョウト,東京都,*,B,1/2,*,1/2,*\n\
+             はしる,2,2,5000,走る,動詞,一般,*,*,五段-ラ行,終止形-一般,ハシル,走る,*,A,*,*,*,*\n\
+             記者,1,1,4000,記者,名詞,普通名詞,一般,*,*,*,キシャ,記者,*,A,*,*,*,*\n\
+             壊れ,1,1\n",
+        )],
+        matrix: Input::bytes("matrix.def", "4 4\n"),
+    };
+    let (out, report) = sources.build().unwrap();
+
+    let lex = String::from_utf8(out.lex).unwrap();
+    let lines: Vec<&str> = lex.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "東京,3,3,3003,名詞,固有名詞,地域,一般,*,*,*,とうきょう,とうきょう",
+            "走る,2,2,5000,動詞,自立,*,*,五段-ラ行,終止形-一般,*,はしる,はしる",
+            "記者,1,1,4000,名詞,一般,*,*,*,*,*,きしゃ,きしゃ",
+        ]
+    );
+    assert_eq!((report.entries, report.rejected), (3, 1));
+    let unk_def = String::from_utf8(out.unk_def).unwrap();
+    assert!(unk_def.starts_with("DEFAULT,1,1,4000,名詞,一般,"), "{}", unk_def);
+    assert!(unk_def.contains("\nKATAKANA,1,1,4000,名詞,一般,"), "{}", unk_def);
+}
@@ -1,4 +1,5 @@
 use fst::{IntoStreamer, Map, Streamer};
+use keyboard_formats::ngram_key::KeySchema;
 use memmap2::Mmap;
 use std::fs::File;
 
@@ -170,3 +171,22 @@ fn test_predictive_search() {
     
     assert!(found_prediction, "Should find predictive candidates for '{}'", prefix);
 }
+
+#[test]
+fn test_all_keys_match_schema() {
+    let file = File::open(get_fst_path())
+        .expect("Failed to open FST file. Set WIKI_NGRAM_FST_PATH env var or run 'cargo run -p wiki-ngram --release' first.");
+    let mmap = unsafe { Mmap::map(&file) }
+        .expect("Failed to mmap FST file");
+    let fst = Map::new(mmap)
+        .expect("Failed to load FST");
+
+    // Bigrams and trigrams with the default --max-ngram
+    let schema = KeySchema::new(2, 3);
+    let mut stream = fst.stream();
+    while let Some((key, _)) = stream.next() {
+        if let Err(e) = schema.validate(key) {
+            panic!("{:#}", e);
+        }
+    }
+}
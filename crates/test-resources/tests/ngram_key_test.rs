@@ -0,0 +1,69 @@
+use keyboard_formats::ngram_key::{is_valid_token, KeySchema, SEPARATOR};
+use proptest::prelude::*;
+
+const TRIGRAMS: KeySchema = KeySchema::new(2, 3);
+
+/// Tokens as the tokenizer may produce them, including whitespace and control characters.
+fn any_token() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "[ぁ-んァ-ン一-龯a-z0-9/,]{1,4}",
+        Just(" ".to_string()),
+        Just("\t".to_string()),
+        Just(String::new()),
+        "\\PC{0,3}",
+    ]
+}
+
+/// Joins tokens the way the generator does: only runs of valid tokens form n-grams.
+fn keys_from_tokens(tokens: &[String], max_order: usize) -> Vec<String> {
+    let mut keys = Vec::new();
+    for run in tokens.split(|token| !is_valid_token(token)) {
+        for n in 2..=max_order {
+            for window in run.windows(n) {
+                keys.push(window.join(&SEPARATOR.to_string()));
+            }
+        }
+    }
+    keys
+}
+
+proptest! {
+    #[test]
+    fn prop_generated_keys_conform(tokens in prop::collection::vec(any_token(), 0..12)) {
+        for key in keys_from_tokens(&tokens, 3) {
+            let order = TRIGRAMS.validate(key.as_bytes());
+            prop_assert!(order.is_ok(), "{:?}: {:?}", key, order);
+            prop_assert_eq!(order.unwrap() - 1, key.matches(SEPARATOR).count());
+        }
+    }
+
+    #[test]
+    fn prop_empty_token_rejected(tokens in prop::collection::vec("[a-z]{1,3}", 1..3), at in 0usize..4) {
+        let mut tokens = tokens;
+        tokens.insert(at.min(tokens.len()), String::new());
+        let key = tokens.join(" ");
+        prop_assert!(TRIGRAMS.validate(key.as_bytes()).is_err(), "{:?}", key);
+    }
+
+    #[test]
+    fn prop_invalid_utf8_rejected(prefix in "[a-z]{1,3}", byte in 0x80u8..=0xff) {
+        let mut key = format!("{} ", prefix).into_bytes();
+        key.push(byte);
+        prop_assert!(TRIGRAMS.validate(&key).is_err());
+    }
+}
+
+#[test]
+fn test_order_bounds() {
+    assert!(TRIGRAMS.validate("東京".as_bytes()).is_err());
+    assert_eq!(TRIGRAMS.validate("東京 都".as_bytes()).unwrap(), 2);
+    assert_eq!(TRIGRAMS.validate("東京/とうきょう 都/と に".as_bytes()).unwrap(), 3);
+    assert!(TRIGRAMS.validate("東京 都 の 区".as_bytes()).is_err());
+}
+
+#[test]
+fn test_separators_and_control_characters() {
+    for key in [" 東京 都", "東京 都 ", "東京  都", "東京\t都 の", "東京 都\n"] {
+        assert!(TRIGRAMS.validate(key.as_bytes()).is_err(), "{:?}", key);
+    }
+}
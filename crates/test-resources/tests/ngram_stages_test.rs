@@ -0,0 +1,640 @@
+use anyhow::Result;
+use keyboard_formats::ngram_key::KeySchema;
+use keyboard_formats::text_length::LengthUnit;
+use mozc_dict_gen::{write_dictionary_as, Compression, DictPipeline, DictSource, Input, MozcSources};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use wiki_ngram::aggregate::{self, AggregateArgs};
+use wiki_ngram::boilerplate::{self, BoilerplateClassifier};
+use wiki_ngram::consumer::{self, BuildOptions, NgramConsumer, SentenceConsumer, SentenceStartConsumer, VocabConsumer};
+use wiki_ngram::cooccurrence::CooccurrenceConsumer;
+use wiki_ngram::extract::SentenceLimits;
+use wiki_ngram::histogram::CountHistogram;
+use wiki_ngram::interpolate;
+use wiki_ngram::ngram::{self, FilterConfig, FrequencyComparison, NgramCounts, RareDiscount, Smoothing};
+use wiki_ngram::prune::PrunePolicy;
+use wiki_ngram::prune_model;
+use wiki_ngram::quality::{CorpusStats, QualityGates};
+use wiki_ngram::shard;
+use wiki_ngram::stage::{CountSource, FrequencyFilter, FstSink, ModelSink, NgramFilter, TokenNgrams};
+use wiki_ngram::tokenize::{self, Token, TokenFields, TokenOutput};
+
+fn tokens(surfaces: &[&str]) -> Vec<Token> {
+    surfaces
+        .iter()
+        .map(|surface| Token {
+            surface: surface.to_string(),
+            reading: None,
+            pos: None,
+            lemma: None,
+            unknown: false,
+        })
+        .collect()
+}
+
+fn options(min_frequency: u32) -> BuildOptions {
+    BuildOptions {
+        filter_config: FilterConfig {
+            min_frequency,
+            comparison: FrequencyComparison::Ge,
+            per_order_min_frequency: HashMap::new(),
+            drop_mass_fraction: None,
+            rare_discount: None,
+        },
+        smoothing: Smoothing::LogCount,
+        histogram_max_count: 5,
+        quality_gates: QualityGates {
+            strict: true,
+            max_oov_rate: 1.0,
+            max_markup_ratio: 1.0,
+            min_ngram_count: 0,
+        },
+    }
+}
+
+#[test]
+fn test_default_stages_in_isolation() {
+    let mut source = TokenNgrams {
+        token_output: TokenOutput::Surface,
+        max_ngram: 3,
+    };
+    let mut counts = NgramCounts::default();
+    source.count(&tokens(&["今日", "は", "晴れ"]), 1, &mut counts);
+    source.count(&tokens(&["今日", "は"]), 2, &mut counts);
+    assert_eq!(counts.get("今日 は"), Some(&3));
+    assert_eq!(counts.get("今日 は 晴れ"), Some(&1));
+
+    let filter: FrequencyFilter = options(2).frequency_filter();
+    let kept = filter.filter(&counts, &CountHistogram::from_counts(&counts));
+    let keys: Vec<&str> = kept.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, ["今日 は"]);
+}
+
+#[test]
+fn test_barely_kept_long_ngrams_are_discounted() {
+    let mut counts = NgramCounts::default();
+    for (key, count) in [("東京 に", 3), ("東京 に 行く", 4), ("今日 は 晴れ", 9)] {
+        counts.insert(key.into(), count);
+    }
+    let histogram = CountHistogram::from_counts(&counts);
+    let scores = |discount| -> HashMap<String, u64> {
+        let mut filter = options(2).frequency_filter();
+        filter.config.rare_discount = discount;
+        filter.filter(&counts, &histogram).into_iter().collect()
+    };
+
+    let plain = scores(None);
+    assert!(plain["東京 に 行く"] > plain["東京 に"]);
+    let discounted = scores(Some(RareDiscount {
+        min_order: RareDiscount::MIN_ORDER,
+        margin: 2.0,
+        factor: 0.5,
+    }));
+    assert!(discounted["東京 に 行く"] < discounted["東京 に"], "{:?}", discounted);
+    assert_eq!(discounted["東京 に"], plain["東京 に"], "Bigrams are not discounted");
+    assert_eq!(discounted["今日 は 晴れ"], plain["今日 は 晴れ"], "Well above the threshold");
+}
+
+/// Keeps only n-grams ending with は, scored by their raw count.
+struct EndsWithWa;
+
+impl NgramFilter for EndsWithWa {
+    fn filter(&self, counts: &NgramCounts, _histogram: &CountHistogram) -> Vec<(String, u64)> {
+        let mut kept: Vec<(String, u64)> =
+            counts.iter().filter(|(key, _)| key.ends_with(" は")).map(|(key, &count)| (key.to_string(), count as u64)).collect();
+        kept.sort();
+        kept
+    }
+}
+
+struct Collect(Arc<Mutex<Vec<(String, u64)>>>);
+
+impl ModelSink for Collect {
+    fn write(&mut self, entries: &[(String, u64)]) -> Result<()> {
+        self.0.lock().unwrap().extend_from_slice(entries);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_ngram_consumer_with_plugged_in_stages() {
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let consumer = NgramConsumer::new("plugged", TokenOutput::Surface, 2, "unused.fst".into(), None)
+        .with_filter(Box::new(EndsWithWa))
+        .with_sink(Box::new(Collect(written.clone())));
+    let mut consumer: Box<dyn SentenceConsumer> = Box::new(consumer);
+    consumer.consume(&tokens(&["今日", "は", "晴れ"]), 1);
+    consumer.consume(&tokens(&["明日", "は", "雨"]), 4);
+    assert_eq!(consumer.len(), 4);
+    consumer.finish(&options(100)).unwrap();
+
+    assert_eq!(*written.lock().unwrap(), [("今日 は".to_string(), 1), ("明日 は".to_string(), 4)]);
+}
+
+#[test]
+fn test_model_is_identical_at_any_thread_count() {
+    const WORDS: [&str; 7] = ["今日", "は", "晴れ", "明日", "雨", "東京", "で"];
+    let sentences: Vec<(Vec<Token>, u32)> = (0..200usize)
+        .map(|i| {
+            let surfaces: Vec<&str> = (0..3 + i % 4).map(|j| WORDS[(i * 7 + j * 3) % WORDS.len()]).collect();
+            (tokens(&surfaces), 1 + (i % 3) as u32)
+        })
+        .collect();
+    let dir = std::env::temp_dir().join(format!("ngram-shards-{}", std::process::id()));
+
+    let build = |threads: usize| -> Vec<u8> {
+        let shard_dir = dir.join(format!("shards-{}", threads));
+        let counts = shard::count_parallel(&sentences, threads, &shard_dir, || TokenNgrams {
+            token_output: TokenOutput::Surface,
+            max_ngram: 3,
+        })
+        .unwrap();
+        assert_eq!(std::fs::read_dir(&shard_dir).unwrap().count(), 0, "Shard files are removed once merged");
+
+        let entries = options(2).frequency_filter().filter(&counts, &CountHistogram::from_counts(&counts));
+        let path = dir.join(format!("model-{}.fst", threads));
+        let mut sink = FstSink {
+            schema: KeySchema::new(2, 3),
+            path: path.clone(),
+        };
+        sink.write(&entries).unwrap();
+        std::fs::read(path).unwrap()
+    };
+
+    let single = build(1);
+    assert!(single.len() > 100);
+    assert_eq!(build(4), single);
+    assert_eq!(build(16), single);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_single_quoted_ngrams_are_dropped() {
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let consumer = NgramConsumer::new("quotes", TokenOutput::Surface, 3, "unused.fst".into(), None)
+        .with_quote_filter(3)
+        .with_sink(Box::new(Collect(written.clone())));
+    let mut consumer: Box<dyn SentenceConsumer> = Box::new(consumer);
+    consumer.consume(&tokens(&["彼", "は", "「", "春", "が", "来た", "」", "と", "言う"]), 1);
+    consumer.consume(&tokens(&["「", "雨", "が", "降る", "」"]), 1);
+    consumer.consume(&tokens(&["明日", "雨", "が", "降る"]), 1);
+    consumer.finish(&options(0)).unwrap();
+
+    let written = written.lock().unwrap();
+    let keys: Vec<&str> = written.iter().map(|(key, _)| key.as_str()).collect();
+    assert!(!keys.contains(&"春 が 来た"), "Quoted once: {:?}", keys);
+    assert!(keys.contains(&"雨 が 降る"), "Also said outside the quote");
+    assert!(keys.contains(&"春 が"), "Bigrams are below the filtered order");
+    assert!(keys.contains(&"は 「 春"), "Spans the quote boundary");
+}
+
+#[test]
+fn test_boilerplate_classifier_trains_from_the_samples() {
+    let file = std::fs::File::open("tests/fixtures/boilerplate_samples.tsv").unwrap();
+    let samples = boilerplate::read_samples(std::io::BufReader::new(file)).unwrap();
+    let (classifier, held_out) = BoilerplateClassifier::train_held_out(&samples);
+    assert!(held_out.precision() >= 0.8 && held_out.recall() >= 0.8, "{:?}", held_out);
+
+    let builtin = BoilerplateClassifier::default().evaluate(&samples);
+    assert!(builtin.precision() >= 0.9 && builtin.recall() >= 0.9, "Built-in weights: {:?}", builtin);
+    assert_eq!(BoilerplateClassifier::train(&samples), BoilerplateClassifier::train(&samples), "Training is deterministic");
+    assert!(!classifier.is_boilerplate("駅の周辺には新しい住宅地が広がっている"));
+    assert!(classifier.is_boilerplate("| 2 || 1998 || 30 || 7"));
+}
+
+#[test]
+fn test_sentence_limits_and_length_distribution() {
+    let limits = SentenceLimits {
+        min_length: 3,
+        unit: LengthUnit::Chars,
+        max_tokens: 4,
+    };
+    // Six bytes, but two characters
+    assert!(limits.too_short("東京"));
+    assert!(!limits.too_short("東京都"));
+    assert!(!SentenceLimits { unit: LengthUnit::Bytes, ..limits }.too_short("東京"));
+    assert!(limits.too_long(&tokens(&["a", "b", "c", "d", "e"])));
+    assert!(!limits.too_long(&tokens(&["a", "b", "c", "d"])));
+
+    let mut stats = CorpusStats::default();
+    for sentence in ["東京都", "今日は晴れ", "明日は雨が降る", "あさっては雪が降るでしょう"] {
+        stats.record(sentence, &tokens(&["x", "y"]));
+    }
+    assert_eq!(stats.sentence_length.total(), 4);
+    assert_eq!(stats.sentence_length.percentile(0.5), 5);
+    assert_eq!(stats.sentence_length.percentile(0.99), 13);
+    assert_eq!(stats.sentence_length.max(), 13);
+    assert_eq!(stats.sentence_tokens.summary(), "p10 2, p50 2, p90 2, p99 2, max 2");
+}
+
+#[test]
+fn test_quality_gates_check_oov_markup_and_ngram_count() {
+    let mut stats = CorpusStats::default();
+    let mut unknown = tokens(&["東京", "ほげ"]);
+    unknown[1].unknown = true;
+    stats.record("東京ほげ", &unknown);
+    stats.record("[[東京]]", &tokens(&["東京"]));
+    stats.record("東京", &tokens(&["東京"]));
+    stats.record("東京", &tokens(&["東京"]));
+    assert_eq!(stats.oov_rate(), 0.2);
+    assert_eq!(stats.markup_ratio(), 0.25);
+
+    let gates = QualityGates {
+        strict: true,
+        max_oov_rate: 0.2,
+        max_markup_ratio: 0.25,
+        min_ngram_count: 5,
+    };
+    assert!(gates.check_corpus(&stats).is_ok(), "Rates at the limits pass");
+    let oov = QualityGates { max_oov_rate: 0.1, ..gates.clone() };
+    assert!(oov.check_corpus(&stats).is_err());
+    let markup = QualityGates { max_markup_ratio: 0.1, ..gates.clone() };
+    assert!(markup.check_corpus(&stats).is_err());
+    assert!(QualityGates { strict: false, ..markup }.check_corpus(&stats).is_ok(), "Only a warning without --strict");
+
+    // Occurrences, not distinct N-grams: two N-grams seen 2 and 3 times
+    let mut counts = NgramCounts::default();
+    counts.insert("東京".into(), 2);
+    counts.insert("大阪".into(), 3);
+    assert!(gates.check_ngram_count("surface", &counts).is_ok());
+    let higher = QualityGates { min_ngram_count: 6, ..gates };
+    assert!(higher.check_ngram_count("surface", &counts).is_err());
+}
+
+#[test]
+fn test_failed_strict_gate_writes_no_output() {
+    let dir = std::env::temp_dir().join(format!("strict-gates-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let paths = ["ngrams.fst", "vocab.fst", "sentence-start.fst"].map(|name| dir.join(name));
+    let consumers = || -> Vec<Box<dyn SentenceConsumer>> {
+        let mut consumers: Vec<Box<dyn SentenceConsumer>> = vec![
+            Box::new(NgramConsumer::new("surface", TokenOutput::Surface, 3, paths[0].clone(), None)),
+            Box::new(VocabConsumer::new(paths[1].clone())),
+            Box::new(SentenceStartConsumer::new(10, paths[2].clone())),
+        ];
+        for consumer in &mut consumers {
+            consumer.consume(&tokens(&["東京", "に", "行く", "よ"]), 1);
+        }
+        consumers
+    };
+    // 5 N-grams, 4 words and 2 openers: only the last consumer is short of 3
+    let mut options = options(0);
+    options.quality_gates.min_ngram_count = 3;
+    assert!(consumer::finish_all(consumers(), &options).is_err());
+    let written = || paths.iter().filter(|path| path.exists()).count();
+    assert_eq!(written(), 0, "Nothing is written before the last gate passes");
+
+    options.quality_gates.strict = false;
+    consumer::finish_all(consumers(), &options).unwrap();
+    assert_eq!(written(), 3, "Only warnings without --strict");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_entropy_pruning_removes_what_backoff_predicts_first() {
+    // 東京 に 行く says about what に 行く does; 東京 に 住む is much more
+    // likely after 東京 than after に alone
+    let mut entries: Vec<(String, u64)> = [
+        ("に 住む", 10.0),
+        ("に 行く", 300.0),
+        ("大阪 に", 100.0),
+        ("東京 に", 400.0),
+        ("東京 に 住む", 90.0),
+        ("東京 に 行く", 300.0),
+    ]
+    .into_iter()
+    .map(|(key, count)| (key.to_string(), ngram::encode_score(count)))
+    .collect();
+    entries.sort();
+    let entropies = prune_model::relative_entropies(&entries);
+    assert!(entropies.iter().all(|entropy| entropy.is_finite() && *entropy >= 0.0), "{:?}", entropies);
+
+    let order: Vec<&str> = prune_model::pruning_order(&entries, &entropies)
+        .into_iter()
+        .map(|i| entries[i].0.as_str())
+        .collect();
+    let position = |key: &str| order.iter().position(|&k| k == key).unwrap();
+    assert!(position("東京 に 行く") < position("東京 に 住む"), "{:?}", order);
+    assert!(position("東京 に") > position("東京 に 住む"), "A history goes after the N-grams using it");
+}
+
+#[test]
+fn test_interpolation_mixes_relative_frequencies() {
+    let entries = |pairs: &[(&str, f64)]| -> Vec<(String, u64)> {
+        let mut entries: Vec<(String, u64)> =
+            pairs.iter().map(|&(key, count)| (key.to_string(), ngram::encode_score(count))).collect();
+        entries.sort();
+        entries
+    };
+    // The same share of each corpus, at ten times the size
+    let wiki = entries(&[("今日 は", 100.0), ("東京 に", 300.0)]);
+    let chat = entries(&[("今日 は", 1000.0), ("了解 です", 3000.0)]);
+    let mixed = interpolate::interpolate(&wiki, &chat, 0.5);
+    let keys: Vec<&str> = mixed.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, ["了解 です", "今日 は", "東京 に"]);
+    let count = |key: &str| (mixed.iter().find(|(k, _)| k == key).unwrap().1 as f64 / 1000.0).exp();
+    assert!((count("今日 は") / 2200.0 - 0.25).abs() < 0.01, "A quarter of both, of the mixed size");
+    assert!((count("了解 です") / count("東京 に") - 1.0).abs() < 0.01, "Three quarters of one each");
+    assert_eq!(interpolate::interpolate(&wiki, &chat, 1.0).len(), 2, "Nothing only the second has");
+
+    // The first model explains three of four tokens much better
+    let probabilities = [(0.2, 0.01), (0.2, 0.01), (0.2, 0.01), (0.001, 0.1)];
+    let weight = interpolate::tune_weight(&probabilities);
+    assert!(weight > 0.5 && weight < 1.0, "{}", weight);
+    let best = interpolate::perplexity(&probabilities, weight);
+    assert!(best < interpolate::perplexity(&probabilities, 0.5) && best < interpolate::perplexity(&probabilities, 0.9));
+}
+
+#[test]
+fn test_cooccurrence_matrix_counts_pairs_within_the_window() {
+    let output = std::env::temp_dir().join(format!("cooccurrence-{}.mtx", std::process::id()));
+    let mut consumer: Box<dyn SentenceConsumer> = Box::new(CooccurrenceConsumer::new(2, 2, output.clone()));
+    consumer.consume(&tokens(&["東京", "に", "行く"]), 1);
+    consumer.consume(&tokens(&["東京", "に", "住む", "人"]), 1);
+    consumer.consume(&tokens(&["人", "に", "会う"]), 1);
+    consumer.finish(&options(0)).unwrap();
+
+    let vocab = std::fs::read_to_string(CooccurrenceConsumer::vocab_path(&output)).unwrap();
+    assert_eq!(vocab, "id\tword\tcount\n1\tに\t3\n2\t人\t2\n3\t東京\t2\n");
+    let matrix = std::fs::read_to_string(&output).unwrap();
+    let lines: Vec<&str> = matrix.lines().filter(|line| !line.starts_with('%')).collect();
+    // に next to 東京 and 人 twice each; pairs seen once, such as 東京/住む, are dropped
+    assert_eq!(lines, ["3 3 2", "2 1 2", "3 1 2"]);
+    assert!(matrix.starts_with("%%MatrixMarket matrix coordinate integer symmetric\n"));
+    std::fs::remove_file(CooccurrenceConsumer::vocab_path(&output)).unwrap();
+    std::fs::remove_file(output).unwrap();
+}
+
+#[test]
+fn test_curated_openers_are_keyed_by_their_tokens() {
+    let output = std::env::temp_dir().join(format!("sentence-start-{}.fst", std::process::id()));
+    // Splits the polite openers as IPADIC does and keeps the rest whole
+    let tokenize = |text: &str| match text.strip_suffix("ございます") {
+        Some(stem) => tokens(&[stem, "ござい", "ます"]),
+        None => tokens(&[text]),
+    };
+    let mut consumer: Box<dyn SentenceConsumer> =
+        Box::new(SentenceStartConsumer::new(10, output.clone()).with_curated_openers(tokenize));
+    for _ in 0..5 {
+        consumer.consume(&tokens(&["東京", "は"]), 1);
+    }
+    consumer.consume(&tokens(&["おはよう", "ござい", "ます"]), 1);
+    consumer.finish(&options(0)).unwrap();
+
+    let openers = fst::Map::new(std::fs::read(&output).unwrap()).unwrap();
+    let top = openers.get("東京").unwrap();
+    assert_eq!(openers.get("おはよう ござい"), Some(top), "The counted opener is raised, not duplicated");
+    assert_eq!(openers.get("ありがとう ござい"), Some(top));
+    assert_eq!(openers.get("こんにちは"), Some(top));
+    assert_eq!(openers.get("おはようございます"), None, "No unsplit key the corpus can't produce");
+    std::fs::remove_file(output).unwrap();
+}
+
+fn add_counts(counts: &mut NgramCounts, prefix: &str, entries: usize, count: u32) {
+    for i in 0..entries {
+        counts.insert(format!("{}{}", prefix, i).into(), count);
+    }
+}
+
+#[test]
+fn test_prune_policy_escalates_on_entries_removed_not_rss() {
+    // RSS stays at twice the high watermark, as freed keys aren't returned to the OS
+    let mut policy = PrunePolicy::new(1024, 512).with_memory_probe(|| Some(2048 << 20));
+    let mut counts = NgramCounts::default();
+    add_counts(&mut counts, "once", 600, 1);
+    add_counts(&mut counts, "twice", 300, 2);
+    add_counts(&mut counts, "often", 100, 50);
+
+    // Budget 500 entries, pruned to 250: singletons alone are too few
+    assert!(policy.maybe_prune(&mut counts));
+    assert_eq!(counts.len(), 100);
+    assert_eq!(policy.threshold(), 1, "Decayed after reaching the target");
+
+    assert!(!policy.maybe_prune(&mut counts), "Within budget however high RSS stays");
+    add_counts(&mut counts, "new", 450, 1);
+    assert!(policy.maybe_prune(&mut counts));
+    assert_eq!(counts.len(), 100);
+    assert!(counts.values().all(|&count| count == 50));
+    assert_eq!(policy.threshold(), 1);
+}
+
+#[test]
+fn test_prune_policy_waits_for_the_high_watermark() {
+    let mut policy = PrunePolicy::new(1024, 512).with_memory_probe(|| Some(512 << 20));
+    let mut counts = NgramCounts::default();
+    add_counts(&mut counts, "once", 1000, 1);
+    assert!(!policy.maybe_prune(&mut counts));
+
+    let mut unreadable = PrunePolicy::new(1024, 512).with_memory_probe(|| None);
+    assert!(!unreadable.maybe_prune(&mut counts), "Far below the fallback entry count");
+    assert_eq!(counts.len(), 1000);
+}
+
+/// A consumer holding preset counts.
+struct PresetCounts(NgramCounts);
+
+impl SentenceConsumer for PresetCounts {
+    fn name(&self) -> &str {
+        "preset"
+    }
+
+    fn fields(&self) -> TokenFields {
+        TokenFields::default()
+    }
+
+    fn consume(&mut self, _tokens: &[Token], _weight: u32) {}
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn prune(&mut self, threshold: u32) -> usize {
+        ngram::prune_ngrams(&mut self.0, threshold)
+    }
+
+    fn finish(self: Box<Self>, _options: &BuildOptions) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_consumers_are_pruned_together_at_one_threshold() {
+    let mut first = NgramCounts::default();
+    add_counts(&mut first, "often", 10, 100);
+    add_counts(&mut first, "once", 100, 1);
+    let mut second = NgramCounts::default();
+    add_counts(&mut second, "once", 1000, 1);
+    let mut consumers: Vec<Box<dyn SentenceConsumer>> =
+        vec![Box::new(PresetCounts(first)), Box::new(PresetCounts(second))];
+
+    // The first consumer alone can't bring the total down; singletons of both can
+    let mut policy = PrunePolicy::new(1024, 512).with_memory_probe(|| Some(2048 << 20));
+    assert!(policy.maybe_prune_consumers(&mut consumers));
+    assert_eq!(consumers[0].len(), 10, "The frequent n-grams are kept");
+    assert_eq!(consumers[1].len(), 0);
+    assert_eq!(policy.threshold(), 1);
+}
+
+fn aggregate_args(min_users: u64, epsilon: Option<f64>) -> AggregateArgs {
+    AggregateArgs {
+        input_dir: "users".into(),
+        output: "user-overlay.fst".into(),
+        min_users,
+        max_count_per_user: 2,
+        max_ngrams_per_user: 2,
+        epsilon,
+        delta: 1e-6,
+        seed: Some(7),
+    }
+}
+
+fn user(counts: &[(&str, u32)]) -> Vec<(String, u32)> {
+    counts.iter().map(|&(ngram, count)| (ngram.to_string(), count)).collect()
+}
+
+#[test]
+fn test_aggregation_caps_users_and_applies_the_user_threshold() {
+    let users = || {
+        vec![
+            user(&[("東京 に", 50), ("大阪 に", 3), ("京都 に", 1)]),
+            user(&[("東京 に", 1), ("大阪 に", 1)]),
+            user(&[("東京 に", 1), ("京都 に", 1)]),
+            user(&[("東京 に", 2), ("京都 に", 9), ("大阪 に", 1)]),
+        ]
+    };
+    let overlay = aggregate::aggregate(users(), &aggregate_args(3, None));
+    // Each user keeps their two most frequent N-grams: 東京 に has four users
+    // and, with 50 capped to 2, a count of 2 + 1 + 1 + 2; 大阪 に and 京都 に
+    // have two users each.
+    assert_eq!(
+        overlay,
+        [(String::from("東京 に"), ngram::encode_score(6.0))],
+        "Contributions past a user's first two N-grams don't count"
+    );
+    assert_eq!(aggregate::aggregate(users(), &aggregate_args(2, None)).len(), 3);
+}
+
+#[test]
+fn test_noise_keeps_the_threshold_and_spends_epsilon_once() {
+    let users: Vec<_> = (0..5).map(|_| user(&[("東京 に", 1)])).collect();
+    // Noise shrinks as the budget grows, so a large one keeps the exact result
+    let overlay = aggregate::aggregate(users.clone(), &aggregate_args(4, Some(1e9)));
+    assert_eq!(overlay, aggregate::aggregate(users.clone(), &aggregate_args(4, None)));
+    assert!(aggregate::aggregate(users.clone(), &aggregate_args(6, Some(1e9))).is_empty());
+    let seeded = aggregate::aggregate(users.clone(), &aggregate_args(0, Some(0.1)));
+    assert_eq!(seeded, aggregate::aggregate(users, &aggregate_args(0, Some(0.1))), "A seed fixes the noise");
+
+    let mut rng = StdRng::seed_from_u64(1);
+    let samples: Vec<f64> = (0..100_000).map(|_| aggregate::laplace(&mut rng, 2.0)).collect();
+    assert!(samples.iter().all(|sample| sample.is_finite()));
+    // Laplace(0, b) has mean absolute deviation b
+    let deviation = samples.iter().map(|sample| sample.abs()).sum::<f64>() / samples.len() as f64;
+    assert!((deviation - 2.0).abs() < 0.05, "{}", deviation);
+}
+
+#[test]
+fn test_noise_suppresses_ngrams_of_a_single_user() {
+    // 1000 users with an N-gram of their own, and 100 who share one
+    let mut users: Vec<_> = (0..1000).map(|i| user(&[(&format!("東京 {}", i), 1)])).collect();
+    users.extend((0..100).map(|_| user(&[("東京 に", 1)])));
+    // Noise of scale 2 / (1 / 2) = 4 lifts the threshold of 1 to 1 + 4 ln(1e6)
+    let overlay = aggregate::aggregate(users.clone(), &aggregate_args(1, Some(1.0)));
+    assert_eq!(overlay.iter().map(|(ngram, _)| ngram.as_str()).collect::<Vec<_>>(), ["東京 に"]);
+    assert_eq!(aggregate::aggregate(users, &aggregate_args(1, None)).len(), 1001, "Without noise, one user is enough");
+}
+
+#[test]
+fn test_good_turing_discounts_rare_counts_and_falls_back() {
+    let mut counts = NgramCounts::default();
+    // N_1 = 4, N_2 = 2, N_3 = 1 and no bigram seen 4 times
+    for (key, count) in [("a b", 1), ("a c", 1), ("a d", 1), ("a e", 1), ("b c", 2), ("b d", 2), ("c d", 3)] {
+        counts.insert(key.into(), count);
+    }
+    let histogram = CountHistogram::from_counts(&counts);
+    let adjusted = |count| Smoothing::GoodTuring.adjusted_count(2, count, &histogram);
+
+    assert_eq!(adjusted(1), 1.0, "2 * N_2 / N_1");
+    assert_eq!(adjusted(2), 1.5, "3 * N_3 / N_2");
+    // The mass of each count class moves down one class: N_c * c* = (c + 1) * N_{c+1}
+    assert_eq!(4.0 * adjusted(1), 2.0 * 2.0);
+    assert_eq!(2.0 * adjusted(2), 3.0 * 1.0);
+    assert_eq!(adjusted(3), 3.0, "N_4 = 0 keeps the raw count");
+    assert_eq!(Smoothing::GoodTuring.adjusted_count(3, 1, &histogram), 1.0, "No trigrams counted");
+    assert_eq!(adjusted(7), 7.0, "Large counts are trusted");
+    assert_eq!(Smoothing::LogCount.adjusted_count(2, 2, &histogram), 2.0);
+
+    let mut filter = options(0).frequency_filter();
+    filter.smoothing = Smoothing::GoodTuring;
+    let scores: HashMap<String, u64> = filter.filter(&counts, &histogram).into_iter().collect();
+    assert_eq!(scores["b c"], ngram::encode_score(1.5));
+    assert_eq!(scores["c d"], ngram::encode_score(3.0));
+}
+
+#[test]
+fn test_filter_thresholds() {
+    let mut counts = NgramCounts::default();
+    for (key, count) in [("a b", 2), ("a c", 3), ("a d", 95), ("a b c", 2), ("a b d", 5)] {
+        counts.insert(key.into(), count);
+    }
+    let histogram = CountHistogram::from_counts(&counts);
+    let kept = |config: FilterConfig| -> Vec<String> {
+        let mut filter = options(0).frequency_filter();
+        filter.config = config;
+        filter.filter(&counts, &histogram).into_iter().map(|(key, _)| key).collect()
+    };
+    let config = |comparison| FilterConfig {
+        comparison,
+        ..options(3).filter_config
+    };
+
+    assert_eq!(kept(config(FrequencyComparison::Ge)), ["a b d", "a c", "a d"], "Counts equal to the threshold stay");
+    assert_eq!(kept(config(FrequencyComparison::Gt)), ["a b d", "a d"]);
+    assert_eq!(
+        kept(FilterConfig {
+            per_order_min_frequency: HashMap::from([(3, 1)]),
+            ..config(FrequencyComparison::Ge)
+        }),
+        ["a b c", "a b d", "a c", "a d"],
+        "The trigram threshold overrides the global one"
+    );
+    // Bigram mass is 100: counts 2 and 3 make up 5% of it, 95 the rest
+    assert_eq!(
+        kept(FilterConfig {
+            drop_mass_fraction: Some(0.05),
+            ..options(0).filter_config
+        }),
+        ["a b c", "a b d", "a d"],
+        "No trigram count class fits in 5% of the trigram mass"
+    );
+}
+
+#[test]
+fn test_dictionary_loads_in_every_compression() {
+    let sources = MozcSources {
+        id_def: Input::bytes("id.def", "0 BOS/EOS,*,*,*,*,*,*\n1 名詞,一般,*,*,*,*,*\n"),
+        connection: Input::bytes("connection_single_column.txt", "2\n0\n0\n10\n20\n30\n"),
+        dictionaries: vec![Input::bytes("dictionary00.txt", "きしゃ\t1\t1\t4000\t記者\n")],
+        filters: Vec::new(),
+        single_kanji: None,
+        symbols: Vec::new(),
+    };
+    let (dict, _) = DictPipeline::new(sources).compile().unwrap();
+    for compression in [Compression::default(), Compression::Gzip, Compression::None] {
+        let dir = std::env::temp_dir().join(format!("wiki-ngram-dict-{}-{}", compression, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(compression.file_name("system.dic"));
+        write_dictionary_as(&dict, std::fs::File::create(&path).unwrap(), compression).unwrap();
+
+        assert_eq!(mozc_dict_gen::find_dictionary(&dir, "system.dic"), Some(path.clone()));
+        let tokenizer = tokenize::load_tokenizer(&path)
+            .unwrap_or_else(|e| panic!("{} dictionary should load: {:#}", compression, e));
+        let mut worker = tokenizer.new_worker();
+        worker.reset_sentence("記者");
+        worker.tokenize();
+        let surfaces: Vec<&str> = worker.token_iter().map(|token| token.surface()).collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(surfaces, ["記者"], "{}", compression);
+    }
+}
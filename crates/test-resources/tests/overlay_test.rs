@@ -0,0 +1,107 @@
+use mozc_dict_gen::import::{import_terms, SystemLexicon, TermFormat};
+use mozc_dict_gen::overlay::{read_terms, write_user_lexicon, TermKind};
+use mozc_dict_gen::{read_id_def, Input};
+
+const ID_DEF: &str = "1 名詞,一般,*,*,*,*,*\n2 名詞,固有名詞,一般,*,*,*,*\n3 名詞,固有名詞,組織,*,*,*,*\n";
+
+#[test]
+fn test_read_terms_validates_and_normalizes() {
+    let terms = "# tenant terms\n\
+                 ロコモ電機\tロコモデンキ\torganization\n\
+                 ロコモ電機\tろこもでんき\n\
+                 フォージ\tふぉーじ\tproduct\n\
+                 Forge\tforge\n\
+                 社内便\tしゃないびん\tnoun\t4000\n";
+    let (terms, report) = read_terms(&Input::bytes("terms.tsv", terms)).unwrap();
+
+    assert_eq!(terms.len(), 2);
+    assert_eq!(terms[0].reading, "ろこもでんき", "Katakana readings are converted to hiragana");
+    assert_eq!(terms[0].kind, TermKind::Organization);
+    assert_eq!(terms[1].cost(), 4000);
+
+    let reasons: Vec<&str> = report.rejections.iter().map(|r| r.reason.as_str()).collect();
+    assert_eq!(reasons, ["duplicate term", "unknown kind \"product\"", "reading \"forge\" is not kana"]);
+    assert_eq!(report.rejections[0].line_no, 3);
+}
+
+#[test]
+fn test_longer_terms_cost_less() {
+    let (terms, _) = read_terms(&Input::bytes("terms.tsv", "部\tぶ\n部長会議\tぶちょうかいぎ\n")).unwrap();
+    assert!(terms[1].cost() < terms[0].cost());
+}
+
+#[test]
+fn test_write_user_lexicon() {
+    let (terms, _) = read_terms(&Input::bytes("terms.tsv", "ロコモ電機\tろこもでんき\torganization\t3000\n")).unwrap();
+    let id_map = read_id_def(ID_DEF.as_bytes()).unwrap();
+    let mut out = Vec::new();
+    write_user_lexicon(&terms, &id_map, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "ロコモ電機,3,3,3000,名詞,固有名詞,組織,*,*,*,*,ろこもでんき,ろこもでんき\n"
+    );
+}
+
+#[test]
+fn test_import_excel_csv_with_header() {
+    // Excel's UTF-8 CSV: BOM, CRLF, header in any order, quoted fields
+    let csv = "\u{feff}種類,表記,読み\r\norganization,ロコモ電機,ろこもでんき\r\n,\"フォージ, Inc.\",ふぉーじ\r\n,,\r\n";
+    let (terms, report) = import_terms(&Input::bytes("terms.csv", csv), TermFormat::Csv, None).unwrap();
+    assert_eq!(terms.len(), 2);
+    assert_eq!(terms[0].kind, TermKind::Organization);
+    assert_eq!(terms[1].surface, "フォージ, Inc.");
+    assert!(report.rejections.is_empty(), "{:?}", report.rejections);
+
+    let sjis = encoding_rs::SHIFT_JIS.encode("社内便,しゃないびん,noun,4000\n").0.into_owned();
+    let (terms, _) = import_terms(&Input::bytes("terms.csv", sjis), TermFormat::Csv, None).unwrap();
+    assert_eq!(terms[0].surface, "社内便");
+
+    let bad_header = "surface,reading,owner\n";
+    assert!(import_terms(&Input::bytes("terms.csv", bad_header), TermFormat::Csv, None).is_err());
+}
+
+#[test]
+fn test_import_json() {
+    let json = r#"[{"surface": "社内便", "reading": "シャナイビン", "cost": 4000}, {"surface": "Forge"}, 3]"#;
+    let (terms, report) = import_terms(&Input::bytes("terms.json", json), TermFormat::Json, None).unwrap();
+    assert_eq!(terms.len(), 1);
+    assert_eq!(terms[0].reading, "しゃないびん");
+    assert_eq!(terms[0].cost(), 4000);
+
+    let reasons: Vec<&str> = report.rejections.iter().map(|r| r.reason.as_str()).collect();
+    assert_eq!(reasons, ["no reading, and none found in the system dictionary", "not a JSON object"]);
+    assert_eq!(report.rejections[1].line_no, 3);
+}
+
+struct System;
+
+impl SystemLexicon for System {
+    fn reading(&self, surface: &str) -> Option<String> {
+        match surface {
+            "本社" => Some("ほんしゃ".to_string()),
+            "東京本社" => Some("とうきょうほんしゃ".to_string()),
+            _ => None,
+        }
+    }
+
+    fn contains(&self, surface: &str, reading: &str) -> bool {
+        matches!((surface, reading), ("本社", "ほんしゃ") | ("本部", "ほんぶ"))
+    }
+}
+
+#[test]
+fn test_import_generates_readings_and_skips_known_terms() {
+    let terms = "東京本社\n本社\n本部\tほんぶ\t\t3000\nまるいち\n";
+    let (terms, report) = import_terms(&Input::bytes("terms.tsv", terms), TermFormat::Tsv, Some(&System)).unwrap();
+
+    let readings: Vec<&str> = terms.iter().map(|t| t.reading.as_str()).collect();
+    assert_eq!(readings, ["とうきょうほんしゃ", "ほんぶ", "まるいち"]);
+    assert_eq!(report.generated.len(), 1, "Given and kana readings are not generated");
+    assert_eq!(report.in_system.len(), 1, "Only the term without an explicit cost is skipped");
+    assert_eq!(report.in_system[0].0, 2);
+
+    let mut text = Vec::new();
+    report.write_text(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("row 1: 東京本社 → とうきょうほんしゃ"), "{}", text);
+}
@@ -0,0 +1,83 @@
+use keyboard_formats::places::{self, Name, Place, PlaceTable};
+
+fn name(surface: &str, reading: &str) -> Name {
+    Name {
+        surface: surface.to_string(),
+        reading: reading.to_string(),
+    }
+}
+
+fn table() -> PlaceTable {
+    let mut table = PlaceTable::new();
+    for (code, city, city_reading, town, town_reading) in [
+        ("1000001", "千代田区", "ちよだく", "千代田", "ちよだ"),
+        ("1000002", "千代田区", "ちよだく", "皇居外苑", "こうきょがいえん"),
+        ("1600022", "新宿区", "しんじゅくく", "新宿", "しんじゅく"),
+        ("1600000", "新宿区", "しんじゅくく", "", ""),
+    ] {
+        table.push(Place {
+            postal_code: code.to_string(),
+            prefecture: name("東京都", "とうきょうと"),
+            city: name(city, city_reading),
+            town: name(town, town_reading),
+        });
+    }
+    table.normalize();
+    table
+}
+
+#[test]
+fn test_hierarchy() {
+    let table = table();
+    assert_eq!(table.prefectures(), vec![&name("東京都", "とうきょうと")]);
+    let cities: Vec<&str> = table.cities("東京都").iter().map(|n| n.surface.as_str()).collect();
+    assert_eq!(cities, vec!["千代田区", "新宿区"]);
+    // The whole-city entry has no town
+    let towns: Vec<&str> = table.towns("東京都", "新宿区").iter().map(|n| n.surface.as_str()).collect();
+    assert_eq!(towns, vec!["新宿"]);
+}
+
+#[test]
+fn test_postal_code_lookup() {
+    let table = table();
+    let place = table.by_postal_code("1000002").next().unwrap();
+    assert_eq!(place.town.reading, "こうきょがいえん");
+}
+
+#[test]
+fn test_text_roundtrip() {
+    let table = table();
+    let mut buf = Vec::new();
+    table.write(&mut buf).unwrap();
+    assert_eq!(PlaceTable::read(&buf[..]).unwrap(), table);
+}
+
+#[test]
+fn test_ken_all_rows_are_joined_and_cleaned() {
+    let ken_all = [
+        r#"01101,"060  ","0600042","ﾎｯｶｲﾄﾞｳ","ｻｯﾎﾟﾛｼﾁﾕｳｵｳｸ","ｵｵﾄﾞｵﾘﾆｼ(1","北海道","札幌市中央区","大通西（１",0,0,1,0,0,0"#,
+        r#"01101,"060  ","0600042","ﾎｯｶｲﾄﾞｳ","ｻｯﾎﾟﾛｼﾁﾕｳｵｳｸ","-19","北海道","札幌市中央区","～１９",0,0,1,0,0,0"#,
+        r#"01101,"060  ","0600042","ﾎｯｶｲﾄﾞｳ","ｻｯﾎﾟﾛｼﾁﾕｳｵｳｸ","ﾁﾖｳﾒ)","北海道","札幌市中央区","丁目）",0,0,1,0,0,0"#,
+        r#"13361,"10001","1000101","ﾄｳｷﾖｳﾄ","ｵｵｼﾏﾏﾁ","ｵｵｼﾏﾏﾁｲﾁｴﾝ","東京都","大島町","大島町一円",0,0,0,0,0,0"#,
+        r#"25443,"52223","5220341","ｼｶﾞｹﾝ","ｲﾇｶﾐｸﾞﾝﾀｶﾞﾁﾖｳ","ｲﾁｴﾝ","滋賀県","犬上郡多賀町","一円",0,0,0,0,0,0"#,
+    ]
+    .join("\r\n");
+    let table = places::read_ken_all(&ken_all).unwrap();
+    let rows: Vec<(&str, &str, &str, &str)> = table
+        .iter()
+        .map(|p| (p.postal_code.as_str(), p.city.reading.as_str(), p.town.surface.as_str(), p.town.reading.as_str()))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            ("0600042", "さっぽろしちゆうおうく", "大通西", "おおどおりにし"),
+            ("1000101", "おおしままち", "", ""),
+            // A town named 一円 is a town, not a placeholder
+            ("5220341", "いぬかみぐんたがちよう", "一円", "いちえん"),
+        ]
+    );
+    assert_eq!(table.prefectures()[0], &name("北海道", "ほっかいどう"));
+
+    let short_row = "13101,\"100  \",\"1000001\",\"ﾄｳｷﾖｳﾄ\"";
+    assert!(places::read_ken_all(short_row).is_err());
+}
@@ -0,0 +1,103 @@
+//! Offensive-content regression suite: the shipped n-gram FST is queried
+//! after the sensitive contexts of fixtures/prediction_safety.txt, the
+//! predictions filtered by the shipped suppression list as keyboards filter
+//! them, and none of the top [`TOP_K`] may be a blocklisted word.
+
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, Streamer};
+use keyboard_formats::suppression::{self, SuppressionList, SUGGESTION_FILTERED};
+use memmap2::Mmap;
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Predictions a keyboard shows after a context.
+const TOP_K: usize = 10;
+
+fn get_fst_path() -> PathBuf {
+    std::env::var("WIKI_NGRAM_FST_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("../../output/wiki-ngrams.fst"))
+}
+
+fn get_suppression_path() -> PathBuf {
+    std::env::var("SUPPRESSION_FST_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("../../output/suppression.fst"))
+}
+
+fn open_map(path: &Path) -> Map<Mmap> {
+    let file = File::open(path).unwrap_or_else(|e| panic!("Failed to open {:?}: {}", path, e));
+    Map::new(unsafe { Mmap::map(&file) }.unwrap()).unwrap()
+}
+
+/// (contexts, blocklist) of the fixture.
+fn read_suite() -> (Vec<&'static str>, HashSet<String>) {
+    let mut contexts = Vec::new();
+    let mut blocklist = HashSet::new();
+    let mut section = "";
+    for line in include_str!("fixtures/prediction_safety.txt").lines() {
+        match line {
+            "" => {}
+            _ if line.starts_with('#') => {}
+            "[contexts]" | "[blocklist]" => section = line,
+            _ if section == "[contexts]" => contexts.push(line),
+            _ if section == "[blocklist]" => {
+                blocklist.insert(line.to_lowercase());
+            }
+            _ => panic!("Line outside a section: {}", line),
+        }
+    }
+    (contexts, blocklist)
+}
+
+/// The highest-scored next words after `context` that the suppression list
+/// allows as predictions, as the demo keyboard picks them.
+fn top_predictions<D: AsRef<[u8]>, S: AsRef<[u8]>>(
+    ngrams: &Map<D>,
+    suppression: &SuppressionList<S>,
+    context: &str,
+    k: usize,
+) -> Vec<String> {
+    let prefix = format!("{} ", context);
+    let mut stream = ngrams.search(Str::new(&prefix).starts_with()).into_stream();
+    let mut predictions = Vec::new();
+    while let Some((key, score)) = stream.next() {
+        let Ok(key) = std::str::from_utf8(key) else { continue };
+        let next = &key[prefix.len()..];
+        if !next.is_empty() && !next.contains(' ') && suppression.allows_prediction(next) {
+            predictions.push((score, next.to_string()));
+        }
+    }
+    predictions.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    predictions.into_iter().take(k).map(|(_, word)| word).collect()
+}
+
+#[test]
+fn test_suppressed_words_never_reach_the_top_predictions() {
+    let ngrams = Map::from_iter([("お前 は 天才", 900), ("お前 は 馬鹿", 1000), ("お前 は 馬鹿 だ", 800)]).unwrap();
+    let mut fst = Vec::new();
+    suppression::write_fst([("馬鹿".to_string(), SUGGESTION_FILTERED)], &mut fst).unwrap();
+    let list = SuppressionList::new(fst::Map::new(fst).unwrap());
+
+    assert_eq!(top_predictions(&ngrams, &list, "お前 は", TOP_K), ["天才"]);
+    let (contexts, blocklist) = read_suite();
+    assert!(!contexts.is_empty() && blocklist.contains("馬鹿"));
+}
+
+#[test]
+fn test_sensitive_contexts_predict_no_blocklisted_words() {
+    let ngrams = open_map(&get_fst_path());
+    let suppression = SuppressionList::new(open_map(&get_suppression_path()));
+    let (contexts, blocklist) = read_suite();
+
+    let mut leaks = Vec::new();
+    for context in contexts {
+        for word in top_predictions(&ngrams, &suppression, context, TOP_K) {
+            if blocklist.contains(&word.to_lowercase()) {
+                leaks.push(format!("{} → {}", context, word));
+            }
+        }
+    }
+    assert!(leaks.is_empty(), "Blocklisted words predicted in the top {}: {}", TOP_K, leaks.join(", "));
+}
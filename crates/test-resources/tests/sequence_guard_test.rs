@@ -0,0 +1,30 @@
+use fst::{Map, MapBuilder};
+use keyboard_formats::sequence_guard::SequenceGuard;
+
+fn ngrams(keys: &[&str]) -> Map<Vec<u8>> {
+    let mut keys = keys.to_vec();
+    keys.sort();
+    let mut builder = MapBuilder::memory();
+    for key in keys {
+        builder.insert(key, 1000).unwrap();
+    }
+    Map::new(builder.into_inner().unwrap()).unwrap()
+}
+
+#[test]
+fn test_chained_ngrams_are_cut_off_at_the_limit() {
+    // A passage stored as overlapping trigrams: 吾輩 は 猫 で ある 名前 は まだ 無い
+    let map = ngrams(&[
+        "吾輩 は", "は 猫", "猫 で", "で ある", "ある 名前", "名前 は",
+        "吾輩 は 猫", "は 猫 で", "猫 で ある", "で ある 名前", "ある 名前 は", "名前 は まだ",
+    ]);
+    let guard = SequenceGuard::new(3, 5);
+
+    assert_eq!(guard.reproduced_run(&map, &["吾輩", "は", "猫", "で"]), 4);
+    assert_eq!(guard.reproduced_run(&map, &["犬", "は", "猫", "で"]), 3, "犬 は is not stored");
+    assert_eq!(guard.reproduced_run(&map, &["猫", "が"]), 0);
+
+    assert!(guard.allows(&map, &["吾輩", "は", "猫", "で"], "ある"), "A run of 5 is within the limit");
+    assert!(!guard.allows(&map, &["吾輩", "は", "猫", "で", "ある"], "名前"), "A run of 6 is not");
+    assert!(guard.allows(&map, &["それ", "で", "ある"], "名前"));
+}
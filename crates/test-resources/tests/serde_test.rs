@@ -0,0 +1,55 @@
+use keyboard_formats::annotation::{Annotation, CandidateFlags};
+use keyboard_formats::completion::Completion;
+use keyboard_formats::symbols::{Symbol, SymbolKind};
+use keyboard_formats::text_length::LengthUnit;
+use keyboard_formats::user_history::Decay;
+use mozc_dict_gen::bundle::{Artifact, BundleManifest};
+use serde_json::json;
+
+/// Field names and variant spellings are a public contract; this pins them.
+#[test]
+fn test_runtime_types_serialize_with_stable_names() {
+    let completion = Completion {
+        reading: "てんき".to_string(),
+        start: 6,
+        score: 7.5,
+    };
+    assert_eq!(serde_json::to_value(&completion).unwrap(), json!({ "reading": "てんき", "start": 6, "score": 7.5 }));
+
+    let mut flags = CandidateFlags::EMOJI;
+    flags.insert(CandidateFlags::SLANG);
+    let annotation = Annotation {
+        text: Some("顔文字".to_string()),
+        flags,
+    };
+    let value = serde_json::to_value(&annotation).unwrap();
+    assert_eq!(value, json!({ "text": "顔文字", "flags": ["emoji", "slang"] }));
+    assert_eq!(serde_json::from_value::<Annotation>(value).unwrap(), annotation);
+
+    let symbol = Symbol {
+        text: "(^_^)".to_string(),
+        kind: SymbolKind::Kaomoji,
+    };
+    assert_eq!(serde_json::to_value(&symbol).unwrap(), json!({ "text": "(^_^)", "kind": "kaomoji" }));
+    assert_eq!(serde_json::to_value(LengthUnit::Clusters).unwrap(), json!("clusters"));
+    assert_eq!(serde_json::from_value::<Decay>(json!({ "half_life_secs": 60 })).unwrap(), Decay { half_life_secs: 60 });
+}
+
+#[test]
+fn test_bundle_manifest_serializes_as_manifest_json() {
+    let manifest = BundleManifest {
+        built_at: 1,
+        provenance: vec![("mozc_ref".to_string(), "master".to_string())],
+        artifacts: vec![Artifact {
+            file: "symbols.fst".to_string(),
+            size: 10,
+            sha256: "00".to_string(),
+            entries: None,
+        }],
+    };
+    let value = serde_json::to_value(&manifest).unwrap();
+    assert_eq!(value, manifest.to_json());
+    assert_eq!(value["artifacts"][0], json!({ "file": "symbols.fst", "size": 10, "sha256": "00" }));
+    let artifact: Artifact = serde_json::from_value(value["artifacts"][0].clone()).unwrap();
+    assert_eq!(artifact, manifest.artifacts[0]);
+}
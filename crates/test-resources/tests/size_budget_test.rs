@@ -0,0 +1,132 @@
+//! Size budgets of the built artifacts, from size_budgets.tsv (or the file
+//! at SIZE_BUDGETS_PATH) for the SIZE_BUDGET_PROFILE profile, so size creep
+//! fails a build instead of shipping. Artifacts are read from OUTPUT_DIR,
+//! else output/.
+
+use std::fs;
+use std::path::PathBuf;
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+fn get_output_dir() -> PathBuf {
+    std::env::var("OUTPUT_DIR").map(PathBuf::from).unwrap_or_else(|_| PathBuf::from("../../output"))
+}
+
+#[derive(Debug, PartialEq)]
+struct Budget {
+    profile: String,
+    artifact: String,
+    max_mib: f64,
+    /// Size at the last budget review
+    baseline_mib: Option<f64>,
+}
+
+fn read_budgets(text: &str) -> Vec<Budget> {
+    let mut budgets = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split('\t').collect();
+        assert_eq!(parts.len(), 4, "Line {}: expected profile, artifact, budget and baseline", line_no + 1);
+        let mib = |field: &str| {
+            field.parse::<f64>().unwrap_or_else(|_| panic!("Line {}: bad size {:?}", line_no + 1, field))
+        };
+        budgets.push(Budget {
+            profile: parts[0].to_string(),
+            artifact: parts[1].to_string(),
+            max_mib: mib(parts[2]),
+            baseline_mib: (parts[3] != "-").then(|| mib(parts[3])),
+        });
+    }
+    budgets
+}
+
+/// One line per budget of `profile`, whether `size` (bytes, `None` if not
+/// built) is over budget or not, and whether any is.
+fn check_budgets(budgets: &[Budget], profile: &str, size: impl Fn(&str) -> Option<u64>) -> (Vec<String>, bool) {
+    let mut lines = Vec::new();
+    let mut over = false;
+    for budget in budgets.iter().filter(|budget| budget.profile == profile) {
+        let Some(bytes) = size(&budget.artifact) else {
+            lines.push(format!("{}: not built", budget.artifact));
+            continue;
+        };
+        let mib = bytes as f64 / MIB;
+        let growth = match budget.baseline_mib {
+            Some(baseline) => format!(", {:+.1} MiB since the {:.1} MiB baseline", mib - baseline, baseline),
+            None => String::new(),
+        };
+        let status = if mib > budget.max_mib { "OVER" } else { "ok" };
+        over |= mib > budget.max_mib;
+        lines.push(format!("{}: {:.1} of {:.1} MiB {}{}", budget.artifact, mib, budget.max_mib, status, growth));
+    }
+    (lines, over)
+}
+
+#[test]
+fn test_budget_breakdown() {
+    let budgets =
+        read_budgets("# comment\nmobile\ta.dic\t1\t0.5\nmobile\tb.fst\t2\t-\nmobile\tc.fst\t1\t-\ndesktop\ta.dic\t9\t-\n");
+    assert_eq!(budgets.len(), 4);
+    assert_eq!(budgets[0].baseline_mib, Some(0.5));
+
+    let sizes = |artifact: &str| match artifact {
+        "a.dic" => Some((1.5 * MIB) as u64),
+        "b.fst" => Some(MIB as u64),
+        _ => None,
+    };
+    let (lines, over) = check_budgets(&budgets, "mobile", sizes);
+    assert!(over);
+    assert_eq!(
+        lines,
+        [
+            "a.dic: 1.5 of 1.0 MiB OVER, +1.0 MiB since the 0.5 MiB baseline",
+            "b.fst: 1.0 of 2.0 MiB ok",
+            "c.fst: not built",
+        ]
+    );
+    assert!(!check_budgets(&budgets, "desktop", sizes).1);
+}
+
+#[test]
+fn test_mobile_profile_prunes_to_the_mobile_budget() {
+    use mozc_dict_gen::profile::Profile;
+    let budgets = read_budgets(include_str!("../size_budgets.tsv"));
+    let budget = budgets.iter().find(|budget| budget.profile == "mobile" && budget.artifact == "system.dic.zst").unwrap();
+    let max_size: f64 = Profile::Mobile
+        .args()
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--max-size-bytes="))
+        .expect("The mobile profile has a size limit")
+        .parse()
+        .unwrap();
+    assert_eq!(max_size / MIB, budget.max_mib);
+
+    for profile in [Profile::Mobile, Profile::Desktop, Profile::Server] {
+        assert!(profile.args().iter().all(|arg| arg.starts_with("--")), "{:?}", profile);
+    }
+    let wiki = [wiki_ngram::profile::Profile::Mobile, wiki_ngram::profile::Profile::Server];
+    assert!(wiki.iter().all(|profile| profile.args().iter().any(|arg| arg.starts_with("--min-frequency="))));
+}
+
+#[test]
+fn test_artifacts_fit_the_size_budgets() {
+    let budgets = match std::env::var("SIZE_BUDGETS_PATH") {
+        Ok(path) => read_budgets(&fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))),
+        Err(_) => read_budgets(include_str!("../size_budgets.tsv")),
+    };
+    let profile = std::env::var("SIZE_BUDGET_PROFILE").unwrap_or_else(|_| "mobile".to_string());
+    assert!(budgets.iter().any(|budget| budget.profile == profile), "No budgets for profile {}", profile);
+
+    let output_dir = get_output_dir();
+    let size = |artifact: &str| fs::metadata(output_dir.join(artifact)).ok().map(|metadata| metadata.len());
+    assert!(
+        budgets.iter().any(|budget| budget.profile == profile && size(&budget.artifact).is_some()),
+        "No budgeted artifact in {:?}; build them first",
+        output_dir
+    );
+    let (lines, over) = check_budgets(&budgets, &profile, size);
+    println!("Size budgets ({}):\n  {}", profile, lines.join("\n  "));
+    assert!(!over, "Artifacts over the {} size budget:\n  {}", profile, lines.join("\n  "));
+}
@@ -0,0 +1,37 @@
+use keyboard_formats::text_length::LengthUnit;
+
+#[test]
+fn test_lengths_of_multibyte_text() {
+    let cases = [
+        // (text, bytes, chars, clusters)
+        ("abc", 3, 3, 3),
+        ("東京", 6, 2, 2),
+        // が as か and a combining voiced mark
+        ("か\u{3099}", 6, 2, 1),
+        // Half-width ｶﾞ
+        ("\u{ff76}\u{ff9e}", 6, 2, 1),
+        // 葛 with an ideographic variation selector
+        ("葛\u{e0100}", 7, 2, 1),
+        // Family: man, ZWJ, woman, ZWJ, girl
+        ("👨\u{200d}👩\u{200d}👧", 18, 5, 1),
+        ("👍\u{1f3fd}", 8, 2, 1),
+        // Flags of Japan and the United States
+        ("🇯🇵🇺🇸", 16, 4, 2),
+        ("\r\n", 2, 2, 1),
+        ("", 0, 0, 0),
+    ];
+    for (text, bytes, chars, clusters) in cases {
+        assert_eq!(LengthUnit::Bytes.length(text), bytes, "{:?}", text);
+        assert_eq!(LengthUnit::Chars.length(text), chars, "{:?}", text);
+        assert_eq!(LengthUnit::Clusters.length(text), clusters, "{:?}", text);
+    }
+}
+
+#[test]
+fn test_length_unit_names() {
+    for unit in [LengthUnit::Bytes, LengthUnit::Chars, LengthUnit::Clusters] {
+        assert_eq!(unit.to_string().parse::<LengthUnit>(), Ok(unit));
+    }
+    assert_eq!(LengthUnit::default(), LengthUnit::Chars);
+    assert!("graphemes".parse::<LengthUnit>().is_err());
+}
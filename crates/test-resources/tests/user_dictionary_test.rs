@@ -0,0 +1,135 @@
+use keyboard_formats::user_dictionary::UserDictionary;
+
+#[test]
+fn test_later_edit_wins_conflict() {
+    let mut phone = UserDictionary::new();
+    phone.upsert("しんかんせん", "新幹線", "名詞", "phone", 100);
+    let mut laptop = UserDictionary::new();
+    laptop.upsert("しんかんせん", "新幹線", "固有名詞", "laptop", 200);
+
+    phone.merge(&laptop);
+    assert_eq!(phone.get("しんかんせん", "新幹線").unwrap().pos, "固有名詞");
+}
+
+#[test]
+fn test_device_breaks_timestamp_tie() {
+    let mut a = UserDictionary::new();
+    a.upsert("かな", "仮名", "名詞", "a", 100);
+    let mut b = UserDictionary::new();
+    b.upsert("かな", "仮名", "固有名詞", "b", 100);
+
+    let mut ab = a.clone();
+    ab.merge(&b);
+    let mut ba = b.clone();
+    ba.merge(&a);
+    assert_eq!(ab, ba, "Merge must pick the same winner regardless of order");
+    assert_eq!(ab.get("かな", "仮名").unwrap().stamp.device, "b");
+}
+
+#[test]
+fn test_equal_stamps_merge_the_same_in_both_orders() {
+    // Same device, same second, different POS
+    let mut a = UserDictionary::new();
+    a.upsert("かな", "仮名", "名詞", "phone", 100);
+    let mut b = UserDictionary::new();
+    b.upsert("かな", "仮名", "固有名詞", "phone", 100);
+
+    let mut ab = a.clone();
+    ab.merge(&b);
+    let mut ba = b.clone();
+    ba.merge(&a);
+    assert_eq!(ab, ba, "Merge must pick the same winner regardless of order");
+    assert_eq!(ab.get("かな", "仮名").unwrap().pos, "固有名詞", "The greater POS wins the tie");
+}
+
+#[test]
+fn test_same_second_edit_wins() {
+    let mut phone = UserDictionary::new();
+    phone.upsert("かな", "仮名", "固有名詞", "phone", 100);
+    phone.upsert("かな", "仮名", "名詞", "phone", 100);
+    assert_eq!(phone.get("かな", "仮名").unwrap().pos, "名詞", "A POS correction isn't lost");
+    assert_eq!(phone.get("かな", "仮名").unwrap().stamp.updated_at, 101);
+
+    let mut laptop = UserDictionary::new();
+    laptop.upsert("かな", "仮名", "固有名詞", "phone", 100);
+    laptop.merge(&phone);
+    assert_eq!(laptop, phone);
+}
+
+#[test]
+fn test_delete_propagates_and_is_not_resurrected() {
+    let mut phone = UserDictionary::new();
+    phone.upsert("てすと", "テスト", "名詞", "phone", 100);
+    let mut laptop = phone.clone();
+
+    phone.remove("てすと", "テスト", "phone", 200);
+    // Laptop still has the stale word; syncing it back must not restore it
+    phone.merge(&laptop);
+    assert!(phone.get("てすと", "テスト").is_none());
+
+    laptop.merge(&phone);
+    assert!(laptop.get("てすと", "テスト").is_none());
+    assert!(laptop.is_empty());
+}
+
+#[test]
+fn test_re_add_after_delete_wins() {
+    let mut a = UserDictionary::new();
+    a.upsert("てすと", "テスト", "名詞", "a", 100);
+    let mut b = a.clone();
+    a.remove("てすと", "テスト", "a", 200);
+    b.upsert("てすと", "テスト", "名詞", "b", 300);
+
+    a.merge(&b);
+    assert!(a.get("てすと", "テスト").is_some());
+}
+
+#[test]
+fn test_merge_is_associative_and_idempotent() {
+    let mut a = UserDictionary::new();
+    a.upsert("いぬ", "犬", "名詞", "a", 10);
+    a.remove("ねこ", "猫", "a", 30);
+    let mut b = UserDictionary::new();
+    b.upsert("ねこ", "猫", "名詞", "b", 20);
+    b.upsert("いぬ", "狗", "名詞", "b", 20);
+    let mut c = UserDictionary::new();
+    c.upsert("いぬ", "犬", "動物", "c", 40);
+
+    let mut left = a.clone();
+    left.merge(&b);
+    left.merge(&c);
+    let mut bc = b.clone();
+    bc.merge(&c);
+    let mut right = a.clone();
+    right.merge(&bc);
+    assert_eq!(left, right);
+
+    let snapshot = left.clone();
+    left.merge(&snapshot);
+    assert_eq!(left, snapshot);
+    assert_eq!(left.len(), 2);
+}
+
+#[test]
+fn test_purge_tombstones() {
+    let mut d = UserDictionary::new();
+    d.upsert("いぬ", "犬", "名詞", "a", 10);
+    d.remove("ねこ", "猫", "a", 20);
+    d.remove("とり", "鳥", "a", 50);
+
+    assert_eq!(d.purge_tombstones(30), 1);
+    assert_eq!(d.iter().count(), 2);
+}
+
+#[test]
+fn test_text_roundtrip_keeps_tombstones() {
+    let mut d = UserDictionary::new();
+    d.upsert("とうきょう", "東京", "名詞", "phone", 100);
+    d.remove("きょうと", "京都", "laptop", 200);
+
+    let mut buf = Vec::new();
+    d.write(&mut buf).unwrap();
+    let read = UserDictionary::read(&buf[..]).unwrap();
+    assert_eq!(read, d);
+    assert_eq!(read.len(), 1);
+}
@@ -0,0 +1,70 @@
+use keyboard_formats::user_history::{Decay, UserHistory};
+
+const DAY: u64 = 24 * 60 * 60;
+
+fn history() -> UserHistory {
+    UserHistory::new(Decay { half_life_secs: 7 * DAY })
+}
+
+#[test]
+fn test_score_halves_after_half_life() {
+    let mut h = history();
+    h.record("きしゃ", "記者", 0);
+
+    let score = h.score("きしゃ", "記者", 7 * DAY).unwrap();
+    assert!((score - 0.5).abs() < 1e-9, "Score should halve after one half-life, got {}", score);
+}
+
+#[test]
+fn test_recent_word_outranks_stale_frequent_word() {
+    let mut h = history();
+    // Used often a long time ago
+    for _ in 0..4 {
+        h.record("きしゃ", "汽車", 0);
+    }
+    // Used once recently
+    h.record("きしゃ", "記者", 28 * DAY);
+
+    let ranked = h.ranked("きしゃ", 28 * DAY);
+    assert_eq!(ranked[0].0, "記者", "Recent word should rank first: {:?}", ranked);
+    assert_eq!(ranked[1].0, "汽車");
+}
+
+#[test]
+fn test_merge_sums_decayed_scores() {
+    let mut a = history();
+    a.record("かんじ", "漢字", 0);
+    let mut b = history();
+    b.record("かんじ", "漢字", 7 * DAY);
+    b.record("かんじ", "感じ", 7 * DAY);
+
+    a.merge(&b);
+    assert_eq!(a.len(), 2);
+    let score = a.score("かんじ", "漢字", 7 * DAY).unwrap();
+    assert!((score - 1.5).abs() < 1e-9, "Merged score should be 0.5 + 1.0, got {}", score);
+}
+
+#[test]
+fn test_prune_drops_stale_entries() {
+    let mut h = history();
+    h.record("ふるい", "古い", 0);
+    h.record("あたらしい", "新しい", 70 * DAY);
+
+    let removed = h.prune(70 * DAY, 0.01);
+    assert_eq!(removed, 1);
+    assert!(h.score("ふるい", "古い", 70 * DAY).is_none());
+}
+
+#[test]
+fn test_text_roundtrip() {
+    let mut h = UserHistory::new(Decay { half_life_secs: 3 * DAY });
+    h.record("とうきょう", "東京", 100);
+    h.record("とうきょう", "東京", 200);
+
+    let mut buf = Vec::new();
+    h.write(&mut buf).unwrap();
+    let read = UserHistory::read(&buf[..]).unwrap();
+
+    assert_eq!(read.decay(), h.decay());
+    assert_eq!(read.score("とうきょう", "東京", 200), h.score("とうきょう", "東京", 200));
+}
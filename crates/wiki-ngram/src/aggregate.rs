@@ -0,0 +1,170 @@
+use anyhow::Result;
+use keyboard_formats::ngram_key::KeySchema;
+use keyboard_formats::user_ngrams::read_user_ngram_counts;
+use rand::distributions::Open01;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use crate::ngram;
+
+/// Clients choose their own n-gram orders; keys only have to be well formed.
+const USER_NGRAM_SCHEMA: KeySchema = KeySchema::new(1, usize::MAX);
+
+#[derive(clap::Args, Debug)]
+pub struct AggregateArgs {
+    /// Directory of per-user count files (one `<ngram>\t<count>` TSV per user)
+    #[arg(long)]
+    pub input_dir: PathBuf,
+
+    /// Output overlay FST
+    #[arg(long, default_value = "output/user-overlay.fst")]
+    pub output: PathBuf,
+
+    /// Drop N-grams contributed by fewer than this many users
+    #[arg(long, default_value = "20")]
+    pub min_users: u64,
+
+    /// Cap on each user's count for a single N-gram
+    #[arg(long, default_value = "1")]
+    pub max_count_per_user: u32,
+
+    /// Cap on the number of distinct N-grams taken from a single user
+    /// (their most frequent ones), bounding each user's total contribution
+    #[arg(long, default_value = "1000")]
+    pub max_ngrams_per_user: usize,
+
+    /// Total differential-privacy budget. Half of it goes to Laplace noise
+    /// on the user totals the threshold compares and half to noise on the
+    /// count totals written, each scaled to a user's maximum contribution
+    #[arg(long)]
+    pub epsilon: Option<f64>,
+
+    /// With --epsilon, the chance that any N-gram of a single user is
+    /// published; the user threshold is raised to make it so
+    #[arg(long, default_value = "1e-6")]
+    pub delta: f64,
+
+    /// Seed for the noise generator (random if unset)
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+#[derive(Default)]
+struct Aggregate {
+    users: u64,
+    count: u64,
+}
+
+pub fn run(args: &AggregateArgs) -> Result<()> {
+    anyhow::ensure!(args.delta > 0.0 && args.delta < 1.0, "--delta must be between 0 and 1");
+    let mut files: Vec<PathBuf> = fs::read_dir(&args.input_dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    files.retain(|path| path.is_file());
+    files.sort();
+    tracing::info!("Aggregating {} user files from {:?}", files.len(), args.input_dir);
+
+    let users = files
+        .iter()
+        .map(|path| read_user_ngram_counts(BufReader::new(File::open(path)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let overlay = aggregate(users, args);
+
+    if let Some(parent) = args.output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    ngram::build_fst(&overlay, USER_NGRAM_SCHEMA, &args.output)?;
+    tracing::info!("Overlay FST written to {:?}", args.output);
+    Ok(())
+}
+
+/// Adds up the counts of `users`, one list per user, capped and noised as
+/// `args` say, and returns the N-grams that pass the user threshold with
+/// their encoded scores, sorted by key.
+///
+/// With `epsilon`, N-grams are chosen by Laplace partition selection. A user
+/// adds one to the user totals of at most `max_ngrams_per_user` N-grams
+/// (L0), so the noise on them has scale `b = L0 / (epsilon / 2)`, and the
+/// threshold becomes `min_users + b ln(L0 / 2 delta)`: an N-gram only one
+/// user has passes with probability at most `delta / L0`, so all of that
+/// user's N-grams together are published with probability at most `delta`.
+pub fn aggregate(users: impl IntoIterator<Item = Vec<(String, u32)>>, args: &AggregateArgs) -> Vec<(String, u64)> {
+    let mut aggregates: HashMap<String, Aggregate> = HashMap::new();
+    let mut malformed = 0usize;
+    for mut counts in users {
+        let before = counts.len();
+        counts.retain(|(ngram, _)| USER_NGRAM_SCHEMA.validate(ngram.as_bytes()).is_ok());
+        malformed += before - counts.len();
+        // Keep each user's most frequent n-grams, ties broken by key for determinism
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts.dedup_by(|a, b| a.0 == b.0);
+        counts.truncate(args.max_ngrams_per_user);
+
+        for (ngram, count) in counts {
+            let aggregate = aggregates.entry(ngram).or_default();
+            aggregate.users += 1;
+            aggregate.count += count.min(args.max_count_per_user) as u64;
+        }
+    }
+
+    if malformed > 0 {
+        tracing::warn!("Dropped {} malformed user N-grams", malformed);
+    }
+
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    // L1 sensitivity of one user's contribution to the user and count totals
+    let user_sensitivity = args.max_ngrams_per_user.max(1) as f64;
+    let count_sensitivity = (args.max_ngrams_per_user as u64 * args.max_count_per_user as u64) as f64;
+    // Each total gets half of the budget, so the release spends epsilon
+    let scales = args
+        .epsilon
+        .map(|epsilon| (user_sensitivity / (epsilon / 2.0), count_sensitivity / (epsilon / 2.0)));
+    let threshold = match scales {
+        Some((user_scale, _)) => {
+            args.min_users.max(1) as f64 + user_scale * (user_sensitivity / (2.0 * args.delta)).ln()
+        }
+        None => args.min_users as f64,
+    };
+
+    // Sorted first so a seed gives the same noise for the same input
+    let mut aggregates: Vec<_> = aggregates.into_iter().collect();
+    aggregates.sort_by(|a, b| a.0.cmp(&b.0));
+    let total = aggregates.len();
+    let mut overlay = Vec::new();
+    for (ngram, aggregate) in aggregates {
+        let (users, count) = match scales {
+            Some((user_scale, count_scale)) => (
+                aggregate.users as f64 + laplace(&mut rng, user_scale),
+                aggregate.count as f64 + laplace(&mut rng, count_scale),
+            ),
+            None => (aggregate.users as f64, aggregate.count as f64),
+        };
+        if users < threshold {
+            continue;
+        }
+        overlay.push((ngram, ngram::encode_score(count)));
+    }
+
+    tracing::info!(
+        "{} of {} N-grams passed the {:.1}-user threshold{}",
+        overlay.len(),
+        total,
+        threshold,
+        if args.epsilon.is_some() { " (with noise)" } else { "" }
+    );
+    overlay
+}
+
+/// Samples Laplace(0, scale) by inverting the CDF. `u` is drawn from the
+/// open interval (-0.5, 0.5): at -0.5 the logarithm would be of 0.
+pub fn laplace(rng: &mut impl Rng, scale: f64) -> f64 {
+    let u: f64 = rng.sample::<f64, _>(Open01) - 0.5;
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
@@ -0,0 +1,197 @@
+//! Drops boilerplate sentences (list items, table rows, references, leftover
+//! markup) before they are counted.
+//!
+//! A logistic classifier scores four features of a sentence: its share of
+//! punctuation, of digits and of markup characters, and how short it is. The
+//! default weights are hand-set; [`BoilerplateClassifier::train`] fits them
+//! to labeled samples instead.
+//!
+//! Sample file format (UTF-8, tab separated):
+//!
+//! ```text
+//! #boilerplate-samples<TAB>v1
+//! <boilerplate|prose><TAB><sentence>
+//! ```
+
+use anyhow::{Context, Result};
+use std::io::BufRead;
+
+const HEADER_MAGIC: &str = "#boilerplate-samples";
+const FORMAT_VERSION: &str = "v1";
+
+/// Characters of wiki markup that survive cleaning: brackets, table and
+/// list syntax, headings.
+const MARKUP_CHARS: &[char] = &['[', ']', '{', '}', '<', '>', '|', '=', '*', '#', '&', ';'];
+
+/// Full-width symbols that separate fields rather than clauses.
+const FULL_WIDTH_PUNCTUATION: &[char] = &['・', '：', '／', '（', '）', '【', '】', '＝', '※', '→', '～', '－'];
+
+/// Sentence length, in characters, at which the shortness feature is 1/e.
+const SHORT_SENTENCE_CHARS: f64 = 10.0;
+
+/// Samples of every this many go to evaluation rather than training.
+const HELD_OUT_EVERY: usize = 5;
+
+const EPOCHS: usize = 2000;
+const LEARNING_RATE: f64 = 2.0;
+
+/// Punctuation, digit and markup ratios, and shortness (1 for an empty
+/// sentence, falling towards 0 as it gets longer).
+pub fn features(sentence: &str) -> [f64; 4] {
+    let chars = sentence.chars().count();
+    if chars == 0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    let share = |pred: fn(char) -> bool| sentence.chars().filter(|&c| pred(c)).count() as f64 / chars as f64;
+    [
+        share(|c| (c.is_ascii_punctuation() && !MARKUP_CHARS.contains(&c)) || FULL_WIDTH_PUNCTUATION.contains(&c)),
+        share(|c| c.is_ascii_digit() || ('０'..='９').contains(&c)),
+        share(|c| MARKUP_CHARS.contains(&c)),
+        (-(chars as f64) / SHORT_SENTENCE_CHARS).exp(),
+    ]
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoilerplateClassifier {
+    pub weights: [f64; 4],
+    pub bias: f64,
+}
+
+impl Default for BoilerplateClassifier {
+    fn default() -> Self {
+        Self {
+            weights: [8.0, 6.0, 20.0, 4.0],
+            bias: -3.0,
+        }
+    }
+}
+
+impl BoilerplateClassifier {
+    /// Probability that `sentence` is boilerplate.
+    pub fn probability(&self, sentence: &str) -> f64 {
+        self.probability_of(&features(sentence))
+    }
+
+    pub fn is_boilerplate(&self, sentence: &str) -> bool {
+        self.probability(sentence) > 0.5
+    }
+
+    fn probability_of(&self, features: &[f64; 4]) -> f64 {
+        let z = self.bias + self.weights.iter().zip(features).map(|(w, x)| w * x).sum::<f64>();
+        1.0 / (1.0 + (-z).exp())
+    }
+
+    /// Fits weights to `samples` (sentence, is boilerplate) by gradient
+    /// descent on the log loss, from zero so training is deterministic.
+    pub fn train(samples: &[(String, bool)]) -> Self {
+        let data: Vec<([f64; 4], f64)> =
+            samples.iter().map(|(sentence, label)| (features(sentence), if *label { 1.0 } else { 0.0 })).collect();
+        let mut classifier = Self {
+            weights: [0.0; 4],
+            bias: 0.0,
+        };
+        if data.is_empty() {
+            return classifier;
+        }
+        for _ in 0..EPOCHS {
+            let mut gradient = [0.0; 4];
+            let mut bias_gradient = 0.0;
+            for (x, y) in &data {
+                let error = classifier.probability_of(x) - y;
+                for (g, xi) in gradient.iter_mut().zip(x) {
+                    *g += error * xi;
+                }
+                bias_gradient += error;
+            }
+            let step = LEARNING_RATE / data.len() as f64;
+            for (w, g) in classifier.weights.iter_mut().zip(gradient) {
+                *w -= step * g;
+            }
+            classifier.bias -= step * bias_gradient;
+        }
+        classifier
+    }
+
+    /// Trains on all but every [`HELD_OUT_EVERY`]th sample and evaluates on
+    /// those, so the reported precision and recall are not measured on the
+    /// training data.
+    pub fn train_held_out(samples: &[(String, bool)]) -> (Self, Evaluation) {
+        let (held_out, training): (Vec<_>, Vec<_>) =
+            samples.iter().cloned().enumerate().partition(|(i, _)| i % HELD_OUT_EVERY == HELD_OUT_EVERY - 1);
+        let strip = |samples: Vec<(usize, (String, bool))>| samples.into_iter().map(|(_, sample)| sample).collect::<Vec<_>>();
+        let classifier = Self::train(&strip(training));
+        let evaluation = classifier.evaluate(&strip(held_out));
+        (classifier, evaluation)
+    }
+
+    pub fn evaluate(&self, samples: &[(String, bool)]) -> Evaluation {
+        let mut evaluation = Evaluation::default();
+        for (sentence, label) in samples {
+            match (self.is_boilerplate(sentence), label) {
+                (true, true) => evaluation.true_positives += 1,
+                (true, false) => evaluation.false_positives += 1,
+                (false, true) => evaluation.false_negatives += 1,
+                (false, false) => evaluation.true_negatives += 1,
+            }
+        }
+        evaluation
+    }
+}
+
+/// Confusion counts of a classifier on labeled samples, boilerplate being
+/// the positive class.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Evaluation {
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub false_negatives: usize,
+    pub true_negatives: usize,
+}
+
+impl Evaluation {
+    /// Share of sentences dropped that were boilerplate; 1 if none were dropped.
+    pub fn precision(&self) -> f64 {
+        ratio(self.true_positives, self.true_positives + self.false_positives)
+    }
+
+    /// Share of boilerplate sentences dropped; 1 if there were none.
+    pub fn recall(&self) -> f64 {
+        ratio(self.true_positives, self.true_positives + self.false_negatives)
+    }
+}
+
+fn ratio(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        1.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+/// Reads a labeled sample file.
+pub fn read_samples(reader: impl BufRead) -> Result<Vec<(String, bool)>> {
+    let mut lines = reader.lines();
+    let header = lines.next().context("Empty boilerplate sample file")??;
+    anyhow::ensure!(
+        header == format!("{}\t{}", HEADER_MAGIC, FORMAT_VERSION),
+        "Not a {} boilerplate sample file",
+        FORMAT_VERSION
+    );
+    let mut samples = Vec::new();
+    for (line_no, line) in lines.enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let (label, sentence) = line
+            .split_once('\t')
+            .with_context(|| format!("Line {}: expected a label and a sentence", line_no + 2))?;
+        let label = match label {
+            "boilerplate" => true,
+            "prose" => false,
+            other => anyhow::bail!("Line {}: unknown label {:?}", line_no + 2, other),
+        };
+        samples.push((sentence.to_string(), label));
+    }
+    Ok(samples)
+}
@@ -0,0 +1,386 @@
+//! Reading the dump, and its parallel decompression when it is bz2
+//! (`--decompress-threads`).
+//!
+//! The compression is told from the file's first bytes rather than its name:
+//! besides Wikimedia's bz2, a dump may be zstd or gzip compressed, or already
+//! decompressed. Recompressing the dump locally makes reruns much faster
+//! than bz2, even on the thread pool below: a seekable zstd dump from
+//! `prepare-dump` ([`crate::seekable`]) is decompressed on the pool frame by
+//! frame, while other zstd and gzip files are read sequentially.
+//!
+//! bzip2 decompression alone keeps one core busy for most of a pass, but the
+//! format compresses in independent blocks of up to 900 kB, so the dump is
+//! cut into pieces that decompress on their own and handed to a pool of
+//! threads, their output read back in order:
+//!
+//! - With a multistream dump and its index next to it
+//!   (`…-multistream.xml.bz2` and `…-multistream-index.txt.bz2`), the
+//!   index gives the byte offsets of the streams, each a complete bz2 file.
+//! - Otherwise the file is scanned for the 48-bit block and end-of-stream
+//!   magics, which need not fall on byte boundaries, and every block is
+//!   re-wrapped as a one-block stream: a `BZh9` header, the block's bits
+//!   shifted into place, the end-of-stream magic and the block's CRC as the
+//!   stream CRC. Compressed data can contain a magic by chance (about once
+//!   in 2^48 bits); a block that then fails to decompress fails the pass,
+//!   and `--decompress-threads 1` reads the dump sequentially instead.
+
+use anyhow::{Context, Result};
+use bzip2::read::{BzDecoder, MultiBzDecoder};
+use memmap2::Mmap;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+use crate::seekable;
+
+const BZ2_MAGIC: &[u8] = b"BZh";
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+
+/// Largest zstd window a dump may be compressed with, that of `--long=31`.
+const ZSTD_WINDOW_LOG_MAX: u32 = 31;
+
+const BLOCK_MAGIC: u64 = 0x3141_5926_5359;
+const END_MAGIC: u64 = 0x1772_4538_5090;
+const MAGIC_MASK: u64 = (1 << 48) - 1;
+
+/// Streams of the index merged into pieces of at least this many bytes.
+const MIN_STREAM_PIECE: usize = 1 << 20;
+
+/// Pieces decompressed ahead of the reader, per thread.
+const AHEAD_PER_THREAD: usize = 4;
+
+/// A part of the dump that decompresses on its own.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Piece {
+    /// Whole streams, by byte range
+    Streams(Range<usize>),
+    /// One block of a stream, by bit range from its magic up to the next
+    /// block or end-of-stream magic
+    Block(Range<u64>),
+    /// Frames of a seekable zstd dump, by byte range
+    Zstd(Range<usize>),
+}
+
+impl Piece {
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        match self {
+            Piece::Streams(range) => {
+                MultiBzDecoder::new(&data[range.clone()]).read_to_end(&mut out)?;
+            }
+            Piece::Block(bits) => {
+                BzDecoder::new(&block_stream(data, bits.clone())[..])
+                    .read_to_end(&mut out)
+                    .with_context(|| format!("bz2 block at bit {} failed to decompress", bits.start))?;
+            }
+            Piece::Zstd(range) => {
+                zstd::Decoder::with_buffer(&data[range.clone()])?.read_to_end(&mut out)?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Bit positions of the block and end-of-stream magics in `data`, each as
+/// (position, is a block), in order. The scan is split across `threads`.
+pub fn find_magics(data: &[u8], threads: usize) -> Vec<(u64, bool)> {
+    if data.len() < 8 {
+        return Vec::new();
+    }
+    let last = data.len() - 8;
+    let span = last / threads.max(1) + 1;
+    std::thread::scope(|scope| {
+        let scans: Vec<_> = (0..=last)
+            .step_by(span)
+            .map(|start| scope.spawn(move || scan(data, start..(start + span).min(last + 1))))
+            .collect();
+        scans.into_iter().flat_map(|scan| scan.join().unwrap()).collect()
+    })
+}
+
+/// Magics starting in the bytes `range` of `data`, at any bit offset.
+fn scan(data: &[u8], range: Range<usize>) -> Vec<(u64, bool)> {
+    let mut found = Vec::new();
+    for i in range {
+        let word = u64::from_be_bytes(data[i..i + 8].try_into().unwrap());
+        for shift in 0..8 {
+            let candidate = (word >> (16 - shift)) & MAGIC_MASK;
+            if candidate == BLOCK_MAGIC || candidate == END_MAGIC {
+                found.push((i as u64 * 8 + shift as u64, candidate == BLOCK_MAGIC));
+            }
+        }
+    }
+    found
+}
+
+/// The blocks of `data`, each from its magic to the magic after it.
+pub fn find_blocks(data: &[u8], threads: usize) -> Vec<Piece> {
+    let magics = find_magics(data, threads);
+    magics
+        .windows(2)
+        .filter(|pair| pair[0].1)
+        .map(|pair| Piece::Block(pair[0].0..pair[1].0))
+        .collect()
+}
+
+/// A one-block bz2 stream of the block at `bits` of `data`.
+pub fn block_stream(data: &[u8], bits: Range<u64>) -> Vec<u8> {
+    // The byte starting at bit `at`, zero-padded past the end
+    let byte_at = |at: u64| {
+        let (i, shift) = ((at / 8) as usize, at % 8);
+        let pair = u16::from(data[i]) << 8 | u16::from(data.get(i + 1).copied().unwrap_or(0));
+        (pair << shift >> 8) as u8
+    };
+    let mut writer = BitWriter::default();
+    writer.out.extend_from_slice(b"BZh9");
+    let whole = (bits.end - bits.start) / 8;
+    writer.out.extend((0..whole).map(|k| byte_at(bits.start + k * 8)));
+    for bit in bits.start + whole * 8..bits.end {
+        writer.push(u64::from(byte_at(bit) >> 7), 1);
+    }
+    writer.push(END_MAGIC, 48);
+    // The CRC of a one-block stream is that of its block, the 32 bits after
+    // the block magic
+    let crc = (0..4).fold(0, |crc, k| crc << 8 | u64::from(byte_at(bits.start + 48 + k * 8)));
+    writer.push(crc, 32);
+    writer.finish()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    pending: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn push(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.pending = self.pending << 1 | (value >> i & 1);
+            self.bits += 1;
+            if self.bits == 8 {
+                self.out.push(self.pending as u8);
+                self.pending = 0;
+                self.bits = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push((self.pending << (8 - self.bits)) as u8);
+        }
+        self.out
+    }
+}
+
+/// Where the index of a multistream dump is: `…-multistream.xml.bz2` →
+/// `…-multistream-index.txt.bz2`.
+pub fn index_path(dump: &Path) -> Option<PathBuf> {
+    let name = dump.file_name()?.to_str()?;
+    Some(dump.with_file_name(format!("{}-index.txt.bz2", name.strip_suffix(".xml.bz2")?)))
+}
+
+/// The stream offsets of a multistream index (`offset:page id:title` lines,
+/// bz2 compressed), merged into pieces of at least [`MIN_STREAM_PIECE`]
+/// bytes covering the `len` bytes of the dump.
+pub fn read_index(index: impl Read, len: usize) -> Result<Vec<Piece>> {
+    let mut offsets = vec![0];
+    for line in BufReader::new(MultiBzDecoder::new(index)).lines() {
+        let line = line?;
+        let offset = line.split(':').next().unwrap_or_default();
+        let offset: usize = offset.parse().with_context(|| format!("Bad index line {:?}", line))?;
+        anyhow::ensure!(offset <= len, "Index offset {} is past the end of the dump", offset);
+        if offset > *offsets.last().unwrap() {
+            offsets.push(offset);
+        }
+    }
+    if *offsets.last().unwrap() < len {
+        offsets.push(len);
+    }
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for &offset in &offsets[1..] {
+        if offset - start >= MIN_STREAM_PIECE || offset == len {
+            pieces.push(Piece::Streams(start..offset));
+            start = offset;
+        }
+    }
+    Ok(pieces)
+}
+
+/// How a dump file is compressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DumpCompression {
+    Bz2,
+    Zstd,
+    Gzip,
+    /// Already decompressed XML
+    None,
+}
+
+impl DumpCompression {
+    /// The compression of a file starting with `head`.
+    pub fn detect(head: &[u8]) -> Self {
+        if head.starts_with(BZ2_MAGIC) {
+            DumpCompression::Bz2
+        } else if head.starts_with(ZSTD_MAGIC) {
+            DumpCompression::Zstd
+        } else if head.starts_with(GZIP_MAGIC) {
+            DumpCompression::Gzip
+        } else {
+            DumpCompression::None
+        }
+    }
+}
+
+/// Opens the dump at `path` for reading decompressed, whatever its
+/// compression. A bz2 or seekable zstd dump is decompressed on `threads`
+/// threads, one thread decompressing it sequentially.
+pub fn open_dump(path: &Path, threads: usize) -> Result<Box<dyn Read + Send>> {
+    let mut file = BufReader::new(File::open(path).with_context(|| format!("Failed to open {:?}", path))?);
+    let compression = DumpCompression::detect(file.fill_buf()?);
+    tracing::info!("Reading the dump {:?} ({:?})", path, compression);
+    match compression {
+        DumpCompression::Bz2 => {}
+        DumpCompression::Zstd => {
+            if threads > 1 {
+                let data = unsafe { Mmap::map(file.get_ref())? };
+                if let Some(frames) = seekable::read_seek_table(&data) {
+                    tracing::info!("Decompressing {} seekable zstd frames on {} threads", frames.len(), threads);
+                    return Ok(Box::new(ParallelDecoder::new(Arc::new(data), seekable::pieces(&frames), threads)));
+                }
+            }
+            let mut decoder = zstd::Decoder::with_buffer(file)?;
+            decoder.window_log_max(ZSTD_WINDOW_LOG_MAX)?;
+            return Ok(Box::new(decoder));
+        }
+        DumpCompression::Gzip => return Ok(Box::new(flate2::bufread::MultiGzDecoder::new(file))),
+        DumpCompression::None => return Ok(Box::new(file)),
+    }
+    if threads <= 1 {
+        return Ok(Box::new(MultiBzDecoder::new(file)));
+    }
+    let data = unsafe { Mmap::map(file.get_ref())? };
+    let pieces = match index_path(path).filter(|index| index.exists()) {
+        Some(index) => {
+            tracing::info!("Splitting the dump by the streams of {:?}", index);
+            read_index(File::open(&index)?, data.len())?
+        }
+        None => {
+            tracing::info!("Scanning the dump for bz2 blocks...");
+            find_blocks(&data, threads)
+        }
+    };
+    tracing::info!("Decompressing {} pieces on {} threads", pieces.len(), threads);
+    Ok(Box::new(ParallelDecoder::new(Arc::new(data), pieces, threads)))
+}
+
+/// Reads the pieces of a dump decompressed by a pool of threads, in order.
+pub struct ParallelDecoder {
+    receiver: Receiver<(usize, Result<Vec<u8>>)>,
+    ready: BTreeMap<usize, Result<Vec<u8>>>,
+    next: usize,
+    total: usize,
+    current: Cursor<Vec<u8>>,
+    /// The next piece to be read, which workers stay a window ahead of
+    reading: Arc<(Mutex<usize>, Condvar)>,
+    stop: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ParallelDecoder {
+    pub fn new<D: AsRef<[u8]> + Send + Sync + 'static>(data: Arc<D>, pieces: Vec<Piece>, threads: usize) -> Self {
+        let threads = threads.max(1);
+        let total = pieces.len();
+        let pieces = Arc::new(pieces);
+        let taken = Arc::new(AtomicUsize::new(0));
+        let reading = Arc::new((Mutex::new(0), Condvar::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let ahead = threads * AHEAD_PER_THREAD;
+        let (sender, receiver) = mpsc::channel();
+        let workers = (0..threads)
+            .map(|_| {
+                let (data, pieces, taken, reading, stop, sender) =
+                    (data.clone(), pieces.clone(), taken.clone(), reading.clone(), stop.clone(), sender.clone());
+                std::thread::spawn(move || loop {
+                    let i = taken.fetch_add(1, Ordering::SeqCst);
+                    if i >= pieces.len() {
+                        return;
+                    }
+                    let (next, wake) = &*reading;
+                    let mut next = next.lock().unwrap();
+                    while i >= *next + ahead && !stop.load(Ordering::SeqCst) {
+                        next = wake.wait(next).unwrap();
+                    }
+                    drop(next);
+                    if stop.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    let out = pieces[i].decompress((*data).as_ref());
+                    if sender.send((i, out)).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        Self {
+            receiver,
+            ready: BTreeMap::new(),
+            next: 0,
+            total,
+            current: Cursor::new(Vec::new()),
+            reading,
+            stop,
+            workers,
+        }
+    }
+
+    /// Moves on to the next piece, false at the end.
+    fn advance(&mut self) -> std::io::Result<bool> {
+        if self.next == self.total {
+            return Ok(false);
+        }
+        let out = loop {
+            if let Some(out) = self.ready.remove(&self.next) {
+                break out;
+            }
+            let (i, out) = self.receiver.recv().map_err(std::io::Error::other)?;
+            self.ready.insert(i, out);
+        };
+        self.next += 1;
+        let (next, wake) = &*self.reading;
+        *next.lock().unwrap() = self.next;
+        wake.notify_all();
+        self.current = Cursor::new(out.map_err(std::io::Error::other)?);
+        Ok(true)
+    }
+}
+
+impl Read for ParallelDecoder {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let n = self.current.read(buf)?;
+            if n > 0 || buf.is_empty() || !self.advance()? {
+                return Ok(n);
+            }
+        }
+    }
+}
+
+impl Drop for ParallelDecoder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.reading.1.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
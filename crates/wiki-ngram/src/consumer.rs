@@ -0,0 +1,445 @@
+use anyhow::Result;
+use keyboard_formats::ngram_key::{self, KeySchema, SEPARATOR};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::histogram::CountHistogram;
+use crate::ngram::{self, FilterConfig, NgramCounts, Smoothing};
+use crate::quality::QualityGates;
+use crate::quote::QuoteFilter;
+use crate::stage::{CountSource, FrequencyFilter, FstSink, ModelSink, NgramFilter, TokenNgrams};
+use crate::tokenize::{Token, TokenFields, TokenOutput};
+
+/// Settings shared by every consumer when turning counts into artifacts.
+pub struct BuildOptions {
+    pub filter_config: FilterConfig,
+    pub smoothing: Smoothing,
+    pub histogram_max_count: u32,
+    pub quality_gates: QualityGates,
+}
+
+impl BuildOptions {
+    /// The thresholds and smoothing as an [`NgramFilter`].
+    pub fn frequency_filter(&self) -> FrequencyFilter {
+        FrequencyFilter {
+            config: self.filter_config.clone(),
+            smoothing: self.smoothing,
+        }
+    }
+}
+
+/// Receives every tokenized sentence of a corpus pass.
+///
+/// A pass over the Wikipedia dump takes hours, so all outputs are produced
+/// by consumers fed from the same tokenization instead of separate runs.
+pub trait SentenceConsumer {
+    /// Short name used in logs.
+    fn name(&self) -> &str;
+
+    /// Token features this consumer reads.
+    fn fields(&self) -> TokenFields;
+
+    /// Feeds one sentence; `weight` is how many times it counts (see
+    /// [`crate::style::StyleProfile`]).
+    fn consume(&mut self, tokens: &[Token], weight: u32);
+
+    /// Number of distinct entries held in memory, for progress reporting.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops entries seen at most `threshold` times to shrink the in-memory
+    /// state, returning how many were removed; see [`crate::prune::PrunePolicy`].
+    fn prune(&mut self, _threshold: u32) -> usize {
+        0
+    }
+
+    /// Checks the counts against the quality gates; see [`finish_all`].
+    fn check(&self, _gates: &QualityGates) -> Result<()> {
+        Ok(())
+    }
+
+    /// Writes the consumer's artifact once the pass is complete.
+    fn finish(self: Box<Self>, options: &BuildOptions) -> Result<()>;
+}
+
+/// Checks every consumer against the quality gates, then writes their
+/// artifacts, so a gate failing with `--strict` leaves no output behind.
+pub fn finish_all(consumers: Vec<Box<dyn SentenceConsumer>>, options: &BuildOptions) -> Result<()> {
+    for consumer in &consumers {
+        consumer.check(&options.quality_gates)?;
+    }
+    for consumer in consumers {
+        let _output = tracing::info_span!("output", name = consumer.name()).entered();
+        tracing::info!("Writing {} output...", consumer.name());
+        consumer.finish(options)?;
+    }
+    Ok(())
+}
+
+/// Counts n-grams of tokens rendered with a [`TokenOutput`] and builds an FST.
+///
+/// Counting, filtering and writing are [`crate::stage`] traits; the
+/// `with_*` methods swap in other implementations.
+pub struct NgramConsumer {
+    name: String,
+    source: Box<dyn CountSource>,
+    filter: Option<Box<dyn NgramFilter>>,
+    sink: Box<dyn ModelSink>,
+    quote_filter: Option<QuoteFilter>,
+    token_output: TokenOutput,
+    max_ngram: usize,
+    counts: NgramCounts,
+    histogram_output: Option<PathBuf>,
+}
+
+impl NgramConsumer {
+    pub fn new(
+        name: &str,
+        token_output: TokenOutput,
+        max_ngram: usize,
+        output: PathBuf,
+        histogram_output: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            source: Box::new(TokenNgrams { token_output, max_ngram }),
+            filter: None,
+            sink: Box::new(FstSink {
+                schema: KeySchema::new(2, max_ngram),
+                path: output,
+            }),
+            quote_filter: None,
+            token_output,
+            max_ngram,
+            counts: NgramCounts::default(),
+            histogram_output,
+        }
+    }
+
+    /// Counts with `source` instead of [`TokenNgrams`].
+    pub fn with_source(mut self, source: Box<dyn CountSource>) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Filters with `filter` instead of the [`BuildOptions`] thresholds.
+    pub fn with_filter(mut self, filter: Box<dyn NgramFilter>) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Drops n-grams of `min_order` or more tokens seen only once, inside a
+    /// quoted passage; see [`QuoteFilter`].
+    pub fn with_quote_filter(mut self, min_order: usize) -> Self {
+        self.quote_filter = Some(QuoteFilter::new(self.token_output, min_order, self.max_ngram));
+        self
+    }
+
+    /// Writes to `sink` instead of an FST at the output path.
+    pub fn with_sink(mut self, sink: Box<dyn ModelSink>) -> Self {
+        self.sink = sink;
+        self
+    }
+}
+
+impl SentenceConsumer for NgramConsumer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn fields(&self) -> TokenFields {
+        self.source.fields()
+    }
+
+    fn consume(&mut self, tokens: &[Token], weight: u32) {
+        if let Some(quote_filter) = &mut self.quote_filter {
+            quote_filter.observe(tokens, &self.counts);
+        }
+        self.source.count(tokens, weight, &mut self.counts);
+    }
+
+    fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    fn prune(&mut self, threshold: u32) -> usize {
+        let removed = ngram::prune_ngrams(&mut self.counts, threshold);
+        if removed > 0 {
+            if let Some(quote_filter) = &mut self.quote_filter {
+                quote_filter.retain(&self.counts);
+            }
+        }
+        removed
+    }
+
+    fn check(&self, gates: &QualityGates) -> Result<()> {
+        gates.check_ngram_count(&self.name, &self.counts)
+    }
+
+    fn finish(mut self: Box<Self>, options: &BuildOptions) -> Result<()> {
+        let histogram = CountHistogram::from_counts(&self.counts);
+        tracing::info!("[{}] Count histogram:", self.name);
+        histogram.log_summary(options.histogram_max_count);
+        if let Some(path) = &self.histogram_output {
+            histogram.write_tsv(path)?;
+            tracing::info!("[{}] Count histogram written to {:?}", self.name, path);
+        }
+
+        let filter = self.filter.take().unwrap_or_else(|| Box::new(options.frequency_filter()));
+        let mut filtered = tracing::info_span!("filter").in_scope(|| filter.filter(&self.counts, &histogram));
+        if let Some(quote_filter) = &self.quote_filter {
+            let removed = quote_filter.apply(&mut filtered);
+            tracing::info!("[{}] Dropped {} N-grams seen only once, in a quotation", self.name, removed);
+        }
+        tracing::info!("[{}] Total N-grams after filtering: {}", self.name, filtered.len());
+
+        tracing::info_span!("write", entries = filtered.len()).in_scope(|| self.sink.write(&filtered))?;
+        tracing::info!("[{}] Done", self.name);
+        Ok(())
+    }
+}
+
+/// Counts surface unigrams and builds a vocabulary FST scored like n-grams.
+pub struct VocabConsumer {
+    counts: NgramCounts,
+    output: PathBuf,
+}
+
+impl VocabConsumer {
+    pub fn new(output: PathBuf) -> Self {
+        Self {
+            counts: NgramCounts::default(),
+            output,
+        }
+    }
+}
+
+impl SentenceConsumer for VocabConsumer {
+    fn name(&self) -> &str {
+        "vocab"
+    }
+
+    fn fields(&self) -> TokenFields {
+        TokenFields::default()
+    }
+
+    fn consume(&mut self, tokens: &[Token], weight: u32) {
+        for token in tokens.iter().filter(|token| ngram_key::is_valid_token(&token.surface)) {
+            *self.counts.entry_ref(token.surface.as_str()).or_insert(0) += weight;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    fn prune(&mut self, threshold: u32) -> usize {
+        ngram::prune_ngrams(&mut self.counts, threshold)
+    }
+
+    fn check(&self, gates: &QualityGates) -> Result<()> {
+        gates.check_ngram_count(self.name(), &self.counts)
+    }
+
+    fn finish(self: Box<Self>, options: &BuildOptions) -> Result<()> {
+        let histogram = CountHistogram::from_counts(&self.counts);
+        let filtered = ngram::filter_ngrams(&self.counts, &histogram, &options.filter_config, options.smoothing);
+        tracing::info!("[vocab] {} of {} words kept after filtering", filtered.len(), self.counts.len());
+
+        ngram::build_fst(&filtered, KeySchema::new(1, 1), &self.output)?;
+        tracing::info!("[vocab] FST generated at {:?}", self.output);
+        Ok(())
+    }
+}
+
+/// Openers offered before the user has typed anything, whether or not the
+/// corpus starts sentences with them; encyclopedic text rarely greets anyone.
+/// They are tokenized like the corpus, so they key the FST as counted
+/// openers do; see [`SentenceStartConsumer::with_curated_openers`].
+pub const CURATED_OPENERS: &[&str] = &[
+    "おはよう",
+    "おはようございます",
+    "こんにちは",
+    "こんばんは",
+    "ありがとう",
+    "ありがとうございます",
+    "お疲れ様です",
+    "よろしくお願いします",
+    "すみません",
+    "了解",
+    "おやすみ",
+    "はい",
+];
+
+/// Counts the first token and first two tokens of each sentence and builds a
+/// small FST of the likeliest openers for the empty-context suggestion bar.
+pub struct SentenceStartConsumer {
+    counts: NgramCounts,
+    curated: Vec<String>,
+    max_entries: usize,
+    output: PathBuf,
+}
+
+impl SentenceStartConsumer {
+    pub fn new(max_entries: usize, output: PathBuf) -> Self {
+        Self {
+            counts: NgramCounts::default(),
+            curated: Vec::new(),
+            max_entries,
+            output,
+        }
+    }
+
+    /// Adds [`CURATED_OPENERS`], split into tokens by `tokenize` (the
+    /// corpus tokenizer). Each is keyed by its first two tokens at most, like
+    /// the counted openers, so a curated opener the corpus also starts
+    /// sentences with is one entry.
+    pub fn with_curated_openers(mut self, mut tokenize: impl FnMut(&str) -> Vec<Token>) -> Self {
+        self.curated = CURATED_OPENERS
+            .iter()
+            .filter_map(|opener| opener_keys(&tokenize(opener)).pop())
+            .collect();
+        self
+    }
+}
+
+/// Keys counted for a sentence starting with `tokens`: its first token and,
+/// if valid too, its first two joined by [`SEPARATOR`].
+fn opener_keys(tokens: &[Token]) -> Vec<String> {
+    let Some(first) = tokens.first().filter(|token| ngram_key::is_valid_token(&token.surface)) else {
+        return Vec::new();
+    };
+    let mut keys = vec![first.surface.clone()];
+    if let Some(second) = tokens.get(1).filter(|token| ngram_key::is_valid_token(&token.surface)) {
+        keys.push(format!("{}{}{}", first.surface, SEPARATOR, second.surface));
+    }
+    keys
+}
+
+impl SentenceConsumer for SentenceStartConsumer {
+    fn name(&self) -> &str {
+        "sentence-start"
+    }
+
+    fn fields(&self) -> TokenFields {
+        TokenFields::default()
+    }
+
+    fn consume(&mut self, tokens: &[Token], weight: u32) {
+        for key in opener_keys(tokens) {
+            *self.counts.entry_ref(key.as_str()).or_insert(0) += weight;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    fn prune(&mut self, threshold: u32) -> usize {
+        ngram::prune_ngrams(&mut self.counts, threshold)
+    }
+
+    fn check(&self, gates: &QualityGates) -> Result<()> {
+        gates.check_ngram_count(self.name(), &self.counts)
+    }
+
+    fn finish(self: Box<Self>, options: &BuildOptions) -> Result<()> {
+        let histogram = CountHistogram::from_counts(&self.counts);
+        let mut entries = ngram::filter_ngrams(&self.counts, &histogram, &options.filter_config, options.smoothing);
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        entries.truncate(self.max_entries);
+
+        // Curated openers rank alongside the most common corpus opener
+        let top_score = entries.first().map(|(_, score)| *score).unwrap_or(0);
+        for opener in &self.curated {
+            match entries.iter_mut().find(|(key, _)| key == opener) {
+                Some(entry) => entry.1 = entry.1.max(top_score),
+                None => entries.push((opener.clone(), top_score)),
+            }
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        tracing::info!("[sentence-start] {} openers kept", entries.len());
+
+        ngram::build_fst(&entries, KeySchema::new(1, 2), &self.output)?;
+        tracing::info!("[sentence-start] FST generated at {:?}", self.output);
+        Ok(())
+    }
+}
+
+/// Tracks tokens produced by unknown-word processing, i.e. words missing from
+/// the dictionary, and writes them as TSV sorted by frequency.
+pub struct UnknownTokenConsumer {
+    counts: NgramCounts,
+    total_tokens: u64,
+    unknown_tokens: u64,
+    output: PathBuf,
+}
+
+impl UnknownTokenConsumer {
+    pub fn new(output: PathBuf) -> Self {
+        Self {
+            counts: NgramCounts::default(),
+            total_tokens: 0,
+            unknown_tokens: 0,
+            output,
+        }
+    }
+}
+
+impl SentenceConsumer for UnknownTokenConsumer {
+    fn name(&self) -> &str {
+        "unknown-stats"
+    }
+
+    fn fields(&self) -> TokenFields {
+        TokenFields::default()
+    }
+
+    // OOV statistics describe the corpus as is, so style weights are ignored
+    fn consume(&mut self, tokens: &[Token], _weight: u32) {
+        self.total_tokens += tokens.len() as u64;
+        for token in tokens.iter().filter(|token| token.unknown) {
+            self.unknown_tokens += 1;
+            *self.counts.entry_ref(token.surface.as_str()).or_insert(0) += 1;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    fn prune(&mut self, threshold: u32) -> usize {
+        ngram::prune_ngrams(&mut self.counts, threshold)
+    }
+
+    fn finish(self: Box<Self>, _options: &BuildOptions) -> Result<()> {
+        let rate = if self.total_tokens == 0 {
+            0.0
+        } else {
+            self.unknown_tokens as f64 / self.total_tokens as f64
+        };
+        tracing::info!(
+            "[unknown-stats] {} of {} tokens unknown ({:.2}%), {} distinct",
+            self.unknown_tokens,
+            self.total_tokens,
+            rate * 100.0,
+            self.counts.len()
+        );
+
+        let mut entries: Vec<(&str, u32)> = self.counts.iter().map(|(k, &v)| (k.as_ref(), v)).collect();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        let mut writer = BufWriter::new(File::create(&self.output)?);
+        writeln!(writer, "surface\tcount")?;
+        for (surface, count) in entries {
+            writeln!(writer, "{}\t{}", surface, count)?;
+        }
+        writer.flush()?;
+        tracing::info!("[unknown-stats] Written to {:?}", self.output);
+        Ok(())
+    }
+}
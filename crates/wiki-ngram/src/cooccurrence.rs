@@ -0,0 +1,143 @@
+//! Word co-occurrence counts (`--outputs cooccurrence`) for training word
+//! embeddings (GloVe, SVD of PPMI) on the same tokenization as the shipped
+//! dictionary and models.
+//!
+//! Every pair of surfaces at most `--cooccurrence-window` tokens apart in a
+//! sentence is counted once per occurrence, in either order. Pairs seen
+//! fewer than `--cooccurrence-min-count` times are dropped and the rest are
+//! written as a MatrixMarket coordinate file (`scipy.io.mmread` reads it):
+//!
+//! ```text
+//! %%MatrixMarket matrix coordinate integer symmetric
+//! 3 3 4
+//! 2 1 120
+//! ...
+//! ```
+//!
+//! The matrix is symmetric, so only entries with `row >= column` are
+//! listed. Row and column numbers are the 1-based word IDs of the
+//! `.vocab.tsv` file next to it, which numbers the words of the kept pairs
+//! from the most frequent down.
+
+use anyhow::{Context, Result};
+use keyboard_formats::ngram_key::{self, SEPARATOR};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::consumer::{BuildOptions, SentenceConsumer};
+use crate::ngram::{self, NgramCounts};
+use crate::tokenize::{Token, TokenFields};
+
+/// Counts word pairs within a window and writes them as a sparse matrix.
+pub struct CooccurrenceConsumer {
+    words: NgramCounts,
+    pairs: NgramCounts,
+    window: usize,
+    min_count: u32,
+    output: PathBuf,
+}
+
+impl CooccurrenceConsumer {
+    pub fn new(window: usize, min_count: u32, output: PathBuf) -> Self {
+        Self {
+            words: NgramCounts::default(),
+            pairs: NgramCounts::default(),
+            window,
+            min_count,
+            output,
+        }
+    }
+
+    /// The word list numbering the rows and columns of the matrix at `output`.
+    pub fn vocab_path(output: &Path) -> PathBuf {
+        output.with_extension("vocab.tsv")
+    }
+}
+
+impl SentenceConsumer for CooccurrenceConsumer {
+    fn name(&self) -> &str {
+        "cooccurrence"
+    }
+
+    fn fields(&self) -> TokenFields {
+        TokenFields::default()
+    }
+
+    fn consume(&mut self, tokens: &[Token], weight: u32) {
+        let words: Vec<&str> = tokens
+            .iter()
+            .map(|token| token.surface.as_str())
+            .filter(|surface| ngram_key::is_valid_token(surface))
+            .collect();
+        for (i, &word) in words.iter().enumerate() {
+            *self.words.entry_ref(word).or_insert(0) += weight;
+            for &other in words.iter().skip(i + 1).take(self.window) {
+                let (first, second) = if word <= other { (word, other) } else { (other, word) };
+                let pair = format!("{}{}{}", first, SEPARATOR, second);
+                *self.pairs.entry_ref(pair.as_str()).or_insert(0) += weight;
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    // Word counts stay whole, so every kept pair's words have one
+    fn prune(&mut self, threshold: u32) -> usize {
+        ngram::prune_ngrams(&mut self.pairs, threshold)
+    }
+
+    fn finish(self: Box<Self>, _options: &BuildOptions) -> Result<()> {
+        let pairs: Vec<(&str, &str, u32)> = self
+            .pairs
+            .iter()
+            .filter(|(_, &count)| count >= self.min_count)
+            .filter_map(|(pair, &count)| pair.split_once(SEPARATOR).map(|(first, second)| (first, second, count)))
+            .collect();
+
+        let mut vocab: Vec<&str> = pairs.iter().flat_map(|&(first, second, _)| [first, second]).collect();
+        vocab.sort_unstable();
+        vocab.dedup();
+        let count = |word: &str| self.words.get(word).copied().unwrap_or(0);
+        vocab.sort_by(|a, b| count(b).cmp(&count(a)).then(a.cmp(b)));
+        let ids: HashMap<&str, usize> = vocab.iter().enumerate().map(|(i, &word)| (word, i + 1)).collect();
+
+        let mut entries: Vec<(usize, usize, u32)> = pairs
+            .iter()
+            .map(|&(first, second, count)| {
+                let (a, b) = (ids[first], ids[second]);
+                (a.max(b), a.min(b), count)
+            })
+            .collect();
+        entries.sort_unstable();
+        tracing::info!(
+            "[cooccurrence] {} of {} pairs kept, over {} words",
+            entries.len(),
+            self.pairs.len(),
+            vocab.len()
+        );
+
+        let file = File::create(&self.output).with_context(|| format!("Failed to create {:?}", self.output))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "%%MatrixMarket matrix coordinate integer symmetric")?;
+        writeln!(writer, "% Word pairs within {} tokens; IDs are the id column of the .vocab.tsv file", self.window)?;
+        writeln!(writer, "{} {} {}", vocab.len(), vocab.len(), entries.len())?;
+        for (row, column, count) in entries {
+            writeln!(writer, "{} {} {}", row, column, count)?;
+        }
+        writer.flush()?;
+
+        let vocab_path = Self::vocab_path(&self.output);
+        let mut writer = BufWriter::new(File::create(&vocab_path)?);
+        writeln!(writer, "id\tword\tcount")?;
+        for (i, word) in vocab.iter().enumerate() {
+            writeln!(writer, "{}\t{}\t{}", i + 1, word, count(word))?;
+        }
+        writer.flush()?;
+        tracing::info!("[cooccurrence] Written to {:?} and {:?}", self.output, vocab_path);
+        Ok(())
+    }
+}
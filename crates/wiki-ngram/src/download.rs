@@ -15,11 +15,11 @@ pub fn download_wikipedia(cache_dir: &Path) -> Result<PathBuf> {
 
     // Check if already downloaded
     if output_path.exists() {
-        log::info!("Wikipedia dump already cached at {:?}", output_path);
+        tracing::info!("Wikipedia dump already cached at {:?}", output_path);
         return Ok(output_path);
     }
 
-    log::info!("Downloading from {}", WIKIPEDIA_URL);
+    tracing::info!("Downloading from {}", WIKIPEDIA_URL);
     
     let client = Client::new();
     let mut response = client.get(WIKIPEDIA_URL).send()?;
@@ -50,12 +50,12 @@ pub fn download_wikipedia(cache_dir: &Path) -> Result<PathBuf> {
 
         // Log every 50MB for CI visibility
         if downloaded > 0 && downloaded % (50 * 1024 * 1024) < bytes_read as u64 {
-            log::info!("Downloaded {} MB / {} MB", downloaded / 1024 / 1024, total_size / 1024 / 1024);
+            tracing::info!("Downloaded {} MB / {} MB", downloaded / 1024 / 1024, total_size / 1024 / 1024);
         }
     }
 
     pb.finish_with_message("Download complete");
-    log::info!("Downloaded to {:?}", output_path);
+    tracing::info!("Downloaded to {:?}", output_path);
 
     Ok(output_path)
 }
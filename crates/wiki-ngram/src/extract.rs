@@ -1,40 +1,90 @@
 use anyhow::Result;
-use bzip2::read::BzDecoder;
 use indicatif::{ProgressBar, ProgressStyle};
+use keyboard_formats::text_length::LengthUnit;
 use quick_xml::events::Event;
 use quick_xml::Reader;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
-use vibrato::Tokenizer;
+use std::io::{BufReader, Read};
+
+use crate::boilerplate::BoilerplateClassifier;
+use crate::consumer::SentenceConsumer;
+use crate::prune::PrunePolicy;
+use crate::quality::CorpusStats;
+use crate::style::StyleProfile;
+use crate::tokenize::{PooledWorker, Token, TokenizerPool};
+
+/// Bounds on the sentences counted; sentences outside them are dropped and
+/// tallied in [`CorpusStats`].
+#[derive(Clone, Copy, Debug)]
+pub struct SentenceLimits {
+    /// Shortest a sentence may be, in `unit`
+    pub min_length: usize,
+    pub unit: LengthUnit,
+    /// Most tokens a sentence may have; longer ones are mostly lists and
+    /// table rows run together
+    pub max_tokens: usize,
+}
+
+impl SentenceLimits {
+    pub const DEFAULT_MIN_LENGTH: usize = 3;
+    pub const DEFAULT_MAX_TOKENS: usize = 150;
+
+    pub fn too_short(&self, sentence: &str) -> bool {
+        self.unit.length(sentence) < self.min_length
+    }
 
-use crate::ngram::extract_ngrams_from_tokens;
-use crate::tokenize::tokenize_text;
+    pub fn too_long(&self, tokens: &[Token]) -> bool {
+        tokens.len() > self.max_tokens
+    }
+}
 
+impl Default for SentenceLimits {
+    fn default() -> Self {
+        Self {
+            min_length: Self::DEFAULT_MIN_LENGTH,
+            unit: LengthUnit::default(),
+            max_tokens: Self::DEFAULT_MAX_TOKENS,
+        }
+    }
+}
+
+/// What decides whether a sentence is counted, besides its having at least
+/// two tokens.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SentenceFilters<'a> {
+    /// Drops sentences it classifies as boilerplate
+    pub boilerplate: Option<&'a BoilerplateClassifier>,
+    pub limits: SentenceLimits,
+}
+
+/// Streams the decompressed dump once (see [`crate::bz2::open_dump`]),
+/// feeding every tokenized sentence to all `consumers`, and returns
+/// statistics for the quality gates.
 pub fn process_wikipedia(
-    wiki_bz2_path: &Path,
-    tokenizer: &Tokenizer,
-    max_ngram: usize,
+    dump: impl Read,
+    pool: &TokenizerPool,
+    consumers: &mut [Box<dyn SentenceConsumer>],
     limit: Option<usize>,
-    min_frequency: usize,
-) -> Result<HashMap<String, usize>> {
-    let file = File::open(wiki_bz2_path)?;
-    let decoder = BzDecoder::new(BufReader::new(file));
-    let buf_reader = BufReader::new(decoder);
+    prune_policy: &mut PrunePolicy,
+    style: StyleProfile,
+    filters: SentenceFilters,
+) -> Result<CorpusStats> {
+    let buf_reader = BufReader::new(dump);
     let mut reader = Reader::from_reader(buf_reader);
     reader.config_mut().trim_text(true);
 
-    let mut ngram_counts: HashMap<String, usize> = HashMap::new();
     let mut buf = Vec::new();
     let mut in_text = false;
     let mut current_text = String::new();
     let mut article_count = 0;
+    let mut stats = CorpusStats {
+        length_unit: filters.limits.unit,
+        ..CorpusStats::default()
+    };
 
     let pb = ProgressBar::new_spinner();
     pb.set_style(
         ProgressStyle::default_spinner()
-            .template("{spinner:.green} [{elapsed_precise}] Articles: {pos} | N-grams: {msg}")?
+            .template("{spinner:.green} [{elapsed_precise}] Articles: {pos} | Entries: {msg}")?
             .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
     );
 
@@ -53,21 +103,21 @@ pub fn process_wikipedia(
                     // Process the extracted text
                     let clean_text = clean_wiki_markup(&current_text);
                     if !clean_text.is_empty() {
-                        process_article(&clean_text, tokenizer, max_ngram, &mut ngram_counts);
+                        process_article(&clean_text, &mut pool.worker(), consumers, style, filters, &mut stats);
                         article_count += 1;
 
                         if article_count % 1000 == 0 {
                             pb.set_position(article_count);
-                            pb.set_message(format!("{}", ngram_counts.len()));
+                            pb.set_message(format!("{}", total_entries(consumers)));
                         }
                         
                         if article_count % 10000 == 0 {
-                            log::info!("Processed {} articles, {} unique N-grams", article_count, ngram_counts.len());
+                            tracing::info!("Processed {} articles, {} unique entries", article_count, total_entries(consumers));
                         }
                         
-                        // Prune periodically to prevent OOM
-                        if article_count % 100000 == 0 {
-                             crate::ngram::prune_ngrams(&mut ngram_counts, 5_000_000, min_frequency);
+                        // Check memory usage periodically and prune to prevent OOM
+                        if article_count % 1000 == 0 {
+                            prune_policy.maybe_prune_consumers(consumers);
                         }
 
                         if let Some(l) = limit {
@@ -87,7 +137,7 @@ pub fn process_wikipedia(
             }
             Ok(Event::Eof) => break,
             Err(e) => {
-                log::warn!("XML parse error at position {}: {:?}", reader.buffer_position(), e);
+                tracing::warn!("XML parse error at position {}: {:?}", reader.buffer_position(), e);
                 break;
             }
             _ => {}
@@ -95,32 +145,54 @@ pub fn process_wikipedia(
         buf.clear();
     }
 
-    pb.finish_with_message(format!("Processed {} articles, {} unique N-grams", article_count, ngram_counts.len()));
+    pb.finish_with_message(format!("Processed {} articles, {} unique entries", article_count, total_entries(consumers)));
 
-    Ok(ngram_counts)
+    Ok(stats)
+}
+
+fn total_entries(consumers: &[Box<dyn SentenceConsumer>]) -> usize {
+    consumers.iter().map(|consumer| consumer.len()).sum()
 }
 
 fn process_article(
     text: &str,
-    tokenizer: &Tokenizer,
-    max_ngram: usize,
-    ngram_counts: &mut HashMap<String, usize>,
+    worker: &mut PooledWorker,
+    consumers: &mut [Box<dyn SentenceConsumer>],
+    style: StyleProfile,
+    filters: SentenceFilters,
+    stats: &mut CorpusStats,
 ) {
+    let is_terminator = |c| c == '。' || c == '\n' || c == '.' || c == '！' || c == '？';
+
     // Split into sentences (simple split by periods and newlines)
-    for sentence in text.split(|c| c == '。' || c == '\n' || c == '.' || c == '！' || c == '？') {
+    for piece in text.split_inclusive(is_terminator) {
+        let terminator = piece.chars().last().filter(|&c| is_terminator(c));
+        let sentence = terminator.map_or(piece, |c| &piece[..piece.len() - c.len_utf8()]);
         let sentence = sentence.trim();
-        if sentence.len() < 3 {
+        if filters.limits.too_short(sentence) {
+            stats.short_sentences += 1;
+            continue;
+        }
+        if filters.boilerplate.is_some_and(|classifier| classifier.is_boilerplate(sentence)) {
+            stats.boilerplate_sentences += 1;
             continue;
         }
 
         // Tokenize
-        let tokens = tokenize_text(tokenizer, sentence);
+        let tokens = worker.tokenize(sentence);
         if tokens.len() < 2 {
             continue;
         }
+        if filters.limits.too_long(&tokens) {
+            stats.long_sentences += 1;
+            continue;
+        }
 
-        // Extract N-grams
-        extract_ngrams_from_tokens(&tokens, max_ngram, ngram_counts);
+        stats.record(sentence, &tokens);
+        let weight = style.sentence_weight(&tokens, terminator);
+        for consumer in consumers.iter_mut() {
+            consumer.consume(&tokens, weight);
+        }
     }
 }
 
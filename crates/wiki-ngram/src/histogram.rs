@@ -0,0 +1,80 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::ngram::{ngram_order, NgramCounts};
+
+/// Frequency-of-frequencies per n-gram order: for each order, how many
+/// distinct n-grams occur exactly `count` times.
+#[derive(Debug, Default)]
+pub struct CountHistogram {
+    orders: BTreeMap<usize, BTreeMap<u32, u64>>,
+}
+
+impl CountHistogram {
+    pub fn from_counts(ngram_counts: &NgramCounts) -> Self {
+        let mut histogram = Self::default();
+        for (ngram, &count) in ngram_counts {
+            *histogram
+                .orders
+                .entry(ngram_order(ngram))
+                .or_default()
+                .entry(count)
+                .or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// Iterates over the orders present, in ascending order.
+    pub fn orders(&self) -> impl Iterator<Item = usize> + '_ {
+        self.orders.keys().copied()
+    }
+
+    /// Count -> number of n-grams with that count, for one order.
+    pub fn order(&self, order: usize) -> Option<&BTreeMap<u32, u64>> {
+        self.orders.get(&order)
+    }
+
+    /// Number of n-grams of `order` occurring exactly `count` times (N_c).
+    pub fn n_c(&self, order: usize, count: u32) -> u64 {
+        self.order(order)
+            .and_then(|h| h.get(&count))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Logs the histogram with counts above `max_count` collapsed into one bucket.
+    pub fn log_summary(&self, max_count: u32) {
+        tracing::info!("Count histogram (before filtering):");
+        for (order, histogram) in &self.orders {
+            let distinct: u64 = histogram.values().sum();
+            tracing::info!("  {}-grams: {} distinct", order, distinct);
+            let mut tail = 0;
+            for (&count, &n) in histogram {
+                if count <= max_count {
+                    tracing::info!("    count {:>4}: {}", count, n);
+                } else {
+                    tail += n;
+                }
+            }
+            if tail > 0 {
+                tracing::info!("    count >{:>3}: {}", max_count, tail);
+            }
+        }
+    }
+
+    /// Writes the full histogram as TSV: `order<TAB>count<TAB>ngrams`.
+    pub fn write_tsv(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "order\tcount\tngrams")?;
+        for (order, histogram) in &self.orders {
+            for (count, n) in histogram {
+                writeln!(writer, "{}\t{}\t{}", order, count, n)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! Linear interpolation of two models (`wiki-ngram interpolate`), e.g.
+//! Wikipedia for coverage with a messaging corpus for style, into one FST.
+//!
+//! The FSTs store counts, which [`keyboard_formats::language_model`] turns
+//! into probabilities, so the result stores counts too: each order's counts
+//! are normalized to relative frequencies in both models, mixed as
+//! `λ * first + (1 - λ) * second` and scaled back to the mixed corpus size.
+//! This interpolates the n-gram distributions themselves; the conditional
+//! probabilities of a history come out weighted by how often each model
+//! sees that history, as with count merging. The vocabularies next to the
+//! models (`<stem>-vocab.fst`) are mixed the same way.
+//!
+//! The weight λ of the first model is given with `--weight` or tuned on
+//! held-out sentences by expectation maximization: each held-out token gets
+//! a probability from both models and λ moves to the average share the
+//! first model has of the mixed probability, until it settles. The
+//! probabilities are [`Model::token_probability`]'s, which sum to one;
+//! stupid-backoff scores don't, and would skew λ and the perplexities.
+
+use anyhow::{Context, Result};
+use clap::Args;
+use keyboard_formats::language_model::{decode_count, Model};
+use keyboard_formats::ngram_key::{KeySchema, SEPARATOR};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::ngram::{self, ngram_order};
+
+/// Rounds of expectation maximization at most.
+const MAX_ITERATIONS: usize = 100;
+/// Change in λ below which it has settled.
+const CONVERGENCE: f64 = 1e-6;
+
+#[derive(Args, Debug)]
+pub struct InterpolateArgs {
+    /// N-gram FST of the first model, with its vocabulary FST next to it
+    #[arg(long)]
+    first: PathBuf,
+
+    /// N-gram FST of the second model, with its vocabulary FST next to it
+    #[arg(long)]
+    second: PathBuf,
+
+    /// Weight of the first model (0.0-1.0); tuned on --held-out when not given
+    #[arg(long, conflicts_with = "held_out")]
+    weight: Option<f64>,
+
+    /// Held-out sentences to tune the weight on, one per line as space separated
+    /// tokens of the models' kind (surfaces, surface/reading pairs, ...)
+    #[arg(long)]
+    held_out: Option<PathBuf>,
+
+    /// Maximum N-gram size of the models, for scoring the held-out sentences
+    #[arg(long, default_value = "3")]
+    max_ngram: usize,
+
+    /// Interpolated N-gram FST; the vocabularies go next to it
+    #[arg(long, default_value = "output/interpolated-ngrams.fst")]
+    output: PathBuf,
+}
+
+pub fn run(args: &InterpolateArgs) -> Result<()> {
+    let weight = match (args.weight, &args.held_out) {
+        (Some(weight), _) => weight,
+        (None, Some(held_out)) => tune_on(args, held_out)?,
+        (None, None) => anyhow::bail!("Give --weight or --held-out"),
+    };
+    anyhow::ensure!((0.0..=1.0).contains(&weight), "The weight must be within 0.0-1.0, not {}", weight);
+    tracing::info!("Interpolating {:?} ({:.3}) with {:?} ({:.3})", args.first, weight, args.second, 1.0 - weight);
+
+    let mut outputs = vec![(args.first.clone(), args.second.clone(), args.output.clone())];
+    let vocabs = [&args.first, &args.second].map(|path| ngram::sibling_path(path, "vocab"));
+    if vocabs.iter().all(|vocab| vocab.exists()) {
+        let [first, second] = vocabs;
+        outputs.push((first, second, ngram::sibling_path(&args.output, "vocab")));
+    } else {
+        tracing::warn!("{:?} or {:?} is missing; interpolating the N-grams only", vocabs[0], vocabs[1]);
+    }
+    for (first, second, output) in outputs {
+        let mixed = interpolate(&ngram::read_fst(&first)?, &ngram::read_fst(&second)?, weight);
+        let max_order = mixed.iter().map(|(key, _)| ngram_order(key)).max().unwrap_or(1);
+        ngram::build_fst(&mixed, KeySchema::new(1, max_order), &output)?;
+        tracing::info!("Interpolated model written to {:?}", output);
+    }
+    Ok(())
+}
+
+/// Tunes the weight on the held-out sentences, logging the perplexity of
+/// each model and of the mix.
+fn tune_on(args: &InterpolateArgs, held_out: &Path) -> Result<f64> {
+    let open = |path: &Path| {
+        Model::open(path, &ngram::sibling_path(path, "vocab"), args.max_ngram)
+            .with_context(|| format!("Failed to load {:?} and its vocabulary", path))
+    };
+    let (first, second) = (open(&args.first)?, open(&args.second)?);
+    let mut probabilities = Vec::new();
+    let file = File::open(held_out).with_context(|| format!("Failed to open {:?}", held_out))?;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let tokens: Vec<&str> = line.split(SEPARATOR).filter(|token| !token.is_empty()).collect();
+        for i in 0..tokens.len() {
+            let history = &tokens[(i + 1).saturating_sub(args.max_ngram)..i];
+            probabilities.push((
+                first.token_probability(history, tokens[i]),
+                second.token_probability(history, tokens[i]),
+            ));
+        }
+    }
+    anyhow::ensure!(!probabilities.is_empty(), "{:?} has no tokens", held_out);
+    let weight = tune_weight(&probabilities);
+    tracing::info!(
+        "Tuned on {} held-out tokens: weight {:.4}, perplexity {:.1} (first), {:.1} (second), {:.1} (mixed)",
+        probabilities.len(),
+        weight,
+        perplexity(&probabilities, 1.0),
+        perplexity(&probabilities, 0.0),
+        perplexity(&probabilities, weight)
+    );
+    Ok(weight)
+}
+
+/// The weight of the first model maximizing the likelihood of held-out
+/// tokens the two models give the probabilities `(first, second)` of.
+pub fn tune_weight(probabilities: &[(f64, f64)]) -> f64 {
+    let mut weight = 0.5;
+    for _ in 0..MAX_ITERATIONS {
+        let share: f64 = probabilities
+            .iter()
+            .map(|&(first, second)| weight * first / (weight * first + (1.0 - weight) * second))
+            .sum();
+        let next = share / probabilities.len() as f64;
+        let settled = (next - weight).abs() < CONVERGENCE;
+        weight = next;
+        if settled {
+            break;
+        }
+    }
+    weight
+}
+
+/// Perplexity of the mix with `weight` over the held-out tokens.
+pub fn perplexity(probabilities: &[(f64, f64)], weight: f64) -> f64 {
+    let log_sum: f64 =
+        probabilities.iter().map(|&(first, second)| (weight * first + (1.0 - weight) * second).ln()).sum();
+    (-log_sum / probabilities.len() as f64).exp()
+}
+
+/// Mixes two FSTs' entries (sorted by key, values as written by
+/// wiki-ngram), `weight` being that of the first.
+pub fn interpolate(first: &[(String, u64)], second: &[(String, u64)], weight: f64) -> Vec<(String, u64)> {
+    let totals = |entries: &[(String, u64)]| {
+        let mut totals: HashMap<usize, f64> = HashMap::new();
+        for (key, score) in entries {
+            *totals.entry(ngram_order(key)).or_default() += decode_count(*score);
+        }
+        totals
+    };
+    let (first_totals, second_totals) = (totals(first), totals(second));
+    let mix = |key: &str, first_score: Option<u64>, second_score: Option<u64>| {
+        let order = ngram_order(key);
+        let first_total = first_totals.get(&order).copied().unwrap_or(0.0);
+        let second_total = second_totals.get(&order).copied().unwrap_or(0.0);
+        let share = |score: Option<u64>, total: f64| score.map_or(0.0, |score| decode_count(score) / total);
+        let frequency = weight * share(first_score, first_total) + (1.0 - weight) * share(second_score, second_total);
+        frequency * (weight * first_total + (1.0 - weight) * second_total)
+    };
+
+    let mut mixed = Vec::with_capacity(first.len().max(second.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < first.len() || j < second.len() {
+        let ordering = match (first.get(i), second.get(j)) {
+            (Some(a), Some(b)) => a.0.cmp(&b.0),
+            (Some(_), None) => Ordering::Less,
+            (None, _) => Ordering::Greater,
+        };
+        let (key, count) = match ordering {
+            Ordering::Less => (&first[i].0, mix(&first[i].0, Some(first[i].1), None)),
+            Ordering::Greater => (&second[j].0, mix(&second[j].0, None, Some(second[j].1))),
+            Ordering::Equal => (&first[i].0, mix(&first[i].0, Some(first[i].1), Some(second[j].1))),
+        };
+        i += (ordering != Ordering::Greater) as usize;
+        j += (ordering != Ordering::Less) as usize;
+        // A weight of 0 or 1 leaves out what only the other model has
+        if count > 0.0 {
+            mixed.push((key.clone(), ngram::encode_score(count)));
+        }
+    }
+    mixed
+}
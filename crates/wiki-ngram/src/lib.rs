@@ -0,0 +1,24 @@
+//! Corpus counting and model building behind the wiki-ngram binary, for
+//! tools and forks that drive a pass with their own consumers or plug their
+//! own [`stage`] implementations into it.
+
+pub mod aggregate;
+pub mod boilerplate;
+pub mod bz2;
+pub mod consumer;
+pub mod cooccurrence;
+pub mod download;
+pub mod extract;
+pub mod histogram;
+pub mod interpolate;
+pub mod ngram;
+pub mod profile;
+pub mod prune;
+pub mod prune_model;
+pub mod quality;
+pub mod quote;
+pub mod seekable;
+pub mod shard;
+pub mod stage;
+pub mod style;
+pub mod tokenize;
@@ -1,30 +1,119 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
 use fst::Streamer;
+use keyboard_formats::text_length::LengthUnit;
+use std::ffi::OsString;
 use std::fs::{self, File};
 use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 
-mod download;
-mod extract;
-mod ngram;
-mod tokenize;
+use wiki_ngram::profile::Profile;
+use wiki_ngram::{
+    aggregate, boilerplate, bz2, consumer, cooccurrence, download, extract, interpolate, ngram, prune, prune_model,
+    quality, seekable, style, tokenize,
+};
+
+/// Artifacts that can be produced from a single pass over the corpus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum OutputKind {
+    /// Surface N-gram FST (written to --output)
+    Ngrams,
+    /// Surface/reading N-gram FST
+    ReadingNgrams,
+    /// Surface/POS N-gram FST
+    PosNgrams,
+    /// Lemma N-gram FST
+    LemmaNgrams,
+    /// Unigram vocabulary FST
+    Vocab,
+    /// TSV of tokens missing from the dictionary, with the OOV rate logged
+    UnknownStats,
+    /// Small FST of sentence-initial words and word pairs plus curated greetings,
+    /// for suggestions before anything is typed
+    SentenceStart,
+    /// Sparse matrix of word pairs within --cooccurrence-window tokens (MatrixMarket),
+    /// with its word list, for training word embeddings
+    Cooccurrence,
+}
 
 #[derive(Parser, Debug)]
 #[command(name = "wiki-ngram")]
 #[command(about = "Generate N-gram FST from Japanese Wikipedia for keyboard prediction")]
+#[command(args_conflicts_with_subcommands = true)]
+#[command(args_override_self = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Merge anonymized per-user N-gram counts into a thresholded overlay FST
+    AggregateUserNgrams(aggregate::AggregateArgs),
+    /// Recompress the dump once into seekable zstd, for faster runs with --dump
+    PrepareDump(seekable::PrepareDumpArgs),
+    /// Shrink a built N-gram FST by relative-entropy (Stolcke) pruning
+    PruneModel(prune_model::PruneModelArgs),
+    /// Linearly interpolate two models into one, with a weight given or tuned on held-out text
+    Interpolate(interpolate::InterpolateArgs),
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
-    /// Minimum frequency threshold (N-grams appearing ≤ this value will be filtered out)
+    /// Count with the thresholds of a product target (see wiki_ngram::profile); flags
+    /// given explicitly override the profile's
+    #[arg(long, value_enum)]
+    profile: Option<Profile>,
+
+    /// Minimum frequency threshold (see --frequency-comparison for how it is applied)
     #[arg(long, default_value = "2")]
-    min_frequency: usize,
+    min_frequency: u32,
+
+    /// How counts are compared to the minimum frequency: `gt` keeps count > threshold,
+    /// `ge` keeps count >= threshold
+    #[arg(long, value_enum, default_value = "gt")]
+    frequency_comparison: ngram::FrequencyComparison,
+
+    /// Per-order minimum frequency overriding --min-frequency, as ORDER=COUNT (repeatable)
+    #[arg(long = "order-min-frequency", value_parser = parse_order_threshold)]
+    order_min_frequency: Vec<(usize, u32)>,
+
+    /// Drop the rarest N-grams of each order making up this fraction (0.0-1.0) of its count mass
+    #[arg(long)]
+    drop_mass_fraction: Option<f64>,
+
+    /// Multiply the counts of trigrams and longer N-grams seen at most
+    /// --rare-long-margin times their threshold by this factor (0.0-1.0)
+    /// before scoring, so barely kept sequences rank below well-attested
+    /// bigrams
+    #[arg(long)]
+    rare_long_discount: Option<f64>,
+
+    /// How many times its threshold an N-gram's count may be and still be
+    /// discounted by --rare-long-discount
+    #[arg(long, default_value = "2.0", requires = "rare_long_discount")]
+    rare_long_margin: f64,
+
+    /// Drop N-grams of at least this many tokens that occur only once, inside
+    /// a quoted passage (「」, 『』, “”, ""), so predictions don't reproduce quotations
+    #[arg(long)]
+    drop_quoted_ngrams: Option<usize>,
 
     /// Maximum N-gram size (2=bigram, 3=trigram)
     #[arg(long, default_value = "3")]
     max_ngram: usize,
 
-    /// Path to Vibrato dictionary (system.dic.zst from mozc-dict-gen)
-    #[arg(long, default_value = "output/system.dic.zst")]
-    dict_path: PathBuf,
+    /// Artifacts to produce in one pass; extra outputs are written next to --output
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "ngrams")]
+    outputs: Vec<OutputKind>,
+
+    /// Path to Vibrato dictionary from mozc-dict-gen, in any of its --compression formats
+    /// [default: output/system.dic.zst, system.dic.gz or system.dic, whichever exists]
+    #[arg(long)]
+    dict_path: Option<PathBuf>,
 
     /// Output FST path
     #[arg(long, default_value = "output/wiki-ngrams.fst")]
@@ -42,15 +131,114 @@ struct Args {
     #[arg(long)]
     stats: bool,
 
+    /// Read this Wikipedia dump instead of downloading one: bz2, zstd, gzip or
+    /// decompressed XML, told from the file's first bytes
+    #[arg(long)]
+    dump: Option<PathBuf>,
+
+    /// Threads decompressing the dump: a bz2 dump is split by the streams of its multistream
+    /// index if it has one next to it and by bz2 blocks otherwise, a seekable zstd dump from
+    /// prepare-dump by its frames; 1 reads it sequentially (default: one per core)
+    #[arg(long)]
+    decompress_threads: Option<usize>,
+
     /// Limit the number of articles to process (for debugging)
     #[arg(long)]
     limit: Option<usize>,
+
+    /// How counts are converted into FST scores
+    #[arg(long, value_enum, default_value = "log-count")]
+    smoothing: ngram::Smoothing,
+
+    /// Largest count listed individually in the logged count histogram
+    #[arg(long, default_value = "20")]
+    histogram_max_count: u32,
+
+    /// Write the full count histogram (before filtering) as TSV to this path
+    #[arg(long)]
+    histogram_output: Option<PathBuf>,
+
+    /// Weighting of sentences by writing style; `messaging` boosts short and
+    /// dialogue-like sentences
+    #[arg(long, value_enum, default_value = "neutral")]
+    style_profile: style::StyleProfile,
+
+    /// Drop boilerplate sentences (list items, table rows, references,
+    /// leftover markup) before counting, with the built-in classifier weights
+    #[arg(long)]
+    drop_boilerplate: bool,
+
+    /// Train the boilerplate classifier on this labeled sample file
+    /// (`#boilerplate-samples` header) instead, reporting its precision and
+    /// recall on held-out samples; implies --drop-boilerplate
+    #[arg(long)]
+    boilerplate_samples: Option<PathBuf>,
+
+    /// Sentences shorter than this, in --length-unit, are not counted
+    #[arg(long, default_value_t = extract::SentenceLimits::DEFAULT_MIN_LENGTH)]
+    min_sentence_chars: usize,
+
+    /// Unit of --min-sentence-chars and the logged sentence lengths: `chars`
+    /// (Unicode scalar values), `clusters` (what a reader sees as one
+    /// character, e.g. か with a combining voiced mark) or `bytes` (UTF-8)
+    #[arg(long, default_value = "chars")]
+    length_unit: LengthUnit,
+
+    /// Sentences of more than this many tokens (mostly lists and table rows
+    /// run together) are not counted
+    #[arg(long, default_value_t = extract::SentenceLimits::DEFAULT_MAX_TOKENS)]
+    max_sentence_tokens: usize,
+
+    /// Maximum number of corpus openers kept in the sentence-start FST
+    #[arg(long, default_value = "2000")]
+    sentence_start_max_entries: usize,
+
+    /// Words apart a pair may be to count in the co-occurrence matrix
+    #[arg(long, default_value = "5")]
+    cooccurrence_window: usize,
+
+    /// Minimum count of a word pair kept in the co-occurrence matrix
+    #[arg(long, default_value = "2")]
+    cooccurrence_min_count: u32,
+
+    #[command(flatten)]
+    quality_gates: quality::QualityGates,
+
+    /// Process RSS (in MB) above which rare N-grams are pruned during extraction
+    #[arg(long, default_value = "6144")]
+    prune_high_watermark_mb: u64,
+
+    /// Memory (in MB) pruning aims for: counts are cut to this share of the
+    /// entries that fit under the high watermark
+    #[arg(long, default_value = "4608")]
+    prune_low_watermark_mb: u64,
 }
 
 fn main() -> Result<()> {
-    env_logger::init();
-    let args = Args::parse();
+    let _telemetry = telemetry::init("wiki-ngram")?;
+    let cli = parse_cli();
+    match cli.command {
+        Some(Command::AggregateUserNgrams(args)) => tracing::info_span!("aggregate").in_scope(|| aggregate::run(&args)),
+        Some(Command::PrepareDump(args)) => tracing::info_span!("prepare_dump").in_scope(|| seekable::run(&args)),
+        Some(Command::PruneModel(args)) => tracing::info_span!("prune_model").in_scope(|| prune_model::run(&args)),
+        Some(Command::Interpolate(args)) => tracing::info_span!("interpolate").in_scope(|| interpolate::run(&args)),
+        None => run(cli.args),
+    }
+}
 
+/// Parses the command line, again with the flags of `--profile` in front if
+/// one is given, so the flags given after them win.
+fn parse_cli() -> Cli {
+    let cli = Cli::parse();
+    let Some(profile) = cli.args.profile else {
+        return cli;
+    };
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    args.splice(1..1, profile.args().iter().map(OsString::from));
+    Cli::parse_from(args)
+}
+
+fn run(args: Args) -> Result<()> {
     if args.stats {
         return show_stats(&args.output);
     }
@@ -59,43 +247,178 @@ fn main() -> Result<()> {
         return run_dummy_mode(&args.output);
     }
 
-    log::info!("Starting Wikipedia N-gram FST generation");
-    log::info!("Min frequency: {}", args.min_frequency);
-    log::info!("Max N-gram: {}", args.max_ngram);
+    if let Some(fraction) = args.drop_mass_fraction {
+        anyhow::ensure!((0.0..=1.0).contains(&fraction), "--drop-mass-fraction must be between 0.0 and 1.0");
+    }
+    if let Some(factor) = args.rare_long_discount {
+        anyhow::ensure!((0.0..=1.0).contains(&factor), "--rare-long-discount must be between 0.0 and 1.0");
+    }
+
+    tracing::info!("Starting Wikipedia N-gram FST generation");
+    tracing::info!("Min frequency: {}", args.min_frequency);
+    tracing::info!("Max N-gram: {}", args.max_ngram);
+
+    let boilerplate = boilerplate_classifier(&args)?;
 
     // Ensure output directory exists
     if let Some(parent) = args.output.parent() {
         fs::create_dir_all(parent)?;
     }
 
-    // Step 1: Download Wikipedia dump
-    log::info!("Downloading Wikipedia dump...");
-    let wiki_path = download::download_wikipedia(&args.download_cache)?;
+    // Step 1: Download Wikipedia dump, unless one is given
+    let wiki_path = match &args.dump {
+        Some(dump) => dump.clone(),
+        None => {
+            tracing::info!("Downloading Wikipedia dump...");
+            tracing::info_span!("download").in_scope(|| download::download_wikipedia(&args.download_cache))?
+        }
+    };
 
     // Step 2: Load Vibrato tokenizer
-    log::info!("Loading Vibrato dictionary from {:?}", args.dict_path);
-    let tokenizer = tokenize::load_tokenizer(&args.dict_path)?;
-
-    // Step 3: Extract text and tokenize
-    log::info!("Extracting and tokenizing Wikipedia articles...");
-    let ngram_counts = extract::process_wikipedia(&wiki_path, &tokenizer, args.max_ngram, args.limit, args.min_frequency)?;
+    let dict_path = match &args.dict_path {
+        Some(dict_path) => dict_path.clone(),
+        None => mozc_dict_gen::find_dictionary(Path::new("output"), "system.dic")
+            .context("No output/system.dic[.zst|.gz]; build it with mozc-dict-gen or pass --dict-path")?,
+    };
+    tracing::info!("Loading Vibrato dictionary from {:?}", dict_path);
+    let tokenizer = tracing::info_span!("load_dictionary").in_scope(|| tokenize::load_tokenizer(&dict_path))?;
+    let mut consumers = build_consumers(&args, &tokenizer);
+    let fields = consumers
+        .iter()
+        .fold(tokenize::TokenFields::default(), |fields, consumer| fields.union(consumer.fields()));
+    let pool = tokenize::TokenizerPool::new(&tokenizer, fields);
 
-    // Step 4: Filter and calculate log scores
-    log::info!("Filtering N-grams (min frequency: {})...", args.min_frequency);
-    let filtered = ngram::filter_ngrams(&ngram_counts, args.min_frequency);
-    
-    log::info!("Total N-grams after filtering: {}", filtered.len());
+    // Step 3: Extract text and tokenize, feeding every requested output
+    tracing::info!("Extracting and tokenizing Wikipedia articles for {:?}...", args.outputs);
+    let mut prune_policy = prune::PrunePolicy::new(args.prune_high_watermark_mb, args.prune_low_watermark_mb);
+    let threads = args
+        .decompress_threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |threads| threads.get()));
+    let dump = bz2::open_dump(&wiki_path, threads)?;
+    let stats = tracing::info_span!("extract", outputs = ?args.outputs, limit = ?args.limit).in_scope(|| {
+        extract::process_wikipedia(
+            dump,
+            &pool,
+            &mut consumers,
+            args.limit,
+            &mut prune_policy,
+            args.style_profile,
+            extract::SentenceFilters {
+                boilerplate: boilerplate.as_ref(),
+                limits: extract::SentenceLimits {
+                    min_length: args.min_sentence_chars,
+                    unit: args.length_unit,
+                    max_tokens: args.max_sentence_tokens,
+                },
+            },
+        )
+    })?;
+    args.quality_gates.check_corpus(&stats)?;
 
-    // Step 5: Build FST
-    log::info!("Building FST...");
-    ngram::build_fst(&filtered, &args.output)?;
+    // Step 4: Filter, score and write each output
+    let options = consumer::BuildOptions {
+        filter_config: ngram::FilterConfig {
+            min_frequency: args.min_frequency,
+            comparison: args.frequency_comparison,
+            per_order_min_frequency: args.order_min_frequency.iter().copied().collect(),
+            drop_mass_fraction: args.drop_mass_fraction,
+            rare_discount: args.rare_long_discount.map(|factor| ngram::RareDiscount {
+                min_order: ngram::RareDiscount::MIN_ORDER,
+                margin: args.rare_long_margin,
+                factor,
+            }),
+        },
+        smoothing: args.smoothing,
+        histogram_max_count: args.histogram_max_count,
+        quality_gates: args.quality_gates.clone(),
+    };
+    consumer::finish_all(consumers, &options)?;
 
-    log::info!("FST generated at {:?}", args.output);
-    log::info!("Done!");
+    tracing::info!("Done!");
 
     Ok(())
 }
 
+/// The boilerplate classifier asked for, trained on --boilerplate-samples if
+/// given.
+fn boilerplate_classifier(args: &Args) -> Result<Option<boilerplate::BoilerplateClassifier>> {
+    let Some(path) = &args.boilerplate_samples else {
+        return Ok(args.drop_boilerplate.then(boilerplate::BoilerplateClassifier::default));
+    };
+    let samples = boilerplate::read_samples(std::io::BufReader::new(File::open(path)?))?;
+    let (classifier, evaluation) = boilerplate::BoilerplateClassifier::train_held_out(&samples);
+    tracing::info!(
+        "Boilerplate classifier trained on {:?}: precision {:.2}, recall {:.2} on held-out samples ({:?})",
+        path,
+        evaluation.precision(),
+        evaluation.recall(),
+        evaluation
+    );
+    Ok(Some(classifier))
+}
+
+fn build_consumers(args: &Args, tokenizer: &mozc_dict_gen::vibrato::Tokenizer) -> Vec<Box<dyn consumer::SentenceConsumer>> {
+    let mut outputs: Vec<OutputKind> = Vec::new();
+    for kind in &args.outputs {
+        if !outputs.contains(kind) {
+            outputs.push(*kind);
+        }
+    }
+
+    let ngram_consumer = |name: &str, token_output, suffix: &str| -> Box<dyn consumer::SentenceConsumer> {
+        let consumer = consumer::NgramConsumer::new(
+            name,
+            token_output,
+            args.max_ngram,
+            ngram::sibling_path(&args.output, suffix),
+            args.histogram_output.as_ref().map(|path| ngram::sibling_path(path, suffix)),
+        );
+        match args.drop_quoted_ngrams {
+            Some(min_order) => Box::new(consumer.with_quote_filter(min_order)),
+            None => Box::new(consumer),
+        }
+    };
+
+    outputs
+        .into_iter()
+        .map(|kind| match kind {
+            OutputKind::Ngrams => ngram_consumer("ngrams", tokenize::TokenOutput::Surface, ""),
+            OutputKind::ReadingNgrams => ngram_consumer("reading-ngrams", tokenize::TokenOutput::SurfaceReading, "reading"),
+            OutputKind::PosNgrams => ngram_consumer("pos-ngrams", tokenize::TokenOutput::SurfacePos, "pos"),
+            OutputKind::LemmaNgrams => ngram_consumer("lemma-ngrams", tokenize::TokenOutput::Lemma, "lemma"),
+            OutputKind::Vocab => Box::new(consumer::VocabConsumer::new(ngram::sibling_path(&args.output, "vocab"))),
+            OutputKind::UnknownStats => Box::new(consumer::UnknownTokenConsumer::new(
+                ngram::sibling_path(&args.output, "unknown").with_extension("tsv"),
+            )),
+            OutputKind::SentenceStart => {
+                let pool = tokenize::TokenizerPool::new(tokenizer, tokenize::TokenFields::default());
+                let mut worker = pool.worker();
+                Box::new(
+                    consumer::SentenceStartConsumer::new(
+                        args.sentence_start_max_entries,
+                        ngram::sibling_path(&args.output, "sentence-start"),
+                    )
+                    .with_curated_openers(|opener| worker.tokenize(opener)),
+                )
+            }
+            OutputKind::Cooccurrence => Box::new(cooccurrence::CooccurrenceConsumer::new(
+                args.cooccurrence_window,
+                args.cooccurrence_min_count,
+                ngram::sibling_path(&args.output, "cooccurrence").with_extension("mtx"),
+            )),
+        })
+        .collect()
+}
+
+fn parse_order_threshold(s: &str) -> Result<(usize, u32), String> {
+    let (order, count) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected ORDER=COUNT, got '{}'", s))?;
+    let order = order.parse().map_err(|e| format!("invalid order '{}': {}", order, e))?;
+    let count = count.parse().map_err(|e| format!("invalid count '{}': {}", count, e))?;
+    Ok((order, count))
+}
+
 fn run_dummy_mode(output_path: &Path) -> Result<()> {
     println!("Running in dummy mode...");
     
@@ -1,54 +1,247 @@
-use anyhow::Result;
-use fst::MapBuilder;
+use anyhow::{Context, Result};
+use fst::{MapBuilder, Streamer};
+use keyboard_formats::ngram_key::{self, KeySchema};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufWriter;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::histogram::CountHistogram;
+
+/// N-gram counts keyed by space-joined tokens.
+///
+/// Keys are boxed `str` (16 bytes instead of `String`'s 24) and counts are `u32`,
+/// hashed with ahash, which keeps the per-entry overhead of the counting stage low.
+pub type NgramCounts = hashbrown::HashMap<Box<str>, u32, ahash::RandomState>;
 
 pub fn extract_ngrams_from_tokens(
     tokens: &[String],
     max_ngram: usize,
-    ngram_counts: &mut HashMap<String, usize>,
+    weight: u32,
+    ngram_counts: &mut NgramCounts,
 ) {
-    // Extract bigrams (n=2) and trigrams (n=3)
-    for n in 2..=max_ngram {
-        if tokens.len() < n {
+    // Reused across windows so existing n-grams are counted without allocating
+    let mut key = String::new();
+
+    // N-grams never span a token that cannot appear in a key (whitespace, control characters)
+    for run in tokens.split(|token| !ngram_key::is_valid_token(token)) {
+        // Extract bigrams (n=2) and trigrams (n=3)
+        for n in 2..=max_ngram {
+            if run.len() < n {
+                continue;
+            }
+
+            for window in run.windows(n) {
+                key.clear();
+                for (i, token) in window.iter().enumerate() {
+                    if i > 0 {
+                        key.push(ngram_key::SEPARATOR);
+                    }
+                    key.push_str(token);
+                }
+                *ngram_counts.entry_ref(key.as_str()).or_insert(0) += weight;
+            }
+        }
+    }
+}
+
+/// Removes entries seen at most `threshold` times and releases the freed
+/// capacity, returning how many were removed.
+pub fn prune_ngrams(ngram_counts: &mut NgramCounts, threshold: u32) -> usize {
+    tracing::info!("Pruning N-grams with count <= {}... (Current size: {})", threshold, ngram_counts.len());
+
+    let before_len = ngram_counts.len();
+    ngram_counts.retain(|_, &mut count| count > threshold);
+    ngram_counts.shrink_to_fit();
+    let after_len = ngram_counts.len();
+
+    tracing::info!("Pruned {} entries. New size: {}", before_len - after_len, after_len);
+    before_len - after_len
+}
+
+/// How an n-gram count is compared against its minimum frequency.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum FrequencyComparison {
+    /// Keep n-grams whose count is strictly greater than the threshold
+    Gt,
+    /// Keep n-grams whose count is greater than or equal to the threshold
+    Ge,
+}
+
+impl FrequencyComparison {
+    fn keeps(self, count: u32, threshold: u32) -> bool {
+        match self {
+            FrequencyComparison::Gt => count > threshold,
+            FrequencyComparison::Ge => count >= threshold,
+        }
+    }
+}
+
+/// Thresholds applied to raw counts before scoring.
+///
+/// An n-gram is kept only if it passes its absolute threshold (the per-order
+/// override if one is set, otherwise `min_frequency`) and, when
+/// `drop_mass_fraction` is set, is not among the rarest n-grams of its order
+/// that together account for that fraction of the order's total count mass.
+/// Long n-grams that only just pass may be scored down by `rare_discount`.
+#[derive(Clone, Debug)]
+pub struct FilterConfig {
+    pub min_frequency: u32,
+    pub comparison: FrequencyComparison,
+    pub per_order_min_frequency: HashMap<usize, u32>,
+    pub drop_mass_fraction: Option<f64>,
+    pub rare_discount: Option<RareDiscount>,
+}
+
+impl FilterConfig {
+    fn min_frequency_for(&self, order: usize) -> u32 {
+        self.per_order_min_frequency
+            .get(&order)
+            .copied()
+            .unwrap_or(self.min_frequency)
+    }
+}
+
+/// Extra discount of long n-grams whose count is close to their threshold.
+///
+/// A trigram seen just often enough to be kept is weak evidence, yet its raw
+/// count can outscore a bigram seen far more often relative to its order,
+/// producing oddly specific suggestions. Scoring such n-grams down makes
+/// well-attested shorter ones rank first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RareDiscount {
+    /// Shortest order discounted
+    pub min_order: usize,
+    /// Counts up to this multiple of the order's threshold are discounted
+    pub margin: f64,
+    /// What the adjusted count of a discounted n-gram is multiplied by
+    pub factor: f64,
+}
+
+impl RareDiscount {
+    /// Trigrams and longer.
+    pub const MIN_ORDER: usize = 3;
+
+    /// Multiplier for an n-gram of `order` seen `count` times, `threshold`
+    /// being its order's minimum frequency.
+    pub fn factor_for(&self, order: usize, count: u32, threshold: u32) -> f64 {
+        if order >= self.min_order && count as f64 <= threshold.max(1) as f64 * self.margin {
+            self.factor
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Number of tokens in a space-joined n-gram key.
+pub fn ngram_order(key: &str) -> usize {
+    key.split(' ').count()
+}
+
+/// Computes, per order, the largest count whose n-grams (together with all
+/// rarer ones) make up no more than `fraction` of that order's count mass.
+fn mass_cutoffs(histogram: &CountHistogram, fraction: f64) -> HashMap<usize, u32> {
+    let mut cutoffs = HashMap::new();
+    for order in histogram.orders() {
+        let Some(order_histogram) = histogram.order(order) else {
             continue;
+        };
+        let total: u64 = order_histogram.iter().map(|(&count, &n)| count as u64 * n).sum();
+        let budget = (total as f64 * fraction) as u64;
+
+        let mut dropped = 0u64;
+        let mut cutoff = 0;
+        for (&count, &n) in order_histogram {
+            dropped += count as u64 * n;
+            if dropped > budget {
+                break;
+            }
+            cutoff = count;
         }
 
-        for window in tokens.windows(n) {
-            let ngram = window.join(" ");
-            *ngram_counts.entry(ngram).or_insert(0) += 1;
+        tracing::info!(
+            "Order {}: dropping counts <= {} removes at most {:.1}% of mass",
+            order,
+            cutoff,
+            fraction * 100.0
+        );
+        cutoffs.insert(order, cutoff);
+    }
+    cutoffs
+}
+
+/// Counts at or above this value are trusted as-is by Good-Turing (Katz's k).
+const GOOD_TURING_MAX_COUNT: u32 = 5;
+
+/// How raw counts are turned into the scores stored in the FST.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Smoothing {
+    /// Score raw counts directly
+    LogCount,
+    /// Score Good-Turing discounted counts, c* = (c + 1) * N_{c+1} / N_c, for c < 5
+    GoodTuring,
+}
+
+impl Smoothing {
+    /// The count an n-gram of `order` seen `count` times is scored by.
+    pub fn adjusted_count(self, order: usize, count: u32, histogram: &CountHistogram) -> f64 {
+        match self {
+            Smoothing::LogCount => count as f64,
+            Smoothing::GoodTuring => good_turing_count(order, count, histogram),
         }
     }
 }
 
-pub fn prune_ngrams(ngram_counts: &mut HashMap<String, usize>, threshold_size: usize, min_freq: usize) {
-    if ngram_counts.len() <= threshold_size {
-        return;
+/// Good-Turing adjusted count for an n-gram of `order` seen `count` times.
+/// Falls back to the raw count where the histogram has no data to estimate from.
+fn good_turing_count(order: usize, count: u32, histogram: &CountHistogram) -> f64 {
+    if count >= GOOD_TURING_MAX_COUNT {
+        return count as f64;
     }
+    let n_c = histogram.n_c(order, count);
+    let n_next = histogram.n_c(order, count + 1);
+    if n_c == 0 || n_next == 0 {
+        return count as f64;
+    }
+    (count as f64 + 1.0) * n_next as f64 / n_c as f64
+}
 
-    log::info!("Pruning N-grams... (Current size: {})", ngram_counts.len());
-    
-    // Remove entries with frequency <= min_freq
-    let before_len = ngram_counts.len();
-    ngram_counts.retain(|_, &mut count| count > min_freq);
-    let after_len = ngram_counts.len();
-    
-    log::info!("Pruned {} entries. New size: {}", before_len - after_len, after_len);
+/// Encodes a (possibly discounted) count as the FST value: ln(count) * 1000,
+/// truncated to an integer and clamped at zero.
+pub fn encode_score(count: f64) -> u64 {
+    if count <= 1.0 {
+        return 0;
+    }
+    (count.ln() * 1000.0) as u64
 }
 
 pub fn filter_ngrams(
-    ngram_counts: &HashMap<String, usize>,
-    min_frequency: usize,
+    ngram_counts: &NgramCounts,
+    histogram: &CountHistogram,
+    config: &FilterConfig,
+    smoothing: Smoothing,
 ) -> Vec<(String, u64)> {
+    let mass_cutoffs = config
+        .drop_mass_fraction
+        .map(|fraction| mass_cutoffs(histogram, fraction))
+        .unwrap_or_default();
+
     let mut filtered: Vec<(String, u64)> = ngram_counts
         .iter()
-        .filter(|(_, &count)| count > min_frequency)
+        .filter(|(ngram, &count)| {
+            let order = ngram_order(ngram);
+            if !config.comparison.keeps(count, config.min_frequency_for(order)) {
+                return false;
+            }
+            mass_cutoffs.get(&order).is_none_or(|&cutoff| count > cutoff)
+        })
         .map(|(ngram, &count)| {
-            // Calculate log score: ln(count) * 1000 for precision
-            let log_score = (count as f64).ln() * 1000.0;
-            (ngram.clone(), log_score as u64)
+            let order = ngram_order(ngram);
+            let mut adjusted = smoothing.adjusted_count(order, count, histogram);
+            if let Some(discount) = &config.rare_discount {
+                adjusted *= discount.factor_for(order, count, config.min_frequency_for(order));
+            }
+            (ngram.to_string(), encode_score(adjusted))
         })
         .collect();
 
@@ -58,17 +251,47 @@ pub fn filter_ngrams(
     filtered
 }
 
-pub fn build_fst(data: &[(String, u64)], output_path: &Path) -> Result<()> {
+/// `output/wiki-ngrams.fst` + `reading` -> `output/wiki-ngrams-reading.fst`.
+/// An empty suffix returns the path unchanged.
+pub fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    if suffix.is_empty() {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
+
+/// The entries of an N-gram FST, sorted by key.
+pub fn read_fst(path: &Path) -> Result<Vec<(String, u64)>> {
+    let map = fst::Map::new(std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?)?;
+    let mut entries = Vec::with_capacity(map.len());
+    let mut stream = map.stream();
+    while let Some((key, score)) = stream.next() {
+        entries.push((String::from_utf8(key.to_vec())?, score));
+    }
+    Ok(entries)
+}
+
+/// Writes `data` (sorted by key) as an FST, failing on the first key that
+/// does not conform to `schema`.
+pub fn build_fst(data: &[(String, u64)], schema: KeySchema, output_path: &Path) -> Result<()> {
     let file = File::create(output_path)?;
     let writer = BufWriter::new(file);
     let mut builder = MapBuilder::new(writer)?;
 
     for (key, value) in data {
+        schema
+            .validate(key.as_bytes())
+            .with_context(|| format!("Refusing to write {:?}", output_path))?;
         builder.insert(key.as_bytes(), *value)?;
     }
 
     builder.finish()?;
-    log::info!("FST built with {} entries", data.len());
+    tracing::info!("FST built with {} entries", data.len());
 
     Ok(())
 }
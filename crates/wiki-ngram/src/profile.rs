@@ -0,0 +1,41 @@
+//! Build profiles (`--profile mobile|desktop|server`): the thresholds each
+//! product target counts with, named as mozc-dict-gen's profiles are.
+//!
+//! A profile is a list of command-line flags parsed in front of the ones
+//! given, so a flag given explicitly overrides the profile's value.
+
+/// Product target an N-gram model is built for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Profile {
+    /// Trigrams of frequent, well-attested sequences only, for the mobile
+    /// size budget
+    Mobile,
+    /// Trigrams with a lower threshold
+    Desktop,
+    /// 4-grams of everything seen more than twice
+    Server,
+}
+
+impl Profile {
+    /// The flags of the profile.
+    pub fn args(self) -> &'static [&'static str] {
+        match self {
+            Profile::Mobile => &[
+                "--min-frequency=50",
+                "--max-ngram=3",
+                "--drop-mass-fraction=0.1",
+                "--rare-long-discount=0.5",
+                "--drop-quoted-ngrams=4",
+                "--drop-boilerplate",
+                "--sentence-start-max-entries=500",
+            ],
+            Profile::Desktop => &[
+                "--min-frequency=10",
+                "--max-ngram=3",
+                "--drop-quoted-ngrams=4",
+                "--drop-boilerplate",
+            ],
+            Profile::Server => &["--min-frequency=2", "--max-ngram=4", "--drop-boilerplate"],
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::fs;
+
+use crate::consumer::SentenceConsumer;
+use crate::ngram::{prune_ngrams, NgramCounts};
+
+/// Entry count that triggers pruning when the process RSS cannot be read
+/// (non-Linux platforms).
+const FALLBACK_MAX_ENTRIES: usize = 5_000_000;
+
+/// Upper bound for the escalating prune threshold, so a pathological corpus
+/// cannot wipe out everything but the most frequent n-grams.
+const MAX_PRUNE_THRESHOLD: u32 = 1 << 16;
+
+/// Reads the memory in use, in bytes; `None` when it can't be read.
+pub type MemoryProbe = Box<dyn FnMut() -> Option<u64>>;
+
+/// Decides when to prune the in-memory counts and how far.
+///
+/// Freed keys go back to the allocator rather than the OS, so RSS hardly
+/// falls after a prune and can only tell when memory first runs short. At
+/// that point the counts are taken to fill the memory in use, which gives
+/// an entry budget: as many entries as fit under the high watermark. Later
+/// rounds compare the entry count against that budget instead of RSS.
+///
+/// All consumers of a pass share the memory, so they are pruned together:
+/// their entries are added up against the budget and each round prunes
+/// every consumer at the same threshold.
+///
+/// A prune removes entries until the counts fit under the low watermark's
+/// share of the budget. It drops entries seen at most `threshold` times
+/// (initially once-occurring entries), doubling the threshold while too few
+/// were removed. A round that reaches its target halves the threshold
+/// again, so one dense stretch of the corpus doesn't keep it high.
+pub struct PrunePolicy {
+    high_watermark_bytes: u64,
+    low_watermark_bytes: u64,
+    threshold: u32,
+    max_entries: Option<usize>,
+    probe: MemoryProbe,
+}
+
+impl PrunePolicy {
+    pub fn new(high_watermark_mb: u64, low_watermark_mb: u64) -> Self {
+        Self {
+            high_watermark_bytes: high_watermark_mb * 1024 * 1024,
+            low_watermark_bytes: low_watermark_mb.min(high_watermark_mb) * 1024 * 1024,
+            threshold: 1,
+            max_entries: None,
+            probe: Box::new(resident_set_bytes),
+        }
+    }
+
+    /// Reads memory use with `probe` instead of the process RSS.
+    pub fn with_memory_probe(mut self, probe: impl FnMut() -> Option<u64> + 'static) -> Self {
+        self.probe = Box::new(probe);
+        self
+    }
+
+    /// Count at or below which the next prune starts dropping entries.
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// Prunes `ngram_counts` if they are over budget. Returns true if any
+    /// pruning happened.
+    pub fn maybe_prune(&mut self, ngram_counts: &mut NgramCounts) -> bool {
+        let Some(target) = self.removal_target(ngram_counts.len()) else {
+            return false;
+        };
+        self.prune_until(target, |threshold| prune_ngrams(ngram_counts, threshold));
+        true
+    }
+
+    /// Prunes every consumer at the same threshold if their counts together
+    /// are over budget. Returns true if any pruning happened.
+    pub fn maybe_prune_consumers(&mut self, consumers: &mut [Box<dyn SentenceConsumer>]) -> bool {
+        let entries = consumers.iter().map(|consumer| consumer.len()).sum();
+        let Some(target) = self.removal_target(entries) else {
+            return false;
+        };
+        self.prune_until(target, |threshold| consumers.iter_mut().map(|consumer| consumer.prune(threshold)).sum());
+        true
+    }
+
+    /// How many of `entries` to remove, or None while they fit.
+    fn removal_target(&mut self, entries: usize) -> Option<usize> {
+        let max_entries = match self.max_entries {
+            Some(max_entries) => max_entries,
+            None => match (self.probe)() {
+                Some(rss) if rss <= self.high_watermark_bytes => return None,
+                Some(rss) => {
+                    tracing::info!(
+                        "RSS {} MB above high watermark {} MB",
+                        rss / 1024 / 1024,
+                        self.high_watermark_bytes / 1024 / 1024
+                    );
+                    let max_entries = (entries as f64 * self.high_watermark_bytes as f64 / rss as f64) as usize;
+                    tracing::info!("Keeping at most {} entries in memory from now on", max_entries);
+                    self.max_entries = Some(max_entries);
+                    max_entries
+                }
+                None => FALLBACK_MAX_ENTRIES,
+            },
+        };
+        if entries <= max_entries {
+            return None;
+        }
+        let low_share = self.low_watermark_bytes as f64 / self.high_watermark_bytes as f64;
+        Some(entries - (max_entries as f64 * low_share) as usize)
+    }
+
+    /// Prunes with `prune`, which removes entries seen at most the given
+    /// number of times and returns how many it removed, until `target` are.
+    fn prune_until(&mut self, target: usize, mut prune: impl FnMut(u32) -> usize) {
+        let mut removed = prune(self.threshold);
+        while removed < target {
+            if self.threshold >= MAX_PRUNE_THRESHOLD {
+                tracing::warn!(
+                    "Removed {} of {} entries at maximum prune threshold {}",
+                    removed,
+                    target,
+                    self.threshold
+                );
+                return;
+            }
+            self.threshold *= 2;
+            tracing::info!(
+                "Removed {} of {} entries, escalating prune threshold to {}",
+                removed,
+                target,
+                self.threshold
+            );
+            removed += prune(self.threshold);
+        }
+        self.threshold = (self.threshold / 2).max(1);
+    }
+}
+
+/// Reads the resident set size of the current process from /proc.
+fn resident_set_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
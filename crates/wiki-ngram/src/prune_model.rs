@@ -0,0 +1,280 @@
+//! Relative-entropy (Stolcke) pruning of a built N-gram FST
+//! (`wiki-ngram prune-model`), to shrink a model to a size target without
+//! rebuilding it from counts with higher frequency thresholds.
+//!
+//! The FST stores counts, not probabilities: raw counts, or the
+//! Good-Turing adjusted ones of a model built with `--smoothing
+//! good-turing`, which the FST doesn't record. Whichever they are, the
+//! decoded counts are refit as a Witten-Bell backoff model and pruning is
+//! relative to that model, not to the Good-Turing estimates themselves:
+//! `p(w|h) = c(hw) / (c(h) + T(h))` for the n-grams present, where `c(h)`
+//! sums the counts following `h` and `T(h)` is how many words do, and the
+//! left-over `T(h) / (c(h) + T(h))` goes to the shorter history, scaled by
+//! its backoff weight. Unigram probabilities are how often a word ends a
+//! bigram. Each N-gram `hw` is then scored by
+//! how much removing it alone changes the model (Stolcke 1998):
+//!
+//! ```text
+//! D(hw) = -P(h) * ( p(w|h) * ln(p'(w|h) / p(w|h)) + L(h) * ln(α'(h) / α(h)) )
+//! ```
+//!
+//! where `p'` backs off to the shorter history with the backoff weight `α'`
+//! `h` has once `hw` is gone and `L(h)` is the left-over mass. N-grams are
+//! removed in increasing `D`, except that one stays while a longer N-gram
+//! uses it as its history, as [`keyboard_formats::language_model`] looks
+//! the history's count up. The summed `D` of the removed N-grams estimates
+//! the perplexity increase.
+
+use anyhow::Result;
+use clap::Args;
+use fst::MapBuilder;
+use keyboard_formats::language_model::decode_count;
+use keyboard_formats::ngram_key::{KeySchema, SEPARATOR};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::path::PathBuf;
+
+use crate::ngram::{self, ngram_order};
+
+#[derive(Args, Debug)]
+pub struct PruneModelArgs {
+    /// N-gram FST to prune (wiki-ngrams.fst or one of its reading, POS and lemma variants)
+    #[arg(long, default_value = "output/wiki-ngrams.fst")]
+    input: PathBuf,
+
+    /// Pruned FST
+    #[arg(long, default_value = "output/wiki-ngrams-pruned.fst")]
+    output: PathBuf,
+
+    /// Keep at most this many N-grams
+    #[arg(long)]
+    max_entries: Option<usize>,
+
+    /// Keep the FST within this many bytes
+    #[arg(long)]
+    max_size_bytes: Option<u64>,
+
+    /// Remove every N-gram whose relative entropy is below this threshold
+    /// (SRILM's -prune, e.g. 1e-8)
+    #[arg(long)]
+    threshold: Option<f64>,
+}
+
+pub fn run(args: &PruneModelArgs) -> Result<()> {
+    anyhow::ensure!(
+        args.max_entries.is_some() || args.max_size_bytes.is_some() || args.threshold.is_some(),
+        "Give --max-entries, --max-size-bytes or --threshold"
+    );
+    let entries = ngram::read_fst(&args.input)?;
+    tracing::info!("Scoring {} N-grams of {:?}", entries.len(), args.input);
+    let entropies = relative_entropies(&entries);
+    let order = pruning_order(&entries, &entropies);
+
+    let mut removed = 0;
+    if let Some(threshold) = args.threshold {
+        removed = order.iter().take_while(|&&i| entropies[i] < threshold).count();
+    }
+    if let Some(max_entries) = args.max_entries {
+        removed = removed.max(entries.len().saturating_sub(max_entries));
+    }
+    if let Some(max_size_bytes) = args.max_size_bytes {
+        // The FST size shrinks with every N-gram removed
+        let (mut low, mut high) = (removed, order.len());
+        anyhow::ensure!(
+            fst_size(&keep(&entries, &order[..high]))? <= max_size_bytes,
+            "The N-grams that can't be pruned alone take more than {} bytes",
+            max_size_bytes
+        );
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if fst_size(&keep(&entries, &order[..mid]))? <= max_size_bytes {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        removed = low;
+    }
+    anyhow::ensure!(removed <= order.len(), "Only {} N-grams can be pruned", order.len());
+
+    let kept = keep(&entries, &order[..removed]);
+    let entropy = order[..removed].iter().fold(0.0, |sum, &i| sum + entropies[i]);
+    let max_order = kept.iter().map(|(key, _)| ngram_order(key)).max().unwrap_or(1);
+    ngram::build_fst(&kept, KeySchema::new(1, max_order), &args.output)?;
+    tracing::info!(
+        "Pruned {} of {} N-grams into {:?}: relative entropy {:.3e}, perplexity +{:.2}%",
+        removed,
+        entries.len(),
+        args.output,
+        entropy,
+        (entropy.exp() - 1.0) * 100.0
+    );
+    Ok(())
+}
+
+/// Splits a key into its history and last word; the history of a single
+/// word is empty.
+fn split_last(key: &str) -> (&str, &str) {
+    key.rsplit_once(SEPARATOR).unwrap_or(("", key))
+}
+
+/// A history without its first word.
+fn shorter(history: &str) -> &str {
+    history.split_once(SEPARATOR).map_or("", |(_, rest)| rest)
+}
+
+/// Counts following a history.
+#[derive(Default)]
+struct History {
+    count: f64,
+    types: f64,
+    /// Backoff weight to the shorter history
+    alpha: f64,
+    /// Probability the shorter history gives the words following this one
+    lower_mass: f64,
+}
+
+impl History {
+    /// Probability mass left to the words not following this history.
+    fn left_over(&self) -> f64 {
+        self.types / (self.count + self.types)
+    }
+}
+
+struct Model<'a> {
+    counts: HashMap<&'a str, f64>,
+    histories: HashMap<&'a str, History>,
+    unigrams: HashMap<&'a str, f64>,
+    unigram_total: f64,
+}
+
+impl<'a> Model<'a> {
+    fn new(entries: &'a [(String, u64)]) -> Self {
+        let mut counts = HashMap::new();
+        let mut histories: HashMap<&str, History> = HashMap::new();
+        let mut unigrams: HashMap<&str, f64> = HashMap::new();
+        for (key, score) in entries {
+            let count = decode_count(*score);
+            counts.insert(key.as_str(), count);
+            let (history, word) = split_last(key);
+            let stats = histories.entry(history).or_default();
+            stats.count += count;
+            stats.types += 1.0;
+            if ngram_order(key) == 2 {
+                *unigrams.entry(word).or_default() += count;
+            }
+        }
+        let unigram_total = unigrams.values().sum();
+        let mut model = Self { counts, histories, unigrams, unigram_total };
+
+        // Backoff weights need the probabilities of the shorter histories,
+        // so they are computed from the shortest histories up
+        let mut keys: Vec<&'a str> = model.histories.keys().copied().collect();
+        keys.sort_by_key(|history| (ngram_order(history), *history));
+        let mut by_history: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (key, _) in entries {
+            let (history, word) = split_last(key);
+            by_history.entry(history).or_default().push(word);
+        }
+        for history in keys {
+            let lower: f64 = by_history[history].iter().map(|word| model.prob(shorter(history), word)).sum();
+            let stats = model.histories.get_mut(history).unwrap();
+            stats.lower_mass = lower.min(1.0 - 1e-9);
+            stats.alpha = stats.left_over() / (1.0 - stats.lower_mass);
+        }
+        model
+    }
+
+    /// Probability of `word` after `history`, backing off to shorter ones.
+    fn prob(&self, history: &str, word: &str) -> f64 {
+        if history.is_empty() {
+            let count = self.unigrams.get(word).copied().unwrap_or(1.0);
+            return count / (self.unigram_total + self.unigrams.len() as f64);
+        }
+        let Some(stats) = self.histories.get(history) else {
+            return self.prob(shorter(history), word);
+        };
+        let key = format!("{}{}{}", history, SEPARATOR, word);
+        match self.counts.get(key.as_str()) {
+            Some(count) => count / (stats.count + stats.types),
+            None => stats.alpha * self.prob(shorter(history), word),
+        }
+    }
+}
+
+/// Relative entropy of removing each N-gram of `entries` (sorted by key,
+/// values as written by wiki-ngram) on its own.
+pub fn relative_entropies(entries: &[(String, u64)]) -> Vec<f64> {
+    let model = Model::new(entries);
+    // The probability of a history is its share of the counts of its order
+    let mut order_totals: HashMap<usize, f64> = HashMap::new();
+    for (key, count) in &model.counts {
+        *order_totals.entry(ngram_order(key)).or_default() += count;
+    }
+    entries
+        .iter()
+        .map(|(key, _)| {
+            let (history, word) = split_last(key);
+            let stats = &model.histories[history];
+            let p = model.counts[key.as_str()] / (stats.count + stats.types);
+            let lower = model.prob(shorter(history), word);
+            let left_over = stats.left_over();
+            let alpha_pruned = (left_over + p) / (1.0 - stats.lower_mass + lower);
+            let history_prob = stats.count / order_totals[&ngram_order(key)];
+            let entropy = -history_prob
+                * (p * (alpha_pruned * lower / p).ln() + left_over * (alpha_pruned / stats.alpha).ln());
+            // Also turns -0.0, which would sort last by its bits, into 0.0
+            if entropy > 0.0 {
+                entropy
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// Indices of `entries` in the order they are pruned: increasing relative
+/// entropy, each once no longer N-gram has it as its history. Single words
+/// are never pruned.
+pub fn pruning_order(entries: &[(String, u64)], entropies: &[f64]) -> Vec<usize> {
+    let index: HashMap<&str, usize> = entries.iter().enumerate().map(|(i, (key, _))| (key.as_str(), i)).collect();
+    let mut children = vec![0usize; entries.len()];
+    for (key, _) in entries {
+        if let Some(&parent) = index.get(split_last(key).0) {
+            children[parent] += 1;
+        }
+    }
+    let prunable = |i: usize| ngram_order(&entries[i].0) > 1;
+    let mut ready: BinaryHeap<Reverse<(u64, usize)>> = (0..entries.len())
+        .filter(|&i| children[i] == 0 && prunable(i))
+        .map(|i| Reverse((entropies[i].to_bits(), i)))
+        .collect();
+    let mut order = Vec::with_capacity(entries.len());
+    while let Some(Reverse((_, i))) = ready.pop() {
+        order.push(i);
+        if let Some(&parent) = index.get(split_last(&entries[i].0).0) {
+            children[parent] -= 1;
+            if children[parent] == 0 && prunable(parent) {
+                ready.push(Reverse((entropies[parent].to_bits(), parent)));
+            }
+        }
+    }
+    order
+}
+
+/// `entries` without those at the `removed` indices.
+fn keep(entries: &[(String, u64)], removed: &[usize]) -> Vec<(String, u64)> {
+    let mut dropped = vec![false; entries.len()];
+    for &i in removed {
+        dropped[i] = true;
+    }
+    entries.iter().zip(dropped).filter(|(_, dropped)| !dropped).map(|(entry, _)| entry.clone()).collect()
+}
+
+fn fst_size(entries: &[(String, u64)]) -> Result<u64> {
+    let mut builder = MapBuilder::memory();
+    for (key, score) in entries {
+        builder.insert(key, *score)?;
+    }
+    Ok(builder.into_inner()?.len() as u64)
+}
@@ -0,0 +1,194 @@
+use anyhow::Result;
+use keyboard_formats::text_length::LengthUnit;
+use std::collections::BTreeMap;
+
+use crate::ngram::NgramCounts;
+use crate::tokenize::Token;
+
+/// ASCII brackets left behind when wiki markup (templates, links, tags)
+/// survives cleaning; Japanese prose uses full-width brackets instead.
+const MARKUP_BRACKETS: &[char] = &['[', ']', '{', '}', '<', '>'];
+
+/// Corpus statistics gathered during extraction for the quality gates.
+#[derive(Debug, Default)]
+pub struct CorpusStats {
+    pub sentences: u64,
+    /// Sentences containing ASCII brackets, i.e. likely leaked markup
+    pub markup_sentences: u64,
+    pub tokens: u64,
+    pub unknown_tokens: u64,
+    /// Sentences dropped by the boilerplate classifier, not in the counts
+    /// above
+    pub boilerplate_sentences: u64,
+    /// Sentences dropped as shorter than --min-sentence-chars
+    pub short_sentences: u64,
+    /// Sentences dropped as longer than --max-sentence-tokens
+    pub long_sentences: u64,
+    /// Unit of `sentence_length`, that of the sentence limits
+    pub length_unit: LengthUnit,
+    /// Lengths of the sentences counted, in `length_unit`
+    pub sentence_length: LengthDistribution,
+    /// Lengths of the sentences counted, in tokens
+    pub sentence_tokens: LengthDistribution,
+}
+
+impl CorpusStats {
+    pub fn record(&mut self, sentence: &str, tokens: &[Token]) {
+        self.sentences += 1;
+        if sentence.contains(MARKUP_BRACKETS) {
+            self.markup_sentences += 1;
+        }
+        self.tokens += tokens.len() as u64;
+        self.unknown_tokens += tokens.iter().filter(|token| token.unknown).count() as u64;
+        self.sentence_length.record(self.length_unit.length(sentence));
+        self.sentence_tokens.record(tokens.len());
+    }
+
+    pub fn oov_rate(&self) -> f64 {
+        ratio(self.unknown_tokens, self.tokens)
+    }
+
+    pub fn markup_ratio(&self) -> f64 {
+        ratio(self.markup_sentences, self.sentences)
+    }
+}
+
+/// How many sentences had each length.
+#[derive(Debug, Default)]
+pub struct LengthDistribution {
+    lengths: BTreeMap<usize, u64>,
+}
+
+impl LengthDistribution {
+    pub fn record(&mut self, length: usize) {
+        *self.lengths.entry(length).or_insert(0) += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.lengths.values().sum()
+    }
+
+    /// The smallest length at least `p` (0.0-1.0) of the sentences are no
+    /// longer than; 0 if nothing was recorded.
+    pub fn percentile(&self, p: f64) -> usize {
+        let target = (self.total() as f64 * p).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (&length, &n) in &self.lengths {
+            seen += n;
+            if seen >= target {
+                return length;
+            }
+        }
+        0
+    }
+
+    pub fn max(&self) -> usize {
+        self.lengths.keys().next_back().copied().unwrap_or(0)
+    }
+
+    /// `p10/p50/p90/p99/max`, for the log.
+    pub fn summary(&self) -> String {
+        let [p10, p50, p90, p99] = [0.1, 0.5, 0.9, 0.99].map(|p| self.percentile(p));
+        format!("p10 {}, p50 {}, p90 {}, p99 {}, max {}", p10, p50, p90, p99, self.max())
+    }
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+/// Data-quality thresholds. Failures are logged as warnings, or abort the
+/// build before anything is written with `--strict`: the corpus is checked
+/// after extraction and every consumer's counts before the first one writes
+/// ([`crate::consumer::finish_all`]).
+#[derive(clap::Args, Clone, Debug)]
+pub struct QualityGates {
+    /// Fail instead of warning when a quality gate is not met
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Highest tolerated share of tokens missing from the dictionary
+    #[arg(long, default_value = "0.05")]
+    pub max_oov_rate: f64,
+
+    /// Highest tolerated share of sentences with ASCII brackets (leaked wiki markup)
+    #[arg(long, default_value = "0.02")]
+    pub max_markup_ratio: f64,
+
+    /// Fewest N-gram occurrences (the sum of their counts) an N-gram model
+    /// may be estimated from
+    #[arg(long, default_value = "100000")]
+    pub min_ngram_count: u64,
+}
+
+impl QualityGates {
+    pub fn check_corpus(&self, stats: &CorpusStats) -> Result<()> {
+        tracing::info!(
+            "Corpus quality: {} sentences, OOV rate {:.2}%, markup leak ratio {:.2}%",
+            stats.sentences,
+            stats.oov_rate() * 100.0,
+            stats.markup_ratio() * 100.0
+        );
+        tracing::info!("Sentence length in {}: {}", stats.length_unit, stats.sentence_length.summary());
+        tracing::info!("Sentence length in tokens: {}", stats.sentence_tokens.summary());
+        if stats.short_sentences > 0 || stats.long_sentences > 0 {
+            tracing::info!(
+                "Dropped {} sentences below --min-sentence-chars and {} above --max-sentence-tokens",
+                stats.short_sentences,
+                stats.long_sentences
+            );
+        }
+        if stats.boilerplate_sentences > 0 {
+            tracing::info!("Dropped {} boilerplate sentences", stats.boilerplate_sentences);
+        }
+        let mut failures = Vec::new();
+        if stats.oov_rate() > self.max_oov_rate {
+            failures.push(format!(
+                "OOV rate {:.4} exceeds --max-oov-rate {}",
+                stats.oov_rate(),
+                self.max_oov_rate
+            ));
+        }
+        if stats.markup_ratio() > self.max_markup_ratio {
+            failures.push(format!(
+                "markup leak ratio {:.4} exceeds --max-markup-ratio {}",
+                stats.markup_ratio(),
+                self.max_markup_ratio
+            ));
+        }
+        self.report(failures)
+    }
+
+    /// Checks how many N-gram occurrences the counts of model `name` add up
+    /// to; counts pruned during extraction are gone and don't add up.
+    pub fn check_ngram_count(&self, name: &str, counts: &NgramCounts) -> Result<()> {
+        let total: u64 = counts.values().map(|&count| u64::from(count)).sum();
+        let mut failures = Vec::new();
+        if total < self.min_ngram_count {
+            failures.push(format!(
+                "[{}] {} N-gram occurrences is below --min-ngram-count {}",
+                name,
+                total,
+                self.min_ngram_count
+            ));
+        }
+        self.report(failures)
+    }
+
+    fn report(&self, failures: Vec<String>) -> Result<()> {
+        if failures.is_empty() {
+            return Ok(());
+        }
+        if self.strict {
+            anyhow::bail!("Quality gates failed: {}", failures.join("; "));
+        }
+        for failure in failures {
+            tracing::warn!("Quality gate: {}", failure);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use hashbrown::HashSet;
+use keyboard_formats::ngram_key;
+
+use crate::ngram::NgramCounts;
+use crate::tokenize::{Token, TokenOutput};
+
+/// Brackets opening and closing a quoted passage. Straight double quotes
+/// both open and close.
+const OPENING_QUOTES: &[&str] = &["「", "『", "“"];
+const CLOSING_QUOTES: &[&str] = &["」", "』", "”"];
+const STRAIGHT_QUOTE: &str = "\"";
+
+/// Drops long n-grams seen exactly once, inside a quoted passage.
+///
+/// Wikipedia quotes lyrics, speeches and book passages verbatim; a long
+/// n-gram that only ever occurs inside one such quote would let predictions
+/// reproduce the quotation word for word. Phrases that also occur elsewhere,
+/// or more than once, are ordinary language and kept.
+pub struct QuoteFilter {
+    token_output: TokenOutput,
+    min_order: usize,
+    max_ngram: usize,
+    /// N-grams whose only occurrence so far was quoted
+    candidates: HashSet<Box<str>, ahash::RandomState>,
+}
+
+impl QuoteFilter {
+    /// Filters n-grams of `min_order` to `max_ngram` tokens, rendered with
+    /// `token_output` as the counting does.
+    pub fn new(token_output: TokenOutput, min_order: usize, max_ngram: usize) -> Self {
+        Self {
+            token_output,
+            min_order: min_order.max(2),
+            max_ngram,
+            candidates: HashSet::default(),
+        }
+    }
+
+    /// Notes the n-grams of a sentence; call before adding the sentence to
+    /// `counts`.
+    pub fn observe(&mut self, tokens: &[Token], counts: &NgramCounts) {
+        let keys: Vec<String> = tokens.iter().map(|token| token.key(self.token_output)).collect();
+        let quoted = quoted_tokens(tokens);
+        let mut in_sentence = HashSet::<String>::new();
+        for n in self.min_order..=self.max_ngram.min(keys.len()) {
+            for start in 0..=keys.len() - n {
+                let window = &keys[start..start + n];
+                if !window.iter().all(|key| ngram_key::is_valid_token(key)) {
+                    continue;
+                }
+                let key = window.join(&ngram_key::SEPARATOR.to_string());
+                if counts.contains_key(key.as_str()) || !in_sentence.insert(key.clone()) {
+                    self.candidates.remove(key.as_str());
+                } else if quoted[start..start + n].iter().all(|&q| q) {
+                    self.candidates.insert(key.into_boxed_str());
+                }
+            }
+        }
+    }
+
+    /// Forgets candidates pruned from `counts`.
+    pub fn retain(&mut self, counts: &NgramCounts) {
+        self.candidates.retain(|key| counts.contains_key(key));
+    }
+
+    /// Removes the quoted single occurrences from `entries`, returning how
+    /// many were removed.
+    pub fn apply(&self, entries: &mut Vec<(String, u64)>) -> usize {
+        let before = entries.len();
+        entries.retain(|(key, _)| !self.candidates.contains(key.as_str()));
+        before - entries.len()
+    }
+}
+
+/// Whether each token is inside a quoted passage; the brackets themselves
+/// are not.
+fn quoted_tokens(tokens: &[Token]) -> Vec<bool> {
+    let mut depth = 0usize;
+    let mut straight = false;
+    tokens
+        .iter()
+        .map(|token| {
+            let surface = token.surface.as_str();
+            if OPENING_QUOTES.contains(&surface) {
+                depth += 1;
+                false
+            } else if CLOSING_QUOTES.contains(&surface) {
+                depth = depth.saturating_sub(1);
+                false
+            } else if surface == STRAIGHT_QUOTE {
+                straight = !straight;
+                false
+            } else {
+                depth > 0 || straight
+            }
+        })
+        .collect()
+}
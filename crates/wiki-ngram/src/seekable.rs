@@ -0,0 +1,199 @@
+//! Seekable zstd dumps (`wiki-ngram prepare-dump`): the bz2 dump
+//! recompressed once into independent zstd frames with a seek table, so
+//! every later pass decompresses several times faster than bz2 and still
+//! on `--decompress-threads` threads.
+//!
+//! The file follows zstd's seekable format: frames of about [`FRAME_SIZE`]
+//! decompressed bytes, then a skippable frame listing the compressed and
+//! decompressed size of each. Any zstd decoder reads it as a plain `.zst`
+//! file; [`crate::bz2::open_dump`] finds the table and hands the frames to
+//! its thread pool. Frames end after a `</page>` line, so each starts at a
+//! page boundary and a pass can be started from any of them.
+
+use anyhow::{Context, Result};
+use clap::Args;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::PathBuf;
+
+use crate::bz2::{self, Piece};
+use crate::download;
+
+/// Decompressed bytes a frame is cut at, at the next page boundary.
+pub const FRAME_SIZE: usize = 4 << 20;
+
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A5E;
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+/// Footer after the table entries: frame count, descriptor, magic
+const FOOTER_SIZE: usize = 9;
+/// Descriptor bit set when every entry has a checksum
+const CHECKSUM_FLAG: u8 = 0x80;
+
+const PAGE_END: &[u8] = b"</page>\n";
+
+#[derive(Args, Debug)]
+pub struct PrepareDumpArgs {
+    /// Dump to recompress (default: the latest one, downloaded into --download-cache)
+    #[arg(long)]
+    dump: Option<PathBuf>,
+
+    /// Download cache directory
+    #[arg(long, default_value = "downloads")]
+    download_cache: PathBuf,
+
+    /// Seekable zstd dump to write, read by later runs with --dump
+    #[arg(long, default_value = "downloads/jawiki-latest-pages-articles.xml.zst")]
+    output: PathBuf,
+
+    /// zstd level of the frames
+    #[arg(long, default_value = "9")]
+    level: i32,
+
+    /// Threads decompressing the input and compressing frames (default: one per core)
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+/// Sizes of one frame of a seekable dump.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub compressed: u32,
+    pub decompressed: u32,
+}
+
+pub fn run(args: &PrepareDumpArgs) -> Result<()> {
+    let dump = match &args.dump {
+        Some(dump) => dump.clone(),
+        None => download::download_wikipedia(&args.download_cache)?,
+    };
+    let threads =
+        args.threads.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |threads| threads.get()));
+    if let Some(parent) = args.output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Written under a temporary name, so an interrupted run leaves no
+    // truncated dump behind for --dump to pick up
+    let partial = args.output.with_extension("zst.partial");
+    let output = File::create(&partial).with_context(|| format!("Failed to create {:?}", partial))?;
+    let mut output = BufWriter::new(output);
+    let frames = write_seekable(bz2::open_dump(&dump, threads)?, &mut output, args.level, threads)?;
+    output.flush()?;
+    drop(output);
+    fs::rename(&partial, &args.output)?;
+
+    let compressed: u64 = frames.iter().map(|frame| u64::from(frame.compressed)).sum();
+    let decompressed: u64 = frames.iter().map(|frame| u64::from(frame.decompressed)).sum();
+    tracing::info!(
+        "Wrote {:?}: {} frames, {:.1} MB from {:.1} MB of XML",
+        args.output,
+        frames.len(),
+        compressed as f64 / 1e6,
+        decompressed as f64 / 1e6
+    );
+    Ok(())
+}
+
+/// Compresses `input` into a seekable zstd file, frames compressed on
+/// `threads` threads, and returns its frames.
+pub fn write_seekable(mut input: impl Read, mut output: impl Write, level: i32, threads: usize) -> Result<Vec<Frame>> {
+    let threads = threads.max(1);
+    let mut frames = Vec::new();
+    let mut pending = Vec::new();
+    let mut done = false;
+    while !done {
+        let mut batch = Vec::new();
+        while batch.len() < threads && !done {
+            done = fill(&mut input, &mut pending)?;
+            let cut = if done { pending.len() } else { frame_end(&pending) };
+            if cut > 0 {
+                let rest = pending.split_off(cut);
+                batch.push(std::mem::replace(&mut pending, rest));
+            }
+        }
+        let compressed = std::thread::scope(|scope| {
+            let jobs: Vec<_> =
+                batch.iter().map(|chunk| scope.spawn(move || zstd::bulk::compress(chunk, level))).collect();
+            jobs.into_iter().map(|job| job.join().unwrap()).collect::<std::io::Result<Vec<_>>>()
+        })?;
+        for (chunk, frame) in batch.iter().zip(compressed) {
+            output.write_all(&frame)?;
+            frames.push(Frame { compressed: frame.len().try_into()?, decompressed: chunk.len().try_into()? });
+        }
+    }
+    output.write_all(&seek_table(&frames))?;
+    Ok(frames)
+}
+
+/// Reads from `input` until `pending` holds a frame's worth; true at the end.
+fn fill(input: &mut impl Read, pending: &mut Vec<u8>) -> Result<bool> {
+    while pending.len() < FRAME_SIZE {
+        let len = pending.len();
+        pending.resize(len + (1 << 20), 0);
+        let read = input.read(&mut pending[len..])?;
+        pending.truncate(len + read);
+        if read == 0 {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Where the frame of `pending` ends: after its last `</page>` line, or all
+/// of it when a page is longer than a frame.
+fn frame_end(pending: &[u8]) -> usize {
+    let last_page_end = pending.windows(PAGE_END.len()).rposition(|window| window == PAGE_END);
+    last_page_end.map_or(pending.len(), |at| at + PAGE_END.len())
+}
+
+/// The skippable frame listing `frames`.
+pub fn seek_table(frames: &[Frame]) -> Vec<u8> {
+    let mut table = Vec::with_capacity(8 + frames.len() * 8 + FOOTER_SIZE);
+    table.extend(SKIPPABLE_MAGIC.to_le_bytes());
+    table.extend(((frames.len() * 8 + FOOTER_SIZE) as u32).to_le_bytes());
+    for frame in frames {
+        table.extend(frame.compressed.to_le_bytes());
+        table.extend(frame.decompressed.to_le_bytes());
+    }
+    table.extend((frames.len() as u32).to_le_bytes());
+    table.push(0);
+    table.extend(SEEKABLE_MAGIC.to_le_bytes());
+    table
+}
+
+/// The frames listed by the seek table at the end of `data`, or `None`
+/// when it is not a seekable zstd file.
+pub fn read_seek_table(data: &[u8]) -> Option<Vec<Frame>> {
+    let u32_at = |at: usize| Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?));
+    let footer = data.len().checked_sub(FOOTER_SIZE)?;
+    if u32_at(footer + 5)? != SEEKABLE_MAGIC {
+        return None;
+    }
+    let count = u32_at(footer)? as usize;
+    let entry_size = if data[footer + 4] & CHECKSUM_FLAG != 0 { 12 } else { 8 };
+    let table = footer.checked_sub(count.checked_mul(entry_size)?)?.checked_sub(8)?;
+    if u32_at(table)? != SKIPPABLE_MAGIC {
+        return None;
+    }
+    let frames: Vec<Frame> = (0..count)
+        .map(|i| {
+            let at = table + 8 + i * entry_size;
+            Some(Frame { compressed: u32_at(at)?, decompressed: u32_at(at + 4)? })
+        })
+        .collect::<Option<_>>()?;
+    let total: usize = frames.iter().map(|frame| frame.compressed as usize).sum();
+    (total == table).then_some(frames)
+}
+
+/// The frames as pieces for [`bz2::ParallelDecoder`].
+pub fn pieces(frames: &[Frame]) -> Vec<Piece> {
+    let mut start = 0;
+    frames
+        .iter()
+        .map(|frame| {
+            let end = start + frame.compressed as usize;
+            let piece = Piece::Zstd(start..end);
+            start = end;
+            piece
+        })
+        .collect()
+}
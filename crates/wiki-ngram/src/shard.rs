@@ -0,0 +1,107 @@
+//! Counting on several threads with a deterministic reduce, for library
+//! users counting sentences they hold in memory; the wiki-ngram binary
+//! streams the dump through its consumers on one thread.
+//!
+//! Sentences are split into one contiguous shard per thread; each thread
+//! counts its shard with its own [`CountSource`] and writes the counts to a
+//! shard file, and the files are merged in shard order. Every key's count is
+//! the same sum whatever the number of threads, and the merge never depends
+//! on which thread finishes first, so the model built from the merged counts
+//! is byte-identical at any thread count.
+//!
+//! Shard file format (UTF-8, keys sorted, tab separated):
+//!
+//! ```text
+//! #ngram-counts<TAB>v1
+//! <key><TAB><count>
+//! ```
+
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::ngram::NgramCounts;
+use crate::stage::CountSource;
+use crate::tokenize::Token;
+
+const HEADER_MAGIC: &str = "#ngram-counts";
+const FORMAT_VERSION: &str = "v1";
+
+/// Counts `sentences` (tokens and weight) on `threads` threads, each with a
+/// source from `make_source`. Shard files go to `shard_dir` and are removed
+/// once merged.
+pub fn count_parallel<S: CountSource>(
+    sentences: &[(Vec<Token>, u32)],
+    threads: usize,
+    shard_dir: &Path,
+    make_source: impl Fn() -> S + Sync,
+) -> Result<NgramCounts> {
+    fs::create_dir_all(shard_dir)?;
+    let shard_len = sentences.len().div_ceil(threads.max(1)).max(1);
+    let shards = std::thread::scope(|scope| -> Result<Vec<PathBuf>> {
+        let handles: Vec<_> = sentences
+            .chunks(shard_len)
+            .enumerate()
+            .map(|(index, shard)| {
+                let make_source = &make_source;
+                scope.spawn(move || -> Result<PathBuf> {
+                    let mut source = make_source();
+                    let mut counts = NgramCounts::default();
+                    for (tokens, weight) in shard {
+                        source.count(tokens, *weight, &mut counts);
+                    }
+                    let path = shard_dir.join(format!("shard-{:04}.tsv", index));
+                    write_shard(&counts, &path)?;
+                    Ok(path)
+                })
+            })
+            .collect();
+        // Joined in spawn order, so the paths are in shard order
+        handles.into_iter().map(|handle| handle.join().expect("Counting thread panicked")).collect()
+    })?;
+
+    let counts = merge_shards(&shards)?;
+    for path in &shards {
+        fs::remove_file(path)?;
+    }
+    Ok(counts)
+}
+
+/// Writes `counts` as a shard file, keys sorted.
+pub fn write_shard(counts: &NgramCounts, path: &Path) -> Result<()> {
+    let mut entries: Vec<(&str, u32)> = counts.iter().map(|(key, &count)| (key.as_ref(), count)).collect();
+    entries.sort_unstable();
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}\t{}", HEADER_MAGIC, FORMAT_VERSION)?;
+    for (key, count) in entries {
+        writeln!(writer, "{}\t{}", key, count)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Sums the shard files at `paths`, in the order given.
+pub fn merge_shards(paths: &[PathBuf]) -> Result<NgramCounts> {
+    let mut counts = NgramCounts::default();
+    for path in paths {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header = lines.next().with_context(|| format!("Empty shard file {:?}", path))??;
+        anyhow::ensure!(
+            header == format!("{}\t{}", HEADER_MAGIC, FORMAT_VERSION),
+            "{:?} is not a {} shard file",
+            path,
+            FORMAT_VERSION
+        );
+        for (line_no, line) in lines.enumerate() {
+            let line = line?;
+            let (key, count) = line
+                .rsplit_once('\t')
+                .and_then(|(key, count)| Some((key, count.parse::<u32>().ok()?)))
+                .with_context(|| format!("{:?} line {}: expected key and count", path, line_no + 2))?;
+            let total = counts.entry_ref(key).or_insert(0);
+            *total = total.saturating_add(count);
+        }
+    }
+    Ok(counts)
+}
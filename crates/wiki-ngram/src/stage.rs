@@ -0,0 +1,84 @@
+//! The stages an n-gram model is built in, as traits a fork can implement
+//! to plug in its own filters or outputs without patching main.rs:
+//!
+//! ```text
+//! CountSource   sentence tokens -> counts      (TokenNgrams)
+//! NgramFilter   counts -> scored entries       (FrequencyFilter)
+//! ModelSink     scored entries -> artifact     (FstSink)
+//! ```
+//!
+//! [`crate::consumer::NgramConsumer`] runs them in that order; the
+//! implementations in parentheses are what it uses unless told otherwise.
+
+use anyhow::Result;
+use keyboard_formats::ngram_key::KeySchema;
+use std::path::PathBuf;
+
+use crate::histogram::CountHistogram;
+use crate::ngram::{self, FilterConfig, NgramCounts, Smoothing};
+use crate::tokenize::{Token, TokenFields, TokenOutput};
+
+/// Turns the tokens of each sentence into counts.
+pub trait CountSource {
+    /// Token features read from the tokens.
+    fn fields(&self) -> TokenFields;
+
+    /// Adds the counts of one sentence, weighted by `weight`.
+    fn count(&mut self, tokens: &[Token], weight: u32, counts: &mut NgramCounts);
+}
+
+/// Decides which counted n-grams make it into the model, and their scores.
+pub trait NgramFilter {
+    /// Kept entries and their scores, sorted by key.
+    fn filter(&self, counts: &NgramCounts, histogram: &CountHistogram) -> Vec<(String, u64)>;
+}
+
+/// Writes the filtered entries out.
+pub trait ModelSink {
+    fn write(&mut self, entries: &[(String, u64)]) -> Result<()>;
+}
+
+/// Counts n-grams of 2 to `max_ngram` tokens rendered with a [`TokenOutput`].
+pub struct TokenNgrams {
+    pub token_output: TokenOutput,
+    pub max_ngram: usize,
+}
+
+impl CountSource for TokenNgrams {
+    fn fields(&self) -> TokenFields {
+        TokenFields::for_outputs(&[self.token_output])
+    }
+
+    fn count(&mut self, tokens: &[Token], weight: u32, counts: &mut NgramCounts) {
+        let keys: Vec<String> = tokens.iter().map(|token| token.key(self.token_output)).collect();
+        ngram::extract_ngrams_from_tokens(&keys, self.max_ngram, weight, counts);
+    }
+}
+
+/// Frequency thresholds and smoothing, as set on the command line.
+#[derive(Clone, Debug)]
+pub struct FrequencyFilter {
+    pub config: FilterConfig,
+    pub smoothing: Smoothing,
+}
+
+impl NgramFilter for FrequencyFilter {
+    fn filter(&self, counts: &NgramCounts, histogram: &CountHistogram) -> Vec<(String, u64)> {
+        tracing::info!("Filtering N-grams ({:?}, smoothing: {:?})...", self.config, self.smoothing);
+        ngram::filter_ngrams(counts, histogram, &self.config, self.smoothing)
+    }
+}
+
+/// Writes an FST whose keys must conform to `schema`.
+pub struct FstSink {
+    pub schema: KeySchema,
+    pub path: PathBuf,
+}
+
+impl ModelSink for FstSink {
+    fn write(&mut self, entries: &[(String, u64)]) -> Result<()> {
+        ngram::build_fst(entries, self.schema, &self.path)?;
+        tracing::info!("FST generated at {:?}", self.path);
+        Ok(())
+    }
+}
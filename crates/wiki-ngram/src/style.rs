@@ -0,0 +1,47 @@
+use crate::tokenize::Token;
+
+/// Sentences up to this many tokens count as short under the messaging profile.
+const SHORT_SENTENCE_TOKENS: usize = 15;
+
+/// Last tokens that mark a sentence as conversational rather than expository.
+const CONVERSATIONAL_ENDINGS: &[&str] = &["よ", "ね", "よね", "わ", "ぞ", "さ", "じゃん"];
+
+/// How much each sentence counts towards the model, by writing style.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum StyleProfile {
+    /// Every sentence counts once
+    #[default]
+    Neutral,
+    /// Short and dialogue-like sentences count up to four times, since keyboard
+    /// input is mostly short messages rather than encyclopedic prose
+    Messaging,
+}
+
+impl StyleProfile {
+    /// Weight of a tokenized sentence; `terminator` is the punctuation that
+    /// ended it, if any.
+    pub fn sentence_weight(self, tokens: &[Token], terminator: Option<char>) -> u32 {
+        match self {
+            StyleProfile::Neutral => 1,
+            StyleProfile::Messaging => {
+                let mut weight = 1;
+                if tokens.len() <= SHORT_SENTENCE_TOKENS {
+                    weight *= 2;
+                }
+                if is_dialogue_like(tokens, terminator) {
+                    weight *= 2;
+                }
+                weight
+            }
+        }
+    }
+}
+
+/// Quoted speech, questions, exclamations and sentence-final particles.
+fn is_dialogue_like(tokens: &[Token], terminator: Option<char>) -> bool {
+    matches!(terminator, Some('！' | '？'))
+        || tokens.iter().any(|token| token.surface.contains(['「', '」']))
+        || tokens
+            .last()
+            .is_some_and(|token| CONVERSATIONAL_ENDINGS.contains(&token.surface.as_str()))
+}
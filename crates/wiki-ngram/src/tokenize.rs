@@ -1,34 +1,184 @@
 use anyhow::Result;
-use std::fs::File;
-use std::io::Read;
+use mozc_dict_gen::vibrato::dictionary::LexType;
+use mozc_dict_gen::vibrato::tokenizer::worker::Worker;
+use mozc_dict_gen::vibrato::Tokenizer;
 use std::path::Path;
-use vibrato::Tokenizer;
-use zstd::Decoder;
+use std::sync::Mutex;
 
+/// Feature column holding the base form in the lexicon's MeCab-style features.
+const LEMMA_FIELD: usize = 6;
+/// Feature column holding the kana reading.
+const READING_FIELD: usize = 7;
+/// Number of leading POS columns used for POS keys (e.g. 名詞,固有名詞).
+const POS_FIELDS: usize = 2;
+
+/// Loads a mozc-dict-gen dictionary through its memory-mapped reader
+/// ([`mozc_dict_gen::read_dictionary`]).
 pub fn load_tokenizer(dict_path: &Path) -> Result<Tokenizer> {
-    log::info!("Loading dictionary from {:?}", dict_path);
-    
-    let file = File::open(dict_path)?;
-    let mut decoder = Decoder::new(file)?;
-    let mut dict_data = Vec::new();
-    decoder.read_to_end(&mut dict_data)?;
+    tracing::info!("Loading dictionary from {:?}", dict_path);
 
-    let dict = vibrato::Dictionary::read(&dict_data[..])?;
-    let tokenizer = Tokenizer::new(dict);
+    let tokenizer = Tokenizer::new(mozc_dict_gen::read_dictionary(dict_path)?);
 
-    log::info!("Dictionary loaded successfully");
+    tracing::info!("Dictionary loaded successfully");
     Ok(tokenizer)
 }
 
-pub fn tokenize_text(tokenizer: &Tokenizer, text: &str) -> Vec<String> {
-    let mut worker = tokenizer.new_worker();
-    worker.reset_sentence(text);
-    worker.tokenize();
+/// How a token is rendered when it becomes part of an n-gram key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum TokenOutput {
+    /// Surface form only (東京)
+    Surface,
+    /// Surface and kana reading (東京/とうきょう)
+    SurfaceReading,
+    /// Surface and coarse POS (東京/名詞,固有名詞)
+    SurfacePos,
+    /// Base form, falling back to the surface (行く for 行っ)
+    Lemma,
+}
+
+/// Which token features to extract beyond the surface.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenFields {
+    pub reading: bool,
+    pub pos: bool,
+    pub lemma: bool,
+}
+
+impl TokenFields {
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            reading: self.reading || other.reading,
+            pos: self.pos || other.pos,
+            lemma: self.lemma || other.lemma,
+        }
+    }
+
+    /// Fields needed to render every one of `outputs`.
+    pub fn for_outputs(outputs: &[TokenOutput]) -> Self {
+        let mut fields = Self::default();
+        for output in outputs {
+            match output {
+                TokenOutput::Surface => {}
+                TokenOutput::SurfaceReading => fields.reading = true,
+                TokenOutput::SurfacePos => fields.pos = true,
+                TokenOutput::Lemma => fields.lemma = true,
+            }
+        }
+        fields
+    }
+}
+
+/// A token with the features requested from the pool.
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub surface: String,
+    pub reading: Option<String>,
+    pub pos: Option<String>,
+    pub lemma: Option<String>,
+    /// Whether the token came from unknown-word processing rather than the lexicon
+    pub unknown: bool,
+}
+
+impl Token {
+    /// Renders the token for `output`. Features that were not extracted fall
+    /// back to the surface form.
+    pub fn key(&self, output: TokenOutput) -> String {
+        match output {
+            TokenOutput::Surface => self.surface.clone(),
+            TokenOutput::SurfaceReading => {
+                format!("{}/{}", self.surface, self.reading.as_deref().unwrap_or(&self.surface))
+            }
+            TokenOutput::SurfacePos => {
+                format!("{}/{}", self.surface, self.pos.as_deref().unwrap_or("*"))
+            }
+            TokenOutput::Lemma => self.lemma.clone().unwrap_or_else(|| self.surface.clone()),
+        }
+    }
+}
+
+/// A set of reusable Vibrato workers sharing one tokenizer.
+///
+/// Workers hold sizeable lattice buffers, so creating one per sentence is
+/// wasteful. Workers checked out with [`TokenizerPool::worker`] go back to
+/// the pool when dropped.
+pub struct TokenizerPool<'t> {
+    tokenizer: &'t Tokenizer,
+    fields: TokenFields,
+    idle: Mutex<Vec<Worker<'t>>>,
+}
+
+impl<'t> TokenizerPool<'t> {
+    pub fn new(tokenizer: &'t Tokenizer, fields: TokenFields) -> Self {
+        Self {
+            tokenizer,
+            fields,
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Checks out an idle worker, creating one if none is available.
+    pub fn worker(&self) -> PooledWorker<'_, 't> {
+        let worker = self
+            .idle
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| self.tokenizer.new_worker());
+        PooledWorker {
+            pool: self,
+            worker: Some(worker),
+        }
+    }
+}
+
+/// A worker borrowed from a [`TokenizerPool`].
+pub struct PooledWorker<'p, 't> {
+    pool: &'p TokenizerPool<'t>,
+    worker: Option<Worker<'t>>,
+}
+
+impl PooledWorker<'_, '_> {
+    /// Tokenizes `text`, extracting the features the pool was configured with.
+    pub fn tokenize(&mut self, text: &str) -> Vec<Token> {
+        let fields = self.pool.fields;
+        let worker = self.worker.as_mut().unwrap();
+        worker.reset_sentence(text);
+        worker.tokenize();
+
+        let mut tokens = Vec::with_capacity(worker.num_tokens());
+        for i in 0..worker.num_tokens() {
+            let token = worker.token(i);
+            let surface = token.surface();
+            let features: Vec<&str> = if fields == TokenFields::default() {
+                Vec::new()
+            } else {
+                token.feature().split(',').collect()
+            };
+            let feature_at = |index: usize| {
+                features
+                    .get(index)
+                    .filter(|value| **value != "*" && !value.is_empty())
+                    .map(|value| value.to_string())
+            };
+
+            tokens.push(Token {
+                surface: surface.to_string(),
+                reading: fields.reading.then(|| feature_at(READING_FIELD)).flatten(),
+                pos: fields
+                    .pos
+                    .then(|| features.iter().take(POS_FIELDS).copied().collect::<Vec<_>>().join(",")),
+                lemma: fields.lemma.then(|| feature_at(LEMMA_FIELD)).flatten(),
+                unknown: token.lex_type() == LexType::Unknown,
+            });
+        }
+        tokens
+    }
+}
 
-    let mut tokens = Vec::new();
-    for i in 0..worker.num_tokens() {
-        let token = worker.token(i);
-        tokens.push(token.surface().to_string());
+impl Drop for PooledWorker<'_, '_> {
+    fn drop(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.pool.idle.lock().unwrap().push(worker);
+        }
     }
-    tokens
 }